@@ -0,0 +1,169 @@
+use crate::packet::MacAddr;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArpInfo {
+    pub hardware_type: u16,
+    pub protocol_type: u16,
+    pub operation: u16,
+    // Addresses are only decoded for 6-byte hardware and IPv4/IPv6 protocol addresses
+    pub sender_mac: Option<MacAddr>,
+    pub sender_ip: Option<IpAddr>,
+    pub target_mac: Option<MacAddr>,
+    pub target_ip: Option<IpAddr>,
+}
+
+impl ArpInfo {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let header = data.get(..8)?;
+        let hlen = header[4] as usize;
+        let plen = header[5] as usize;
+        let body = data.get(8..8 + 2 * (hlen + plen))?;
+        let (sender, target) = body.split_at(hlen + plen);
+
+        let mac = |bytes: &[u8]| -> Option<MacAddr> { bytes.try_into().ok().map(MacAddr) };
+        let ip = |bytes: &[u8]| -> Option<IpAddr> {
+            match bytes.len() {
+                4 => <[u8; 4]>::try_from(bytes).ok().map(|b| IpAddr::V4(Ipv4Addr::from(b))),
+                16 => <[u8; 16]>::try_from(bytes).ok().map(|b| IpAddr::V6(Ipv6Addr::from(b))),
+                _ => None,
+            }
+        };
+
+        Some(ArpInfo {
+            hardware_type: u16::from_be_bytes([header[0], header[1]]),
+            protocol_type: u16::from_be_bytes([header[2], header[3]]),
+            operation: u16::from_be_bytes([header[6], header[7]]),
+            sender_mac: mac(&sender[..hlen]),
+            sender_ip: ip(&sender[hlen..]),
+            target_mac: mac(&target[..hlen]),
+            target_ip: ip(&target[hlen..]),
+        })
+    }
+
+    // Gratuitous ARP announces the sender's own address
+    pub fn is_gratuitous(&self) -> bool {
+        self.sender_ip.is_some() && self.sender_ip == self.target_ip
+    }
+
+    pub fn summary(&self) -> String {
+        let sender_ip = or_unknown(self.sender_ip);
+        let sender_mac = or_unknown(self.sender_mac);
+        let target_ip = or_unknown(self.target_ip);
+        match self.operation {
+            1 if self.is_gratuitous() => format!("gratuitous {} is-at {}", sender_ip, sender_mac),
+            1 => format!("who-has {} tell {}", target_ip, sender_ip),
+            2 => format!("{} is-at {}", sender_ip, sender_mac),
+            _ => format!("{} {} -> {}", operation_name(self.operation), sender_ip, target_ip),
+        }
+    }
+}
+
+pub fn operation_name(operation: u16) -> String {
+    match operation {
+        1 => "Request".to_string(),
+        2 => "Reply".to_string(),
+        3 => "RARP Request".to_string(),
+        4 => "RARP Reply".to_string(),
+        8 => "InARP Request".to_string(),
+        9 => "InARP Reply".to_string(),
+        other => format!("op {}", other),
+    }
+}
+
+pub fn write_arp_details(out: &mut dyn Write, arp: &ArpInfo) -> io::Result<()> {
+    writeln!(out, "  ARP: {} ({})", operation_name(arp.operation), arp.summary())?;
+    writeln!(out, "    Sender: {} / {}", or_unknown(arp.sender_mac), or_unknown(arp.sender_ip))?;
+    writeln!(out, "    Target: {} / {}", or_unknown(arp.target_mac), or_unknown(arp.target_ip))?;
+    Ok(())
+}
+
+fn or_unknown<T: fmt::Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "?".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listening::parse_packet_with_etherparse;
+    use crate::packet::NetworkLayer;
+    use crate::source::RawPacket;
+    use pcap::Linktype;
+
+    const ROUTER_MAC: [u8; 6] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+    const HOST_MAC: [u8; 6] = [0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+
+    // Ethernet/IPv4 ARP body
+    fn arp(operation: u16, sender: ([u8; 6], [u8; 4]), target: ([u8; 6], [u8; 4])) -> Vec<u8> {
+        let mut body = vec![0x00, 0x01, 0x08, 0x00, 6, 4];
+        body.extend_from_slice(&operation.to_be_bytes());
+        for (mac, ip) in [sender, target] {
+            body.extend_from_slice(&mac);
+            body.extend_from_slice(&ip);
+        }
+        body
+    }
+
+    #[test]
+    fn parses_requests_and_replies() {
+        let request = ArpInfo::parse(&arp(1, (HOST_MAC, [192, 168, 1, 20]), ([0; 6], [192, 168, 1, 1]))).unwrap();
+        assert_eq!((request.hardware_type, request.protocol_type, request.operation), (1, 0x0800, 1));
+        assert_eq!(request.sender_mac.unwrap().to_string(), "66:77:88:99:aa:bb");
+        assert_eq!(request.target_ip, Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1))));
+        assert!(!request.is_gratuitous());
+        assert_eq!(request.summary(), "who-has 192.168.1.1 tell 192.168.1.20");
+
+        let reply = ArpInfo::parse(&arp(2, (ROUTER_MAC, [192, 168, 1, 1]), (HOST_MAC, [192, 168, 1, 20]))).unwrap();
+        assert_eq!(reply.summary(), "192.168.1.1 is-at 00:11:22:33:44:55");
+
+        let mut details = Vec::new();
+        write_arp_details(&mut details, &reply).unwrap();
+        assert_eq!(
+            String::from_utf8(details).unwrap(),
+            "  ARP: Reply (192.168.1.1 is-at 00:11:22:33:44:55)\n    \
+             Sender: 00:11:22:33:44:55 / 192.168.1.1\n    \
+             Target: 66:77:88:99:aa:bb / 192.168.1.20\n"
+        );
+    }
+
+    #[test]
+    fn gratuitous_arp_announces_the_sender() {
+        let announce = ArpInfo::parse(&arp(1, (HOST_MAC, [10, 0, 0, 7]), ([0; 6], [10, 0, 0, 7]))).unwrap();
+        assert!(announce.is_gratuitous());
+        assert_eq!(announce.summary(), "gratuitous 10.0.0.7 is-at 66:77:88:99:aa:bb");
+    }
+
+    #[test]
+    fn odd_address_sizes_and_truncation() {
+        // 8-byte hardware addresses: the operation is still known, the MACs are not
+        let mut body = vec![0x00, 0x06, 0x08, 0x00, 8, 4, 0x00, 0x08];
+        body.extend_from_slice(&[0xee; 8]);
+        body.extend_from_slice(&[10, 0, 0, 1]);
+        body.extend_from_slice(&[0xdd; 8]);
+        body.extend_from_slice(&[10, 0, 0, 2]);
+        let inarp = ArpInfo::parse(&body).unwrap();
+        assert_eq!((inarp.sender_mac, inarp.target_mac), (None, None));
+        assert_eq!(inarp.summary(), "InARP Request 10.0.0.1 -> 10.0.0.2");
+
+        let full = arp(2, (ROUTER_MAC, [192, 168, 1, 1]), (HOST_MAC, [192, 168, 1, 20]));
+        assert!(ArpInfo::parse(&full[..full.len() - 1]).is_none());
+        assert!(ArpInfo::parse(&full[..7]).is_none());
+    }
+
+    #[test]
+    fn arp_frames_count_as_their_operation() {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&HOST_MAC);
+        frame.extend_from_slice(&[0x08, 0x06]);
+        frame.extend(arp(1, (HOST_MAC, [192, 168, 1, 20]), ([0; 6], [192, 168, 1, 1])));
+        let raw = RawPacket::new(0, 0, frame);
+        let decoded = parse_packet_with_etherparse(1, &raw, Linktype::ETHERNET).unwrap();
+
+        assert!(matches!(decoded.network, Some(NetworkLayer::Arp(_))));
+        assert_eq!(decoded.protocol_name(), "ARP");
+        assert_eq!(decoded.message_type().as_deref(), Some("ARP Request"));
+    }
+}
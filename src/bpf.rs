@@ -0,0 +1,271 @@
+use crate::recovery::CaptureError;
+use pcap::{BpfInstruction, Capture, Linktype};
+use std::error::Error;
+use std::fs;
+
+// Instruction classes and fields of classic BPF opcodes
+const LD: u16 = 0x00;
+const LDX: u16 = 0x01;
+const ST: u16 = 0x02;
+const STX: u16 = 0x03;
+const ALU: u16 = 0x04;
+const JMP: u16 = 0x05;
+const RET: u16 = 0x06;
+const MISC: u16 = 0x07;
+
+const IMM: u16 = 0x00;
+const ABS: u16 = 0x20;
+const IND: u16 = 0x40;
+const MEM: u16 = 0x60;
+const LEN: u16 = 0x80;
+const MSH: u16 = 0xa0;
+
+// Operand source: the constant k or the X register
+const SRC_X: u16 = 0x08;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Instruction {
+    // pcap only exposes an instruction through Display, as "code jt jf k"
+    fn from_pcap(instruction: &BpfInstruction) -> Option<Self> {
+        let text = instruction.to_string();
+        let mut fields = text.split_whitespace().map(|field| field.parse::<u32>().ok());
+        Some(Instruction {
+            code: u16::try_from(fields.next()??).ok()?,
+            jt: u8::try_from(fields.next()??).ok()?,
+            jf: u8::try_from(fields.next()??).ok()?,
+            k: fields.next()??,
+        })
+    }
+
+    // Mnemonic and operand, as printed by `tcpdump -d`
+    fn image(&self, n: usize) -> String {
+        let k = self.k;
+        let x = self.code & SRC_X != 0;
+        let size = match self.code & 0x18 {
+            0x08 => "h",
+            0x10 => "b",
+            _ => "",
+        };
+        let (op, operand) = match self.code & 0x07 {
+            LD => match self.code & 0xe0 {
+                IMM => ("ld".to_string(), format!("#0x{:x}", k)),
+                ABS => (format!("ld{}", size), format!("[{}]", k)),
+                IND => (format!("ld{}", size), format!("[x + {}]", k)),
+                MEM => ("ld".to_string(), format!("M[{}]", k)),
+                LEN => ("ld".to_string(), "#pktlen".to_string()),
+                _ => return self.unknown(n),
+            },
+            LDX => match self.code & 0xe0 {
+                IMM => ("ldx".to_string(), format!("#0x{:x}", k)),
+                MEM => ("ldx".to_string(), format!("M[{}]", k)),
+                LEN => ("ldx".to_string(), "#pktlen".to_string()),
+                MSH => ("ldxb".to_string(), format!("4*([{}]&0xf)", k)),
+                _ => return self.unknown(n),
+            },
+            ST => ("st".to_string(), format!("M[{}]", k)),
+            STX => ("stx".to_string(), format!("M[{}]", k)),
+            ALU => {
+                let op = match self.code & 0xf0 {
+                    0x00 => "add",
+                    0x10 => "sub",
+                    0x20 => "mul",
+                    0x30 => "div",
+                    0x40 => "or",
+                    0x50 => "and",
+                    0x60 => "lsh",
+                    0x70 => "rsh",
+                    0x80 => return format!("({:03}) neg", n),
+                    0x90 => "mod",
+                    0xa0 => "xor",
+                    _ => return self.unknown(n),
+                };
+                let operand = match (x, op) {
+                    (true, _) => "x".to_string(),
+                    (false, "or" | "and" | "xor") => format!("#0x{:x}", k),
+                    (false, _) => format!("#{}", k),
+                };
+                (op.to_string(), operand)
+            }
+            JMP => {
+                let op = match self.code & 0xf0 {
+                    0x00 => return format!("({:03}) {:<8} {}", n, "ja", n + 1 + k as usize),
+                    0x10 => "jeq",
+                    0x20 => "jgt",
+                    0x30 => "jge",
+                    0x40 => "jset",
+                    _ => return self.unknown(n),
+                };
+                let operand = if x { "x".to_string() } else { format!("#0x{:x}", k) };
+                return format!(
+                    "({:03}) {:<8} {:<16} jt {}\tjf {}",
+                    n,
+                    op,
+                    operand,
+                    n + 1 + self.jt as usize,
+                    n + 1 + self.jf as usize
+                );
+            }
+            RET => match self.code & 0x18 {
+                0x00 => ("ret".to_string(), format!("#{}", k)),
+                0x08 => ("ret".to_string(), "x".to_string()),
+                _ => ("ret".to_string(), "a".to_string()),
+            },
+            MISC if self.code & 0xf8 == 0x80 => return format!("({:03}) txa", n),
+            MISC => return format!("({:03}) tax", n),
+            _ => return self.unknown(n),
+        };
+        format!("({:03}) {:<8} {}", n, op, operand)
+    }
+
+    fn unknown(&self, n: usize) -> String {
+        format!("({:03}) unimp    0x{:x}", n, self.code)
+    }
+}
+
+// Compile `filter` as a capture with `linktype` would, without opening a device
+pub fn compile(filter: &str, linktype: Linktype) -> Result<Vec<Instruction>, CaptureError> {
+    let program = Capture::dead(linktype)
+        .and_then(|cap| cap.compile(filter, true))
+        .map_err(|e| CaptureError::bad_filter(filter, &e))?;
+    Ok(program.get_instructions().iter().filter_map(Instruction::from_pcap).collect())
+}
+
+// The program in `tcpdump -d` form, one line per instruction
+pub fn disassemble(program: &[Instruction]) -> Vec<String> {
+    program.iter().enumerate().map(|(n, instruction)| instruction.image(n)).collect()
+}
+
+// Datalink named as libpcap does ("EN10MB", "LINUX_SLL", ...) or by number
+pub fn parse_linktype(name: &str) -> Result<Linktype, Box<dyn Error>> {
+    match name.parse::<i32>() {
+        Ok(number) => Ok(Linktype(number)),
+        Err(_) => Linktype::from_name(name).map_err(|_| format!("Unknown datalink '{}'", name).into()),
+    }
+}
+
+pub fn linktype_name(linktype: Linktype) -> String {
+    linktype.get_name().unwrap_or_else(|_| format!("DLT {}", linktype.0))
+}
+
+// Read a filter from a file. Everything after a '#' on a line is a comment, and
+// lines are joined so long filters can be split up freely.
+pub fn read_filter_file(path: &str) -> Result<String, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read filter file {}: {}", path, e))?;
+    let filter = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if filter.is_empty() {
+        return Err(format!("Filter file {} contains no filter", path).into());
+    }
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insn(code: u16, jt: u8, jf: u8, k: u32) -> Instruction {
+        Instruction { code, jt, jf, k }
+    }
+
+    #[test]
+    fn disassembles_like_tcpdump() {
+        let program = [
+            insn(0x28, 0, 0, 12),
+            insn(0x15, 0, 3, 0x800),
+            insn(0xb1, 0, 0, 14),
+            insn(0x48, 0, 0, 16),
+            insn(0x45, 1, 0, 0x1fff),
+            insn(0x00, 0, 0, 42),
+            insn(0x80, 0, 0, 0),
+            insn(0x60, 0, 0, 1),
+            insn(0x01, 0, 0, 5),
+            insn(0x02, 0, 0, 0),
+            insn(0x03, 0, 0, 2),
+            insn(0x04, 0, 0, 4),
+            insn(0x54, 0, 0, 0xff),
+            insn(0x1c, 0, 0, 0),
+            insn(0x84, 0, 0, 0),
+            insn(0x05, 0, 0, 1),
+            insn(0x2d, 2, 0, 0),
+            insn(0x07, 0, 0, 0),
+            insn(0x87, 0, 0, 0),
+            insn(0x16, 0, 0, 0),
+            insn(0x06, 0, 0, 262_144),
+            insn(0xc0, 0, 0, 0),
+        ];
+        assert_eq!(
+            disassemble(&program),
+            [
+                "(000) ldh      [12]",
+                "(001) jeq      #0x800           jt 2\tjf 5",
+                "(002) ldxb     4*([14]&0xf)",
+                "(003) ldh      [x + 16]",
+                "(004) jset     #0x1fff          jt 6\tjf 5",
+                "(005) ld       #0x2a",
+                "(006) ld       #pktlen",
+                "(007) ld       M[1]",
+                "(008) ldx      #0x5",
+                "(009) st       M[0]",
+                "(010) stx      M[2]",
+                "(011) add      #4",
+                "(012) and      #0xff",
+                "(013) sub      x",
+                "(014) neg",
+                "(015) ja       17",
+                "(016) jgt      x                jt 19\tjf 17",
+                "(017) tax",
+                "(018) txa",
+                "(019) ret      a",
+                "(020) ret      #262144",
+                "(021) unimp    0xc0",
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_tcp_for_ethernet() {
+        let lines = disassemble(&compile("tcp", Linktype::ETHERNET).unwrap());
+        // The exact program depends on the libpcap version; its shape does not
+        assert_eq!(lines[0], "(000) ldh      [12]");
+        assert!(lines.iter().any(|line| line.contains("jeq      #0x800 ")), "{:#?}", lines);
+        assert!(lines.iter().any(|line| line.contains("jeq      #0x86dd ")), "{:#?}", lines);
+        assert!(lines.iter().any(|line| line.ends_with("ldb      [23]")), "{:#?}", lines);
+        assert!(lines.last().unwrap().ends_with("ret      #0"));
+        assert!(lines.iter().all(|line| !line.contains("unimp")), "{:#?}", lines);
+
+        let error = compile("tcp port", Linktype::ETHERNET).unwrap_err();
+        assert!(matches!(error, CaptureError::BadFilter { ref filter, .. } if filter == "tcp port"));
+    }
+
+    #[test]
+    fn datalinks_by_name_or_number() {
+        assert_eq!(parse_linktype("EN10MB").unwrap(), Linktype::ETHERNET);
+        assert_eq!(parse_linktype("113").unwrap(), Linktype::LINUX_SLL);
+        assert!(parse_linktype("NOT_A_DLT").is_err());
+        assert_eq!(linktype_name(Linktype::ETHERNET), "EN10MB");
+    }
+
+    #[test]
+    fn filter_files_drop_comments_and_join_lines() {
+        let path = std::env::temp_dir().join(format!("testgame-filter-{}.bpf", std::process::id()));
+        fs::write(&path, "# web traffic only\ntcp port 80   # plain http\n\tor tcp port 443\n").unwrap();
+        let filter = read_filter_file(path.to_str().unwrap());
+        fs::write(&path, "# nothing here\n\n").unwrap();
+        let empty = read_filter_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(filter.unwrap(), "tcp port 80 or tcp port 443");
+        assert!(empty.unwrap_err().to_string().contains("contains no filter"));
+    }
+}
@@ -0,0 +1,478 @@
+use crate::packet::{DecodedPacket, NetworkLayer, Reassembled};
+use crate::source::RawPacket;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+// Largest IP datagram; fragments reaching past it are a ping-of-death style anomaly
+const MAX_DATAGRAM: usize = 65535;
+
+// Fragments belong to the same datagram when all four match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub protocol: u8,
+    pub id: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DefragConfig {
+    // Seconds to wait for the rest of a datagram (the Linux ipfrag_time default)
+    pub timeout: f64,
+    // Incomplete datagrams held at once before dropping the oldest
+    pub max_datagrams: usize,
+}
+
+impl Default for DefragConfig {
+    fn default() -> Self {
+        DefragConfig {
+            timeout: 30.0,
+            max_datagrams: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefragStats {
+    pub fragments: u64,
+    pub reassembled: u64,
+    pub timed_out: u64,
+    pub evicted: u64,
+    // Datagrams still missing fragments at the end of the capture
+    pub pending: u64,
+    // Fragments covering bytes already received; the first copy is kept
+    pub overlapping: u64,
+    // First fragments too short to hold the transport header
+    pub tiny: u64,
+    // Fragments that would make the datagram larger than 65535 bytes
+    pub oversized: u64,
+}
+
+impl DefragStats {
+    pub fn anomalies(&self) -> u64 {
+        self.overlapping + self.tiny + self.oversized
+    }
+}
+
+struct Datagram {
+    // Frame bytes up to the end of the IP headers, taken from the offset-0 fragment
+    head: Option<Vec<u8>>,
+    header_offset: usize,
+    ipv6: bool,
+    protocol: u8,
+    // Non-overlapping payload pieces keyed by offset
+    pieces: BTreeMap<usize, Vec<u8>>,
+    // Payload length, known once the last fragment has arrived
+    total: Option<usize>,
+    fragments: u32,
+    first_seen: f64,
+}
+
+impl Datagram {
+    // Keep the parts of the fragment not already held. Returns true if it overlapped.
+    fn insert(&mut self, offset: usize, data: &[u8]) -> bool {
+        let end = offset + data.len();
+        let mut overlapped = false;
+        let mut start = offset;
+        let mut holes = Vec::new();
+        for (&piece_start, piece) in self.pieces.range(..end) {
+            let piece_end = piece_start + piece.len();
+            if piece_end <= offset {
+                continue;
+            }
+            overlapped = true;
+            if piece_start > start {
+                holes.push((start, piece_start));
+            }
+            start = start.max(piece_end);
+        }
+        if start < end {
+            holes.push((start, end));
+        }
+        for (from, to) in holes {
+            self.pieces.insert(from, data[from - offset..to - offset].to_vec());
+        }
+        overlapped
+    }
+
+    // The whole payload, once the first and last fragments and everything between are in
+    fn payload(&self) -> Option<Vec<u8>> {
+        let total = self.total?;
+        self.head.as_ref()?;
+        let mut payload = Vec::with_capacity(total);
+        for (&start, piece) in &self.pieces {
+            if start != payload.len() {
+                return None;
+            }
+            payload.extend_from_slice(piece);
+        }
+        (payload.len() == total).then_some(payload)
+    }
+
+    // An unfragmented frame: the first fragment's headers with the fragment fields cleared
+    fn rebuild(&self, payload: &[u8]) -> Vec<u8> {
+        let mut frame = self.head.clone().unwrap_or_default();
+        let ip = self.header_offset;
+        if self.ipv6 {
+            // Extension headers are dropped so the fixed header points straight at the payload
+            frame.truncate(ip + 40);
+            frame[ip + 4..ip + 6].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+            frame[ip + 6] = self.protocol;
+        } else {
+            let ihl = (frame[ip] & 0x0f) as usize * 4;
+            frame.truncate(ip + ihl);
+            frame[ip + 2..ip + 4].copy_from_slice(&((ihl + payload.len()) as u16).to_be_bytes());
+            // Keep DF, clear MF and the offset
+            frame[ip + 6] &= 0x40;
+            frame[ip + 7] = 0;
+            frame[ip + 10..ip + 12].copy_from_slice(&[0, 0]);
+            let checksum = ipv4_checksum(&frame[ip..ip + ihl]);
+            frame[ip + 10..ip + 12].copy_from_slice(&checksum.to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+// Smallest header a first fragment has to carry for the transport to be decodable
+fn min_transport_header(protocol: u8) -> usize {
+    match protocol {
+        6 => 20,
+        17 | 1 | 58 => 8,
+        _ => 0,
+    }
+}
+
+// Collects IPv4 fragments and IPv6 Fragment-header packets until a datagram is complete
+pub struct Defragmenter {
+    config: DefragConfig,
+    datagrams: HashMap<FragmentKey, Datagram>,
+    last_sweep: f64,
+    stats: DefragStats,
+}
+
+impl Defragmenter {
+    pub fn new(config: DefragConfig) -> Self {
+        Defragmenter {
+            config,
+            datagrams: HashMap::new(),
+            last_sweep: 0.0,
+            stats: DefragStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> DefragStats {
+        DefragStats {
+            pending: self.datagrams.len() as u64,
+            ..self.stats
+        }
+    }
+
+    // Feed a decoded packet. When it completes a datagram, returns the rebuilt
+    // unfragmented frame, ready to be decoded again.
+    pub fn process(&mut self, raw: &RawPacket, packet: &DecodedPacket) -> Option<(RawPacket, Reassembled)> {
+        let (ipv6, ip) = match &packet.network {
+            Some(NetworkLayer::Ipv4(ip)) => (false, ip),
+            Some(NetworkLayer::Ipv6(ip)) => (true, ip),
+            _ => return None,
+        };
+        let fragment = ip.fragment?;
+        let range = packet.payload?;
+        self.stats.fragments += 1;
+
+        let now = packet.timestamp();
+        if now - self.last_sweep >= 1.0 {
+            self.expire(now);
+            self.last_sweep = now;
+        }
+        // A fragment cut short by the snaplen can't be put back together
+        if raw.caplen < raw.len {
+            return None;
+        }
+
+        let key = FragmentKey {
+            src: ip.src,
+            dst: ip.dst,
+            protocol: ip.protocol,
+            id: fragment.id,
+        };
+        let data = packet.payload(&raw.data);
+        let offset = fragment.offset as usize;
+        let end = offset + data.len();
+        if end + (range.offset - fragment.header_offset) > MAX_DATAGRAM {
+            self.stats.oversized += 1;
+            self.datagrams.remove(&key);
+            return None;
+        }
+        if offset == 0 && data.len() < min_transport_header(ip.protocol) {
+            self.stats.tiny += 1;
+        }
+
+        if !self.datagrams.contains_key(&key) && self.datagrams.len() >= self.config.max_datagrams {
+            self.evict_oldest();
+        }
+        let datagram = self.datagrams.entry(key).or_insert_with(|| Datagram {
+            head: None,
+            header_offset: 0,
+            ipv6,
+            protocol: ip.protocol,
+            pieces: BTreeMap::new(),
+            total: None,
+            fragments: 0,
+            first_seen: now,
+        });
+        datagram.fragments += 1;
+        if offset == 0 && datagram.head.is_none() {
+            datagram.head = Some(raw.data[..range.offset].to_vec());
+            datagram.header_offset = fragment.header_offset;
+        }
+        if !fragment.more_fragments {
+            datagram.total.get_or_insert(end);
+        }
+        if datagram.insert(offset, data) {
+            self.stats.overlapping += 1;
+        }
+
+        let payload = datagram.payload()?;
+        let datagram = self.datagrams.remove(&key)?;
+        self.stats.reassembled += 1;
+
+        let mut rebuilt = RawPacket::new(raw.ts_sec, raw.ts_usec, datagram.rebuild(&payload));
+        rebuilt.interface = raw.interface;
+        let reassembled = Reassembled {
+            fragments: datagram.fragments,
+            length: payload.len(),
+        };
+        Some((rebuilt, reassembled))
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .datagrams
+            .iter()
+            .min_by(|a, b| a.1.first_seen.total_cmp(&b.1.first_seen))
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest {
+            self.datagrams.remove(&key);
+            self.stats.evicted += 1;
+        }
+    }
+
+    fn expire(&mut self, now: f64) {
+        let before = self.datagrams.len();
+        let timeout = self.config.timeout;
+        self.datagrams.retain(|_, datagram| now - datagram.first_seen <= timeout);
+        self.stats.timed_out += (before - self.datagrams.len()) as u64;
+    }
+}
+
+impl Default for Defragmenter {
+    fn default() -> Self {
+        Defragmenter::new(DefragConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{FragmentInfo, IpLayer, PayloadRange};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const ETHERNET_LEN: usize = 14;
+
+    fn decoded(ts: f64, network: NetworkLayer, payload: PayloadRange, frame_len: usize) -> DecodedPacket {
+        DecodedPacket {
+            number: 1,
+            interface: 0,
+            ts_sec: ts.trunc() as i64,
+            ts_usec: (ts.fract() * 1_000_000.0).round() as i64,
+            caplen: frame_len as u32,
+            len: frame_len as u32,
+            link: None,
+            network: Some(network),
+            transport: None,
+            application: None,
+            payload: Some(payload),
+            reassembled: None,
+            encapsulation: Vec::new(),
+            wlan: None,
+        }
+    }
+
+    fn raw(packet: &DecodedPacket, frame: Vec<u8>) -> RawPacket {
+        RawPacket::new(packet.ts_sec, packet.ts_usec, frame)
+    }
+
+    // Ethernet + IPv4 fragment of a UDP datagram from 10.0.0.1 to 10.0.0.2
+    fn ipv4_fragment(ts: f64, id: u16, offset: u16, more: bool, data: &[u8]) -> (RawPacket, DecodedPacket) {
+        let mut frame = vec![0; ETHERNET_LEN];
+        frame[12..14].copy_from_slice(&[0x08, 0x00]);
+        let flags = ((more as u16) << 13) | (offset / 8);
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&id.to_be_bytes());
+        frame.extend_from_slice(&flags.to_be_bytes());
+        frame.extend_from_slice(&[64, 17, 0xab, 0xcd, 10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(data);
+
+        let ip = IpLayer {
+            src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            ttl: 64,
+            ip_id: Some(id),
+            protocol: 17,
+            fragment: Some(FragmentInfo {
+                id: id as u32,
+                offset,
+                more_fragments: more,
+                dont_fragment: false,
+                header_offset: ETHERNET_LEN,
+            }),
+        };
+        let range = PayloadRange { offset: ETHERNET_LEN + 20, len: data.len() };
+        let packet = decoded(ts, NetworkLayer::Ipv4(ip), range, frame.len());
+        (raw(&packet, frame.clone()), packet)
+    }
+
+    // Ethernet + IPv6 header + Fragment extension header
+    fn ipv6_fragment(ts: f64, id: u32, offset: u16, more: bool, data: &[u8]) -> (RawPacket, DecodedPacket) {
+        let mut frame = vec![0; ETHERNET_LEN];
+        frame[12..14].copy_from_slice(&[0x86, 0xdd]);
+        frame.extend_from_slice(&[0x60, 0, 0, 0]);
+        frame.extend_from_slice(&((8 + data.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[44, 64]);
+        let src = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let dst = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        frame.extend_from_slice(&src.octets());
+        frame.extend_from_slice(&dst.octets());
+        frame.extend_from_slice(&[17, 0]);
+        frame.extend_from_slice(&(offset | more as u16).to_be_bytes());
+        frame.extend_from_slice(&id.to_be_bytes());
+        frame.extend_from_slice(data);
+
+        let ip = IpLayer {
+            src: IpAddr::V6(src),
+            dst: IpAddr::V6(dst),
+            ttl: 64,
+            ip_id: None,
+            protocol: 17,
+            fragment: Some(FragmentInfo {
+                id,
+                offset,
+                more_fragments: more,
+                dont_fragment: false,
+                header_offset: ETHERNET_LEN,
+            }),
+        };
+        let range = PayloadRange { offset: ETHERNET_LEN + 48, len: data.len() };
+        let packet = decoded(ts, NetworkLayer::Ipv6(ip), range, frame.len());
+        (raw(&packet, frame.clone()), packet)
+    }
+
+    fn feed(defrag: &mut Defragmenter, (raw, packet): (RawPacket, DecodedPacket)) -> Option<(RawPacket, Reassembled)> {
+        defrag.process(&raw, &packet)
+    }
+
+    fn payload() -> Vec<u8> {
+        (0..24).collect()
+    }
+
+    #[test]
+    fn reassembles_out_of_order_fragments() {
+        let data = payload();
+        let mut defrag = Defragmenter::default();
+        assert!(feed(&mut defrag, ipv4_fragment(1.0, 7, 16, false, &data[16..])).is_none());
+        assert!(feed(&mut defrag, ipv4_fragment(1.1, 7, 0, true, &data[..8])).is_none());
+        let (rebuilt, reassembled) = feed(&mut defrag, ipv4_fragment(1.2, 7, 8, true, &data[8..16])).unwrap();
+
+        assert_eq!((reassembled.fragments, reassembled.length), (3, 24));
+        let ip = &rebuilt.data[ETHERNET_LEN..];
+        assert_eq!(&ip[2..4], &44u16.to_be_bytes());
+        // MF and offset cleared, checksum recomputed
+        assert_eq!(&ip[6..8], &[0, 0]);
+        assert_eq!(ipv4_checksum(&ip[..20]), 0);
+        assert_eq!(&ip[20..], &data[..]);
+
+        let stats = defrag.stats();
+        assert_eq!((stats.fragments, stats.reassembled, stats.pending), (3, 1, 0));
+        assert_eq!(stats.anomalies(), 0);
+    }
+
+    #[test]
+    fn first_copy_of_overlapping_bytes_wins() {
+        let mut defrag = Defragmenter::default();
+        assert!(feed(&mut defrag, ipv4_fragment(1.0, 9, 0, true, &[0xaa; 16])).is_none());
+        let (rebuilt, _) = feed(&mut defrag, ipv4_fragment(1.1, 9, 8, false, &[0xbb; 16])).unwrap();
+        let payload = &rebuilt.data[ETHERNET_LEN + 20..];
+        assert_eq!(payload, [&[0xaa; 16][..], &[0xbb; 8]].concat());
+        assert_eq!(defrag.stats().overlapping, 1);
+
+        // A fragment arriving inside an existing hole only fills the hole
+        let mut defrag = Defragmenter::default();
+        feed(&mut defrag, ipv4_fragment(1.0, 9, 0, true, &[1; 8]));
+        feed(&mut defrag, ipv4_fragment(1.0, 9, 16, false, &[3; 8]));
+        let (rebuilt, _) = feed(&mut defrag, ipv4_fragment(1.0, 9, 0, true, &[2; 24])).unwrap();
+        assert_eq!(&rebuilt.data[ETHERNET_LEN + 20..], [[1; 8], [2; 8], [3; 8]].concat());
+    }
+
+    #[test]
+    fn incomplete_datagrams_time_out() {
+        let data = payload();
+        let mut defrag = Defragmenter::new(DefragConfig { timeout: 30.0, max_datagrams: 4096 });
+        feed(&mut defrag, ipv4_fragment(100.0, 1, 0, true, &data[..8]));
+        assert_eq!(defrag.stats().pending, 1);
+
+        // Another datagram's fragment past the timeout triggers the sweep
+        feed(&mut defrag, ipv4_fragment(131.0, 2, 0, true, &data[..8]));
+        // The rest of datagram 1 now starts a new, headless datagram
+        assert!(feed(&mut defrag, ipv4_fragment(131.5, 1, 8, false, &data[8..])).is_none());
+
+        let stats = defrag.stats();
+        assert_eq!((stats.timed_out, stats.reassembled, stats.pending), (1, 0, 2));
+    }
+
+    #[test]
+    fn oldest_datagram_is_evicted_at_the_limit() {
+        let mut defrag = Defragmenter::new(DefragConfig { timeout: 30.0, max_datagrams: 2 });
+        for (ts, id) in [(1.0, 1), (1.1, 2), (1.2, 3)] {
+            feed(&mut defrag, ipv4_fragment(ts, id, 0, true, &[0; 8]));
+        }
+        let stats = defrag.stats();
+        assert_eq!((stats.evicted, stats.pending), (1, 2));
+    }
+
+    #[test]
+    fn flags_tiny_and_oversized_fragments() {
+        let mut defrag = Defragmenter::default();
+        feed(&mut defrag, ipv4_fragment(1.0, 4, 0, true, &[0; 4]));
+        assert!(feed(&mut defrag, ipv4_fragment(1.0, 5, 65520, false, &[0; 16])).is_none());
+        let stats = defrag.stats();
+        assert_eq!((stats.tiny, stats.oversized, stats.pending), (1, 1, 1));
+    }
+
+    #[test]
+    fn reassembles_ipv6_fragment_headers() {
+        let data = payload();
+        let mut defrag = Defragmenter::default();
+        assert!(feed(&mut defrag, ipv6_fragment(1.0, 0xdeadbeef, 8, false, &data[8..])).is_none());
+        let (rebuilt, reassembled) = feed(&mut defrag, ipv6_fragment(1.1, 0xdeadbeef, 0, true, &data[..8])).unwrap();
+        assert_eq!((reassembled.fragments, reassembled.length), (2, 24));
+
+        let ip = &rebuilt.data[ETHERNET_LEN..];
+        // The Fragment header is gone and the fixed header describes the whole payload
+        assert_eq!(&ip[4..6], &24u16.to_be_bytes());
+        assert_eq!(ip[6], 17);
+        assert_eq!(&ip[40..], &data[..]);
+    }
+}
@@ -0,0 +1,727 @@
+use crate::listening::ParsedPacket;
+use crate::packet::{ApplicationLayer, DecodedPacket, MacAddr, TransportLayer};
+use crate::sink::PacketSink;
+use crate::source::InterfaceInfo;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+const DHCPV6_CLIENT_PORT: u16 = 546;
+const DHCPV6_SERVER_PORT: u16 = 547;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+// Option 82, added by relays to say where the client is attached
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RelayAgentInfo {
+    pub circuit_id: Option<String>,
+    pub remote_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DhcpMessage {
+    // 1 BOOTREQUEST, 2 BOOTREPLY
+    pub op: u8,
+    pub xid: u32,
+    pub client_mac: Option<MacAddr>,
+    pub client_ip: Ipv4Addr,
+    // Address being offered or assigned (yiaddr)
+    pub your_ip: Ipv4Addr,
+    pub server_ip: Ipv4Addr,
+    pub relay_ip: Ipv4Addr,
+    pub message_type: Option<u8>,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub routers: Vec<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    pub hostname: Option<String>,
+    pub requested_ip: Option<Ipv4Addr>,
+    pub lease_time: Option<u32>,
+    pub server_id: Option<Ipv4Addr>,
+    pub parameter_requests: Vec<u8>,
+    // Hex-encoded option 61
+    pub client_id: Option<String>,
+    pub relay_agent: Option<RelayAgentInfo>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
+
+fn ipv4_at(data: &[u8], at: usize) -> Option<Ipv4Addr> {
+    data.get(at..at + 4)
+        .and_then(|b| <[u8; 4]>::try_from(b).ok())
+        .map(Ipv4Addr::from)
+}
+
+fn ipv6_at(data: &[u8], at: usize) -> Option<Ipv6Addr> {
+    data.get(at..at + 16)
+        .and_then(|b| <[u8; 16]>::try_from(b).ok())
+        .map(Ipv6Addr::from)
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+impl DhcpMessage {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 240 || data[236..240] != MAGIC_COOKIE {
+            return None;
+        }
+        let hlen = data[2] as usize;
+        let mut message = DhcpMessage {
+            op: data[0],
+            xid: u32_at(data, 4)?,
+            client_mac: if hlen == 6 { data[28..34].try_into().ok().map(MacAddr) } else { None },
+            client_ip: ipv4_at(data, 12)?,
+            your_ip: ipv4_at(data, 16)?,
+            server_ip: ipv4_at(data, 20)?,
+            relay_ip: ipv4_at(data, 24)?,
+            message_type: None,
+            subnet_mask: None,
+            routers: Vec::new(),
+            dns_servers: Vec::new(),
+            hostname: None,
+            requested_ip: None,
+            lease_time: None,
+            server_id: None,
+            parameter_requests: Vec::new(),
+            client_id: None,
+            relay_agent: None,
+        };
+
+        let addresses = |value: &[u8]| -> Vec<Ipv4Addr> {
+            value.chunks_exact(4).filter_map(|b| ipv4_at(b, 0)).collect()
+        };
+        let mut options = &data[240..];
+        while let Some(&code) = options.first() {
+            match code {
+                0 => {
+                    options = &options[1..];
+                    continue;
+                }
+                255 => break,
+                _ => {}
+            }
+            let len = *options.get(1)? as usize;
+            let value = options.get(2..2 + len)?;
+            match code {
+                1 => message.subnet_mask = ipv4_at(value, 0),
+                3 => message.routers = addresses(value),
+                6 => message.dns_servers = addresses(value),
+                12 => message.hostname = Some(String::from_utf8_lossy(value).to_string()),
+                50 => message.requested_ip = ipv4_at(value, 0),
+                51 => message.lease_time = u32_at(value, 0),
+                53 => message.message_type = value.first().copied(),
+                54 => message.server_id = ipv4_at(value, 0),
+                55 => message.parameter_requests = value.to_vec(),
+                61 => message.client_id = Some(hex(value)),
+                82 => message.relay_agent = Some(parse_relay_agent(value)),
+                _ => {}
+            }
+            options = &options[2 + len..];
+        }
+        Some(message)
+    }
+
+    pub fn is_reply(&self) -> bool {
+        self.op == 2
+    }
+
+    pub fn summary(&self) -> String {
+        let kind = self.message_type.map_or("BOOTP".to_string(), message_type_name);
+        let mac = self.client_mac.map(|m| m.to_string()).unwrap_or_default();
+        match self.message_type {
+            Some(2) | Some(5) => format!("{} {} to {} xid 0x{:08x}", kind, self.your_ip, mac, self.xid),
+            _ => format!("{} from {} xid 0x{:08x}", kind, mac, self.xid),
+        }
+    }
+}
+
+fn parse_relay_agent(mut data: &[u8]) -> RelayAgentInfo {
+    let mut info = RelayAgentInfo::default();
+    while data.len() >= 2 {
+        let (code, len) = (data[0], data[1] as usize);
+        let Some(value) = data.get(2..2 + len) else {
+            break;
+        };
+        match code {
+            1 => info.circuit_id = Some(hex(value)),
+            2 => info.remote_id = Some(hex(value)),
+            _ => {}
+        }
+        data = &data[2 + len..];
+    }
+    info
+}
+
+pub fn message_type_name(message_type: u8) -> String {
+    match message_type {
+        1 => "DISCOVER".to_string(),
+        2 => "OFFER".to_string(),
+        3 => "REQUEST".to_string(),
+        4 => "DECLINE".to_string(),
+        5 => "ACK".to_string(),
+        6 => "NAK".to_string(),
+        7 => "RELEASE".to_string(),
+        8 => "INFORM".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Dhcpv6Address {
+    pub address: Ipv6Addr,
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Dhcpv6Message {
+    pub message_type: u8,
+    pub transaction_id: u32,
+    // Hex-encoded DUIDs
+    pub client_duid: Option<String>,
+    pub server_duid: Option<String>,
+    // Link-layer address embedded in a DUID-LL or DUID-LLT
+    pub client_mac: Option<MacAddr>,
+    pub addresses: Vec<Dhcpv6Address>,
+    pub dns_servers: Vec<Ipv6Addr>,
+    pub fqdn: Option<String>,
+    pub status_code: Option<u16>,
+    // Link address of the relay, when the message arrived relayed
+    pub relay_link: Option<Ipv6Addr>,
+}
+
+// Iterate the (code, value) pairs of DHCPv6 options
+fn v6_options(mut data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut options = Vec::new();
+    while data.len() >= 4 {
+        let code = u16::from_be_bytes([data[0], data[1]]);
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let Some(value) = data.get(4..4 + len) else {
+            break;
+        };
+        options.push((code, value));
+        data = &data[4 + len..];
+    }
+    options
+}
+
+// Hardware type 1 (Ethernet) link-layer address from DUID-LLT (type 1) or DUID-LL (type 3)
+fn duid_mac(duid: &[u8]) -> Option<MacAddr> {
+    let address = match (duid.get(..4)?, duid.len()) {
+        ([0, 1, 0, 1], 14) => &duid[8..14],
+        ([0, 3, 0, 1], 10) => &duid[4..10],
+        _ => return None,
+    };
+    address.try_into().ok().map(MacAddr)
+}
+
+// Domain name in DNS wire format, as used by the Client FQDN option
+fn wire_name(mut data: &[u8]) -> String {
+    let mut labels = Vec::new();
+    while let Some((&len, rest)) = data.split_first() {
+        if len == 0 || len as usize > rest.len() {
+            break;
+        }
+        labels.push(String::from_utf8_lossy(&rest[..len as usize]).to_string());
+        data = &rest[len as usize..];
+    }
+    labels.join(".")
+}
+
+impl Dhcpv6Message {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let message_type = *data.first()?;
+        // RELAY-FORW and RELAY-REPL wrap the client's message in option 9
+        if message_type == 12 || message_type == 13 {
+            let link = ipv6_at(data, 2)?;
+            let (_, inner) = v6_options(data.get(34..)?).into_iter().find(|(code, _)| *code == 9)?;
+            let mut message = Dhcpv6Message::parse(inner)?;
+            message.relay_link.get_or_insert(link);
+            return Some(message);
+        }
+
+        let mut message = Dhcpv6Message {
+            message_type,
+            transaction_id: u32::from_be_bytes([0, *data.get(1)?, *data.get(2)?, *data.get(3)?]),
+            client_duid: None,
+            server_duid: None,
+            client_mac: None,
+            addresses: Vec::new(),
+            dns_servers: Vec::new(),
+            fqdn: None,
+            status_code: None,
+            relay_link: None,
+        };
+        for (code, value) in v6_options(&data[4..]) {
+            match code {
+                1 => {
+                    message.client_duid = Some(hex(value));
+                    message.client_mac = duid_mac(value);
+                }
+                2 => message.server_duid = Some(hex(value)),
+                // IA_NA: IAID, T1, T2, then IA Address options
+                3 if value.len() >= 12 => {
+                    for (code, address) in v6_options(&value[12..]) {
+                        if let (5, Some(ip)) = (code, ipv6_at(address, 0)) {
+                            message.addresses.push(Dhcpv6Address {
+                                address: ip,
+                                preferred_lifetime: u32_at(address, 16).unwrap_or_default(),
+                                valid_lifetime: u32_at(address, 20).unwrap_or_default(),
+                            });
+                        }
+                    }
+                }
+                13 if value.len() >= 2 => message.status_code = Some(u16::from_be_bytes([value[0], value[1]])),
+                23 => message.dns_servers = value.chunks_exact(16).filter_map(|b| ipv6_at(b, 0)).collect(),
+                39 if !value.is_empty() => message.fqdn = Some(wire_name(&value[1..])),
+                _ => {}
+            }
+        }
+        Some(message)
+    }
+
+    pub fn is_reply(&self) -> bool {
+        matches!(self.message_type, 2 | 7 | 10)
+    }
+
+    pub fn summary(&self) -> String {
+        let addresses: Vec<String> = self.addresses.iter().map(|a| a.address.to_string()).collect();
+        format!(
+            "{} xid 0x{:06x} {}",
+            v6_message_type_name(self.message_type),
+            self.transaction_id,
+            addresses.join(",")
+        )
+        .trim_end()
+        .to_string()
+    }
+}
+
+pub fn v6_message_type_name(message_type: u8) -> String {
+    match message_type {
+        1 => "SOLICIT".to_string(),
+        2 => "ADVERTISE".to_string(),
+        3 => "REQUEST".to_string(),
+        4 => "CONFIRM".to_string(),
+        5 => "RENEW".to_string(),
+        6 => "REBIND".to_string(),
+        7 => "REPLY".to_string(),
+        8 => "RELEASE".to_string(),
+        9 => "DECLINE".to_string(),
+        10 => "RECONFIGURE".to_string(),
+        11 => "INFORMATION-REQUEST".to_string(),
+        12 => "RELAY-FORW".to_string(),
+        13 => "RELAY-REPL".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+// Decode the packet's UDP payload as DHCP or DHCPv6 if it is on their ports
+pub fn decode(packet: &DecodedPacket, data: &[u8]) -> Option<ApplicationLayer> {
+    let Some(TransportLayer::Udp(udp)) = &packet.transport else {
+        return None;
+    };
+    let ports = [udp.src_port, udp.dst_port];
+    let payload = packet.payload(data);
+    if ports.contains(&DHCP_SERVER_PORT) || ports.contains(&DHCP_CLIENT_PORT) {
+        DhcpMessage::parse(payload).map(|message| ApplicationLayer::Dhcp(Box::new(message)))
+    } else if ports.contains(&DHCPV6_SERVER_PORT) || ports.contains(&DHCPV6_CLIENT_PORT) {
+        Dhcpv6Message::parse(payload).map(|message| ApplicationLayer::Dhcpv6(Box::new(message)))
+    } else {
+        None
+    }
+}
+
+// What the capture saw being handed to one client
+#[derive(Debug, Clone, Serialize)]
+pub struct DhcpLease {
+    pub client_mac: MacAddr,
+    pub ip: IpAddr,
+    pub hostname: Option<String>,
+    pub server: Option<IpAddr>,
+    // Seconds; the valid lifetime for DHCPv6
+    pub lease_time: Option<u32>,
+    // Capture time of the ACK or REPLY
+    pub assigned_at: f64,
+}
+
+// A server seen answering clients; identified by the address it answers from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DhcpServer {
+    pub ip: IpAddr,
+    pub mac: Option<MacAddr>,
+}
+
+impl fmt::Display for DhcpServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mac {
+            Some(mac) => write!(f, "{} ({})", self.ip, mac),
+            None => write!(f, "{}", self.ip),
+        }
+    }
+}
+
+// Where servers are compared: one interface and VLAN, with DHCPv4 and DHCPv6
+// kept apart since a dual-stack router answers both
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DhcpSegment {
+    pub name: String,
+    pub ipv6: bool,
+}
+
+impl fmt::Display for DhcpSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name, if self.ipv6 { "DHCPv6" } else { "DHCPv4" })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DhcpStats {
+    // DHCP and DHCPv6 packets; per-type counts are in `CaptureStats::message_types`
+    pub packets: u64,
+    pub leases: HashMap<MacAddr, DhcpLease>,
+    // Servers answering on each segment
+    pub servers: HashMap<DhcpSegment, Vec<DhcpServer>>,
+}
+
+impl DhcpStats {
+    pub fn is_empty(&self) -> bool {
+        self.packets == 0
+    }
+
+    // Segments where more than one server answered, which usually means a rogue one
+    pub fn rogue_segments(&self) -> Vec<(&DhcpSegment, &Vec<DhcpServer>)> {
+        let mut rogue: Vec<_> = self.servers.iter().filter(|(_, servers)| servers.len() > 1).collect();
+        rogue.sort();
+        rogue
+    }
+}
+
+// Builds the lease table and watches for more than one server per segment
+pub struct DhcpSink {
+    interface_names: Vec<String>,
+    // Hostnames clients asked for, until their lease is acknowledged
+    hostnames: HashMap<MacAddr, String>,
+    stats: DhcpStats,
+}
+
+impl DhcpSink {
+    pub fn new(interfaces: &[InterfaceInfo]) -> Self {
+        DhcpSink {
+            interface_names: interfaces.iter().map(|info| info.name.clone()).collect(),
+            hostnames: HashMap::new(),
+            stats: DhcpStats::default(),
+        }
+    }
+
+    pub fn into_stats(self) -> DhcpStats {
+        self.stats
+    }
+
+    fn segment(&self, packet: &DecodedPacket) -> String {
+        let name = self.interface_names.get(packet.interface as usize).map_or("?", |n| n.as_str());
+        match packet.vlan_key() {
+            Some(vlan) => format!("{} vlan {}", name, vlan),
+            None => name.to_string(),
+        }
+    }
+
+    // Rogue servers are reported in the summary, see `DhcpStats::rogue_segments`
+    fn server_seen(&mut self, packet: &DecodedPacket, ip: IpAddr) {
+        let segment = DhcpSegment {
+            name: self.segment(packet),
+            ipv6: ip.is_ipv6(),
+        };
+        let server = DhcpServer {
+            ip,
+            mac: packet.link.as_ref().map(|link| link.src_mac),
+        };
+        let servers = self.stats.servers.entry(segment).or_default();
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+
+    fn on_dhcp(&mut self, packet: &DecodedPacket, message: &DhcpMessage) {
+        self.stats.packets += 1;
+        let Some(mac) = message.client_mac else {
+            return;
+        };
+
+        if !message.is_reply() {
+            if let Some(hostname) = &message.hostname {
+                self.hostnames.insert(mac, hostname.clone());
+            }
+            return;
+        }
+        let server = message.server_id.map(IpAddr::V4).or(packet.src_addr());
+        if let Some(server) = server {
+            self.server_seen(packet, server);
+        }
+        match message.message_type {
+            Some(5) if !message.your_ip.is_unspecified() => {
+                let lease = DhcpLease {
+                    client_mac: mac,
+                    ip: IpAddr::V4(message.your_ip),
+                    hostname: message.hostname.clone().or_else(|| self.hostnames.get(&mac).cloned()),
+                    server,
+                    lease_time: message.lease_time,
+                    assigned_at: packet.timestamp(),
+                };
+                self.stats.leases.insert(mac, lease);
+            }
+            Some(6) => {
+                self.stats.leases.remove(&mac);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_dhcpv6(&mut self, packet: &DecodedPacket, message: &Dhcpv6Message) {
+        self.stats.packets += 1;
+        // Without a link-layer DUID, a direct reply goes to the client's MAC
+        let mac = message.client_mac.or_else(|| {
+            let link = packet.link.as_ref()?;
+            Some(if message.is_reply() { link.dst_mac } else { link.src_mac })
+        });
+        let Some(mac) = mac else {
+            return;
+        };
+
+        if !message.is_reply() {
+            if let Some(fqdn) = &message.fqdn {
+                self.hostnames.insert(mac, fqdn.clone());
+            }
+            return;
+        }
+        let server = packet.src_addr();
+        if let Some(server) = server {
+            self.server_seen(packet, server);
+        }
+        if message.message_type == 7 {
+            if let Some(address) = message.addresses.first() {
+                let lease = DhcpLease {
+                    client_mac: mac,
+                    ip: IpAddr::V6(address.address),
+                    hostname: message.fqdn.clone().or_else(|| self.hostnames.get(&mac).cloned()),
+                    server,
+                    lease_time: Some(address.valid_lifetime),
+                    assigned_at: packet.timestamp(),
+                };
+                self.stats.leases.insert(mac, lease);
+            }
+        }
+    }
+}
+
+impl PacketSink for DhcpSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let Ok(decoded) = &packet.decoded else {
+            return Ok(());
+        };
+        match &decoded.application {
+            Some(ApplicationLayer::Dhcp(message)) => self.on_dhcp(decoded, message),
+            Some(ApplicationLayer::Dhcpv6(message)) => self.on_dhcpv6(decoded, message),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+pub fn write_dhcp_details(out: &mut dyn Write, message: &DhcpMessage) -> io::Result<()> {
+    let kind = message.message_type.map_or("BOOTP".to_string(), message_type_name);
+    writeln!(out, "  DHCP: {} xid=0x{:08x}", kind, message.xid)?;
+    if let Some(mac) = message.client_mac {
+        writeln!(out, "    Client MAC: {}", mac)?;
+    }
+    for (label, ip) in [
+        ("Client IP", message.client_ip),
+        ("Your IP", message.your_ip),
+        ("Next server", message.server_ip),
+        ("Relay", message.relay_ip),
+    ] {
+        if !ip.is_unspecified() {
+            writeln!(out, "    {}: {}", label, ip)?;
+        }
+    }
+    if let Some(ip) = message.requested_ip {
+        writeln!(out, "    Requested IP: {}", ip)?;
+    }
+    if let Some(server) = message.server_id {
+        writeln!(out, "    Server identifier: {}", server)?;
+    }
+    if let Some(lease) = message.lease_time {
+        writeln!(out, "    Lease time: {}s", lease)?;
+    }
+    if let Some(mask) = message.subnet_mask {
+        writeln!(out, "    Subnet mask: {}", mask)?;
+    }
+    let join = |addresses: &[Ipv4Addr]| addresses.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ");
+    if !message.routers.is_empty() {
+        writeln!(out, "    Routers: {}", join(&message.routers))?;
+    }
+    if !message.dns_servers.is_empty() {
+        writeln!(out, "    DNS servers: {}", join(&message.dns_servers))?;
+    }
+    if let Some(hostname) = &message.hostname {
+        writeln!(out, "    Hostname: {}", hostname)?;
+    }
+    if let Some(client_id) = &message.client_id {
+        writeln!(out, "    Client identifier: {}", client_id)?;
+    }
+    if !message.parameter_requests.is_empty() {
+        let requests: Vec<String> = message.parameter_requests.iter().map(|code| code.to_string()).collect();
+        writeln!(out, "    Parameter requests: {}", requests.join(","))?;
+    }
+    if let Some(relay) = &message.relay_agent {
+        writeln!(
+            out,
+            "    Relay agent: circuit-id={} remote-id={}",
+            relay.circuit_id.as_deref().unwrap_or("-"),
+            relay.remote_id.as_deref().unwrap_or("-")
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_dhcpv6_details(out: &mut dyn Write, message: &Dhcpv6Message) -> io::Result<()> {
+    writeln!(
+        out,
+        "  DHCPv6: {} xid=0x{:06x}",
+        v6_message_type_name(message.message_type),
+        message.transaction_id
+    )?;
+    if let Some(duid) = &message.client_duid {
+        writeln!(out, "    Client DUID: {}", duid)?;
+    }
+    if let Some(duid) = &message.server_duid {
+        writeln!(out, "    Server DUID: {}", duid)?;
+    }
+    for address in &message.addresses {
+        writeln!(
+            out,
+            "    Address: {} preferred={}s valid={}s",
+            address.address, address.preferred_lifetime, address.valid_lifetime
+        )?;
+    }
+    if !message.dns_servers.is_empty() {
+        let servers: Vec<String> = message.dns_servers.iter().map(|s| s.to_string()).collect();
+        writeln!(out, "    DNS servers: {}", servers.join(", "))?;
+    }
+    if let Some(fqdn) = &message.fqdn {
+        writeln!(out, "    FQDN: {}", fqdn)?;
+    }
+    if let Some(status) = message.status_code {
+        writeln!(out, "    Status: {}", status)?;
+    }
+    if let Some(link) = message.relay_link {
+        writeln!(out, "    Relayed via: {}", link)?;
+    }
+    Ok(())
+}
+
+pub fn write_dhcp_summary(out: &mut dyn Write, stats: &DhcpStats) -> io::Result<()> {
+    writeln!(out, "\n=== DHCP Statistics ===")?;
+    writeln!(out, "Packets: {}, Leases: {}", stats.packets, stats.leases.len())?;
+    if !stats.leases.is_empty() {
+        writeln!(out, "Leases:")?;
+        let mut leases: Vec<&DhcpLease> = stats.leases.values().collect();
+        leases.sort_by_key(|lease| lease.client_mac);
+        for lease in leases {
+            writeln!(
+                out,
+                "  {} {:<39} {:<24} server {} lease {}",
+                lease.client_mac,
+                lease.ip,
+                lease.hostname.as_deref().unwrap_or("-"),
+                lease.server.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+                lease.lease_time.map(|t| format!("{}s", t)).unwrap_or_else(|| "-".to_string())
+            )?;
+        }
+    }
+    for (segment, servers) in stats.rogue_segments() {
+        let list: Vec<String> = servers.iter().map(|s| s.to_string()).collect();
+        writeln!(out, "WARNING: {} DHCP servers on {}: {}", servers.len(), segment, list.join(", "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::LinkLayer;
+    use pcap::Linktype;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn reply_from(mac: u8) -> DecodedPacket {
+        DecodedPacket {
+            number: 1,
+            interface: 0,
+            ts_sec: 0,
+            ts_usec: 0,
+            caplen: 0,
+            len: 0,
+            link: Some(LinkLayer {
+                src_mac: MacAddr([0x02, 0, 0, 0, 0, mac]),
+                dst_mac: MacAddr([0xff; 6]),
+                ether_type: 0x0800,
+                vlan_tags: Vec::new(),
+            }),
+            network: None,
+            transport: None,
+            application: None,
+            payload: None,
+            reassembled: None,
+            encapsulation: Vec::new(),
+            wlan: None,
+        }
+    }
+
+    fn sink() -> DhcpSink {
+        DhcpSink::new(&[InterfaceInfo {
+            name: "eth0".to_string(),
+            description: None,
+            linktype: Linktype::ETHERNET,
+            snaplen: 0,
+        }])
+    }
+
+    #[test]
+    fn dual_stack_router_is_not_a_rogue_server() {
+        let mut sink = sink();
+        let router = reply_from(1);
+        sink.server_seen(&router, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        sink.server_seen(&router, IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)));
+        sink.server_seen(&router, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        let stats = sink.into_stats();
+        assert_eq!(stats.servers.len(), 2);
+        assert!(stats.rogue_segments().is_empty());
+    }
+
+    #[test]
+    fn second_server_on_a_segment_is_reported() {
+        let mut sink = sink();
+        sink.server_seen(&reply_from(1), IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        sink.server_seen(&reply_from(2), IpAddr::V4(Ipv4Addr::new(192, 168, 1, 99)));
+        sink.server_seen(&reply_from(1), IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)));
+        let stats = sink.into_stats();
+        let rogue = stats.rogue_segments();
+        assert_eq!(rogue.len(), 1);
+        assert_eq!(rogue[0].0.to_string(), "eth0 (DHCPv4)");
+        assert_eq!(rogue[0].1.len(), 2);
+
+        let mut out = Vec::new();
+        write_dhcp_summary(&mut out, &stats).unwrap();
+        let summary = String::from_utf8(out).unwrap();
+        assert!(summary.contains(
+            "WARNING: 2 DHCP servers on eth0 (DHCPv4): 192.168.1.1 (02:00:00:00:00:01), 192.168.1.99 (02:00:00:00:00:02)"
+        ));
+    }
+}
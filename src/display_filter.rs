@@ -0,0 +1,1027 @@
+use crate::dns::RecordData;
+use crate::encap::Encapsulation;
+use crate::http::HttpStartLine;
+use crate::packet::{ApplicationLayer, DecodedPacket, MacAddr, NetworkLayer, TransportLayer};
+use regex::{Regex, RegexBuilder};
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+
+// A display filter that did not parse; `column` counts characters from 1
+#[derive(Debug, Clone)]
+pub struct FilterError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl FilterError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        FilterError {
+            column,
+            message: message.into(),
+        }
+    }
+
+    // The expression with a caret under the offending column
+    pub fn pointer(&self, expression: &str) -> String {
+        format!("{}\n{}^", expression, " ".repeat(self.column.saturating_sub(1)))
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl Error for FilterError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    // Protocol names: only test for presence
+    Protocol,
+    Bool,
+    Int,
+    Ip,
+    Mac,
+    Str,
+}
+
+impl FieldType {
+    fn describe(&self) -> &'static str {
+        match self {
+            FieldType::Protocol => "a protocol",
+            FieldType::Bool => "a boolean",
+            FieldType::Int => "a number",
+            FieldType::Ip => "an IP address",
+            FieldType::Mac => "a MAC address",
+            FieldType::Str => "a string",
+        }
+    }
+}
+
+// Field names follow Wireshark's where there is an equivalent
+const FIELDS: &[(&str, FieldType)] = &[
+    ("frame", FieldType::Protocol),
+    ("frame.number", FieldType::Int),
+    ("frame.len", FieldType::Int),
+    ("frame.cap_len", FieldType::Int),
+    ("frame.interface_id", FieldType::Int),
+    ("eth", FieldType::Protocol),
+    ("eth.src", FieldType::Mac),
+    ("eth.dst", FieldType::Mac),
+    ("eth.addr", FieldType::Mac),
+    ("eth.type", FieldType::Int),
+    ("vlan", FieldType::Protocol),
+    ("vlan.id", FieldType::Int),
+    ("vlan.priority", FieldType::Int),
+    ("arp", FieldType::Protocol),
+    ("arp.opcode", FieldType::Int),
+    ("arp.src.hw_mac", FieldType::Mac),
+    ("arp.dst.hw_mac", FieldType::Mac),
+    ("arp.src.proto_ipv4", FieldType::Ip),
+    ("arp.dst.proto_ipv4", FieldType::Ip),
+    ("ip", FieldType::Protocol),
+    ("ip.src", FieldType::Ip),
+    ("ip.dst", FieldType::Ip),
+    ("ip.addr", FieldType::Ip),
+    ("ip.proto", FieldType::Int),
+    ("ip.ttl", FieldType::Int),
+    ("ip.id", FieldType::Int),
+    ("ip.flags.mf", FieldType::Bool),
+    ("ip.flags.df", FieldType::Bool),
+    ("ip.frag_offset", FieldType::Int),
+    ("ipv6", FieldType::Protocol),
+    ("ipv6.src", FieldType::Ip),
+    ("ipv6.dst", FieldType::Ip),
+    ("ipv6.addr", FieldType::Ip),
+    ("ipv6.nxt", FieldType::Int),
+    ("ipv6.hlim", FieldType::Int),
+    ("tcp", FieldType::Protocol),
+    ("tcp.srcport", FieldType::Int),
+    ("tcp.dstport", FieldType::Int),
+    ("tcp.port", FieldType::Int),
+    ("tcp.seq", FieldType::Int),
+    ("tcp.ack", FieldType::Int),
+    ("tcp.window_size", FieldType::Int),
+    ("tcp.len", FieldType::Int),
+    ("tcp.flags.fin", FieldType::Bool),
+    ("tcp.flags.syn", FieldType::Bool),
+    ("tcp.flags.reset", FieldType::Bool),
+    ("tcp.flags.push", FieldType::Bool),
+    ("tcp.flags.ack", FieldType::Bool),
+    ("tcp.flags.urg", FieldType::Bool),
+    ("tcp.flags.ece", FieldType::Bool),
+    ("tcp.flags.cwr", FieldType::Bool),
+    ("udp", FieldType::Protocol),
+    ("udp.srcport", FieldType::Int),
+    ("udp.dstport", FieldType::Int),
+    ("udp.port", FieldType::Int),
+    ("udp.length", FieldType::Int),
+    ("icmp", FieldType::Protocol),
+    ("icmp.type", FieldType::Int),
+    ("icmp.code", FieldType::Int),
+    ("icmpv6", FieldType::Protocol),
+    ("icmpv6.type", FieldType::Int),
+    ("icmpv6.code", FieldType::Int),
+    ("dns", FieldType::Protocol),
+    ("dns.id", FieldType::Int),
+    ("dns.flags.response", FieldType::Bool),
+    ("dns.flags.rcode", FieldType::Int),
+    ("dns.qry.name", FieldType::Str),
+    ("dns.qry.type", FieldType::Int),
+    ("dns.resp.name", FieldType::Str),
+    ("dns.a", FieldType::Ip),
+    ("dns.aaaa", FieldType::Ip),
+    ("http", FieldType::Protocol),
+    ("http.request", FieldType::Bool),
+    ("http.response", FieldType::Bool),
+    ("http.request.method", FieldType::Str),
+    ("http.request.uri", FieldType::Str),
+    ("http.response.code", FieldType::Int),
+    ("http.host", FieldType::Str),
+    ("http.content_type", FieldType::Str),
+    ("tls", FieldType::Protocol),
+    ("tls.handshake.type", FieldType::Int),
+    ("tls.handshake.extensions_server_name", FieldType::Str),
+    ("tls.handshake.extensions_alpn_str", FieldType::Str),
+    ("tls.handshake.ja3", FieldType::Str),
+    ("tls.handshake.ja4", FieldType::Str),
+    ("quic", FieldType::Protocol),
+    ("quic.version", FieldType::Int),
+    ("quic.long.packet_type", FieldType::Str),
+    ("quic.dcid", FieldType::Str),
+    ("quic.scid", FieldType::Str),
+    ("dhcp", FieldType::Protocol),
+    ("dhcp.option.dhcp", FieldType::Int),
+    ("dhcp.hw.mac_addr", FieldType::Mac),
+    ("dhcp.ip.your", FieldType::Ip),
+    ("dhcp.option.hostname", FieldType::Str),
+    ("dhcpv6", FieldType::Protocol),
+    ("dhcpv6.msgtype", FieldType::Int),
+    ("wlan", FieldType::Protocol),
+    ("wlan.fc.type", FieldType::Int),
+    ("wlan.fc.subtype", FieldType::Int),
+    ("wlan.fc.protected", FieldType::Bool),
+    ("wlan.ra", FieldType::Mac),
+    ("wlan.ta", FieldType::Mac),
+    ("wlan.bssid", FieldType::Mac),
+    ("wlan.ssid", FieldType::Str),
+    ("wlan_radio.channel", FieldType::Int),
+    ("wlan_radio.signal_dbm", FieldType::Int),
+    ("mpls", FieldType::Protocol),
+    ("mpls.label", FieldType::Int),
+    ("pppoe", FieldType::Protocol),
+    ("pppoe.session_id", FieldType::Int),
+    ("gre", FieldType::Protocol),
+    ("gre.key", FieldType::Int),
+    ("vxlan", FieldType::Protocol),
+    ("vxlan.vni", FieldType::Int),
+    ("geneve", FieldType::Protocol),
+    ("geneve.vni", FieldType::Int),
+];
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Value {
+    Bool(bool),
+    Int(i64),
+    Ip(IpAddr),
+    Mac(MacAddr),
+    Str(String),
+}
+
+// Right-hand side of a comparison, already converted to the field's type
+#[derive(Debug, Clone)]
+enum Literal {
+    Value(Value),
+    // CIDR block, for IP fields
+    Network(IpAddr, u8),
+    // Inclusive range inside a set, e.g. {8000..8080}
+    Range(i64, i64),
+}
+
+impl Literal {
+    fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Literal::Value(literal), value) => literal == value,
+            (Literal::Network(network, prefix), Value::Ip(addr)) => in_network(*addr, *network, *prefix),
+            (Literal::Range(low, high), Value::Int(n)) => (low..=high).contains(&n),
+            _ => false,
+        }
+    }
+}
+
+fn in_network(addr: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(addr) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Matches,
+    In,
+}
+
+impl Op {
+    fn from_word(word: &str) -> Option<Op> {
+        match word {
+            "eq" => Some(Op::Eq),
+            "ne" => Some(Op::Ne),
+            "lt" => Some(Op::Lt),
+            "le" => Some(Op::Le),
+            "gt" => Some(Op::Gt),
+            "ge" => Some(Op::Ge),
+            "contains" => Some(Op::Contains),
+            "matches" => Some(Op::Matches),
+            "in" => Some(Op::In),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // Field names and unquoted values
+    Word(String),
+    Str(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | ':' | '/' | '-')
+}
+
+// Split the expression into tokens, each with the column it starts at
+fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, FilterError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('=', _) => return Err(FilterError::new(column, "'=' is not an operator, use '=='")),
+            ('!', _) => (Token::Not, 1),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('~', _) => (Token::Op(Op::Matches), 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('{', _) => (Token::LBrace, 1),
+            ('}', _) => (Token::RBrace, 1),
+            (',', _) => (Token::Comma, 1),
+            ('"', _) => {
+                let mut value = String::new();
+                let mut end = i + 1;
+                loop {
+                    match chars.get(end) {
+                        None => return Err(FilterError::new(column, "unterminated string")),
+                        Some('"') => break,
+                        Some('\\') if end + 1 < chars.len() => {
+                            value.push(chars[end + 1]);
+                            end += 2;
+                        }
+                        Some(c) => {
+                            value.push(*c);
+                            end += 1;
+                        }
+                    }
+                }
+                (Token::Str(value), end + 1 - i)
+            }
+            (c, _) if is_word_char(c) => {
+                let len = chars[i..].iter().take_while(|c| is_word_char(**c)).count();
+                let word: String = chars[i..i + len].iter().collect();
+                let token = match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    other => Op::from_word(other).map_or(Token::Word(word), Token::Op),
+                };
+                (token, len)
+            }
+            (c, _) => return Err(FilterError::new(column, format!("unexpected character '{}'", c))),
+        };
+        tokens.push((token, column));
+        i += len;
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Present(&'static str),
+    Compare {
+        field: &'static str,
+        op: Op,
+        literals: Vec<Literal>,
+    },
+    Matches {
+        field: &'static str,
+        regex: Regex,
+    },
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    // Column just past the end of the expression, for "unexpected end" errors
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(_, column)| *column)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut left = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, FilterError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, FilterError> {
+        let column = self.column();
+        match self.next() {
+            Some((Token::LParen, _)) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some((Token::RParen, _)) => Ok(expr),
+                    Some((_, at)) => Err(FilterError::new(at, "expected ')'")),
+                    None => Err(FilterError::new(column, "unclosed '('")),
+                }
+            }
+            Some((Token::Word(name), _)) => {
+                let (field, kind) = FIELDS
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .copied()
+                    .ok_or_else(|| FilterError::new(column, format!("unknown field '{}'", name)))?;
+                match self.peek() {
+                    Some(Token::Op(op)) => {
+                        let op = *op;
+                        self.pos += 1;
+                        self.comparison(field, kind, op, column)
+                    }
+                    _ => Ok(Expr::Present(field)),
+                }
+            }
+            Some((_, at)) => Err(FilterError::new(at, "expected a field name, '!' or '('")),
+            None => Err(FilterError::new(column, "unexpected end of filter")),
+        }
+    }
+
+    fn comparison(&mut self, field: &'static str, kind: FieldType, op: Op, column: usize) -> Result<Expr, FilterError> {
+        if kind == FieldType::Protocol {
+            return Err(FilterError::new(column, format!("'{}' is a protocol and cannot be compared", field)));
+        }
+        let value_column = self.column();
+        match op {
+            Op::Contains | Op::Matches if kind != FieldType::Str => Err(FilterError::new(
+                column,
+                format!("'{}' is {}, not a string", field, kind.describe()),
+            )),
+            Op::Lt | Op::Le | Op::Gt | Op::Ge if !matches!(kind, FieldType::Int | FieldType::Ip | FieldType::Str) => {
+                Err(FilterError::new(column, format!("'{}' is {} and has no order", field, kind.describe())))
+            }
+            Op::Matches => {
+                let pattern = self.text()?;
+                // Case-insensitive, as in Wireshark
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| {
+                        // The regex crate's message spans lines; its last one names the problem
+                        let message = e.to_string();
+                        let reason = message.lines().last().unwrap_or_default().trim_start_matches("error: ");
+                        FilterError::new(value_column, format!("invalid regex: {}", reason))
+                    })?;
+                Ok(Expr::Matches { field, regex })
+            }
+            Op::In if self.peek() == Some(&Token::LBrace) => {
+                self.pos += 1;
+                let mut literals = Vec::new();
+                loop {
+                    let item_column = self.column();
+                    match self.next() {
+                        Some((Token::RBrace, _)) if !literals.is_empty() => break,
+                        Some((Token::Comma, _)) => {}
+                        Some((Token::Word(text), _)) => literals.push(literal(kind, &text, false, true, item_column)?),
+                        Some((Token::Str(text), _)) => literals.push(literal(kind, &text, true, true, item_column)?),
+                        Some((_, at)) => return Err(FilterError::new(at, "expected a value or '}'")),
+                        None => return Err(FilterError::new(item_column, "unclosed '{'")),
+                    }
+                }
+                Ok(Expr::Compare { field, op, literals })
+            }
+            Op::In => {
+                let value = self.value(kind, false)?;
+                if !matches!(value, Literal::Network(..)) {
+                    return Err(FilterError::new(value_column, "expected a '{...}' set or a CIDR block after 'in'"));
+                }
+                Ok(Expr::Compare { field, op, literals: vec![value] })
+            }
+            _ => {
+                let value = self.value(kind, false)?;
+                Ok(Expr::Compare { field, op, literals: vec![value] })
+            }
+        }
+    }
+
+    // A string, quoted or not
+    fn text(&mut self) -> Result<String, FilterError> {
+        let column = self.column();
+        match self.next() {
+            Some((Token::Word(text), _)) | Some((Token::Str(text), _)) => Ok(text),
+            _ => Err(FilterError::new(column, "expected a string")),
+        }
+    }
+
+    fn value(&mut self, kind: FieldType, in_set: bool) -> Result<Literal, FilterError> {
+        let column = self.column();
+        match self.next() {
+            Some((Token::Word(text), _)) => literal(kind, &text, false, in_set, column),
+            Some((Token::Str(text), _)) => literal(kind, &text, true, in_set, column),
+            _ => Err(FilterError::new(column, format!("expected {}", kind.describe()))),
+        }
+    }
+}
+
+fn parse_int(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_mac(text: &str) -> Option<MacAddr> {
+    let parts: Vec<&str> = text.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (byte, part) in mac.iter_mut().zip(parts) {
+        if part.len() != 2 {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some(MacAddr(mac))
+}
+
+// Convert the text of a value to the type of the field it is compared with
+fn literal(kind: FieldType, text: &str, quoted: bool, in_set: bool, column: usize) -> Result<Literal, FilterError> {
+    let mismatch = || FilterError::new(column, format!("'{}' is not {}", text, kind.describe()));
+    let value = match kind {
+        FieldType::Str => Value::Str(text.to_string()),
+        _ if quoted => return Err(mismatch()),
+        FieldType::Bool => match text {
+            "1" | "true" => Value::Bool(true),
+            "0" | "false" => Value::Bool(false),
+            _ => return Err(mismatch()),
+        },
+        FieldType::Int => match text.split_once("..") {
+            Some((low, high)) if in_set => {
+                let (low, high) = parse_int(low).zip(parse_int(high)).ok_or_else(mismatch)?;
+                return Ok(Literal::Range(low, high));
+            }
+            _ => Value::Int(parse_int(text).ok_or_else(mismatch)?),
+        },
+        FieldType::Ip => match text.split_once('/') {
+            Some((addr, prefix)) => {
+                let addr: IpAddr = addr.parse().map_err(|_| mismatch())?;
+                let max = if addr.is_ipv4() { 32 } else { 128 };
+                let prefix = prefix.parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(|| {
+                    FilterError::new(column, format!("invalid prefix length in '{}'", text))
+                })?;
+                return Ok(Literal::Network(addr, prefix));
+            }
+            None => Value::Ip(text.parse().map_err(|_| mismatch())?),
+        },
+        FieldType::Mac => Value::Mac(parse_mac(text).ok_or_else(mismatch)?),
+        FieldType::Protocol => return Err(mismatch()),
+    };
+    Ok(Literal::Value(value))
+}
+
+// Every value a field takes in the packet; empty when absent. Fields that name
+// either endpoint (ip.addr, tcp.port, ...) yield both.
+fn field_values(packet: &DecodedPacket, field: &str) -> Vec<Value> {
+    let present = |yes: bool| if yes { vec![Value::Bool(true)] } else { Vec::new() };
+    let int = |n: Option<i64>| n.map(Value::Int).into_iter().collect::<Vec<_>>();
+    let text = |s: Option<&str>| s.map(|s| Value::Str(s.to_string())).into_iter().collect::<Vec<_>>();
+
+    let link = packet.link.as_ref();
+    let (ipv4, ipv6) = match &packet.network {
+        Some(NetworkLayer::Ipv4(ip)) => (Some(ip), None),
+        Some(NetworkLayer::Ipv6(ip)) => (None, Some(ip)),
+        _ => (None, None),
+    };
+    let tcp = packet.tcp();
+    let udp = packet.udp();
+    let icmp = match &packet.transport {
+        Some(TransportLayer::Icmpv4(info)) => Some((info, false)),
+        Some(TransportLayer::Icmpv6(info)) => Some((info, true)),
+        _ => None,
+    };
+    let dns = packet.dns();
+    let http = packet.http();
+    let dhcp = match &packet.application {
+        Some(ApplicationLayer::Dhcp(message)) => Some(message.as_ref()),
+        _ => None,
+    };
+    let dhcpv6 = match &packet.application {
+        Some(ApplicationLayer::Dhcpv6(message)) => Some(message.as_ref()),
+        _ => None,
+    };
+    // ClientHello from TLS over TCP or from a QUIC Initial
+    let hello = packet
+        .tls()
+        .and_then(|tls| tls.client_hello.as_ref())
+        .or_else(|| packet.quic().and_then(|quic| quic.client_hello.as_ref()));
+    let wlan = packet.wlan.as_ref();
+    let encapsulated = |kind: &str| present(packet.encapsulation.iter().any(|e| e.kind() == kind));
+
+    match field {
+        "frame" => present(true),
+        "frame.number" => int(Some(packet.number as i64)),
+        "frame.len" => int(Some(packet.len as i64)),
+        "frame.cap_len" => int(Some(packet.caplen as i64)),
+        "frame.interface_id" => int(Some(packet.interface as i64)),
+        "eth" => present(link.is_some()),
+        "eth.src" => link.map(|l| Value::Mac(l.src_mac)).into_iter().collect(),
+        "eth.dst" => link.map(|l| Value::Mac(l.dst_mac)).into_iter().collect(),
+        "eth.addr" => link.map_or(Vec::new(), |l| vec![Value::Mac(l.src_mac), Value::Mac(l.dst_mac)]),
+        "eth.type" => int(link.map(|l| l.ether_type as i64)),
+        "vlan" => present(link.is_some_and(|l| !l.vlan_tags.is_empty())),
+        "vlan.id" => link.map_or(Vec::new(), |l| l.vlan_tags.iter().map(|t| Value::Int(t.id as i64)).collect()),
+        "vlan.priority" => link.map_or(Vec::new(), |l| l.vlan_tags.iter().map(|t| Value::Int(t.pcp as i64)).collect()),
+        "arp" => present(packet.arp().is_some()),
+        "arp.opcode" => int(packet.arp().map(|a| a.operation as i64)),
+        "arp.src.hw_mac" => packet.arp().and_then(|a| a.sender_mac).map(Value::Mac).into_iter().collect(),
+        "arp.dst.hw_mac" => packet.arp().and_then(|a| a.target_mac).map(Value::Mac).into_iter().collect(),
+        "arp.src.proto_ipv4" => packet.arp().and_then(|a| a.sender_ip).map(Value::Ip).into_iter().collect(),
+        "arp.dst.proto_ipv4" => packet.arp().and_then(|a| a.target_ip).map(Value::Ip).into_iter().collect(),
+        "ip" => present(ipv4.is_some()),
+        "ip.src" => ipv4.map(|ip| Value::Ip(ip.src)).into_iter().collect(),
+        "ip.dst" => ipv4.map(|ip| Value::Ip(ip.dst)).into_iter().collect(),
+        "ip.addr" => ipv4.map_or(Vec::new(), |ip| vec![Value::Ip(ip.src), Value::Ip(ip.dst)]),
+        "ip.proto" => int(ipv4.map(|ip| ip.protocol as i64)),
+        "ip.ttl" => int(ipv4.map(|ip| ip.ttl as i64)),
+        "ip.id" => int(ipv4.and_then(|ip| ip.ip_id).map(|id| id as i64)),
+        "ip.flags.mf" => ipv4.map(|ip| Value::Bool(ip.fragment.is_some_and(|f| f.more_fragments))).into_iter().collect(),
+        "ip.flags.df" => ipv4.map(|ip| Value::Bool(ip.fragment.is_some_and(|f| f.dont_fragment))).into_iter().collect(),
+        "ip.frag_offset" => ipv4.map(|ip| Value::Int(ip.fragment.map_or(0, |f| f.offset as i64))).into_iter().collect(),
+        "ipv6" => present(ipv6.is_some()),
+        "ipv6.src" => ipv6.map(|ip| Value::Ip(ip.src)).into_iter().collect(),
+        "ipv6.dst" => ipv6.map(|ip| Value::Ip(ip.dst)).into_iter().collect(),
+        "ipv6.addr" => ipv6.map_or(Vec::new(), |ip| vec![Value::Ip(ip.src), Value::Ip(ip.dst)]),
+        "ipv6.nxt" => int(ipv6.map(|ip| ip.protocol as i64)),
+        "ipv6.hlim" => int(ipv6.map(|ip| ip.ttl as i64)),
+        "tcp" => present(tcp.is_some()),
+        "tcp.srcport" => int(tcp.map(|t| t.src_port as i64)),
+        "tcp.dstport" => int(tcp.map(|t| t.dst_port as i64)),
+        "tcp.port" => tcp.map_or(Vec::new(), |t| vec![Value::Int(t.src_port as i64), Value::Int(t.dst_port as i64)]),
+        "tcp.seq" => int(tcp.map(|t| t.seq as i64)),
+        "tcp.ack" => int(tcp.map(|t| t.ack as i64)),
+        "tcp.window_size" => int(tcp.map(|t| t.window as i64)),
+        "tcp.len" => int(tcp.map(|_| packet.payload.map_or(0, |p| p.len as i64))),
+        "tcp.flags.fin" => tcp.map(|t| Value::Bool(t.flags.fin)).into_iter().collect(),
+        "tcp.flags.syn" => tcp.map(|t| Value::Bool(t.flags.syn)).into_iter().collect(),
+        "tcp.flags.reset" => tcp.map(|t| Value::Bool(t.flags.rst)).into_iter().collect(),
+        "tcp.flags.push" => tcp.map(|t| Value::Bool(t.flags.psh)).into_iter().collect(),
+        "tcp.flags.ack" => tcp.map(|t| Value::Bool(t.flags.ack)).into_iter().collect(),
+        "tcp.flags.urg" => tcp.map(|t| Value::Bool(t.flags.urg)).into_iter().collect(),
+        "tcp.flags.ece" => tcp.map(|t| Value::Bool(t.flags.ece)).into_iter().collect(),
+        "tcp.flags.cwr" => tcp.map(|t| Value::Bool(t.flags.cwr)).into_iter().collect(),
+        "udp" => present(udp.is_some()),
+        "udp.srcport" => int(udp.map(|u| u.src_port as i64)),
+        "udp.dstport" => int(udp.map(|u| u.dst_port as i64)),
+        "udp.port" => udp.map_or(Vec::new(), |u| vec![Value::Int(u.src_port as i64), Value::Int(u.dst_port as i64)]),
+        "udp.length" => int(udp.map(|u| u.length as i64)),
+        "icmp" => present(icmp.is_some_and(|(_, v6)| !v6)),
+        "icmp.type" => int(icmp.filter(|(_, v6)| !v6).map(|(i, _)| i.icmp_type as i64)),
+        "icmp.code" => int(icmp.filter(|(_, v6)| !v6).map(|(i, _)| i.code as i64)),
+        "icmpv6" => present(icmp.is_some_and(|(_, v6)| v6)),
+        "icmpv6.type" => int(icmp.filter(|(_, v6)| *v6).map(|(i, _)| i.icmp_type as i64)),
+        "icmpv6.code" => int(icmp.filter(|(_, v6)| *v6).map(|(i, _)| i.code as i64)),
+        "dns" => present(dns.is_some()),
+        "dns.id" => int(dns.map(|d| d.id as i64)),
+        "dns.flags.response" => dns.map(|d| Value::Bool(d.flags.response)).into_iter().collect(),
+        "dns.flags.rcode" => int(dns.map(|d| d.flags.rcode as i64)),
+        "dns.qry.name" => dns.map_or(Vec::new(), |d| d.questions.iter().map(|q| Value::Str(q.name.clone())).collect()),
+        "dns.qry.type" => dns.map_or(Vec::new(), |d| d.questions.iter().map(|q| Value::Int(q.qtype as i64)).collect()),
+        "dns.resp.name" => dns.map_or(Vec::new(), |d| d.answers.iter().map(|a| Value::Str(a.name.clone())).collect()),
+        "dns.a" | "dns.aaaa" => dns.map_or(Vec::new(), |d| {
+            d.answers
+                .iter()
+                .filter_map(|a| match (&a.data, field) {
+                    (RecordData::A(addr), "dns.a") => Some(Value::Ip(IpAddr::V4(*addr))),
+                    (RecordData::Aaaa(addr), "dns.aaaa") => Some(Value::Ip(IpAddr::V6(*addr))),
+                    _ => None,
+                })
+                .collect()
+        }),
+        "http" => present(http.is_some()),
+        "http.request" => present(http.is_some_and(|h| matches!(h.start, HttpStartLine::Request { .. }))),
+        "http.response" => present(http.is_some_and(|h| matches!(h.start, HttpStartLine::Response { .. }))),
+        "http.request.method" => match http.map(|h| &h.start) {
+            Some(HttpStartLine::Request { method, .. }) => text(Some(method)),
+            _ => Vec::new(),
+        },
+        "http.request.uri" => match http.map(|h| &h.start) {
+            Some(HttpStartLine::Request { path, .. }) => text(Some(path)),
+            _ => Vec::new(),
+        },
+        "http.response.code" => match http.map(|h| &h.start) {
+            Some(HttpStartLine::Response { status, .. }) => int(Some(*status as i64)),
+            _ => Vec::new(),
+        },
+        "http.host" => text(http.and_then(|h| h.host.as_deref())),
+        "http.content_type" => text(http.and_then(|h| h.content_type.as_deref())),
+        "tls" => present(packet.tls().is_some()),
+        "tls.handshake.type" => packet.tls().map_or(Vec::new(), |tls| {
+            let mut types = Vec::new();
+            if tls.client_hello.is_some() {
+                types.push(Value::Int(1));
+            }
+            if tls.server_hello.is_some() {
+                types.push(Value::Int(2));
+            }
+            if !tls.certificates.is_empty() {
+                types.push(Value::Int(11));
+            }
+            types
+        }),
+        "tls.handshake.extensions_server_name" => text(hello.and_then(|h| h.sni.as_deref())),
+        "tls.handshake.extensions_alpn_str" => {
+            hello.map_or(Vec::new(), |h| h.alpn.iter().map(|a| Value::Str(a.clone())).collect())
+        }
+        "tls.handshake.ja3" => text(hello.map(|h| h.ja3_hash.as_str())),
+        "tls.handshake.ja4" => text(hello.map(|h| h.ja4.as_str())),
+        "quic" => present(packet.quic().is_some()),
+        "quic.version" => int(packet.quic().map(|q| q.version as i64)),
+        "quic.long.packet_type" => text(packet.quic().map(|q| q.packet_type.name())),
+        "quic.dcid" => packet.quic().map(|q| Value::Str(q.dcid.to_string())).into_iter().collect(),
+        "quic.scid" => packet.quic().map(|q| Value::Str(q.scid.to_string())).into_iter().collect(),
+        "dhcp" => present(dhcp.is_some()),
+        "dhcp.option.dhcp" => int(dhcp.and_then(|d| d.message_type).map(|t| t as i64)),
+        "dhcp.hw.mac_addr" => dhcp.and_then(|d| d.client_mac).map(Value::Mac).into_iter().collect(),
+        "dhcp.ip.your" => dhcp.map(|d| Value::Ip(IpAddr::V4(d.your_ip))).into_iter().collect(),
+        "dhcp.option.hostname" => text(dhcp.and_then(|d| d.hostname.as_deref())),
+        "dhcpv6" => present(dhcpv6.is_some()),
+        "dhcpv6.msgtype" => int(dhcpv6.map(|d| d.message_type as i64)),
+        "wlan" => present(wlan.is_some()),
+        "wlan.fc.type" => int(wlan.map(|w| w.frame_type as i64)),
+        "wlan.fc.subtype" => int(wlan.map(|w| w.subtype as i64)),
+        "wlan.fc.protected" => wlan.map(|w| Value::Bool(w.protected)).into_iter().collect(),
+        "wlan.ra" => wlan.and_then(|w| w.receiver).map(Value::Mac).into_iter().collect(),
+        "wlan.ta" => wlan.and_then(|w| w.transmitter).map(Value::Mac).into_iter().collect(),
+        "wlan.bssid" => wlan.and_then(|w| w.bssid).map(Value::Mac).into_iter().collect(),
+        "wlan.ssid" => text(wlan.and_then(|w| w.ssid.as_deref())),
+        "wlan_radio.channel" => int(wlan.and_then(|w| w.channel).map(|c| c as i64)),
+        "wlan_radio.signal_dbm" => int(wlan.and_then(|w| w.rssi).map(|r| r as i64)),
+        "mpls" => encapsulated("MPLS"),
+        "mpls.label" => packet
+            .encapsulation
+            .iter()
+            .flat_map(|e| match e {
+                Encapsulation::Mpls { labels } => labels.iter().map(|l| Value::Int(l.label as i64)).collect(),
+                _ => Vec::new(),
+            })
+            .collect(),
+        "pppoe" => present(packet.encapsulation.iter().any(|e| matches!(e, Encapsulation::Pppoe { .. }))),
+        "pppoe.session_id" => packet
+            .encapsulation
+            .iter()
+            .filter_map(|e| match e {
+                Encapsulation::Pppoe { session_id, .. } => Some(Value::Int(*session_id as i64)),
+                _ => None,
+            })
+            .collect(),
+        "gre" => encapsulated("GRE"),
+        "gre.key" => packet
+            .encapsulation
+            .iter()
+            .filter_map(|e| match e {
+                Encapsulation::Gre { key: Some(key), .. } => Some(Value::Int(*key as i64)),
+                _ => None,
+            })
+            .collect(),
+        "vxlan" => encapsulated("VXLAN"),
+        "vxlan.vni" | "geneve.vni" => packet
+            .encapsulation
+            .iter()
+            .filter_map(|e| match (e, field) {
+                (Encapsulation::Vxlan { vni, .. }, "vxlan.vni") | (Encapsulation::Geneve { vni, .. }, "geneve.vni") => {
+                    Some(Value::Int(*vni as i64))
+                }
+                _ => None,
+            })
+            .collect(),
+        "geneve" => encapsulated("Geneve"),
+        _ => Vec::new(),
+    }
+}
+
+impl Expr {
+    fn eval(&self, packet: &DecodedPacket) -> bool {
+        match self {
+            Expr::And(left, right) => left.eval(packet) && right.eval(packet),
+            Expr::Or(left, right) => left.eval(packet) || right.eval(packet),
+            Expr::Not(inner) => !inner.eval(packet),
+            // A boolean field must also be set, so `tcp.flags.syn` reads naturally
+            Expr::Present(field) => field_values(packet, field).iter().any(|v| *v != Value::Bool(false)),
+            Expr::Matches { field, regex } => field_values(packet, field)
+                .iter()
+                .any(|v| matches!(v, Value::Str(s) if regex.is_match(s))),
+            Expr::Compare { field, op, literals } => {
+                let values = field_values(packet, field);
+                let equal = || values.iter().any(|v| literals.iter().any(|l| l.matches(v)));
+                let ordered = |accept: fn(std::cmp::Ordering) -> bool| {
+                    values.iter().any(|v| match &literals[0] {
+                        Literal::Value(literal) => v.partial_cmp(literal).is_some_and(accept),
+                        _ => false,
+                    })
+                };
+                match op {
+                    Op::Eq | Op::In => equal(),
+                    // True only when no value is equal, so `ip.addr != x` excludes x entirely
+                    Op::Ne => !equal(),
+                    Op::Lt => ordered(|o| o.is_lt()),
+                    Op::Le => ordered(|o| o.is_le()),
+                    Op::Gt => ordered(|o| o.is_gt()),
+                    Op::Ge => ordered(|o| o.is_ge()),
+                    Op::Contains => values.iter().any(|v| match (v, &literals[0]) {
+                        (Value::Str(s), Literal::Value(Value::Str(needle))) => s.contains(needle.as_str()),
+                        _ => false,
+                    }),
+                    Op::Matches => false,
+                }
+            }
+        }
+    }
+}
+
+// A compiled Wireshark-style display filter, e.g.
+// `tcp.flags.syn && !tcp.flags.ack && ip.dst in 10.0.0.0/8`
+#[derive(Debug)]
+pub struct DisplayFilter {
+    expression: String,
+    root: Expr,
+}
+
+impl DisplayFilter {
+    pub fn parse(expression: &str) -> Result<Self, FilterError> {
+        let tokens = tokenize(expression)?;
+        let end = expression.chars().count() + 1;
+        if tokens.is_empty() {
+            return Err(FilterError::new(1, "empty filter"));
+        }
+        let mut parser = Parser { tokens, pos: 0, end };
+        let root = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            let column = parser.column();
+            let message = match parser.peek() {
+                Some(Token::RParen) => "unmatched ')'",
+                _ => "expected '&&', '||' or end of filter",
+            };
+            return Err(FilterError::new(column, message));
+        }
+        Ok(DisplayFilter {
+            expression: expression.to_string(),
+            root,
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    pub fn matches(&self, packet: &DecodedPacket) -> bool {
+        self.root.eval(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpMessage;
+    use crate::packet::{IpLayer, TcpFlags, TcpInfo};
+
+    // A TCP segment over IPv4, optionally carrying an HTTP request head
+    fn tcp_packet(src: &str, dst: &str, dst_port: u16, flags: TcpFlags, http: Option<&str>) -> DecodedPacket {
+        DecodedPacket {
+            number: 1,
+            interface: 0,
+            ts_sec: 0,
+            ts_usec: 0,
+            caplen: 60,
+            len: 60,
+            link: None,
+            network: Some(NetworkLayer::Ipv4(IpLayer {
+                src: src.parse().unwrap(),
+                dst: dst.parse().unwrap(),
+                ttl: 64,
+                ip_id: Some(1),
+                protocol: 6,
+                fragment: None,
+            })),
+            transport: Some(TransportLayer::Tcp(TcpInfo {
+                src_port: 40000,
+                dst_port,
+                flags,
+                seq: 1,
+                ack: 0,
+                window: 65535,
+            })),
+            application: http.map(|head| ApplicationLayer::Http(HttpMessage::parse_head(head.as_bytes()).unwrap().0)),
+            payload: None,
+            reassembled: None,
+            encapsulation: Vec::new(),
+            wlan: None,
+        }
+    }
+
+    fn syn() -> TcpFlags {
+        TcpFlags {
+            syn: true,
+            ..Default::default()
+        }
+    }
+
+    fn syn_ack() -> TcpFlags {
+        TcpFlags {
+            syn: true,
+            ack: true,
+            ..Default::default()
+        }
+    }
+
+    fn matches(expression: &str, packet: &DecodedPacket) -> bool {
+        DisplayFilter::parse(expression).unwrap().matches(packet)
+    }
+
+    fn error(expression: &str) -> FilterError {
+        DisplayFilter::parse(expression).unwrap_err()
+    }
+
+    #[test]
+    fn matches_syns_into_a_network() {
+        let filter = DisplayFilter::parse("tcp.flags.syn && !tcp.flags.ack && ip.dst in 10.0.0.0/8").unwrap();
+        assert!(filter.matches(&tcp_packet("192.168.1.2", "10.1.2.3", 22, syn(), None)));
+        assert!(!filter.matches(&tcp_packet("192.168.1.2", "10.1.2.3", 22, syn_ack(), None)));
+        assert!(!filter.matches(&tcp_packet("192.168.1.2", "11.1.2.3", 22, syn(), None)));
+        assert!(!filter.matches(&tcp_packet("10.1.2.3", "192.168.1.2", 22, syn(), None)));
+    }
+
+    #[test]
+    fn cidr_prefix_edges() {
+        let packet = tcp_packet("192.168.1.2", "10.1.2.3", 22, syn(), None);
+        assert!(matches("ip.src in 0.0.0.0/0", &packet));
+        assert!(matches("ip.dst in 10.1.2.3/32", &packet));
+        assert!(!matches("ip.dst in 10.1.2.4/32", &packet));
+        // An IPv6 block never holds an IPv4 address, even /0
+        assert!(!matches("ip.src in ::/0", &packet));
+        assert!(error("ip.dst in 10.0.0.0/33").message.contains("prefix length"));
+        assert!(DisplayFilter::parse("ipv6.dst in ::/128").is_ok());
+        assert!(error("ipv6.dst in ::/129").message.contains("prefix length"));
+    }
+
+    #[test]
+    fn sets_and_ranges() {
+        let packet = tcp_packet("192.168.1.2", "10.1.2.3", 8080, syn(), None);
+        assert!(matches("tcp.dstport in {8000..8080}", &packet));
+        assert!(matches("tcp.dstport in {22, 8080}", &packet));
+        assert!(matches("tcp.dstport in {22 443 8000..9000}", &packet));
+        assert!(!matches("tcp.dstport in {8081..9000}", &packet));
+        assert!(!matches("tcp.dstport in {22, 443}", &packet));
+        // Ranges only make sense inside a set
+        assert!(DisplayFilter::parse("tcp.dstport == 1..2").is_err());
+        assert_eq!(error("tcp.dstport in 80").column, 16);
+        assert_eq!(error("tcp.dstport in {}").column, 17);
+    }
+
+    #[test]
+    fn not_equal_excludes_every_value_of_a_multi_valued_field() {
+        let packet = tcp_packet("192.168.1.2", "10.1.2.3", 22, syn(), None);
+        assert!(matches("ip.addr == 10.1.2.3", &packet));
+        assert!(!matches("ip.addr != 10.1.2.3", &packet));
+        assert!(!matches("ip.addr != 192.168.1.2", &packet));
+        assert!(matches("ip.addr != 10.9.9.9", &packet));
+        assert!(!matches("tcp.port != 22", &packet));
+        assert!(!matches("tcp.port != 40000", &packet));
+        assert!(matches("tcp.port != 80", &packet));
+        // An absent field has no value equal to anything
+        assert!(matches("udp.port != 53", &packet));
+    }
+
+    #[test]
+    fn strings_contain_and_match() {
+        let packet = tcp_packet(
+            "192.168.1.2",
+            "10.1.2.3",
+            80,
+            syn(),
+            Some("GET /index.html HTTP/1.1\r\nHost: WWW.Example.com\r\n\r\n"),
+        );
+        assert!(matches("http.host contains \"Example\"", &packet));
+        assert!(!matches("http.host contains \"example\"", &packet));
+        assert!(matches("http.host matches \"example\\.com$\"", &packet));
+        assert!(matches("http.request.method == GET && http.request.uri matches \"^/index\"", &packet));
+        assert!(!matches("http.host matches \"^example\"", &packet));
+    }
+
+    #[test]
+    fn invalid_regex_is_reported_at_the_pattern() {
+        let err = error("http.host matches \"a(\"");
+        assert_eq!(err.column, 19);
+        assert!(err.message.starts_with("invalid regex: "), "{}", err.message);
+        assert!(!err.message.contains('\n'));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_column() {
+        assert_eq!(error("tcp.port == 80 && ip.src = 10.0.0.1").column, 26);
+        assert_eq!(error("tcp.port == 80 && bogus.field").column, 19);
+        assert_eq!(error("tcp.port == 80 &&").column, 18);
+        assert_eq!(error("(tcp || udp").column, 1);
+        assert_eq!(error("tcp || udp)").column, 11);
+        assert_eq!(error("tcp.port == http").column, 13);
+        assert_eq!(error("ip.src == 10.0.0.1 tcp").column, 20);
+        assert_eq!(error("tcp == 1").column, 1);
+        assert_eq!(error("   ").column, 1);
+
+        let err = error("ip.src = 10.0.0.1");
+        assert_eq!(err.to_string(), "column 8: '=' is not an operator, use '=='");
+        assert_eq!(err.pointer("ip.src = 10.0.0.1"), "ip.src = 10.0.0.1\n       ^");
+    }
+}
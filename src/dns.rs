@@ -0,0 +1,969 @@
+use crate::listening::ParsedPacket;
+use crate::packet::{ApplicationLayer, DecodedPacket, TransportLayer};
+use crate::reassembly::{CloseReason, Direction, StreamConsumer, StreamInfo};
+use crate::report::sorted_counts;
+use crate::sink::PacketSink;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// Ports carrying plain DNS messages (DNS and mDNS)
+const DNS_PORTS: [u16; 2] = [53, 5353];
+
+// Queries without a response after this many seconds count as unanswered
+const QUERY_TIMEOUT: f64 = 30.0;
+
+// Guard against compression pointer loops
+const MAX_POINTER_JUMPS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsError {
+    Truncated,
+    PointerLoop,
+    BadLabel,
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DnsError::Truncated => write!(f, "DNS message truncated"),
+            DnsError::PointerLoop => write!(f, "DNS name compression loop"),
+            DnsError::BadLabel => write!(f, "invalid DNS label"),
+        }
+    }
+}
+
+impl Error for DnsError {}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DnsFlags {
+    pub response: bool,
+    pub opcode: u8,
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_desired: bool,
+    pub recursion_available: bool,
+    pub authentic_data: bool,
+    pub checking_disabled: bool,
+    pub rcode: u8,
+}
+
+impl DnsFlags {
+    fn from_bits(bits: u16) -> Self {
+        DnsFlags {
+            response: bits & 0x8000 != 0,
+            opcode: ((bits >> 11) & 0x0f) as u8,
+            authoritative: bits & 0x0400 != 0,
+            truncated: bits & 0x0200 != 0,
+            recursion_desired: bits & 0x0100 != 0,
+            recursion_available: bits & 0x0080 != 0,
+            authentic_data: bits & 0x0020 != 0,
+            checking_disabled: bits & 0x0010 != 0,
+            rcode: (bits & 0x000f) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DnsFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [
+            (self.response, "QR"),
+            (self.authoritative, "AA"),
+            (self.truncated, "TC"),
+            (self.recursion_desired, "RD"),
+            (self.recursion_available, "RA"),
+            (self.authentic_data, "AD"),
+            (self.checking_disabled, "CD"),
+        ];
+        let set: Vec<&str> = names.iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+        write!(f, "{}", set.join(","))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ns(String),
+    Ptr(String),
+    Mx { preference: u16, exchange: String },
+    Txt(Vec<String>),
+    Srv { priority: u16, weight: u16, port: u16, target: String },
+    // SVCB and HTTPS share a format; only ALPN is pulled out of the parameters
+    Https { priority: u16, target: String, alpn: Vec<String> },
+    // Anything else is kept as its length only
+    Other { length: usize },
+}
+
+impl fmt::Display for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordData::A(addr) => write!(f, "{}", addr),
+            RecordData::Aaaa(addr) => write!(f, "{}", addr),
+            RecordData::Cname(name) | RecordData::Ns(name) | RecordData::Ptr(name) => write!(f, "{}", name),
+            RecordData::Mx { preference, exchange } => write!(f, "{} {}", preference, exchange),
+            RecordData::Txt(strings) => write!(f, "\"{}\"", strings.join("\" \"")),
+            RecordData::Srv { priority, weight, port, target } => {
+                write!(f, "{} {} {} {}", priority, weight, port, target)
+            }
+            RecordData::Https { priority, target, alpn } => {
+                write!(f, "{} {}", priority, target)?;
+                if !alpn.is_empty() {
+                    write!(f, " alpn={}", alpn.join(","))?;
+                }
+                Ok(())
+            }
+            RecordData::Other { length } => write!(f, "<{} bytes>", length),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnsRecord {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub flags: DnsFlags,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    // Authority and additional sections are counted but not decoded
+    pub authority_count: u16,
+    pub additional_count: u16,
+}
+
+impl DnsMessage {
+    pub fn parse(data: &[u8]) -> Result<Self, DnsError> {
+        let mut reader = Reader { data, pos: 0 };
+        let id = reader.u16()?;
+        let flags = DnsFlags::from_bits(reader.u16()?);
+        let question_count = reader.u16()?;
+        let answer_count = reader.u16()?;
+        let authority_count = reader.u16()?;
+        let additional_count = reader.u16()?;
+
+        let mut questions = Vec::new();
+        for _ in 0..question_count {
+            let name = reader.name()?;
+            questions.push(DnsQuestion {
+                name,
+                qtype: reader.u16()?,
+                qclass: reader.u16()?,
+            });
+        }
+
+        let mut answers = Vec::new();
+        for _ in 0..answer_count {
+            answers.push(reader.record()?);
+        }
+
+        Ok(DnsMessage {
+            id,
+            flags,
+            questions,
+            answers,
+            authority_count,
+            additional_count,
+        })
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags.response
+    }
+
+    // One-line summary, e.g. "query A example.com" or "response NXDOMAIN A example.com"
+    pub fn summary(&self) -> String {
+        let question = self
+            .questions
+            .first()
+            .map(|q| format!("{} {}", type_name(q.qtype), q.name))
+            .unwrap_or_default();
+        if self.is_response() {
+            format!("response 0x{:04x} {} {}", self.id, rcode_name(self.flags.rcode), question)
+        } else {
+            format!("query 0x{:04x} {}", self.id, question)
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DnsError> {
+        let slice = self.data.get(self.pos..self.pos + len).ok_or(DnsError::Truncated)?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, DnsError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DnsError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DnsError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // Read a possibly compressed name, leaving the position after it
+    fn name(&mut self) -> Result<String, DnsError> {
+        let (name, next) = read_name(self.data, self.pos)?;
+        self.pos = next;
+        Ok(name)
+    }
+
+    fn record(&mut self) -> Result<DnsRecord, DnsError> {
+        let name = self.name()?;
+        let rtype = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let length = self.u16()? as usize;
+        let start = self.pos;
+        let end = start + length;
+        if end > self.data.len() {
+            return Err(DnsError::Truncated);
+        }
+
+        let data = match rtype {
+            1 if length == 4 => {
+                let b = self.bytes(4)?;
+                RecordData::A(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+            }
+            28 if length == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(self.bytes(16)?);
+                RecordData::Aaaa(Ipv6Addr::from(octets))
+            }
+            2 => RecordData::Ns(self.name()?),
+            5 => RecordData::Cname(self.name()?),
+            12 => RecordData::Ptr(self.name()?),
+            15 => RecordData::Mx {
+                preference: self.u16()?,
+                exchange: self.name()?,
+            },
+            16 => {
+                let mut strings = Vec::new();
+                while self.pos < end {
+                    let len = self.u8()? as usize;
+                    strings.push(String::from_utf8_lossy(self.bytes(len)?).into_owned());
+                }
+                RecordData::Txt(strings)
+            }
+            33 => RecordData::Srv {
+                priority: self.u16()?,
+                weight: self.u16()?,
+                port: self.u16()?,
+                target: self.name()?,
+            },
+            64 | 65 => {
+                let priority = self.u16()?;
+                let target = self.name()?;
+                let mut alpn = Vec::new();
+                while self.pos + 4 <= end {
+                    let key = self.u16()?;
+                    let len = self.u16()? as usize;
+                    let value = self.bytes(len)?;
+                    if key == 1 {
+                        alpn.extend(length_prefixed_strings(value));
+                    }
+                }
+                RecordData::Https { priority, target, alpn }
+            }
+            _ => RecordData::Other { length },
+        };
+
+        // Always continue with the next record, whatever the data parser consumed
+        self.pos = end;
+        Ok(DnsRecord { name, rtype, class, ttl, data })
+    }
+}
+
+fn length_prefixed_strings(mut data: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    while let Some((&len, rest)) = data.split_first() {
+        let len = (len as usize).min(rest.len());
+        strings.push(String::from_utf8_lossy(&rest[..len]).into_owned());
+        data = &rest[len..];
+    }
+    strings
+}
+
+// Decode the name at `pos`, following compression pointers. Returns the
+// dotted name and the offset just past the name in the original position.
+fn read_name(data: &[u8], mut pos: usize) -> Result<(String, usize), DnsError> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *data.get(pos).ok_or(DnsError::Truncated)? as usize;
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                end.get_or_insert(pos + 1);
+                break;
+            }
+            0x00 => {
+                let label = data.get(pos + 1..pos + 1 + len).ok_or(DnsError::Truncated)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            0xc0 => {
+                let low = *data.get(pos + 1).ok_or(DnsError::Truncated)? as usize;
+                end.get_or_insert(pos + 2);
+                jumps += 1;
+                if jumps > MAX_POINTER_JUMPS {
+                    return Err(DnsError::PointerLoop);
+                }
+                pos = ((len & 0x3f) << 8) | low;
+            }
+            _ => return Err(DnsError::BadLabel),
+        }
+    }
+
+    let name = if labels.is_empty() { ".".to_string() } else { labels.join(".") };
+    Ok((name, end.unwrap_or(pos)))
+}
+
+pub fn type_name(rtype: u16) -> String {
+    match rtype {
+        1 => "A".to_string(),
+        2 => "NS".to_string(),
+        5 => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        12 => "PTR".to_string(),
+        15 => "MX".to_string(),
+        16 => "TXT".to_string(),
+        28 => "AAAA".to_string(),
+        33 => "SRV".to_string(),
+        41 => "OPT".to_string(),
+        64 => "SVCB".to_string(),
+        65 => "HTTPS".to_string(),
+        255 => "ANY".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+pub fn rcode_name(rcode: u8) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+// Decode the packet's payload as DNS if it is on a DNS port. Over TCP each
+// message carries a two-byte length prefix; only a message complete within
+// this segment is decoded here, `DnsStreamConsumer` sees all of them.
+pub fn decode(packet: &DecodedPacket, data: &[u8]) -> Option<DnsMessage> {
+    let on_dns_port = |src: u16, dst: u16| DNS_PORTS.contains(&src) || DNS_PORTS.contains(&dst);
+    let payload = packet.payload(data);
+    match &packet.transport {
+        Some(TransportLayer::Udp(udp)) if on_dns_port(udp.src_port, udp.dst_port) => DnsMessage::parse(payload).ok(),
+        Some(TransportLayer::Tcp(tcp)) if on_dns_port(tcp.src_port, tcp.dst_port) && payload.len() > 2 => {
+            let len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+            let message = payload.get(2..2 + len)?;
+            DnsMessage::parse(message).ok()
+        }
+        _ => None,
+    }
+}
+
+// Transaction ID plus the client/server endpoints and transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TransactionKey {
+    id: u16,
+    protocol: u8,
+    client: (IpAddr, u16),
+    server: (IpAddr, u16),
+}
+
+struct PendingQuery {
+    sent: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DnsStats {
+    pub queries: u64,
+    pub responses: u64,
+    // Responses with no outstanding query (query missed or already answered)
+    pub unmatched_responses: u64,
+    // Queries that never got a response within the timeout
+    pub unanswered: u64,
+    pub nxdomain: u64,
+    pub rcodes: HashMap<String, u64>,
+    pub query_types: HashMap<String, u64>,
+    pub names: HashMap<String, u64>,
+    pub latency: LatencyStats,
+}
+
+impl DnsStats {
+    pub fn is_empty(&self) -> bool {
+        self.queries == 0 && self.responses == 0
+    }
+
+    // Add statistics gathered separately, e.g. from DNS over TCP
+    pub fn merge(&mut self, other: DnsStats) {
+        self.queries += other.queries;
+        self.responses += other.responses;
+        self.unmatched_responses += other.unmatched_responses;
+        self.unanswered += other.unanswered;
+        self.nxdomain += other.nxdomain;
+        for (counts, other) in [
+            (&mut self.rcodes, other.rcodes),
+            (&mut self.query_types, other.query_types),
+            (&mut self.names, other.names),
+        ] {
+            for (key, count) in other {
+                *counts.entry(key).or_insert(0) += count;
+            }
+        }
+        self.latency.merge(&other.latency);
+    }
+}
+
+// Query to response time in seconds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub total: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl LatencyStats {
+    fn add(&mut self, seconds: f64) {
+        if self.count == 0 || seconds < self.min {
+            self.min = seconds;
+        }
+        self.max = self.max.max(seconds);
+        self.total += seconds;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total / self.count as f64)
+    }
+
+    fn merge(&mut self, other: &LatencyStats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        self.max = self.max.max(other.max);
+        self.total += other.total;
+        self.count += other.count;
+    }
+}
+
+// Matches responses to queries and accumulates DNS statistics
+#[derive(Default)]
+struct Tracker {
+    pending: HashMap<TransactionKey, PendingQuery>,
+    stats: DnsStats,
+    last_sweep: f64,
+}
+
+impl Tracker {
+    fn into_stats(mut self) -> DnsStats {
+        self.stats.unanswered += self.pending.len() as u64;
+        self.stats
+    }
+
+    fn expire(&mut self, now: f64) {
+        let before = self.pending.len();
+        self.pending.retain(|_, query| now - query.sent <= QUERY_TIMEOUT);
+        self.stats.unanswered += (before - self.pending.len()) as u64;
+    }
+
+    fn record(&mut self, message: &DnsMessage, protocol: u8, src: (IpAddr, u16), dst: (IpAddr, u16), now: f64) {
+        // Sweep at most once per second of capture time
+        if now - self.last_sweep >= 1.0 {
+            self.expire(now);
+            self.last_sweep = now;
+        }
+
+        let stats = &mut self.stats;
+        if message.is_response() {
+            stats.responses += 1;
+            *stats.rcodes.entry(rcode_name(message.flags.rcode)).or_insert(0) += 1;
+            if message.flags.rcode == 3 {
+                stats.nxdomain += 1;
+            }
+            let key = TransactionKey { id: message.id, protocol, client: dst, server: src };
+            match self.pending.remove(&key) {
+                Some(query) => stats.latency.add((now - query.sent).max(0.0)),
+                None => stats.unmatched_responses += 1,
+            }
+        } else {
+            stats.queries += 1;
+            for question in &message.questions {
+                *stats.query_types.entry(type_name(question.qtype)).or_insert(0) += 1;
+                *stats.names.entry(question.name.to_lowercase()).or_insert(0) += 1;
+            }
+            let key = TransactionKey { id: message.id, protocol, client: src, server: dst };
+            // Retransmissions keep the original send time
+            self.pending.entry(key).or_insert(PendingQuery { sent: now });
+        }
+    }
+}
+
+// DNS statistics from UDP packets; DNS over TCP is counted by `DnsStreamConsumer`
+#[derive(Default)]
+pub struct DnsSink {
+    tracker: Tracker,
+}
+
+impl DnsSink {
+    pub fn into_stats(self) -> DnsStats {
+        self.tracker.into_stats()
+    }
+}
+
+impl PacketSink for DnsSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let Ok(decoded) = &packet.decoded else {
+            return Ok(());
+        };
+        let (Some(ApplicationLayer::Dns(message)), Some(ip)) = (&decoded.application, decoded.ip()) else {
+            return Ok(());
+        };
+        if decoded.udp().is_none() {
+            return Ok(());
+        }
+        let src = (ip.src, decoded.src_port().unwrap_or(0));
+        let dst = (ip.dst, decoded.dst_port().unwrap_or(0));
+        self.tracker.record(message, ip.protocol, src, dst, decoded.timestamp());
+        Ok(())
+    }
+}
+
+// One direction of a DNS over TCP connection
+#[derive(Default)]
+struct DnsSide {
+    buffer: Vec<u8>,
+    // Bytes were lost, so the length prefixes can no longer be found
+    lost: bool,
+}
+
+// Frames DNS messages on reassembled TCP streams, so messages split across
+// segments and several queries pipelined in one are all counted
+#[derive(Default)]
+pub struct DnsStreamConsumer {
+    // Per stream, indexed by `Direction::index`
+    streams: HashMap<u64, [DnsSide; 2]>,
+    tracker: Tracker,
+}
+
+impl DnsStreamConsumer {
+    pub fn into_stats(self) -> DnsStats {
+        self.tracker.into_stats()
+    }
+}
+
+impl StreamConsumer for DnsStreamConsumer {
+    fn on_data(&mut self, stream: &StreamInfo, direction: Direction, data: &[u8], ts: f64) {
+        if !DNS_PORTS.contains(&stream.server.1) && !DNS_PORTS.contains(&stream.client.1) {
+            return;
+        }
+        let side = &mut self.streams.entry(stream.id).or_default()[direction.index()];
+        if side.lost {
+            return;
+        }
+        side.buffer.extend_from_slice(data);
+        let (src, dst) = match direction {
+            Direction::ClientToServer => (stream.client, stream.server),
+            Direction::ServerToClient => (stream.server, stream.client),
+        };
+
+        // Each message is preceded by its two-byte length
+        let mut offset = 0;
+        while let Some(prefix) = side.buffer.get(offset..offset + 2) {
+            let len = u16::from_be_bytes([prefix[0], prefix[1]]) as usize;
+            let Some(message) = side.buffer.get(offset + 2..offset + 2 + len) else {
+                break;
+            };
+            if let Ok(message) = DnsMessage::parse(message) {
+                self.tracker.record(&message, 6, src, dst, ts);
+            }
+            offset += 2 + len;
+        }
+        side.buffer.drain(..offset);
+    }
+
+    fn on_gap(&mut self, stream: &StreamInfo, direction: Direction, _missing: u64) {
+        if let Some(sides) = self.streams.get_mut(&stream.id) {
+            let side = &mut sides[direction.index()];
+            side.buffer.clear();
+            side.lost = true;
+        }
+    }
+
+    fn on_close(&mut self, stream: &StreamInfo, _reason: CloseReason) {
+        self.streams.remove(&stream.id);
+    }
+}
+
+pub fn write_dns_details(out: &mut dyn Write, message: &DnsMessage) -> io::Result<()> {
+    writeln!(
+        out,
+        "  DNS: {} id=0x{:04x} flags=[{}] rcode={}",
+        if message.is_response() { "response" } else { "query" },
+        message.id,
+        message.flags,
+        rcode_name(message.flags.rcode)
+    )?;
+    for question in &message.questions {
+        writeln!(out, "    Question: {} {}", question.name, type_name(question.qtype))?;
+    }
+    for answer in &message.answers {
+        writeln!(out, "    Answer: {} {} ttl={} {}", answer.name, type_name(answer.rtype), answer.ttl, answer.data)?;
+    }
+    Ok(())
+}
+
+pub fn write_dns_summary(out: &mut dyn Write, stats: &DnsStats, top: usize) -> io::Result<()> {
+    writeln!(out, "\n=== DNS Statistics ===")?;
+    writeln!(out, "Queries: {}, Responses: {}", stats.queries, stats.responses)?;
+    writeln!(
+        out,
+        "Unanswered queries: {}, Unmatched responses: {}, NXDOMAIN: {}",
+        stats.unanswered, stats.unmatched_responses, stats.nxdomain
+    )?;
+    if let Some(mean) = stats.latency.mean() {
+        writeln!(
+            out,
+            "Latency: min {:.3} ms, avg {:.3} ms, max {:.3} ms ({} matched)",
+            stats.latency.min * 1000.0,
+            mean * 1000.0,
+            stats.latency.max * 1000.0,
+            stats.latency.count
+        )?;
+    }
+    for (rcode, count) in sorted_counts(&stats.rcodes) {
+        writeln!(out, "  {}: {}", rcode, count)?;
+    }
+    for (qtype, count) in sorted_counts(&stats.query_types) {
+        writeln!(out, "  {} queries: {}", qtype, count)?;
+    }
+    if !stats.names.is_empty() {
+        writeln!(out, "Top queried names:")?;
+        for (name, count) in sorted_counts(&stats.names).into_iter().take(top) {
+            writeln!(out, "  {:<50} {}", name, count)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(id: u16, flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+        let mut message = Vec::new();
+        for field in [id, flags, questions, answers, 0, 0] {
+            message.extend_from_slice(&field.to_be_bytes());
+        }
+        message
+    }
+
+    fn name(dotted: &str) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for label in dotted.split('.') {
+            encoded.push(label.len() as u8);
+            encoded.extend_from_slice(label.as_bytes());
+        }
+        encoded.push(0);
+        encoded
+    }
+
+    fn question(message: &mut Vec<u8>, dotted: &str, qtype: u16) {
+        message.extend(name(dotted));
+        message.extend_from_slice(&qtype.to_be_bytes());
+        message.extend_from_slice(&1u16.to_be_bytes());
+    }
+
+    // Answer owned by the first question's name, via a pointer to offset 12
+    fn answer(message: &mut Vec<u8>, rtype: u16, rdata: &[u8]) {
+        message.extend_from_slice(&[0xc0, 0x0c]);
+        message.extend_from_slice(&rtype.to_be_bytes());
+        message.extend_from_slice(&1u16.to_be_bytes());
+        message.extend_from_slice(&300u32.to_be_bytes());
+        message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        message.extend_from_slice(rdata);
+    }
+
+    fn query(id: u16, dotted: &str) -> Vec<u8> {
+        let mut message = header(id, 0x0100, 1, 0);
+        question(&mut message, dotted, 1);
+        message
+    }
+
+    fn response(id: u16, dotted: &str, rcode: u16, address: Option<[u8; 4]>) -> Vec<u8> {
+        let mut message = header(id, 0x8180 | rcode, 1, address.is_some() as u16);
+        question(&mut message, dotted, 1);
+        if let Some(address) = address {
+            answer(&mut message, 1, &address);
+        }
+        message
+    }
+
+    #[test]
+    fn follows_compression_pointers() {
+        let mut message = header(0x1234, 0x8180, 1, 2);
+        question(&mut message, "example.com", 1);
+        // "www" followed by a pointer to "example.com"
+        answer(&mut message, 5, &[3, b'w', b'w', b'w', 0xc0, 0x0c]);
+        // Owner name pointing at the CNAME target, which itself ends in a pointer
+        let target = message.len() - 6;
+        message.extend_from_slice(&[0xc0, target as u8]);
+        message.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]);
+
+        let parsed = DnsMessage::parse(&message).unwrap();
+        assert_eq!(parsed.id, 0x1234);
+        assert!(parsed.is_response());
+        assert_eq!(parsed.questions[0].name, "example.com");
+        assert_eq!(parsed.answers[0].name, "example.com");
+        assert_eq!(parsed.answers[0].data.to_string(), "www.example.com");
+        assert_eq!(parsed.answers[1].name, "www.example.com");
+        assert_eq!(parsed.answers[1].ttl, 60);
+        assert_eq!(parsed.answers[1].data.to_string(), "93.184.216.34");
+    }
+
+    #[test]
+    fn rejects_pointer_loops() {
+        // The question name points at itself
+        let mut message = header(1, 0x0100, 1, 0);
+        message.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1]);
+        assert_eq!(DnsMessage::parse(&message).unwrap_err(), DnsError::PointerLoop);
+
+        // Two names pointing at each other
+        let mut message = header(1, 0x0100, 1, 0);
+        message.extend_from_slice(&[0xc0, 0x0e, 0xc0, 0x0c, 0, 1, 0, 1]);
+        assert_eq!(DnsMessage::parse(&message).unwrap_err(), DnsError::PointerLoop);
+
+        // A long but finite chain is fine up to the limit
+        let mut message = header(1, 0x0100, 1, 0);
+        message.push(0);
+        for i in 0..MAX_POINTER_JUMPS {
+            // Each pointer refers to the one before it, the first to the root
+            let target = message.len() - if i == 0 { 1 } else { 2 };
+            message.extend_from_slice(&[0xc0, target as u8]);
+        }
+        let start = message.len() - 2;
+        let (name, next) = read_name(&message, start).unwrap();
+        assert_eq!((name.as_str(), next), (".", start + 2));
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        assert_eq!(DnsMessage::parse(&[0, 1, 0x01]).unwrap_err(), DnsError::Truncated);
+
+        let message = response(7, "example.com", 0, Some([10, 0, 0, 1]));
+        for len in [12, 20, message.len() - 10, message.len() - 1] {
+            assert_eq!(DnsMessage::parse(&message[..len]).unwrap_err(), DnsError::Truncated, "cut at {}", len);
+        }
+
+        // Record data length running past the end of the message
+        let mut message = header(7, 0x8180, 0, 1);
+        message.extend_from_slice(&[0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 8, 1, 2, 3, 4]);
+        assert_eq!(DnsMessage::parse(&message).unwrap_err(), DnsError::Truncated);
+
+        // Label types 01 and 10 are reserved
+        let mut message = header(7, 0x0100, 1, 0);
+        message.extend_from_slice(&[0x40, 0, 0, 1, 0, 1]);
+        assert_eq!(DnsMessage::parse(&message).unwrap_err(), DnsError::BadLabel);
+    }
+
+    #[test]
+    fn decodes_each_record_type() {
+        let mut message = header(9, 0x8180, 1, 10);
+        question(&mut message, "example.com", 255);
+        answer(&mut message, 1, &[192, 0, 2, 1]);
+        answer(&mut message, 28, &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        answer(&mut message, 5, &name("alias.example.net"));
+        answer(&mut message, 2, &name("ns1.example.com"));
+        answer(&mut message, 12, &name("host.example.com"));
+        answer(&mut message, 15, &[&[0, 10][..], &name("mail.example.com")].concat());
+        answer(&mut message, 16, b"\x05hello\x05world");
+        answer(&mut message, 33, &[&[0, 1, 0, 5, 0x13, 0xc4][..], &name("sip.example.com")].concat());
+        // HTTPS: priority 1, target ".", alpn=h2,h3
+        answer(&mut message, 65, &[0, 1, 0, 0, 1, 0, 6, 2, b'h', b'2', 2, b'h', b'3']);
+        answer(&mut message, 99, &[1, 2, 3]);
+
+        let parsed = DnsMessage::parse(&message).unwrap();
+        let data: Vec<String> = parsed.answers.iter().map(|answer| answer.data.to_string()).collect();
+        assert_eq!(
+            data,
+            [
+                "192.0.2.1",
+                "2001:db8::1",
+                "alias.example.net",
+                "ns1.example.com",
+                "host.example.com",
+                "10 mail.example.com",
+                "\"hello\" \"world\"",
+                "1 5 5060 sip.example.com",
+                "1 . alpn=h2,h3",
+                "<3 bytes>",
+            ]
+        );
+        let types: Vec<String> = parsed.answers.iter().map(|answer| type_name(answer.rtype)).collect();
+        assert_eq!(types, ["A", "AAAA", "CNAME", "NS", "PTR", "MX", "TXT", "SRV", "HTTPS", "TYPE99"]);
+        assert_eq!(parsed.summary(), "response 0x0009 NOERROR ANY example.com");
+    }
+
+    #[test]
+    fn matches_responses_to_queries() {
+        let client = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 53000);
+        let server = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 53);
+        let other = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9)), 53000);
+        let parse = |message: Vec<u8>| DnsMessage::parse(&message).unwrap();
+
+        let mut tracker = Tracker::default();
+        tracker.record(&parse(query(1, "Example.com")), 17, client, server, 100.0);
+        tracker.record(&parse(query(2, "missing.example")), 17, client, server, 100.5);
+        // Retransmission: latency is measured from the first copy
+        tracker.record(&parse(query(1, "Example.com")), 17, client, server, 100.01);
+        tracker.record(&parse(response(1, "Example.com", 0, Some([1, 2, 3, 4]))), 17, server, client, 100.02);
+        tracker.record(&parse(response(2, "missing.example", 3, None)), 17, server, client, 100.54);
+        // Same ID but sent to another client, and a duplicate response
+        tracker.record(&parse(response(2, "missing.example", 3, None)), 17, server, other, 100.6);
+        tracker.record(&parse(response(1, "Example.com", 0, Some([1, 2, 3, 4]))), 17, server, client, 100.7);
+        // Never answered
+        tracker.record(&parse(query(3, "example.org")), 17, client, server, 101.0);
+
+        let stats = tracker.into_stats();
+        assert_eq!((stats.queries, stats.responses), (4, 4));
+        assert_eq!((stats.unmatched_responses, stats.unanswered, stats.nxdomain), (2, 1, 2));
+        assert_eq!(stats.rcodes["NOERROR"], 2);
+        assert_eq!(stats.rcodes["NXDOMAIN"], 2);
+        assert_eq!(stats.names["example.com"], 2);
+        assert_eq!(stats.latency.count, 2);
+        assert!((stats.latency.min - 0.02).abs() < 1e-9);
+        assert!((stats.latency.max - 0.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn queries_time_out() {
+        let client = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 53000);
+        let server = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 53);
+        let mut tracker = Tracker::default();
+        tracker.record(&DnsMessage::parse(&query(1, "example.com")).unwrap(), 17, client, server, 10.0);
+        let late = DnsMessage::parse(&response(1, "example.com", 0, None)).unwrap();
+        tracker.record(&late, 17, server, client, 10.0 + QUERY_TIMEOUT + 1.0);
+        let stats = tracker.into_stats();
+        assert_eq!((stats.unanswered, stats.unmatched_responses, stats.latency.count), (1, 1, 0));
+    }
+
+    fn framed(messages: &[Vec<u8>]) -> Vec<u8> {
+        let mut stream = Vec::new();
+        for message in messages {
+            stream.extend_from_slice(&(message.len() as u16).to_be_bytes());
+            stream.extend_from_slice(message);
+        }
+        stream
+    }
+
+    #[test]
+    fn frames_dns_over_tcp_across_segments() {
+        let stream = StreamInfo {
+            id: 1,
+            client: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 40000),
+            server: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 53),
+            first_seen: 0.0,
+        };
+        let mut consumer = DnsStreamConsumer::default();
+
+        // Two pipelined queries, split in the middle of the second length prefix
+        let queries = framed(&[query(1, "example.com"), query(2, "example.org")]);
+        let split = query(1, "example.com").len() + 3;
+        consumer.on_data(&stream, Direction::ClientToServer, &queries[..split], 1.0);
+        consumer.on_data(&stream, Direction::ClientToServer, &queries[split..], 1.0);
+
+        // Both responses, delivered one byte at a time
+        let responses = framed(&[
+            response(2, "example.org", 3, None),
+            response(1, "example.com", 0, Some([93, 184, 216, 34])),
+        ]);
+        for byte in &responses {
+            consumer.on_data(&stream, Direction::ServerToClient, std::slice::from_ref(byte), 1.25);
+        }
+        consumer.on_close(&stream, CloseReason::Finished);
+
+        let stats = consumer.into_stats();
+        assert_eq!((stats.queries, stats.responses, stats.nxdomain), (2, 2, 1));
+        assert_eq!((stats.unmatched_responses, stats.unanswered), (0, 0));
+        assert_eq!(stats.latency.count, 2);
+        assert!((stats.latency.mean().unwrap() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stops_framing_after_a_gap() {
+        let stream = StreamInfo {
+            id: 1,
+            client: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 40000),
+            server: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 53),
+            first_seen: 0.0,
+        };
+        let mut consumer = DnsStreamConsumer::default();
+        let queries = framed(&[query(1, "example.com"), query(2, "example.org")]);
+        let first = query(1, "example.com").len() + 2;
+        consumer.on_data(&stream, Direction::ClientToServer, &queries[..first + 5], 1.0);
+        consumer.on_gap(&stream, Direction::ClientToServer, 10);
+        consumer.on_data(&stream, Direction::ClientToServer, &queries[first + 15..], 1.0);
+        assert_eq!(consumer.into_stats().queries, 1);
+    }
+
+    #[test]
+    fn merges_statistics() {
+        let mut udp = DnsStats {
+            queries: 3,
+            names: HashMap::from([("example.com".to_string(), 3)]),
+            ..Default::default()
+        };
+        udp.latency.add(0.5);
+        let mut tcp = DnsStats {
+            queries: 1,
+            names: HashMap::from([("example.com".to_string(), 1)]),
+            ..Default::default()
+        };
+        tcp.latency.add(0.1);
+        tcp.latency.add(0.2);
+        udp.merge(tcp);
+        assert_eq!(udp.queries, 4);
+        assert_eq!(udp.names["example.com"], 4);
+        assert_eq!(udp.latency.count, 3);
+        assert_eq!((udp.latency.min, udp.latency.max), (0.1, 0.5));
+    }
+}
@@ -0,0 +1,402 @@
+use crate::packet::{DecodedPacket, TransportLayer};
+use serde::Serialize;
+use std::fmt;
+use std::net::IpAddr;
+
+// Well-known UDP ports of overlay tunnels
+const VXLAN_PORT: u16 = 4789;
+const GENEVE_PORT: u16 = 6081;
+// Nested tunnels decoded before giving up, against crafted packets
+pub const MAX_TUNNEL_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MplsLabel {
+    pub label: u32,
+    // Traffic class (formerly EXP)
+    pub tc: u8,
+    pub ttl: u8,
+}
+
+// A header wrapped around the decoded packet
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Encapsulation {
+    Mpls {
+        labels: Vec<MplsLabel>,
+    },
+    Pppoe {
+        // 0 for session data, otherwise the discovery stage (PADI, PADO, ...)
+        code: u8,
+        session_id: u16,
+        // PPP protocol of session frames
+        ppp_protocol: Option<u16>,
+    },
+    Gre {
+        src: IpAddr,
+        dst: IpAddr,
+        protocol: u16,
+        key: Option<u32>,
+    },
+    Vxlan {
+        src: IpAddr,
+        dst: IpAddr,
+        vni: u32,
+    },
+    Geneve {
+        src: IpAddr,
+        dst: IpAddr,
+        vni: u32,
+        protocol: u16,
+    },
+}
+
+impl Encapsulation {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Encapsulation::Mpls { .. } => "MPLS",
+            Encapsulation::Pppoe { code: 0, .. } => "PPPoE",
+            Encapsulation::Pppoe { .. } => "PPPoE Discovery",
+            Encapsulation::Gre { .. } => "GRE",
+            Encapsulation::Vxlan { .. } => "VXLAN",
+            Encapsulation::Geneve { .. } => "Geneve",
+        }
+    }
+
+    // Key for per-tunnel statistics
+    pub fn label(&self) -> String {
+        match self {
+            Encapsulation::Mpls { labels } => {
+                let stack: Vec<String> = labels.iter().map(|l| l.label.to_string()).collect();
+                format!("MPLS {}", stack.join("/"))
+            }
+            Encapsulation::Pppoe { session_id, .. } => format!("{} session 0x{:04x}", self.kind(), session_id),
+            Encapsulation::Gre { src, dst, key: Some(key), .. } => format!("GRE {} -> {} key {}", src, dst, key),
+            Encapsulation::Gre { src, dst, .. } => format!("GRE {} -> {}", src, dst),
+            Encapsulation::Vxlan { src, dst, vni } => format!("VXLAN {} -> {} vni {}", src, dst, vni),
+            Encapsulation::Geneve { src, dst, vni, .. } => format!("Geneve {} -> {} vni {}", src, dst, vni),
+        }
+    }
+}
+
+impl fmt::Display for Encapsulation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encapsulation::Mpls { labels } => {
+                let stack: Vec<String> = labels
+                    .iter()
+                    .map(|l| format!("label={} tc={} ttl={}", l.label, l.tc, l.ttl))
+                    .collect();
+                write!(f, "MPLS [{}]", stack.join(", "))
+            }
+            Encapsulation::Pppoe { code, session_id, ppp_protocol } => {
+                write!(f, "{} code=0x{:02x} session=0x{:04x}", self.kind(), code, session_id)?;
+                if let Some(protocol) = ppp_protocol {
+                    write!(f, " ppp=0x{:04x}", protocol)?;
+                }
+                Ok(())
+            }
+            Encapsulation::Gre { protocol, .. } => write!(f, "{} protocol=0x{:04x}", self.label(), protocol),
+            Encapsulation::Vxlan { .. } => write!(f, "{}", self.label()),
+            Encapsulation::Geneve { protocol, .. } => write!(f, "{} protocol=0x{:04x}", self.label(), protocol),
+        }
+    }
+}
+
+// Where the encapsulated packet starts and what it begins with
+pub enum Inner<'a> {
+    Ethernet(&'a [u8]),
+    Ip(&'a [u8]),
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// Payload of an ether type that carries IP or Ethernet
+fn inner_by_ether_type(ether_type: u16, data: &[u8]) -> Option<Inner<'_>> {
+    match ether_type {
+        0x0800 | 0x86dd => Some(Inner::Ip(data)),
+        0x6558 => Some(Inner::Ethernet(data)),
+        _ => None,
+    }
+}
+
+// MPLS and PPPoE sit between Ethernet (and any VLAN tags) and IP, where etherparse
+// stops. Returns the header and, when it carries IP or Ethernet, the packet inside.
+pub fn link_encapsulation(frame: &[u8]) -> Option<(Encapsulation, Option<Inner<'_>>)> {
+    let mut ether_type = u16_at(frame, 12)?;
+    let mut offset = 14;
+    while matches!(ether_type, 0x8100 | 0x88a8 | 0x9100) {
+        ether_type = u16_at(frame, offset + 2)?;
+        offset += 4;
+    }
+    let payload = frame.get(offset..)?;
+
+    match ether_type {
+        // MPLS unicast and multicast
+        0x8847 | 0x8848 => {
+            let mut labels = Vec::new();
+            let mut rest = payload;
+            loop {
+                let entry = u32_at(rest, 0)?;
+                labels.push(MplsLabel {
+                    label: entry >> 12,
+                    tc: ((entry >> 9) & 0x7) as u8,
+                    ttl: (entry & 0xff) as u8,
+                });
+                rest = &rest[4..];
+                if entry & 0x100 != 0 {
+                    break;
+                }
+            }
+            // No protocol field: guess from the first nibble. 0 is the pseudowire
+            // control word ahead of an Ethernet frame.
+            let inner = match rest.first().map(|b| b >> 4) {
+                Some(4) | Some(6) => Some(Inner::Ip(rest)),
+                Some(0) if rest.len() > 4 => Some(Inner::Ethernet(&rest[4..])),
+                _ => None,
+            };
+            Some((Encapsulation::Mpls { labels }, inner))
+        }
+        // PPPoE discovery and session stages
+        0x8863 | 0x8864 => {
+            let code = *payload.get(1)?;
+            let session_id = u16_at(payload, 2)?;
+            let ppp_protocol = if ether_type == 0x8864 { u16_at(payload, 6) } else { None };
+            let inner = match ppp_protocol {
+                Some(0x0021) | Some(0x0057) => payload.get(8..).map(Inner::Ip),
+                _ => None,
+            };
+            Some((Encapsulation::Pppoe { code, session_id, ppp_protocol }, inner))
+        }
+        _ => None,
+    }
+}
+
+// GRE, VXLAN and Geneve carried in the packet decoded so far
+pub fn tunnel<'a>(packet: &DecodedPacket, data: &'a [u8]) -> Option<(Encapsulation, Inner<'a>)> {
+    let ip = packet.ip()?;
+    let (src, dst) = (ip.src, ip.dst);
+    let payload = packet.payload(data);
+
+    match &packet.transport {
+        None if ip.protocol == 47 && ip.fragment.is_none() => {
+            let flags = u16_at(payload, 0)?;
+            // Version 1 is PPTP's enhanced GRE, which carries PPP
+            if flags & 0x7 != 0 {
+                return None;
+            }
+            let protocol = u16_at(payload, 2)?;
+            let mut offset = 4;
+            if flags & 0x8000 != 0 {
+                // Checksum and reserved
+                offset += 4;
+            }
+            let key = if flags & 0x2000 != 0 {
+                offset += 4;
+                u32_at(payload, offset - 4)
+            } else {
+                None
+            };
+            if flags & 0x1000 != 0 {
+                // Sequence number
+                offset += 4;
+            }
+            let inner = inner_by_ether_type(protocol, payload.get(offset..)?)?;
+            Some((Encapsulation::Gre { src, dst, protocol, key }, inner))
+        }
+        Some(TransportLayer::Udp(udp)) if udp.dst_port == VXLAN_PORT => {
+            // The I flag says the VNI is valid
+            if payload.first()? & 0x08 == 0 {
+                return None;
+            }
+            let vni = u32_at(payload, 4)? >> 8;
+            Some((Encapsulation::Vxlan { src, dst, vni }, Inner::Ethernet(payload.get(8..)?)))
+        }
+        Some(TransportLayer::Udp(udp)) if udp.dst_port == GENEVE_PORT => {
+            let first = *payload.first()?;
+            if first >> 6 != 0 {
+                return None;
+            }
+            let header_len = 8 + (first & 0x3f) as usize * 4;
+            let protocol = u16_at(payload, 2)?;
+            let vni = u32_at(payload, 4)? >> 8;
+            let inner = inner_by_ether_type(protocol, payload.get(header_len..)?)?;
+            Some((Encapsulation::Geneve { src, dst, vni, protocol }, inner))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listening::parse_packet_with_etherparse;
+    use crate::source::RawPacket;
+    use pcap::Linktype;
+
+    fn ethernet(ether_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn ipv4(protocol: u8, src: [u8; 4], dst: [u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0x00];
+        packet.extend_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 1, 0, 0, 64, protocol, 0, 0]);
+        packet.extend_from_slice(&src);
+        packet.extend_from_slice(&dst);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::new();
+        for field in [src_port, dst_port, (8 + payload.len()) as u16, 0] {
+            datagram.extend_from_slice(&field.to_be_bytes());
+        }
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    // Bare TCP SYN header
+    fn syn(src_port: u16, dst_port: u16) -> Vec<u8> {
+        let mut segment = Vec::new();
+        segment.extend_from_slice(&src_port.to_be_bytes());
+        segment.extend_from_slice(&dst_port.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0]);
+        segment
+    }
+
+    // Ethernet frame from 10.0.0.1:40000 to 10.0.0.2:80, what the tunnels below carry
+    fn inner_frame() -> Vec<u8> {
+        ethernet(0x0800, &ipv4(6, [10, 0, 0, 1], [10, 0, 0, 2], &syn(40_000, 80)))
+    }
+
+    fn vxlan(vni: u32, inner: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0x08, 0, 0, 0];
+        payload.extend_from_slice(&(vni << 8).to_be_bytes());
+        payload.extend_from_slice(inner);
+        let datagram = udp(50_000, VXLAN_PORT, &payload);
+        ethernet(0x0800, &ipv4(17, [192, 0, 2, 1], [192, 0, 2, 2], &datagram))
+    }
+
+    fn decode(frame: Vec<u8>) -> DecodedPacket {
+        parse_packet_with_etherparse(1, &RawPacket::new(0, 0, frame), Linktype::ETHERNET).unwrap()
+    }
+
+    fn labels(packet: &DecodedPacket) -> Vec<String> {
+        packet.encapsulation.iter().map(|e| e.label()).collect()
+    }
+
+    fn endpoints(packet: &DecodedPacket) -> String {
+        format!(
+            "{}:{} -> {}:{}",
+            packet.src_addr().unwrap(),
+            packet.src_port().unwrap(),
+            packet.dst_addr().unwrap(),
+            packet.dst_port().unwrap()
+        )
+    }
+
+    #[test]
+    fn qinq_frames_keep_both_tags() {
+        let datagram = udp(5353, 53, b"query");
+        let ip = ipv4(17, [10, 0, 0, 1], [10, 0, 0, 53], &datagram);
+        // S-tag 100 with priority 3, then C-tag 200
+        let mut tagged = vec![0x60, 0x64, 0x81, 0x00, 0x00, 0xc8, 0x08, 0x00];
+        tagged.extend_from_slice(&ip);
+        let packet = decode(ethernet(0x88a8, &tagged));
+
+        assert_eq!(packet.vlan_key().as_deref(), Some("100.200"));
+        let tags = &packet.link.as_ref().unwrap().vlan_tags;
+        assert_eq!((tags[0].pcp, tags[1].pcp), (3, 0));
+        assert_eq!(endpoints(&packet), "10.0.0.1:5353 -> 10.0.0.53:53");
+        assert!(packet.encapsulation.is_empty());
+    }
+
+    #[test]
+    fn vxlan_carries_an_inner_ethernet_frame() {
+        let packet = decode(vxlan(0x1234, &inner_frame()));
+        assert_eq!(labels(&packet), ["VXLAN 192.0.2.1 -> 192.0.2.2 vni 4660"]);
+        // The inner packet replaces the outer layers
+        assert_eq!(packet.protocol_name(), "TCP");
+        assert_eq!(endpoints(&packet), "10.0.0.1:40000 -> 10.0.0.2:80");
+
+        // Without the I flag the header is not trusted and the packet stays plain UDP
+        let mut frame = vxlan(0x1234, &inner_frame());
+        frame[14 + 20 + 8] = 0;
+        let packet = decode(frame);
+        assert!(packet.encapsulation.is_empty());
+        assert_eq!(packet.dst_port(), Some(VXLAN_PORT));
+    }
+
+    #[test]
+    fn nested_tunnels_stop_at_the_depth_limit() {
+        let mut frame = inner_frame();
+        for vni in 1..=MAX_TUNNEL_DEPTH as u32 + 2 {
+            frame = vxlan(vni, &frame);
+        }
+        let packet = decode(frame);
+        assert_eq!(packet.encapsulation.len(), MAX_TUNNEL_DEPTH);
+        assert_eq!(packet.encapsulation[0].label(), "VXLAN 192.0.2.1 -> 192.0.2.2 vni 6");
+        assert_eq!(packet.dst_port(), Some(VXLAN_PORT));
+    }
+
+    #[test]
+    fn gre_and_geneve_tunnels() {
+        // GRE with a key (K flag) carrying IPv4
+        let mut gre = vec![0x20, 0x00, 0x08, 0x00, 0, 0, 0, 42];
+        gre.extend_from_slice(&ipv4(6, [10, 0, 0, 1], [10, 0, 0, 2], &syn(40_000, 80)));
+        let packet = decode(ethernet(0x0800, &ipv4(47, [192, 0, 2, 1], [192, 0, 2, 2], &gre)));
+        assert_eq!(labels(&packet), ["GRE 192.0.2.1 -> 192.0.2.2 key 42"]);
+        assert_eq!(packet.encapsulation[0].to_string(), "GRE 192.0.2.1 -> 192.0.2.2 key 42 protocol=0x0800");
+        assert_eq!(endpoints(&packet), "10.0.0.1:40000 -> 10.0.0.2:80");
+
+        // Geneve with one 4-byte option ahead of an Ethernet frame
+        let mut geneve = vec![0x01, 0x00, 0x65, 0x58, 0x00, 0x00, 0x07, 0x00, 0xab, 0xcd, 0x01, 0x00];
+        geneve.extend_from_slice(&inner_frame());
+        let datagram = udp(50_000, GENEVE_PORT, &geneve);
+        let packet = decode(ethernet(0x0800, &ipv4(17, [192, 0, 2, 1], [192, 0, 2, 2], &datagram)));
+        assert_eq!(labels(&packet), ["Geneve 192.0.2.1 -> 192.0.2.2 vni 7"]);
+        assert_eq!(endpoints(&packet), "10.0.0.1:40000 -> 10.0.0.2:80");
+    }
+
+    #[test]
+    fn mpls_label_stacks() {
+        // Label 16 (tc 5), then bottom-of-stack label 1000 with ttl 63, then IPv4
+        let mut stack = vec![0x00, 0x01, 0x0a, 0x40, 0x00, 0x3e, 0x81, 0x3f];
+        stack.extend_from_slice(&ipv4(6, [10, 0, 0, 1], [10, 0, 0, 2], &syn(40_000, 80)));
+        let packet = decode(ethernet(0x8847, &stack));
+        assert_eq!(labels(&packet), ["MPLS 16/1000"]);
+        assert_eq!(
+            packet.encapsulation[0].to_string(),
+            "MPLS [label=16 tc=5 ttl=64, label=1000 tc=0 ttl=63]"
+        );
+        assert_eq!(endpoints(&packet), "10.0.0.1:40000 -> 10.0.0.2:80");
+
+        // A stack that never sets bottom-of-stack runs off the end of the frame
+        assert!(link_encapsulation(&ethernet(0x8847, &[0x00, 0x01, 0x0a, 0x40])).is_none());
+    }
+
+    #[test]
+    fn pppoe_sessions_and_discovery() {
+        let mut session = vec![0x11, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x21];
+        session.extend_from_slice(&ipv4(6, [100, 64, 0, 1], [10, 0, 0, 2], &syn(40_000, 80)));
+        let packet = decode(ethernet(0x8864, &session));
+        assert_eq!(labels(&packet), ["PPPoE session 0x002a"]);
+        assert_eq!(endpoints(&packet), "100.64.0.1:40000 -> 10.0.0.2:80");
+
+        // PADI: discovery stage, nothing inside
+        let padi = ethernet(0x8863, &[0x11, 0x09, 0x00, 0x00, 0x00, 0x00]);
+        let (encapsulation, inner) = link_encapsulation(&padi).unwrap();
+        assert_eq!(encapsulation.to_string(), "PPPoE Discovery code=0x09 session=0x0000");
+        assert!(inner.is_none());
+    }
+}
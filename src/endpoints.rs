@@ -0,0 +1,259 @@
+use crate::listening::{ParsedPacket, TrafficCounters};
+use crate::packet::{MacAddr, TransportLayer};
+use crate::report::{largest_first, sorted_counts};
+use crate::sink::PacketSink;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+
+// Upper bounds (exclusive) of the packet length buckets, as in Wireshark's
+// Packet Lengths statistics; the last bucket is open-ended
+const SIZE_BOUNDS: [u32; 9] = [20, 40, 80, 160, 320, 640, 1280, 2560, 5120];
+
+// What one endpoint sent (tx) and received (rx)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EndpointCounters {
+    pub tx: TrafficCounters,
+    pub rx: TrafficCounters,
+}
+
+impl EndpointCounters {
+    pub fn packets(&self) -> u64 {
+        self.tx.packets + self.rx.packets
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.tx.bytes + self.rx.bytes
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SizeDistribution {
+    pub counts: [u64; SIZE_BOUNDS.len() + 1],
+    pub packets: u64,
+    pub bytes: u64,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl SizeDistribution {
+    fn add(&mut self, len: u32) {
+        let bucket = SIZE_BOUNDS.iter().position(|bound| len < *bound).unwrap_or(SIZE_BOUNDS.len());
+        self.counts[bucket] += 1;
+        self.min = if self.packets == 0 { len } else { self.min.min(len) };
+        self.max = self.max.max(len);
+        self.packets += 1;
+        self.bytes += len as u64;
+    }
+
+    // "0-19", "20-39", ... "5120+"
+    fn label(bucket: usize) -> String {
+        let low = if bucket == 0 { 0 } else { SIZE_BOUNDS[bucket - 1] };
+        match SIZE_BOUNDS.get(bucket) {
+            Some(high) => format!("{}-{}", low, high - 1),
+            None => format!("{}+", low),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EndpointStats {
+    pub ips: HashMap<IpAddr, EndpointCounters>,
+    pub macs: HashMap<MacAddr, EndpointCounters>,
+    // Packets per destination port, by transport
+    pub ports: BTreeMap<&'static str, HashMap<u16, u64>>,
+    pub sizes: SizeDistribution,
+    // Who talks to whom: traffic from the first address to the second
+    pub matrix: HashMap<(IpAddr, IpAddr), TrafficCounters>,
+}
+
+impl EndpointStats {
+    pub fn is_empty(&self) -> bool {
+        self.sizes.packets == 0
+    }
+}
+
+// Per-endpoint, per-port and packet size statistics
+#[derive(Default)]
+pub struct EndpointSink {
+    stats: EndpointStats,
+}
+
+impl EndpointSink {
+    pub fn into_stats(self) -> EndpointStats {
+        self.stats
+    }
+}
+
+impl PacketSink for EndpointSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let len = packet.raw.len;
+        self.stats.sizes.add(len);
+        let Ok(decoded) = &packet.decoded else {
+            return Ok(());
+        };
+
+        // Wireless frames carry transmitter/receiver addresses instead of Ethernet ones
+        let macs = match (&decoded.link, &decoded.wlan) {
+            (Some(link), _) => Some((link.src_mac, link.dst_mac)),
+            (None, Some(wlan)) => wlan.transmitter.zip(wlan.receiver),
+            (None, None) => None,
+        };
+        if let Some((src, dst)) = macs {
+            add_endpoints(&mut self.stats.macs, src, dst, len);
+        }
+
+        if let Some(ip) = decoded.ip() {
+            add_endpoints(&mut self.stats.ips, ip.src, ip.dst, len);
+            self.stats.matrix.entry((ip.src, ip.dst)).or_default().add(len);
+        }
+
+        let transport = match &decoded.transport {
+            Some(TransportLayer::Tcp(_)) => "TCP",
+            Some(TransportLayer::Udp(_)) => "UDP",
+            _ => return Ok(()),
+        };
+        if let Some(port) = decoded.dst_port() {
+            *self.stats.ports.entry(transport).or_default().entry(port).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+}
+
+fn add_endpoints<K: Eq + Hash>(endpoints: &mut HashMap<K, EndpointCounters>, src: K, dst: K, len: u32) {
+    endpoints.entry(src).or_default().tx.add(len);
+    endpoints.entry(dst).or_default().rx.add(len);
+}
+
+// Busiest endpoints first, ties broken by address so output is stable between runs
+fn sorted_endpoints<K: Ord>(endpoints: &HashMap<K, EndpointCounters>) -> Vec<(&K, &EndpointCounters)> {
+    largest_first(endpoints, |counters| counters.bytes())
+}
+
+fn sorted_matrix(matrix: &HashMap<(IpAddr, IpAddr), TrafficCounters>) -> Vec<(&(IpAddr, IpAddr), &TrafficCounters)> {
+    largest_first(matrix, |traffic| traffic.bytes)
+}
+
+fn write_endpoints<K: Ord + Display>(
+    out: &mut dyn Write,
+    title: &str,
+    endpoints: &HashMap<K, EndpointCounters>,
+    top: usize,
+) -> io::Result<()> {
+    writeln!(out, "\n=== Top Talkers by {} ({} total) ===", title, endpoints.len())?;
+    writeln!(
+        out,
+        "{:<40} {:>9} {:>11} {:>9} {:>11}",
+        "Address", "Pkts tx", "Bytes tx", "Pkts rx", "Bytes rx"
+    )?;
+    for (address, counters) in sorted_endpoints(endpoints).into_iter().take(top) {
+        writeln!(
+            out,
+            "{:<40} {:>9} {:>11} {:>9} {:>11}",
+            address.to_string(),
+            counters.tx.packets,
+            counters.tx.bytes,
+            counters.rx.packets,
+            counters.rx.bytes
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_endpoint_summary(out: &mut dyn Write, stats: &EndpointStats, top: usize) -> io::Result<()> {
+    if !stats.ips.is_empty() {
+        write_endpoints(out, "IP", &stats.ips, top)?;
+    }
+    if !stats.macs.is_empty() {
+        write_endpoints(out, "MAC", &stats.macs, top)?;
+    }
+
+    if !stats.matrix.is_empty() {
+        writeln!(out, "\n=== Conversations by Direction ({} total) ===", stats.matrix.len())?;
+        writeln!(out, "{:<40} {:<40} {:>9} {:>11}", "Source", "Destination", "Packets", "Bytes")?;
+        for ((src, dst), traffic) in sorted_matrix(&stats.matrix).into_iter().take(top) {
+            writeln!(
+                out,
+                "{:<40} {:<40} {:>9} {:>11}",
+                src.to_string(),
+                dst.to_string(),
+                traffic.packets,
+                traffic.bytes
+            )?;
+        }
+    }
+
+    for (transport, ports) in &stats.ports {
+        writeln!(out, "\n=== {} Destination Ports ({} distinct) ===", transport, ports.len())?;
+        for (port, count) in sorted_counts(ports).into_iter().take(top) {
+            writeln!(out, "{:>5}: {}", port, count)?;
+        }
+    }
+
+    let sizes = &stats.sizes;
+    writeln!(out, "\n=== Packet Sizes ===")?;
+    writeln!(
+        out,
+        "min {} bytes, avg {:.1} bytes, max {} bytes",
+        sizes.min,
+        sizes.bytes as f64 / sizes.packets.max(1) as f64,
+        sizes.max
+    )?;
+    for (bucket, count) in sizes.counts.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        writeln!(
+            out,
+            "{:>10}: {:>9} ({:.1}%)",
+            SizeDistribution::label(bucket),
+            count,
+            *count as f64 * 100.0 / sizes.packets as f64
+        )?;
+    }
+    Ok(())
+}
+
+// Save the talker matrix for graphing: Graphviz DOT for a .dot or .gv path, CSV otherwise
+pub fn export_matrix(path: &str, stats: &EndpointStats) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    if path.ends_with(".dot") || path.ends_with(".gv") {
+        write_matrix_dot(&mut out, stats)?;
+    } else {
+        write_matrix_csv(&mut out, stats)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+pub fn write_matrix_csv(out: &mut dyn Write, stats: &EndpointStats) -> io::Result<()> {
+    writeln!(out, "src,dst,packets,bytes")?;
+    for ((src, dst), traffic) in sorted_matrix(&stats.matrix) {
+        writeln!(out, "{},{},{},{}", src, dst, traffic.packets, traffic.bytes)?;
+    }
+    Ok(())
+}
+
+// One edge per direction, drawn thicker the more bytes it carried
+pub fn write_matrix_dot(out: &mut dyn Write, stats: &EndpointStats) -> io::Result<()> {
+    let busiest = stats.matrix.values().map(|traffic| traffic.bytes).max().unwrap_or(0).max(1);
+    writeln!(out, "digraph talkers {{")?;
+    writeln!(out, "  node [shape=box];")?;
+    for ((src, dst), traffic) in sorted_matrix(&stats.matrix) {
+        writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [label=\"{} pkts, {} bytes\", penwidth={:.2}];",
+            src,
+            dst,
+            traffic.packets,
+            traffic.bytes,
+            1.0 + 4.0 * traffic.bytes as f64 / busiest as f64
+        )?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use crate::listening::ParsedPacket;
+use crate::packet::DecodedPacket;
+use crate::sink::PacketSink;
+use crate::source::InterfaceInfo;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    // Single JSON array
+    Json,
+    // One JSON object per line
+    Jsonl,
+    Csv,
+    #[default]
+    Text,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "csv" => Ok(ExportFormat::Csv),
+            "text" => Ok(ExportFormat::Text),
+            other => Err(format!("Unknown output format '{}'", other)),
+        }
+    }
+}
+
+// Flat per-packet record shared by all export formats
+#[derive(Debug, Clone, Serialize)]
+pub struct PacketRecord {
+    pub number: u32,
+    pub interface: String,
+    pub timestamp: f64,
+    pub length: u32,
+    pub caplen: u32,
+    pub protocol: String,
+    pub src_addr: Option<IpAddr>,
+    pub src_port: Option<u16>,
+    pub dst_addr: Option<IpAddr>,
+    pub dst_port: Option<u16>,
+    pub tcp_flags: Option<String>,
+    pub vlan: Vec<u16>,
+}
+
+const CSV_HEADER: &str =
+    "number,interface,timestamp,length,caplen,protocol,src_addr,src_port,dst_addr,dst_port,tcp_flags,vlan";
+
+impl PacketRecord {
+    pub fn from_decoded(packet: &DecodedPacket, interface: &str) -> Self {
+        PacketRecord {
+            number: packet.number,
+            interface: interface.to_string(),
+            timestamp: packet.timestamp(),
+            length: packet.len,
+            caplen: packet.caplen,
+            protocol: packet.protocol_name(),
+            src_addr: packet.src_addr(),
+            src_port: packet.src_port(),
+            dst_addr: packet.dst_addr(),
+            dst_port: packet.dst_port(),
+            tcp_flags: packet.tcp().map(|tcp| tcp.flags.to_string()),
+            vlan: packet
+                .link
+                .as_ref()
+                .map(|link| link.vlan_tags.iter().map(|tag| tag.id).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    // Record for a packet etherparse could not slice
+    pub fn from_parsed(packet: &ParsedPacket, interface: &str) -> Self {
+        match &packet.decoded {
+            Ok(decoded) => PacketRecord::from_decoded(decoded, interface),
+            Err(_) => PacketRecord {
+                number: packet.number,
+                interface: interface.to_string(),
+                timestamp: packet.raw.ts_sec as f64 + packet.raw.ts_usec as f64 / 1_000_000.0,
+                length: packet.raw.len,
+                caplen: packet.raw.caplen,
+                protocol: "Malformed".to_string(),
+                src_addr: None,
+                src_port: None,
+                dst_addr: None,
+                dst_port: None,
+                tcp_flags: None,
+                vlan: Vec::new(),
+            },
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        let vlan = self.vlan.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(";");
+        [
+            self.number.to_string(),
+            csv_escape(&self.interface),
+            format!("{:.6}", self.timestamp),
+            self.length.to_string(),
+            self.caplen.to_string(),
+            csv_escape(&self.protocol),
+            opt(self.src_addr.map(|a| a.to_string())),
+            opt(self.src_port.map(|p| p.to_string())),
+            opt(self.dst_addr.map(|a| a.to_string())),
+            opt(self.dst_port.map(|p| p.to_string())),
+            csv_escape(self.tcp_flags.as_deref().unwrap_or("")),
+            vlan,
+        ]
+        .join(",")
+    }
+
+    fn to_text(&self) -> String {
+        let endpoint = |addr: Option<IpAddr>, port: Option<u16>| match (addr, port) {
+            (Some(a), Some(p)) => format!("{}:{}", a, p),
+            (Some(a), None) => a.to_string(),
+            _ => "N/A".to_string(),
+        };
+        let mut line = format!(
+            "{:.6} #{} {} {} -> {} len={}",
+            self.timestamp,
+            self.number,
+            self.protocol,
+            endpoint(self.src_addr, self.src_port),
+            endpoint(self.dst_addr, self.dst_port),
+            self.length
+        );
+        if let Some(flags) = &self.tcp_flags {
+            line.push_str(&format!(" [{}]", flags));
+        }
+        if !self.vlan.is_empty() {
+            let vlan = self.vlan.iter().map(|id| id.to_string()).collect::<Vec<_>>().join("/");
+            line.push_str(&format!(" vlan={}", vlan));
+        }
+        line
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Writes one record per packet to stdout or a file
+pub struct ExportSink {
+    format: ExportFormat,
+    writer: Box<dyn Write>,
+    records_written: u64,
+    interface_names: Vec<String>,
+}
+
+impl ExportSink {
+    pub fn new(format: ExportFormat, path: Option<&str>, interfaces: &[InterfaceInfo]) -> Result<Self, Box<dyn Error>> {
+        let writer: Box<dyn Write> = match path {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(io::stdout())),
+        };
+        Ok(ExportSink::with_writer(format, writer, interfaces)?)
+    }
+
+    fn with_writer(format: ExportFormat, writer: Box<dyn Write>, interfaces: &[InterfaceInfo]) -> io::Result<Self> {
+        let mut sink = ExportSink {
+            format,
+            writer,
+            records_written: 0,
+            interface_names: interfaces.iter().map(|info| info.name.clone()).collect(),
+        };
+        match format {
+            ExportFormat::Json => writeln!(sink.writer, "[")?,
+            ExportFormat::Csv => writeln!(sink.writer, "{}", CSV_HEADER)?,
+            _ => {}
+        }
+        Ok(sink)
+    }
+}
+
+impl PacketSink for ExportSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let interface = self
+            .interface_names
+            .get(packet.raw.interface as usize)
+            .map_or("", |name| name.as_str());
+        let record = PacketRecord::from_parsed(packet, interface);
+        match self.format {
+            ExportFormat::Json => {
+                if self.records_written > 0 {
+                    writeln!(self.writer, ",")?;
+                }
+                write!(self.writer, "  {}", serde_json::to_string(&record)?)?;
+            }
+            ExportFormat::Jsonl => writeln!(self.writer, "{}", serde_json::to_string(&record)?)?,
+            ExportFormat::Csv => writeln!(self.writer, "{}", record.to_csv())?,
+            ExportFormat::Text => writeln!(self.writer, "{}", record.to_text())?,
+        }
+        self.records_written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.format == ExportFormat::Json {
+            if self.records_written > 0 {
+                writeln!(self.writer)?;
+            }
+            writeln!(self.writer, "]")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listening::parse_packet_with_etherparse;
+    use crate::source::RawPacket;
+    use etherparse::PacketBuilder;
+    use pcap::Linktype;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Writer whose bytes stay readable after the sink has taken it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Ethernet + IPv4 + TCP SYN from 10.0.0.1:40000 to 10.0.0.2:80
+    fn syn(ts_sec: i64) -> RawPacket {
+        let builder = PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4([10, 0, 0, 1], [10, 0, 0, 2], 64)
+            .tcp(40_000, 80, 1, 65_535)
+            .syn();
+        let mut frame = Vec::new();
+        builder.write(&mut frame, &[]).unwrap();
+        RawPacket::new(ts_sec, 250_000, frame)
+    }
+
+    // Ethernet header announcing IPv4, followed by only part of an IPv4 header
+    fn truncated(ts_sec: i64) -> RawPacket {
+        let mut frame = vec![2, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 1, 0x08, 0x00];
+        frame.extend_from_slice(&[0x45, 0x00, 0x00]);
+        RawPacket::new(ts_sec, 0, frame)
+    }
+
+    // Run `packets` through an ExportSink and return everything it wrote
+    fn export(format: ExportFormat, interface: &str, packets: &[RawPacket]) -> String {
+        let buffer = SharedBuffer::default();
+        let interfaces = [InterfaceInfo {
+            name: interface.to_string(),
+            description: None,
+            linktype: Linktype::ETHERNET,
+            snaplen: 0,
+        }];
+        let mut sink = ExportSink::with_writer(format, Box::new(buffer.clone()), &interfaces).unwrap();
+        for (index, raw) in packets.iter().enumerate() {
+            let number = index as u32 + 1;
+            let parsed = ParsedPacket {
+                number,
+                raw,
+                decoded: parse_packet_with_etherparse(number, raw, Linktype::ETHERNET),
+                data: &raw.data,
+            };
+            sink.on_packet(&parsed).unwrap();
+        }
+        sink.finish().unwrap();
+        let bytes = buffer.0.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_escape("eth0"), "eth0");
+        assert_eq!(csv_escape(""), "");
+        assert_eq!(csv_escape("SYN,ACK"), "\"SYN,ACK\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn json_output_is_always_one_array() {
+        assert_eq!(export(ExportFormat::Json, "eth0", &[]), "[\n]\n");
+
+        let one = export(ExportFormat::Json, "eth0", &[syn(1)]);
+        assert!(one.starts_with("[\n  {") && one.ends_with("}\n]\n"), "{}", one);
+        let records: Vec<serde_json::Value> = serde_json::from_str(&one).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["protocol"], "TCP");
+        assert_eq!(records[0]["src_addr"], "10.0.0.1");
+        assert_eq!(records[0]["dst_port"], 80);
+        assert_eq!(records[0]["tcp_flags"], "SYN");
+        assert_eq!(records[0]["timestamp"], 1.25);
+
+        let many = export(ExportFormat::Json, "eth0", &[syn(1), syn(2), syn(3)]);
+        assert_eq!(many.matches("},\n  {").count(), 2);
+        let records: Vec<serde_json::Value> = serde_json::from_str(&many).unwrap();
+        let numbers: Vec<_> = records.iter().map(|record| record["number"].as_u64().unwrap()).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+    }
+
+    #[test]
+    fn jsonl_writes_one_object_per_line() {
+        assert_eq!(export(ExportFormat::Jsonl, "eth0", &[]), "");
+
+        let output = export(ExportFormat::Jsonl, "eth0", &[syn(1), syn(2)]);
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for (line, number) in lines.iter().zip(1..) {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(record["number"], number);
+            assert_eq!(record["interface"], "eth0");
+        }
+    }
+
+    #[test]
+    fn csv_rows_follow_the_header() {
+        let output = export(ExportFormat::Csv, "lan, upstairs", &[syn(1)]);
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "1,\"lan, upstairs\",1.250000,54,54,TCP,10.0.0.1,40000,10.0.0.2,80,SYN,");
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn undecodable_packets_are_exported_as_malformed() {
+        let output = export(ExportFormat::Jsonl, "eth0", &[truncated(7)]);
+        let record: serde_json::Value = serde_json::from_str(output.trim_end()).unwrap();
+        assert_eq!(record["protocol"], "Malformed");
+        assert_eq!(record["length"], 17);
+        assert_eq!(record["timestamp"], 7.0);
+        assert!(record["src_addr"].is_null() && record["dst_port"].is_null());
+
+        let text = export(ExportFormat::Text, "eth0", &[truncated(7)]);
+        assert_eq!(text, "7.000000 #1 Malformed N/A -> N/A len=17\n");
+    }
+}
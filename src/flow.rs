@@ -0,0 +1,690 @@
+use crate::listening::ParsedPacket;
+use crate::packet::{DecodedPacket, TransportLayer};
+use crate::quic::ConnectionId;
+use crate::sink::PacketSink;
+use serde::Serialize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+pub const DEFAULT_IDLE_TIMEOUT: f64 = 60.0;
+pub const DEFAULT_ACTIVE_TIMEOUT: f64 = 1800.0;
+// Expired flows kept for the summary; smaller ones beyond this are only counted
+pub const MAX_FINISHED_FLOWS: usize = 10_000;
+
+// Direction-independent 5-tuple: the lower endpoint always comes first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub protocol: u8,
+    pub lower: (IpAddr, u16),
+    pub upper: (IpAddr, u16),
+}
+
+impl FlowKey {
+    pub fn new(protocol: u8, a: (IpAddr, u16), b: (IpAddr, u16)) -> Self {
+        let (lower, upper) = if a <= b { (a, b) } else { (b, a) };
+        FlowKey { protocol, lower, upper }
+    }
+
+    // Key for a decoded packet, None for non-IP traffic
+    pub fn from_packet(packet: &DecodedPacket) -> Option<Self> {
+        let ip = packet.ip()?;
+        let src_port = packet.src_port().unwrap_or(0);
+        let dst_port = packet.dst_port().unwrap_or(0);
+        Some(FlowKey::new(ip.protocol, (ip.src, src_port), (ip.dst, dst_port)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TcpState {
+    pub syn: bool,
+    pub syn_ack: bool,
+    pub fin_fwd: bool,
+    pub fin_rev: bool,
+    pub rst: bool,
+}
+
+impl TcpState {
+    pub fn label(&self) -> &'static str {
+        if self.rst {
+            "RESET"
+        } else if self.fin_fwd && self.fin_rev {
+            "CLOSED"
+        } else if self.fin_fwd || self.fin_rev {
+            "CLOSING"
+        } else if self.syn && self.syn_ack {
+            "ESTABLISHED"
+        } else if self.syn {
+            "SYN_SENT"
+        } else {
+            "MIDSTREAM"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FlowEnd {
+    IdleTimeout,
+    ActiveTimeout,
+    EndOfCapture,
+}
+
+// One conversation; `src` is the endpoint that initiated it
+#[derive(Debug, Clone, Serialize)]
+pub struct Flow {
+    pub protocol: u8,
+    pub src: IpAddr,
+    pub src_port: u16,
+    pub dst: IpAddr,
+    pub dst_port: u16,
+    pub first_seen: f64,
+    pub last_seen: f64,
+    pub fwd_packets: u64,
+    pub fwd_bytes: u64,
+    pub rev_packets: u64,
+    pub rev_bytes: u64,
+    pub tcp: Option<TcpState>,
+    // Times a QUIC connection moved to a new address pair
+    pub migrations: u32,
+    pub end: Option<FlowEnd>,
+}
+
+impl Flow {
+    // Order by total bytes; of two the same size, the earlier one counts as larger
+    pub fn cmp_size(&self, other: &Flow) -> Ordering {
+        self.bytes().cmp(&other.bytes()).then(other.first_seen.total_cmp(&self.first_seen))
+    }
+
+    fn start(packet: &DecodedPacket, protocol: u8, src: (IpAddr, u16), dst: (IpAddr, u16)) -> Self {
+        // A SYN-ACK seen first means we missed the SYN; the sender is the responder
+        let responder_first = packet.tcp().is_some_and(|tcp| tcp.flags.syn && tcp.flags.ack);
+        let (src, dst) = if responder_first { (dst, src) } else { (src, dst) };
+        Flow {
+            protocol,
+            src: src.0,
+            src_port: src.1,
+            dst: dst.0,
+            dst_port: dst.1,
+            first_seen: packet.timestamp(),
+            last_seen: packet.timestamp(),
+            fwd_packets: 0,
+            fwd_bytes: 0,
+            rev_packets: 0,
+            rev_bytes: 0,
+            tcp: packet.tcp().map(|_| TcpState::default()),
+            migrations: 0,
+            end: None,
+        }
+    }
+
+    fn update(&mut self, packet: &DecodedPacket, src: (IpAddr, u16)) {
+        let forward = src == (self.src, self.src_port);
+        if forward {
+            self.fwd_packets += 1;
+            self.fwd_bytes += packet.len as u64;
+        } else {
+            self.rev_packets += 1;
+            self.rev_bytes += packet.len as u64;
+        }
+        self.last_seen = self.last_seen.max(packet.timestamp());
+
+        if let (Some(state), Some(TransportLayer::Tcp(tcp))) = (&mut self.tcp, &packet.transport) {
+            if tcp.flags.syn && !tcp.flags.ack {
+                state.syn = true;
+            }
+            if tcp.flags.syn && tcp.flags.ack {
+                state.syn_ack = true;
+            }
+            if tcp.flags.fin {
+                if forward {
+                    state.fin_fwd = true;
+                } else {
+                    state.fin_rev = true;
+                }
+            }
+            if tcp.flags.rst {
+                state.rst = true;
+            }
+        }
+    }
+
+    // Move the flow to the addresses of a packet from the same QUIC connection.
+    // Whichever endpoint the packet still shares stays put.
+    fn migrate(&mut self, src: (IpAddr, u16), dst: (IpAddr, u16)) {
+        let client = (self.src, self.src_port);
+        let server = (self.dst, self.dst_port);
+        let (client, server) = if dst == server {
+            (src, server)
+        } else if src == server {
+            (dst, server)
+        } else if src == client {
+            (client, dst)
+        } else if dst == client {
+            (client, src)
+        } else {
+            (src, dst)
+        };
+        (self.src, self.src_port) = client;
+        (self.dst, self.dst_port) = server;
+        self.migrations += 1;
+    }
+
+    pub fn packets(&self) -> u64 {
+        self.fwd_packets + self.rev_packets
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.fwd_bytes + self.rev_bytes
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.last_seen - self.first_seen
+    }
+
+    // TCP connection state, or whether a QUIC connection migrated
+    pub fn state(&self) -> String {
+        match self.tcp {
+            Some(tcp) => tcp.label().to_string(),
+            None if self.migrations > 0 => format!("MIGRATED x{}", self.migrations),
+            None => "-".to_string(),
+        }
+    }
+}
+
+// Finished flows that were not kept, summed up
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FlowTotals {
+    pub flows: u64,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl FlowTotals {
+    fn add(&mut self, flow: &Flow) {
+        self.flows += 1;
+        self.packets += flow.packets();
+        self.bytes += flow.bytes();
+    }
+}
+
+// Orders flows by size: more bytes first, then the earlier one
+struct BySize(Flow);
+
+impl Ord for BySize {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp_size(&other.0)
+    }
+}
+
+impl PartialOrd for BySize {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for BySize {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BySize {}
+
+// Active conversations keyed by normalized 5-tuple, plus the largest of the ones
+// already expired (the rest are folded into `omitted`).
+// QUIC flows are also indexed by connection ID so they survive address changes.
+// Timeouts are evaluated against packet timestamps so offline runs are deterministic.
+pub struct FlowTable {
+    active: HashMap<FlowKey, Flow>,
+    // Min-heap, so the smallest kept flow is the one to give up
+    finished: BinaryHeap<Reverse<BySize>>,
+    omitted: FlowTotals,
+    connection_ids: HashMap<ConnectionId, FlowKey>,
+    // Lengths of the connection IDs seen, to look up short headers which omit it
+    cid_lengths: Vec<usize>,
+    idle_timeout: f64,
+    active_timeout: f64,
+    last_sweep: f64,
+}
+
+impl FlowTable {
+    pub fn new(idle_timeout: f64, active_timeout: f64) -> Self {
+        FlowTable {
+            active: HashMap::new(),
+            finished: BinaryHeap::new(),
+            omitted: FlowTotals::default(),
+            connection_ids: HashMap::new(),
+            cid_lengths: Vec::new(),
+            idle_timeout,
+            active_timeout,
+            last_sweep: 0.0,
+        }
+    }
+
+    // Account the packet to its flow, returning the flow key for IP traffic.
+    // `data` is the buffer the packet was decoded from.
+    pub fn update(&mut self, packet: &DecodedPacket, data: &[u8]) -> Option<FlowKey> {
+        let ip = packet.ip()?;
+        let src = (ip.src, packet.src_port().unwrap_or(0));
+        let dst = (ip.dst, packet.dst_port().unwrap_or(0));
+        let key = FlowKey::new(ip.protocol, src, dst);
+        let now = packet.timestamp();
+
+        // Sweep at most once per second of capture time
+        if now - self.last_sweep >= 1.0 {
+            self.expire(now);
+            self.last_sweep = now;
+        }
+
+        // A QUIC connection showing up on a new address pair takes its flow along
+        if !self.active.contains_key(&key) {
+            if let Some(old) = self.quic_flow(packet, data).filter(|old| *old != key) {
+                if let Some(mut flow) = self.active.remove(&old) {
+                    flow.migrate(src, dst);
+                    self.active.insert(key, flow);
+                    for flow_key in self.connection_ids.values_mut() {
+                        if *flow_key == old {
+                            *flow_key = key;
+                        }
+                    }
+                }
+            }
+        }
+
+        let flow = self
+            .active
+            .entry(key)
+            .or_insert_with(|| Flow::start(packet, ip.protocol, src, dst));
+        flow.update(packet, src);
+
+        if let Some(quic) = packet.quic() {
+            for cid in [&quic.dcid, &quic.scid] {
+                if cid.is_empty() {
+                    continue;
+                }
+                if !self.cid_lengths.contains(&cid.0.len()) {
+                    self.cid_lengths.push(cid.0.len());
+                }
+                self.connection_ids.insert(cid.clone(), key);
+            }
+        }
+        Some(key)
+    }
+
+    // Flow of a known QUIC connection: from the decoded long header, or for short
+    // headers, from a destination connection ID of any length seen so far
+    fn quic_flow(&self, packet: &DecodedPacket, data: &[u8]) -> Option<FlowKey> {
+        if let Some(quic) = packet.quic() {
+            return [&quic.dcid, &quic.scid]
+                .into_iter()
+                .find_map(|cid| self.connection_ids.get(cid).copied());
+        }
+        packet.udp()?;
+        let payload = packet.payload(data);
+        if payload.first()? & 0xc0 != 0x40 {
+            return None;
+        }
+        self.cid_lengths.iter().find_map(|len| {
+            let cid = ConnectionId(payload.get(1..1 + len)?.to_vec());
+            self.connection_ids.get(&cid).copied()
+        })
+    }
+
+    fn expire(&mut self, now: f64) {
+        let (idle_timeout, active_timeout) = (self.idle_timeout, self.active_timeout);
+        let mut expired = Vec::new();
+        self.active.retain(|_, flow| {
+            let end = if now - flow.last_seen > idle_timeout {
+                Some(FlowEnd::IdleTimeout)
+            } else if now - flow.first_seen > active_timeout {
+                Some(FlowEnd::ActiveTimeout)
+            } else {
+                None
+            };
+            match end {
+                Some(end) => {
+                    let mut flow = flow.clone();
+                    flow.end = Some(end);
+                    expired.push(flow);
+                    false
+                }
+                None => true,
+            }
+        });
+        for flow in expired {
+            self.retire(flow);
+        }
+        let active = &self.active;
+        self.connection_ids.retain(|_, key| active.contains_key(key));
+    }
+
+    fn retire(&mut self, flow: Flow) {
+        self.finished.push(Reverse(BySize(flow)));
+        if self.finished.len() > MAX_FINISHED_FLOWS {
+            if let Some(Reverse(BySize(smallest))) = self.finished.pop() {
+                self.omitted.add(&smallest);
+            }
+        }
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn get(&self, key: &FlowKey) -> Option<&Flow> {
+        self.active.get(key)
+    }
+
+    pub fn active_flows(&self) -> impl Iterator<Item = &Flow> {
+        self.active.values()
+    }
+
+    // Flows expired so far, which the table then forgets
+    pub fn take_finished(&mut self) -> Vec<Flow> {
+        self.omitted = FlowTotals::default();
+        std::mem::take(&mut self.finished)
+            .into_iter()
+            .map(|Reverse(BySize(flow))| flow)
+            .collect()
+    }
+
+    // Close every remaining flow and return the kept flows, largest first, with
+    // totals for the ones that did not fit
+    pub fn into_flows(mut self) -> (Vec<Flow>, FlowTotals) {
+        let remaining: Vec<Flow> = self.active.drain().map(|(_, flow)| flow).collect();
+        for mut flow in remaining {
+            flow.end = Some(FlowEnd::EndOfCapture);
+            self.retire(flow);
+        }
+        let flows = self
+            .finished
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(BySize(flow))| flow)
+            .collect();
+        (flows, self.omitted)
+    }
+}
+
+pub struct FlowSink {
+    table: FlowTable,
+}
+
+impl FlowSink {
+    pub fn new(idle_timeout: f64, active_timeout: f64) -> Self {
+        FlowSink {
+            table: FlowTable::new(idle_timeout, active_timeout),
+        }
+    }
+
+    pub fn into_flows(self) -> (Vec<Flow>, FlowTotals) {
+        self.table.into_flows()
+    }
+}
+
+impl PacketSink for FlowSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        if let Ok(decoded) = &packet.decoded {
+            self.table.update(decoded, packet.data);
+        }
+        Ok(())
+    }
+}
+
+pub fn ip_protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "ICMP".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        58 => "ICMPv6".to_string(),
+        other => format!("IP({})", other),
+    }
+}
+
+pub(crate) fn endpoint(addr: IpAddr, port: u16) -> String {
+    match addr {
+        IpAddr::V4(a) => format!("{}:{}", a, port),
+        IpAddr::V6(a) => format!("[{}]:{}", a, port),
+    }
+}
+
+// Print the `top` largest conversations by total bytes
+pub fn write_flow_summary(out: &mut dyn Write, flows: &[Flow], omitted: &FlowTotals, top: usize) -> io::Result<()> {
+    writeln!(out, "\n=== Top Flows ({} total) ===", flows.len() as u64 + omitted.flows)?;
+    writeln!(
+        out,
+        "{:<6} {:<47} {:<47} {:>9} {:>11} {:>9} {:>11} {:>9} State",
+        "Proto", "Source", "Destination", "Pkts ->", "Bytes ->", "Pkts <-", "Bytes <-", "Duration"
+    )?;
+    for flow in flows.iter().take(top) {
+        writeln!(
+            out,
+            "{:<6} {:<47} {:<47} {:>9} {:>11} {:>9} {:>11} {:>8.3}s {}",
+            ip_protocol_name(flow.protocol),
+            endpoint(flow.src, flow.src_port),
+            endpoint(flow.dst, flow.dst_port),
+            flow.fwd_packets,
+            flow.fwd_bytes,
+            flow.rev_packets,
+            flow.rev_bytes,
+            flow.duration(),
+            flow.state()
+        )?;
+    }
+    if omitted.flows > 0 {
+        writeln!(
+            out,
+            "({} smaller flows not kept: {} packets, {} bytes)",
+            omitted.flows, omitted.packets, omitted.bytes
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{ApplicationLayer, IpLayer, NetworkLayer, PayloadRange, TcpFlags, TcpInfo, UdpInfo};
+    use crate::quic::{PacketType, QuicInfo};
+
+    fn addr(endpoint: &str) -> (IpAddr, u16) {
+        let (ip, port) = endpoint.rsplit_once(':').unwrap();
+        (ip.parse().unwrap(), port.parse().unwrap())
+    }
+
+    fn ip_packet(ts: i64, src: &str, dst: &str, len: u32, transport: TransportLayer) -> DecodedPacket {
+        let protocol = if matches!(transport, TransportLayer::Tcp(_)) { 6 } else { 17 };
+        DecodedPacket {
+            number: 1,
+            interface: 0,
+            ts_sec: ts,
+            ts_usec: 0,
+            caplen: len,
+            len,
+            link: None,
+            network: Some(NetworkLayer::Ipv4(IpLayer {
+                src: addr(src).0,
+                dst: addr(dst).0,
+                ttl: 64,
+                ip_id: None,
+                protocol,
+                fragment: None,
+            })),
+            transport: Some(transport),
+            application: None,
+            payload: None,
+            reassembled: None,
+            encapsulation: Vec::new(),
+            wlan: None,
+        }
+    }
+
+    // `flags` holds the letters of the flags set, e.g. "SA" for a SYN-ACK
+    fn tcp(ts: i64, src: &str, dst: &str, flags: &str) -> DecodedPacket {
+        let info = TcpInfo {
+            src_port: addr(src).1,
+            dst_port: addr(dst).1,
+            flags: TcpFlags {
+                syn: flags.contains('S'),
+                ack: flags.contains('A'),
+                fin: flags.contains('F'),
+                rst: flags.contains('R'),
+                ..Default::default()
+            },
+            seq: 0,
+            ack: 0,
+            window: 65535,
+        };
+        ip_packet(ts, src, dst, 60, TransportLayer::Tcp(info))
+    }
+
+    fn udp(ts: i64, src: &str, dst: &str, len: u32) -> DecodedPacket {
+        let info = UdpInfo {
+            src_port: addr(src).1,
+            dst_port: addr(dst).1,
+            length: 0,
+            checksum: 0,
+        };
+        ip_packet(ts, src, dst, len, TransportLayer::Udp(info))
+    }
+
+    fn flow_for<'a>(table: &'a FlowTable, a: &str, b: &str, protocol: u8) -> &'a Flow {
+        table.get(&FlowKey::new(protocol, addr(a), addr(b))).unwrap()
+    }
+
+    #[test]
+    fn idle_and_active_flows_expire() {
+        let mut table = FlowTable::new(10.0, 30.0);
+        table.update(&udp(100, "10.0.0.2:5000", "10.0.0.1:53", 80), &[]);
+        for ts in (100..=135).step_by(5) {
+            table.update(&udp(ts, "10.0.0.2:6000", "10.0.0.1:123", 90), &[]);
+        }
+
+        // The DNS flow went quiet at 100 and was swept at 115; the NTP one had run for 35s at 135
+        let finished = table.take_finished();
+        let ends: Vec<(u16, Option<FlowEnd>)> = finished.iter().map(|flow| (flow.src_port, flow.end)).collect();
+        assert!(ends.contains(&(5000, Some(FlowEnd::IdleTimeout))));
+        assert!(ends.contains(&(6000, Some(FlowEnd::ActiveTimeout))));
+        assert_eq!(finished.len(), 2);
+        let expired = finished.iter().find(|flow| flow.src_port == 6000).unwrap();
+        assert_eq!((expired.first_seen, expired.packets()), (100.0, 7));
+
+        // The packet that triggered the sweep starts the flow over
+        assert_eq!(table.active_count(), 1);
+        let restarted = flow_for(&table, "10.0.0.2:6000", "10.0.0.1:123", 17);
+        assert_eq!((restarted.first_seen, restarted.packets()), (135.0, 1));
+        assert!(table.take_finished().is_empty());
+
+        let (flows, omitted) = table.into_flows();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].end, Some(FlowEnd::EndOfCapture));
+        assert_eq!(omitted.flows, 0);
+    }
+
+    #[test]
+    fn only_the_largest_finished_flows_are_kept() {
+        let extra = 5;
+        let mut table = FlowTable::new(DEFAULT_IDLE_TIMEOUT, DEFAULT_ACTIVE_TIMEOUT);
+        for i in 0..(MAX_FINISHED_FLOWS + extra) as u32 {
+            let src = format!("10.{}.{}.{}:1000", i >> 16, (i >> 8) & 0xff, i & 0xff);
+            // The first few are the smallest; the rest are all the same size
+            let len = if i < extra as u32 { 50 + i } else { 200 };
+            table.update(&udp(100, &src, "10.255.0.1:9", len), &[]);
+        }
+        // One more of the big ones, which ties on bytes but started last
+        table.update(&udp(101, "10.254.0.1:1000", "10.255.0.1:9", 200), &[]);
+        let (flows, omitted) = table.into_flows();
+        assert_eq!(flows.len(), MAX_FINISHED_FLOWS);
+        assert!(flows.iter().all(|flow| flow.bytes() == 200 && flow.first_seen == 100.0));
+        assert_eq!(omitted.flows, extra as u64 + 1);
+        assert_eq!(omitted.packets, extra as u64 + 1);
+        assert_eq!(omitted.bytes, (50..55).sum::<u64>() + 200);
+
+        let mut out = Vec::new();
+        write_flow_summary(&mut out, &flows, &omitted, 1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains(&format!("=== Top Flows ({} total) ===", MAX_FINISHED_FLOWS + extra + 1)));
+        assert!(text.contains("(6 smaller flows not kept: 6 packets, 460 bytes)"));
+    }
+
+    #[test]
+    fn a_syn_ack_seen_first_points_the_flow_at_the_server() {
+        let mut table = FlowTable::new(DEFAULT_IDLE_TIMEOUT, DEFAULT_ACTIVE_TIMEOUT);
+        table.update(&tcp(100, "93.184.216.34:80", "10.0.0.2:40000", "SA"), &[]);
+        table.update(&tcp(100, "10.0.0.2:40000", "93.184.216.34:80", "A"), &[]);
+        let flow = flow_for(&table, "10.0.0.2:40000", "93.184.216.34:80", 6);
+        assert_eq!((flow.src, flow.src_port), addr("10.0.0.2:40000"));
+        assert_eq!((flow.dst, flow.dst_port), addr("93.184.216.34:80"));
+        assert_eq!((flow.fwd_packets, flow.rev_packets), (1, 1));
+        assert_eq!(flow.state(), "MIDSTREAM");
+    }
+
+    #[test]
+    fn tcp_state_follows_the_handshake_and_teardown() {
+        let mut table = FlowTable::new(DEFAULT_IDLE_TIMEOUT, DEFAULT_ACTIVE_TIMEOUT);
+        let (client, server) = ("10.0.0.2:40000", "10.0.0.1:22");
+        let state = |table: &FlowTable| flow_for(table, client, server, 6).state();
+
+        table.update(&tcp(100, client, server, "S"), &[]);
+        assert_eq!(state(&table), "SYN_SENT");
+        table.update(&tcp(100, server, client, "SA"), &[]);
+        assert_eq!(state(&table), "ESTABLISHED");
+        table.update(&tcp(101, server, client, "FA"), &[]);
+        assert_eq!(state(&table), "CLOSING");
+        let tcp_state = flow_for(&table, client, server, 6).tcp.unwrap();
+        assert!(tcp_state.fin_rev && !tcp_state.fin_fwd);
+        table.update(&tcp(101, client, server, "FA"), &[]);
+        assert_eq!(state(&table), "CLOSED");
+        table.update(&tcp(101, client, server, "R"), &[]);
+        assert_eq!(state(&table), "RESET");
+
+        table.update(&udp(100, "10.0.0.2:5000", "10.0.0.1:53", 80), &[]);
+        assert_eq!(flow_for(&table, "10.0.0.2:5000", "10.0.0.1:53", 17).state(), "-");
+    }
+
+    #[test]
+    fn quic_flows_follow_their_connection_id() {
+        let (client, server, moved) = ("10.0.0.2:50000", "1.1.1.1:443", "192.168.7.9:61000");
+        let server_cid = ConnectionId(vec![0x5e; 8]);
+        let mut initial = udp(100, client, server, 1200);
+        initial.application = Some(ApplicationLayer::Quic(Box::new(QuicInfo {
+            version: 1,
+            packet_type: PacketType::Initial,
+            dcid: server_cid.clone(),
+            scid: ConnectionId(vec![0xc1; 4]),
+            supported_versions: Vec::new(),
+            coalesced: Vec::new(),
+            packet_number: None,
+            frames: Vec::new(),
+            crypto: Vec::new(),
+            client_hello: None,
+        })));
+
+        let mut table = FlowTable::new(DEFAULT_IDLE_TIMEOUT, DEFAULT_ACTIVE_TIMEOUT);
+        table.update(&initial, &[]);
+        table.update(&udp(100, server, client, 1200), &[]);
+
+        // After a NAT rebinding the client sends a short-header packet from a new address
+        let short_header: Vec<u8> = [&[0x41][..], &server_cid.0, &[0xaa; 20]].concat();
+        let mut rebound = udp(101, moved, server, short_header.len() as u32);
+        rebound.payload = Some(PayloadRange { offset: 0, len: short_header.len() });
+        table.update(&rebound, &short_header);
+        table.update(&udp(101, server, moved, 100), &[]);
+
+        assert_eq!(table.active_count(), 1);
+        let flow = flow_for(&table, moved, server, 17);
+        assert_eq!((flow.src, flow.src_port), addr(moved));
+        assert_eq!((flow.dst, flow.dst_port), addr(server));
+        assert_eq!(flow.packets(), 4);
+        assert_eq!(flow.state(), "MIGRATED x1");
+
+        // An unrelated short header on another address pair starts its own flow
+        let stranger: Vec<u8> = [&[0x41][..], &[0x77; 8], &[0xaa; 20]].concat();
+        let mut other = udp(102, "10.0.0.9:50000", server, stranger.len() as u32);
+        other.payload = Some(PayloadRange { offset: 0, len: stranger.len() });
+        table.update(&other, &stranger);
+        assert_eq!(table.active_count(), 2);
+    }
+}
@@ -0,0 +1,659 @@
+use crate::packet::{DecodedPacket, TransportLayer};
+use crate::reassembly::{CloseReason, Direction, StreamConsumer, StreamInfo};
+use crate::report::sorted_counts;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+
+const METHODS: [&str; 9] = ["GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS", "PATCH", "CONNECT", "TRACE"];
+
+// Larger header blocks are assumed not to be HTTP
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+// How many of the slowest transactions the summary keeps
+const SLOWEST_KEPT: usize = 10;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum HttpStartLine {
+    Request { method: String, path: String, version: String },
+    Response { version: String, status: u16, reason: String },
+}
+
+// Start line plus the headers we report on
+#[derive(Debug, Clone, Serialize)]
+pub struct HttpMessage {
+    pub start: HttpStartLine,
+    pub host: Option<String>,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    pub chunked: bool,
+    // Connection: close, or HTTP/1.0 without keep-alive
+    pub close: bool,
+}
+
+impl HttpMessage {
+    // Parse a complete header block; returns the message and the header length
+    pub fn parse_head(data: &[u8]) -> Option<(Self, usize)> {
+        let end = find(data, b"\r\n\r\n")? + 4;
+        let head = std::str::from_utf8(&data[..end]).ok()?;
+        let mut lines = head.split("\r\n");
+        let start = parse_start_line(lines.next()?)?;
+
+        let mut message = HttpMessage {
+            start,
+            host: None,
+            content_type: None,
+            content_length: None,
+            chunked: false,
+            close: false,
+        };
+        let mut keep_alive = false;
+        for line in lines.filter(|line| !line.is_empty()) {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "host" => message.host = Some(value.to_string()),
+                "content-type" => message.content_type = Some(value.to_string()),
+                "content-length" => message.content_length = value.parse().ok(),
+                "transfer-encoding" => message.chunked = value.to_ascii_lowercase().contains("chunked"),
+                "connection" => {
+                    let value = value.to_ascii_lowercase();
+                    message.close = value.contains("close");
+                    keep_alive = value.contains("keep-alive");
+                }
+                _ => {}
+            }
+        }
+        if message.version() == "HTTP/1.0" && !keep_alive {
+            message.close = true;
+        }
+        Some((message, end))
+    }
+
+    fn version(&self) -> &str {
+        match &self.start {
+            HttpStartLine::Request { version, .. } | HttpStartLine::Response { version, .. } => version,
+        }
+    }
+
+    pub fn is_request(&self) -> bool {
+        matches!(self.start, HttpStartLine::Request { .. })
+    }
+
+    // e.g. "GET example.com/index.html" or "200 OK"
+    pub fn summary(&self) -> String {
+        match &self.start {
+            HttpStartLine::Request { method, path, .. } => {
+                format!("{} {}{}", method, self.host.as_deref().unwrap_or(""), path)
+            }
+            HttpStartLine::Response { status, reason, .. } => format!("{} {}", status, reason),
+        }
+    }
+}
+
+fn parse_start_line(line: &str) -> Option<HttpStartLine> {
+    let mut parts = line.splitn(3, ' ');
+    let first = parts.next()?;
+    if first.starts_with("HTTP/1.") {
+        let status = parts.next()?.parse().ok()?;
+        Some(HttpStartLine::Response {
+            version: first.to_string(),
+            status,
+            reason: parts.next().unwrap_or("").to_string(),
+        })
+    } else if METHODS.contains(&first) {
+        let path = parts.next()?.to_string();
+        let version = parts.next()?;
+        if !version.starts_with("HTTP/1.") {
+            return None;
+        }
+        Some(HttpStartLine::Request {
+            method: first.to_string(),
+            path,
+            version: version.to_string(),
+        })
+    } else {
+        None
+    }
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|window| window == needle)
+}
+
+// Whether the stream could start with an HTTP/1.x message, judged on the bytes so far
+fn looks_like_http(data: &[u8]) -> bool {
+    let starts = |token: &[u8]| {
+        let n = data.len().min(token.len());
+        data[..n] == token[..n]
+    };
+    starts(b"HTTP/1.") || METHODS.iter().any(|method| starts(format!("{} ", method).as_bytes()))
+}
+
+// Header-only decode of a single segment, for per-packet display
+pub fn decode(packet: &DecodedPacket, data: &[u8]) -> Option<HttpMessage> {
+    match &packet.transport {
+        Some(TransportLayer::Tcp(_)) => HttpMessage::parse_head(packet.payload(data)).map(|(message, _)| message),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyState {
+    Head,
+    Length(u64),
+    ChunkSize,
+    // Chunk data plus its trailing CRLF
+    ChunkData(u64),
+    Trailers,
+    UntilClose,
+    NotHttp,
+}
+
+// Message framing for one direction of a connection
+struct HttpSide {
+    buffer: Vec<u8>,
+    state: BodyState,
+}
+
+impl HttpSide {
+    fn new() -> Self {
+        HttpSide {
+            buffer: Vec::new(),
+            state: BodyState::Head,
+        }
+    }
+
+    // Consume body bytes from the buffer; returns false when more data is needed
+    fn advance_body(&mut self) -> bool {
+        match self.state {
+            BodyState::Length(remaining) | BodyState::ChunkData(remaining) => {
+                let take = remaining.min(self.buffer.len() as u64);
+                self.buffer.drain(..take as usize);
+                let remaining = remaining - take;
+                self.state = match (self.state, remaining) {
+                    (BodyState::Length(_), 0) => BodyState::Head,
+                    (BodyState::Length(_), n) => BodyState::Length(n),
+                    (_, 0) => BodyState::ChunkSize,
+                    (_, n) => BodyState::ChunkData(n),
+                };
+                remaining == 0
+            }
+            BodyState::ChunkSize => {
+                let Some(line_end) = find(&self.buffer, b"\r\n") else {
+                    return false;
+                };
+                let line = String::from_utf8_lossy(&self.buffer[..line_end]).into_owned();
+                self.buffer.drain(..line_end + 2);
+                let size = line.split(';').next().unwrap_or("").trim();
+                self.state = match u64::from_str_radix(size, 16) {
+                    Ok(0) => BodyState::Trailers,
+                    // Too large to be followed by its CRLF: not a real chunk
+                    Ok(size) => size.checked_add(2).map_or(BodyState::NotHttp, BodyState::ChunkData),
+                    Err(_) => BodyState::NotHttp,
+                };
+                true
+            }
+            BodyState::Trailers => {
+                let Some(line_end) = find(&self.buffer, b"\r\n") else {
+                    return false;
+                };
+                self.buffer.drain(..line_end + 2);
+                if line_end == 0 {
+                    self.state = BodyState::Head;
+                }
+                true
+            }
+            BodyState::UntilClose | BodyState::NotHttp => {
+                self.buffer.clear();
+                false
+            }
+            BodyState::Head => true,
+        }
+    }
+}
+
+struct PendingRequest {
+    sent: f64,
+    method: String,
+    host: Option<String>,
+    path: String,
+}
+
+// A request paired with its response
+#[derive(Debug, Clone, Serialize)]
+pub struct HttpTransaction {
+    pub method: String,
+    pub host: Option<String>,
+    pub path: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    // Seconds from the request to the response header
+    pub latency: f64,
+}
+
+struct HttpConnection {
+    // Indexed by `Direction::index`
+    sides: [HttpSide; 2],
+    requests: VecDeque<PendingRequest>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HttpStats {
+    pub requests: u64,
+    pub responses: u64,
+    pub unmatched_responses: u64,
+    pub unanswered: u64,
+    pub methods: HashMap<String, u64>,
+    pub hosts: HashMap<String, u64>,
+    pub status_codes: HashMap<u16, u64>,
+    // Slowest transactions first
+    pub slowest: Vec<HttpTransaction>,
+}
+
+impl HttpStats {
+    pub fn is_empty(&self) -> bool {
+        self.requests == 0 && self.responses == 0
+    }
+
+    fn record_request(&mut self, message: &HttpMessage) {
+        self.requests += 1;
+        if let HttpStartLine::Request { method, .. } = &message.start {
+            *self.methods.entry(method.clone()).or_insert(0) += 1;
+        }
+        if let Some(host) = &message.host {
+            *self.hosts.entry(host.to_lowercase()).or_insert(0) += 1;
+        }
+    }
+
+    fn record_transaction(&mut self, transaction: HttpTransaction) {
+        let position = self
+            .slowest
+            .iter()
+            .position(|t| t.latency < transaction.latency)
+            .unwrap_or(self.slowest.len());
+        if position < SLOWEST_KEPT {
+            self.slowest.insert(position, transaction);
+            self.slowest.truncate(SLOWEST_KEPT);
+        }
+    }
+}
+
+// Frames HTTP/1.x messages on reassembled TCP streams (including pipelined
+// requests and chunked bodies) and pairs each response with its request
+#[derive(Default)]
+pub struct HttpConsumer {
+    connections: HashMap<u64, HttpConnection>,
+    stats: HttpStats,
+}
+
+impl HttpConsumer {
+    pub fn into_stats(mut self) -> HttpStats {
+        for (_, connection) in self.connections.drain() {
+            self.stats.unanswered += connection.requests.len() as u64;
+        }
+        self.stats
+    }
+}
+
+impl StreamConsumer for HttpConsumer {
+    fn on_data(&mut self, stream: &StreamInfo, direction: Direction, data: &[u8], ts: f64) {
+        let connection = self.connections.entry(stream.id).or_insert_with(|| HttpConnection {
+            sides: [HttpSide::new(), HttpSide::new()],
+            requests: VecDeque::new(),
+        });
+        let side = direction.index();
+        if connection.sides[side].state == BodyState::NotHttp {
+            return;
+        }
+        connection.sides[side].buffer.extend_from_slice(data);
+        process_side(connection, side, ts, &mut self.stats);
+    }
+
+    fn on_gap(&mut self, stream: &StreamInfo, direction: Direction, _missing: u64) {
+        // Message boundaries are lost; only a new message at the next byte can resync
+        if let Some(connection) = self.connections.get_mut(&stream.id) {
+            let side = &mut connection.sides[direction.index()];
+            side.buffer.clear();
+            if side.state != BodyState::NotHttp {
+                side.state = BodyState::Head;
+            }
+        }
+    }
+
+    fn on_close(&mut self, stream: &StreamInfo, _reason: CloseReason) {
+        if let Some(connection) = self.connections.remove(&stream.id) {
+            self.stats.unanswered += connection.requests.len() as u64;
+        }
+    }
+}
+
+// Frame as many complete messages as the side's buffer holds
+fn process_side(connection: &mut HttpConnection, side: usize, now: f64, stats: &mut HttpStats) {
+    loop {
+        let state = connection.sides[side].state;
+        if state != BodyState::Head {
+            if !connection.sides[side].advance_body() {
+                return;
+            }
+            continue;
+        }
+
+        let buffer = &connection.sides[side].buffer;
+        if buffer.is_empty() {
+            return;
+        }
+        if !looks_like_http(buffer) {
+            connection.sides[side].state = BodyState::NotHttp;
+            continue;
+        }
+        let Some((message, head_len)) = HttpMessage::parse_head(buffer) else {
+            if buffer.len() > MAX_HEADER_LEN || find(buffer, b"\r\n\r\n").is_some() {
+                connection.sides[side].state = BodyState::NotHttp;
+            }
+            return;
+        };
+        connection.sides[side].buffer.drain(..head_len);
+
+        let body = match &message.start {
+            HttpStartLine::Request { method, path, .. } => {
+                stats.record_request(&message);
+                connection.requests.push_back(PendingRequest {
+                    sent: now,
+                    method: method.clone(),
+                    host: message.host.clone(),
+                    path: path.clone(),
+                });
+                request_body(&message)
+            }
+            HttpStartLine::Response { status, .. } => {
+                stats.responses += 1;
+                *stats.status_codes.entry(*status).or_insert(0) += 1;
+                // Interim responses precede the final one for the same request
+                let request = if *status >= 200 { connection.requests.pop_front() } else { None };
+                let head_request = request.as_ref().is_some_and(|r| r.method == "HEAD");
+                match request {
+                    Some(request) => stats.record_transaction(HttpTransaction {
+                        method: request.method,
+                        host: request.host,
+                        path: request.path,
+                        status: *status,
+                        content_type: message.content_type.clone(),
+                        content_length: message.content_length,
+                        latency: (now - request.sent).max(0.0),
+                    }),
+                    None if *status >= 200 => stats.unmatched_responses += 1,
+                    None => {}
+                }
+                response_body(&message, *status, head_request)
+            }
+        };
+        connection.sides[side].state = body;
+    }
+}
+
+fn request_body(message: &HttpMessage) -> BodyState {
+    if message.chunked {
+        BodyState::ChunkSize
+    } else {
+        match message.content_length {
+            Some(len) if len > 0 => BodyState::Length(len),
+            _ => BodyState::Head,
+        }
+    }
+}
+
+fn response_body(message: &HttpMessage, status: u16, head_request: bool) -> BodyState {
+    if head_request || status < 200 || status == 204 || status == 304 {
+        BodyState::Head
+    } else if message.chunked {
+        BodyState::ChunkSize
+    } else {
+        match message.content_length {
+            Some(0) => BodyState::Head,
+            Some(len) => BodyState::Length(len),
+            // Body runs until the server closes the connection
+            None => BodyState::UntilClose,
+        }
+    }
+}
+
+pub fn write_http_details(out: &mut dyn Write, message: &HttpMessage) -> io::Result<()> {
+    match &message.start {
+        HttpStartLine::Request { method, path, version } => {
+            writeln!(out, "  HTTP Request: {} {} {}", method, path, version)?;
+            writeln!(out, "    Host: {}", message.host.as_deref().unwrap_or("N/A"))?;
+        }
+        HttpStartLine::Response { version, status, reason } => {
+            writeln!(out, "  HTTP Response: {} {} {}", version, status, reason)?;
+        }
+    }
+    if let Some(content_type) = &message.content_type {
+        writeln!(out, "    Content-Type: {}", content_type)?;
+    }
+    if let Some(length) = message.content_length {
+        writeln!(out, "    Content-Length: {}", length)?;
+    }
+    if message.chunked {
+        writeln!(out, "    Transfer-Encoding: chunked")?;
+    }
+    Ok(())
+}
+
+pub fn write_http_summary(out: &mut dyn Write, stats: &HttpStats, top: usize) -> io::Result<()> {
+    writeln!(out, "\n=== HTTP Statistics ===")?;
+    writeln!(out, "Requests: {}, Responses: {}", stats.requests, stats.responses)?;
+    writeln!(
+        out,
+        "Unanswered requests: {}, Unmatched responses: {}",
+        stats.unanswered, stats.unmatched_responses
+    )?;
+
+    for (method, count) in sorted_counts(&stats.methods) {
+        writeln!(out, "  {}: {}", method, count)?;
+    }
+
+    let mut statuses: Vec<_> = stats.status_codes.iter().collect();
+    statuses.sort();
+    if !statuses.is_empty() {
+        writeln!(out, "Status codes:")?;
+        for (status, count) in statuses {
+            writeln!(out, "  {}: {}", status, count)?;
+        }
+    }
+
+    let hosts = sorted_counts(&stats.hosts);
+    if !hosts.is_empty() {
+        writeln!(out, "Top hosts:")?;
+        for (host, count) in hosts.into_iter().take(top) {
+            writeln!(out, "  {:<50} {}", host, count)?;
+        }
+    }
+
+    if !stats.slowest.is_empty() {
+        writeln!(out, "Slowest requests:")?;
+        for transaction in stats.slowest.iter().take(top) {
+            writeln!(
+                out,
+                "  {:>10.3} ms  {} {} {}{}",
+                transaction.latency * 1000.0,
+                transaction.status,
+                transaction.method,
+                transaction.host.as_deref().unwrap_or(""),
+                transaction.path
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const CLIENT: Direction = Direction::ClientToServer;
+    const SERVER: Direction = Direction::ServerToClient;
+
+    fn stream() -> StreamInfo {
+        StreamInfo {
+            id: 1,
+            client: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 40000),
+            server: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 80),
+            first_seen: 0.0,
+        }
+    }
+
+    // Deliver `data` in pieces of at most `piece` bytes, as reassembly may
+    fn feed(consumer: &mut HttpConsumer, direction: Direction, data: &str, ts: f64, piece: usize) {
+        for chunk in data.as_bytes().chunks(piece) {
+            consumer.on_data(&stream(), direction, chunk, ts);
+        }
+    }
+
+    fn side_state(consumer: &HttpConsumer, direction: Direction) -> BodyState {
+        consumer.connections[&1].sides[direction.index()].state
+    }
+
+    fn transactions(stats: &HttpStats) -> Vec<(&str, u16)> {
+        let mut found: Vec<(&str, u16)> = stats.slowest.iter().map(|t| (t.path.as_str(), t.status)).collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn pairs_pipelined_requests_in_order() {
+        let mut consumer = HttpConsumer::default();
+        feed(
+            &mut consumer,
+            CLIENT,
+            "GET /a HTTP/1.1\r\nHost: Example.com\r\n\r\n\
+             POST /b HTTP/1.1\r\nHost: example.com\r\nContent-Length: 17\r\n\r\nGET /c HTTP/1.1\r\n\
+             GET /d HTTP/1.1\r\nHost: example.com\r\n\r\n",
+            1.0,
+            7,
+        );
+        assert_eq!(consumer.stats.requests, 3);
+        assert_eq!(consumer.stats.methods, HashMap::from([("GET".to_string(), 2), ("POST".to_string(), 1)]));
+        assert_eq!(consumer.stats.hosts, HashMap::from([("example.com".to_string(), 3)]));
+
+        feed(&mut consumer, SERVER, "HTTP/1.1 100 Continue\r\n\r\n", 1.1, 1000);
+        feed(&mut consumer, SERVER, "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello", 1.25, 1000);
+        feed(&mut consumer, SERVER, "HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n", 1.5, 1000);
+        feed(&mut consumer, SERVER, "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n", 2.0, 1000);
+        feed(&mut consumer, SERVER, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n", 2.5, 1000);
+
+        let stats = consumer.into_stats();
+        assert_eq!(stats.responses, 5);
+        assert_eq!(stats.unmatched_responses, 1);
+        assert_eq!(stats.unanswered, 0);
+        assert_eq!(stats.status_codes[&100], 1);
+        // Slowest first, each response matched to the request in the same position
+        let slowest: Vec<(&str, u16, f64)> =
+            stats.slowest.iter().map(|t| (t.path.as_str(), t.status, t.latency)).collect();
+        assert_eq!(slowest, [("/d", 404, 1.0), ("/b", 201, 0.5), ("/a", 200, 0.25)]);
+        assert_eq!(stats.slowest[2].host.as_deref(), Some("Example.com"));
+        assert_eq!(stats.slowest[2].content_length, Some(5));
+    }
+
+    #[test]
+    fn chunked_body_with_trailers_is_followed_by_the_next_message() {
+        let mut consumer = HttpConsumer::default();
+        feed(&mut consumer, CLIENT, "GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n", 1.0, 1000);
+        // The chunk data looks like a response and must not be taken for one
+        let responses = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         5;ext=1\r\nhello\r\n15\r\nHTTP/1.1 500 Oops\r\n\r\n\r\n0\r\n\
+                         X-Checksum: abc\r\nX-Other: def\r\n\r\n\
+                         HTTP/1.1 304 Not Modified\r\n\r\n";
+        feed(&mut consumer, SERVER, responses, 2.0, 1);
+        assert_eq!(side_state(&consumer, SERVER), BodyState::Head);
+        assert!(consumer.connections[&1].sides[SERVER.index()].buffer.is_empty());
+
+        let stats = consumer.into_stats();
+        assert_eq!(stats.responses, 2);
+        assert_eq!(stats.status_codes, HashMap::from([(200, 1), (304, 1)]));
+        assert_eq!(transactions(&stats), [("/a", 200), ("/b", 304)]);
+    }
+
+    #[test]
+    fn head_204_and_304_responses_have_no_body() {
+        let mut consumer = HttpConsumer::default();
+        feed(
+            &mut consumer,
+            CLIENT,
+            "HEAD /h HTTP/1.1\r\n\r\nDELETE /n HTTP/1.1\r\n\r\nGET /m HTTP/1.1\r\n\r\nGET /last HTTP/1.1\r\n\r\n",
+            1.0,
+            1000,
+        );
+        // Content-Length describes the body the HEAD would have had; none follows
+        feed(
+            &mut consumer,
+            SERVER,
+            "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n\
+             HTTP/1.1 204 No Content\r\nContent-Length: 7\r\n\r\n\
+             HTTP/1.1 304 Not Modified\r\nContent-Length: 50\r\n\r\n\
+             HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+            2.0,
+            1000,
+        );
+        assert_eq!(side_state(&consumer, SERVER), BodyState::Head);
+        let stats = consumer.into_stats();
+        assert_eq!(stats.responses, 4);
+        assert_eq!(stats.unmatched_responses, 0);
+        assert_eq!(transactions(&stats), [("/h", 200), ("/last", 200), ("/m", 304), ("/n", 204)]);
+    }
+
+    #[test]
+    fn body_without_length_runs_until_close() {
+        let mut consumer = HttpConsumer::default();
+        feed(&mut consumer, CLIENT, "GET / HTTP/1.0\r\n\r\nGET /never HTTP/1.0\r\n\r\n", 1.0, 1000);
+        feed(&mut consumer, SERVER, "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nfirst part ", 1.5, 1000);
+        assert_eq!(side_state(&consumer, SERVER), BodyState::UntilClose);
+        feed(&mut consumer, SERVER, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n", 1.6, 1000);
+        assert_eq!(side_state(&consumer, SERVER), BodyState::UntilClose);
+        assert_eq!(consumer.stats.responses, 1);
+
+        consumer.on_close(&stream(), CloseReason::Finished);
+        assert!(consumer.connections.is_empty());
+        let stats = consumer.into_stats();
+        assert_eq!(stats.responses, 1);
+        assert_eq!(stats.unanswered, 1);
+        assert_eq!(stats.slowest[0].content_type.as_deref(), Some("text/plain"));
+    }
+
+    #[test]
+    fn other_protocols_are_left_alone() {
+        let mut consumer = HttpConsumer::default();
+        feed(&mut consumer, CLIENT, "SSH-2.0-OpenSSH_9.6\r\n", 1.0, 3);
+        assert_eq!(side_state(&consumer, CLIENT), BodyState::NotHttp);
+        // Nothing is parsed on that side again, even what looks like a request
+        feed(&mut consumer, CLIENT, "GET / HTTP/1.1\r\n\r\n", 1.1, 1000);
+        assert!(consumer.connections[&1].sides[CLIENT.index()].buffer.is_empty());
+
+        // A method-like start that is not a request line
+        feed(&mut consumer, SERVER, "GETAWAY\r\n\r\n", 1.2, 1000);
+        assert_eq!(side_state(&consumer, SERVER), BodyState::NotHttp);
+        assert!(consumer.into_stats().is_empty());
+    }
+
+    fn chunk_state(line: &str) -> BodyState {
+        let mut side = HttpSide::new();
+        side.state = BodyState::ChunkSize;
+        side.buffer.extend_from_slice(line.as_bytes());
+        side.advance_body();
+        side.state
+    }
+
+    #[test]
+    fn parses_chunk_sizes() {
+        assert_eq!(chunk_state("1a;name=value\r\n"), BodyState::ChunkData(0x1a + 2));
+        assert_eq!(chunk_state("0\r\n"), BodyState::Trailers);
+        assert_eq!(chunk_state("xyz\r\n"), BodyState::NotHttp);
+        assert_eq!(chunk_state("fffffffffffffffe\r\n"), BodyState::NotHttp);
+        assert_eq!(chunk_state("ffffffffffffffff\r\n"), BodyState::NotHttp);
+    }
+}
@@ -0,0 +1,617 @@
+use crate::packet::{IcmpInfo, MacAddr};
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// Start of the datagram an ICMP error was sent about
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddedPacket {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub protocol: u8,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+}
+
+impl fmt::Display for EmbeddedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.src_port, self.dst_port) {
+            (Some(sport), Some(dport)) => write!(
+                f,
+                "{}:{} -> {}:{} proto {}",
+                self.src, sport, self.dst, dport, self.protocol
+            ),
+            _ => write!(f, "{} -> {} proto {}", self.src, self.dst, self.protocol),
+        }
+    }
+}
+
+// ICMPv6 Neighbor Discovery (RFC 4861)
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "message", rename_all = "snake_case")]
+pub enum NdpMessage {
+    RouterSolicitation {
+        options: Vec<NdpOption>,
+    },
+    RouterAdvertisement {
+        hop_limit: u8,
+        managed: bool,
+        other: bool,
+        router_lifetime: u16,
+        reachable_time: u32,
+        retrans_timer: u32,
+        options: Vec<NdpOption>,
+    },
+    NeighborSolicitation {
+        target: Ipv6Addr,
+        options: Vec<NdpOption>,
+    },
+    NeighborAdvertisement {
+        target: Ipv6Addr,
+        router: bool,
+        solicited: bool,
+        #[serde(rename = "override")]
+        override_flag: bool,
+        options: Vec<NdpOption>,
+    },
+    Redirect {
+        target: Ipv6Addr,
+        destination: Ipv6Addr,
+        options: Vec<NdpOption>,
+    },
+}
+
+impl NdpMessage {
+    pub fn options(&self) -> &[NdpOption] {
+        match self {
+            NdpMessage::RouterSolicitation { options }
+            | NdpMessage::RouterAdvertisement { options, .. }
+            | NdpMessage::NeighborSolicitation { options, .. }
+            | NdpMessage::NeighborAdvertisement { options, .. }
+            | NdpMessage::Redirect { options, .. } => options,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "option", rename_all = "snake_case")]
+pub enum NdpOption {
+    SourceLinkAddress {
+        address: MacAddr,
+    },
+    TargetLinkAddress {
+        address: MacAddr,
+    },
+    Prefix {
+        prefix: Ipv6Addr,
+        length: u8,
+        on_link: bool,
+        autonomous: bool,
+        valid_lifetime: u32,
+        preferred_lifetime: u32,
+    },
+    Mtu {
+        mtu: u32,
+    },
+    RecursiveDns {
+        lifetime: u32,
+        servers: Vec<Ipv6Addr>,
+    },
+    Other {
+        kind: u8,
+        length: usize,
+    },
+}
+
+impl fmt::Display for NdpOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NdpOption::SourceLinkAddress { address } => write!(f, "source link-address {}", address),
+            NdpOption::TargetLinkAddress { address } => write!(f, "target link-address {}", address),
+            NdpOption::Prefix {
+                prefix,
+                length,
+                on_link,
+                autonomous,
+                valid_lifetime,
+                preferred_lifetime,
+            } => write!(
+                f,
+                "prefix {}/{} on-link={} autonomous={} valid={}s preferred={}s",
+                prefix, length, on_link, autonomous, valid_lifetime, preferred_lifetime
+            ),
+            NdpOption::Mtu { mtu } => write!(f, "mtu {}", mtu),
+            NdpOption::RecursiveDns { lifetime, servers } => {
+                let servers: Vec<String> = servers.iter().map(|s| s.to_string()).collect();
+                write!(f, "rdnss {} lifetime={}s", servers.join(","), lifetime)
+            }
+            NdpOption::Other { kind, length } => write!(f, "option {} ({} bytes)", kind, length),
+        }
+    }
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn ipv6_at(data: &[u8], at: usize) -> Option<Ipv6Addr> {
+    data.get(at..at + 16)
+        .and_then(|b| <[u8; 16]>::try_from(b).ok())
+        .map(Ipv6Addr::from)
+}
+
+// Type-length-value options following the fixed part of an ND message; length is in 8-byte units
+fn parse_ndp_options(mut data: &[u8]) -> Vec<NdpOption> {
+    let mut options = Vec::new();
+    while data.len() >= 2 {
+        let kind = data[0];
+        let length = data[1] as usize * 8;
+        if length == 0 || length > data.len() {
+            break;
+        }
+        let body = &data[..length];
+        let option = match kind {
+            1 | 2 if length >= 8 => {
+                let address = MacAddr(body[2..8].try_into().unwrap_or_default());
+                if kind == 1 {
+                    NdpOption::SourceLinkAddress { address }
+                } else {
+                    NdpOption::TargetLinkAddress { address }
+                }
+            }
+            3 if length >= 32 => NdpOption::Prefix {
+                prefix: ipv6_at(body, 16).unwrap_or(Ipv6Addr::UNSPECIFIED),
+                length: body[2],
+                on_link: body[3] & 0x80 != 0,
+                autonomous: body[3] & 0x40 != 0,
+                valid_lifetime: u32_at(body, 4).unwrap_or_default(),
+                preferred_lifetime: u32_at(body, 8).unwrap_or_default(),
+            },
+            5 if length >= 8 => NdpOption::Mtu {
+                mtu: u32_at(body, 4).unwrap_or_default(),
+            },
+            25 if length >= 8 => NdpOption::RecursiveDns {
+                lifetime: u32_at(body, 4).unwrap_or_default(),
+                servers: body[8..].chunks_exact(16).filter_map(|b| ipv6_at(b, 0)).collect(),
+            },
+            _ => NdpOption::Other { kind, length },
+        };
+        options.push(option);
+        data = &data[length..];
+    }
+    options
+}
+
+// `header` is the second word of the ICMPv6 header, `body` what follows it
+fn parse_ndp(icmp_type: u8, header: [u8; 4], body: &[u8]) -> Option<NdpMessage> {
+    let message = match icmp_type {
+        133 => NdpMessage::RouterSolicitation {
+            options: parse_ndp_options(body),
+        },
+        134 => NdpMessage::RouterAdvertisement {
+            hop_limit: header[0],
+            managed: header[1] & 0x80 != 0,
+            other: header[1] & 0x40 != 0,
+            router_lifetime: u16::from_be_bytes([header[2], header[3]]),
+            reachable_time: u32_at(body, 0)?,
+            retrans_timer: u32_at(body, 4)?,
+            options: parse_ndp_options(&body[8..]),
+        },
+        135 => NdpMessage::NeighborSolicitation {
+            target: ipv6_at(body, 0)?,
+            options: parse_ndp_options(&body[16..]),
+        },
+        136 => NdpMessage::NeighborAdvertisement {
+            target: ipv6_at(body, 0)?,
+            router: header[0] & 0x80 != 0,
+            solicited: header[0] & 0x40 != 0,
+            override_flag: header[0] & 0x20 != 0,
+            options: parse_ndp_options(&body[16..]),
+        },
+        137 => NdpMessage::Redirect {
+            target: ipv6_at(body, 0)?,
+            destination: ipv6_at(body, 16)?,
+            options: parse_ndp_options(&body[32..]),
+        },
+        _ => return None,
+    };
+    Some(message)
+}
+
+// Addresses and ports of the IP header quoted in an error message
+fn parse_embedded(data: &[u8]) -> Option<EmbeddedPacket> {
+    let version = data.first()? >> 4;
+    let (src, dst, protocol, transport) = match version {
+        4 => {
+            let ihl = (data[0] & 0x0f) as usize * 4;
+            let header = data.get(..20)?;
+            let src = <[u8; 4]>::try_from(&header[12..16]).ok()?;
+            let dst = <[u8; 4]>::try_from(&header[16..20]).ok()?;
+            (
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                header[9],
+                data.get(ihl..).unwrap_or(&[]),
+            )
+        }
+        6 => (
+            IpAddr::V6(ipv6_at(data, 8)?),
+            IpAddr::V6(ipv6_at(data, 24)?),
+            data[6],
+            data.get(40..).unwrap_or(&[]),
+        ),
+        _ => return None,
+    };
+
+    // TCP and UDP both start with the two ports
+    let ports = match (protocol, transport.get(..4)) {
+        (6 | 17, Some(p)) => Some((u16::from_be_bytes([p[0], p[1]]), u16::from_be_bytes([p[2], p[3]]))),
+        _ => None,
+    };
+    Some(EmbeddedPacket {
+        src,
+        dst,
+        protocol,
+        src_port: ports.map(|p| p.0),
+        dst_port: ports.map(|p| p.1),
+    })
+}
+
+// `header` is bytes 5-8 of the ICMP header, `body` the data after it
+pub fn decode_icmpv4(icmp_type: u8, code: u8, header: [u8; 4], body: &[u8]) -> IcmpInfo {
+    let is_error = matches!(icmp_type, 3 | 4 | 5 | 11 | 12);
+    IcmpInfo {
+        icmp_type,
+        code,
+        echo: matches!(icmp_type, 0 | 8).then(|| echo_fields(header)),
+        // Next-hop MTU of "fragmentation needed" (RFC 1191)
+        mtu: (icmp_type == 3 && code == 4).then(|| u16::from_be_bytes([header[2], header[3]]) as u32),
+        original: if is_error { parse_embedded(body) } else { None },
+        ndp: None,
+    }
+}
+
+pub fn decode_icmpv6(icmp_type: u8, code: u8, header: [u8; 4], body: &[u8]) -> IcmpInfo {
+    let is_error = icmp_type < 128;
+    IcmpInfo {
+        icmp_type,
+        code,
+        echo: matches!(icmp_type, 128 | 129).then(|| echo_fields(header)),
+        mtu: (icmp_type == 2).then(|| u32::from_be_bytes(header)),
+        original: if is_error { parse_embedded(body) } else { None },
+        ndp: parse_ndp(icmp_type, header, body),
+    }
+}
+
+fn echo_fields(header: [u8; 4]) -> (u16, u16) {
+    (u16::from_be_bytes([header[0], header[1]]), u16::from_be_bytes([header[2], header[3]]))
+}
+
+pub fn icmpv4_type_name(icmp_type: u8) -> String {
+    let name = match icmp_type {
+        0 => "Echo Reply",
+        3 => "Destination Unreachable",
+        4 => "Source Quench",
+        5 => "Redirect",
+        8 => "Echo Request",
+        9 => "Router Advertisement",
+        10 => "Router Solicitation",
+        11 => "Time Exceeded",
+        12 => "Parameter Problem",
+        13 => "Timestamp",
+        14 => "Timestamp Reply",
+        other => return format!("type {}", other),
+    };
+    name.to_string()
+}
+
+pub fn icmpv6_type_name(icmp_type: u8) -> String {
+    let name = match icmp_type {
+        1 => "Destination Unreachable",
+        2 => "Packet Too Big",
+        3 => "Time Exceeded",
+        4 => "Parameter Problem",
+        128 => "Echo Request",
+        129 => "Echo Reply",
+        130 => "Multicast Listener Query",
+        131 => "Multicast Listener Report",
+        132 => "Multicast Listener Done",
+        133 => "Router Solicitation",
+        134 => "Router Advertisement",
+        135 => "Neighbor Solicitation",
+        136 => "Neighbor Advertisement",
+        137 => "Redirect",
+        143 => "Multicast Listener Report v2",
+        other => return format!("type {}", other),
+    };
+    name.to_string()
+}
+
+pub fn icmpv4_code_name(icmp_type: u8, code: u8) -> Option<&'static str> {
+    let name = match (icmp_type, code) {
+        (3, 0) => "Network Unreachable",
+        (3, 1) => "Host Unreachable",
+        (3, 2) => "Protocol Unreachable",
+        (3, 3) => "Port Unreachable",
+        (3, 4) => "Fragmentation Needed",
+        (3, 5) => "Source Route Failed",
+        (3, 6) => "Destination Network Unknown",
+        (3, 7) => "Destination Host Unknown",
+        (3, 9) => "Network Administratively Prohibited",
+        (3, 10) => "Host Administratively Prohibited",
+        (3, 13) => "Communication Administratively Prohibited",
+        (5, 0) => "Redirect for Network",
+        (5, 1) => "Redirect for Host",
+        (11, 0) => "TTL Exceeded in Transit",
+        (11, 1) => "Fragment Reassembly Time Exceeded",
+        (12, 0) => "Pointer Indicates the Error",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub fn icmpv6_code_name(icmp_type: u8, code: u8) -> Option<&'static str> {
+    let name = match (icmp_type, code) {
+        (1, 0) => "No Route to Destination",
+        (1, 1) => "Administratively Prohibited",
+        (1, 2) => "Beyond Scope of Source Address",
+        (1, 3) => "Address Unreachable",
+        (1, 4) => "Port Unreachable",
+        (1, 5) => "Source Address Failed Policy",
+        (1, 6) => "Reject Route to Destination",
+        (3, 0) => "Hop Limit Exceeded in Transit",
+        (3, 1) => "Fragment Reassembly Time Exceeded",
+        (4, 0) => "Erroneous Header Field",
+        (4, 1) => "Unrecognized Next Header",
+        (4, 2) => "Unrecognized IPv6 Option",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub fn write_icmp_details(out: &mut dyn Write, icmp: &IcmpInfo, v6: bool) -> io::Result<()> {
+    let (label, name, code) = if v6 {
+        ("ICMPv6", icmpv6_type_name(icmp.icmp_type), icmpv6_code_name(icmp.icmp_type, icmp.code))
+    } else {
+        ("ICMPv4", icmpv4_type_name(icmp.icmp_type), icmpv4_code_name(icmp.icmp_type, icmp.code))
+    };
+    match code {
+        Some(code) => writeln!(out, "  {}: {} ({})", label, name, code)?,
+        None => writeln!(out, "  {}: {} (code {})", label, name, icmp.code)?,
+    }
+    if let Some((identifier, sequence)) = icmp.echo {
+        writeln!(out, "    Identifier: {}, Sequence: {}", identifier, sequence)?;
+    }
+    if let Some(mtu) = icmp.mtu {
+        writeln!(out, "    MTU: {}", mtu)?;
+    }
+    if let Some(original) = &icmp.original {
+        writeln!(out, "    Original: {}", original)?;
+    }
+    if let Some(ndp) = &icmp.ndp {
+        match ndp {
+            NdpMessage::RouterSolicitation { .. } => {}
+            NdpMessage::RouterAdvertisement {
+                hop_limit,
+                managed,
+                other,
+                router_lifetime,
+                ..
+            } => writeln!(
+                out,
+                "    Hop limit: {}, Managed: {}, Other: {}, Router lifetime: {}s",
+                hop_limit, managed, other, router_lifetime
+            )?,
+            NdpMessage::NeighborSolicitation { target, .. } => writeln!(out, "    Target: {}", target)?,
+            NdpMessage::NeighborAdvertisement {
+                target,
+                router,
+                solicited,
+                override_flag,
+                ..
+            } => writeln!(
+                out,
+                "    Target: {} (router={}, solicited={}, override={})",
+                target, router, solicited, override_flag
+            )?,
+            NdpMessage::Redirect { target, destination, .. } => {
+                writeln!(out, "    Target: {}, Destination: {}", target, destination)?
+            }
+        }
+        for option in ndp.options() {
+            writeln!(out, "    Option: {}", option)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listening::parse_packet_with_etherparse;
+    use crate::packet::TransportLayer;
+    use crate::source::RawPacket;
+    use pcap::Linktype;
+
+    // IPv4 header with no options, checksum left at zero
+    fn ipv4(protocol: u8, src: [u8; 4], dst: [u8; 4], payload_len: usize) -> Vec<u8> {
+        let total = (20 + payload_len) as u16;
+        let mut header = vec![0x45, 0x00];
+        header.extend_from_slice(&total.to_be_bytes());
+        header.extend_from_slice(&[0x1c, 0x46, 0x40, 0x00, 64, protocol, 0, 0]);
+        header.extend_from_slice(&src);
+        header.extend_from_slice(&dst);
+        header
+    }
+
+    fn ipv6(next_header: u8, src: Ipv6Addr, dst: Ipv6Addr, payload_len: usize) -> Vec<u8> {
+        let mut header = vec![0x60, 0, 0, 0];
+        header.extend_from_slice(&(payload_len as u16).to_be_bytes());
+        header.extend_from_slice(&[next_header, 255]);
+        header.extend_from_slice(&src.octets());
+        header.extend_from_slice(&dst.octets());
+        header
+    }
+
+    // What a router quotes back: the IPv4 header and first 8 bytes of a DNS query
+    fn quoted_udp() -> Vec<u8> {
+        let mut quoted = ipv4(17, [10, 0, 0, 1], [8, 8, 8, 8], 40);
+        quoted.extend_from_slice(&[0x9c, 0x40, 0x00, 0x35, 0x00, 0x28, 0x00, 0x00]);
+        quoted
+    }
+
+    fn icmp(icmp_type: u8, code: u8, rest: [u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut message = vec![icmp_type, code, 0, 0];
+        message.extend_from_slice(&rest);
+        message.extend_from_slice(body);
+        message
+    }
+
+    fn frame(ether_type: u16, network: Vec<u8>, payload: Vec<u8>) -> RawPacket {
+        let mut frame = vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend(network);
+        frame.extend(payload);
+        RawPacket::new(0, 0, frame)
+    }
+
+    fn addr(text: &str) -> Ipv6Addr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn port_unreachable_quotes_the_original_datagram() {
+        let message = icmp(3, 3, [0; 4], &quoted_udp());
+        let raw = frame(0x0800, ipv4(1, [8, 8, 8, 8], [10, 0, 0, 1], message.len()), message);
+        let decoded = parse_packet_with_etherparse(1, &raw, Linktype::ETHERNET).unwrap();
+
+        assert_eq!(decoded.message_type().as_deref(), Some("ICMPv4 Destination Unreachable"));
+        let Some(TransportLayer::Icmpv4(info)) = &decoded.transport else {
+            panic!("expected ICMPv4, got {:?}", decoded.transport);
+        };
+        let original = info.original.as_ref().unwrap();
+        assert_eq!(original.to_string(), "10.0.0.1:40000 -> 8.8.8.8:53 proto 17");
+
+        let mut details = Vec::new();
+        write_icmp_details(&mut details, info, false).unwrap();
+        assert_eq!(
+            String::from_utf8(details).unwrap(),
+            "  ICMPv4: Destination Unreachable (Port Unreachable)\n    \
+             Original: 10.0.0.1:40000 -> 8.8.8.8:53 proto 17\n"
+        );
+    }
+
+    #[test]
+    fn truncated_errors_keep_what_was_quoted() {
+        let quoted = quoted_udp();
+
+        // Only two bytes of the UDP header: addresses but no ports
+        let info = decode_icmpv4(11, 0, [0; 4], &quoted[..22]);
+        let original = info.original.unwrap();
+        assert_eq!((original.src_port, original.dst_port), (None, None));
+        assert_eq!(original.to_string(), "10.0.0.1 -> 8.8.8.8 proto 17");
+
+        // Not even a whole IP header
+        assert!(decode_icmpv4(11, 0, [0; 4], &quoted[..12]).original.is_none());
+        assert!(decode_icmpv4(3, 1, [0; 4], &[]).original.is_none());
+        assert!(decode_icmpv6(1, 4, [0; 4], &[0x60, 0, 0, 0]).original.is_none());
+
+        // The captured frame itself ends inside the quote
+        let message = icmp(11, 0, [0; 4], &quoted[..24]);
+        let raw = frame(0x0800, ipv4(1, [192, 0, 2, 1], [10, 0, 0, 1], message.len()), message);
+        let decoded = parse_packet_with_etherparse(1, &raw, Linktype::ETHERNET).unwrap();
+        let Some(TransportLayer::Icmpv4(info)) = &decoded.transport else {
+            panic!("expected ICMPv4, got {:?}", decoded.transport);
+        };
+        assert_eq!(icmpv4_code_name(info.icmp_type, info.code), Some("TTL Exceeded in Transit"));
+        assert_eq!(info.original.as_ref().unwrap().to_string(), "10.0.0.1:40000 -> 8.8.8.8:53 proto 17");
+    }
+
+    #[test]
+    fn echo_and_fragmentation_needed_fields() {
+        let echo = decode_icmpv4(8, 0, [0x12, 0x34, 0x00, 0x07], b"ping");
+        assert_eq!(echo.echo, Some((0x1234, 7)));
+        assert!(echo.original.is_none() && echo.mtu.is_none());
+
+        let too_big = decode_icmpv4(3, 4, [0, 0, 0x05, 0xdc], &quoted_udp());
+        assert_eq!(too_big.mtu, Some(1500));
+        assert!(too_big.echo.is_none());
+    }
+
+    #[test]
+    fn packet_too_big_quotes_an_ipv6_datagram() {
+        let (client, server) = (addr("2001:db8::1"), addr("2001:db8::2"));
+        let mut quoted = ipv6(6, client, server, 1400);
+        quoted.extend_from_slice(&[0xc3, 0x50, 0x01, 0xbb, 0, 0, 0, 1]);
+        let info = decode_icmpv6(2, 0, 1280u32.to_be_bytes(), &quoted);
+
+        assert_eq!(info.mtu, Some(1280));
+        assert_eq!(info.original.unwrap().to_string(), "2001:db8::1:50000 -> 2001:db8::2:443 proto 6");
+        assert!(info.ndp.is_none());
+    }
+
+    #[test]
+    fn neighbor_discovery_messages_and_options() {
+        let target = addr("fe80::1");
+        let mut solicitation = target.octets().to_vec();
+        solicitation.extend_from_slice(&[1, 1, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb]);
+        let message = icmp(135, 0, [0; 4], &solicitation);
+        let ip = ipv6(58, addr("fe80::2"), addr("ff02::1:ff00:1"), message.len());
+        let raw = frame(0x86dd, ip, message);
+        let decoded = parse_packet_with_etherparse(1, &raw, Linktype::ETHERNET).unwrap();
+        assert_eq!(decoded.message_type().as_deref(), Some("ICMPv6 Neighbor Solicitation"));
+        let Some(TransportLayer::Icmpv6(info)) = &decoded.transport else {
+            panic!("expected ICMPv6, got {:?}", decoded.transport);
+        };
+        let Some(NdpMessage::NeighborSolicitation { target: asked, options }) = &info.ndp else {
+            panic!("expected a neighbor solicitation, got {:?}", info.ndp);
+        };
+        assert_eq!(*asked, target);
+        let options: Vec<_> = options.iter().map(|o| o.to_string()).collect();
+        assert_eq!(options, ["source link-address 66:77:88:99:aa:bb"]);
+
+        let mut advertisement = target.octets().to_vec();
+        advertisement.extend_from_slice(&[2, 1, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let Some(NdpMessage::NeighborAdvertisement { router, solicited, override_flag, .. }) =
+            decode_icmpv6(136, 0, [0xe0, 0, 0, 0], &advertisement).ndp
+        else {
+            panic!("expected a neighbor advertisement");
+        };
+        assert!(router && solicited && override_flag);
+
+        // Router advertisement: reachable time, retrans timer, then prefix, MTU, RDNSS and an unknown option
+        let mut body = vec![0, 0, 0x75, 0x30, 0, 0, 0x03, 0xe8];
+        body.extend_from_slice(&[3, 4, 64, 0xc0]);
+        body.extend_from_slice(&86_400u32.to_be_bytes());
+        body.extend_from_slice(&14_400u32.to_be_bytes());
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(&addr("2001:db8:1::").octets());
+        body.extend_from_slice(&[5, 1, 0, 0, 0, 0, 0x05, 0xdc]);
+        body.extend_from_slice(&[25, 3, 0, 0, 0, 0, 0x0e, 0x10]);
+        body.extend_from_slice(&addr("2001:db8::53").octets());
+        body.extend_from_slice(&[200, 1, 0, 0, 0, 0, 0, 0]);
+        // A zero-length option would loop forever; parsing stops there
+        body.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        let info = decode_icmpv6(134, 0, [64, 0x80, 0x07, 0x08], &body);
+        let Some(NdpMessage::RouterAdvertisement { hop_limit, managed, other, router_lifetime, reachable_time, .. }) =
+            &info.ndp
+        else {
+            panic!("expected a router advertisement, got {:?}", info.ndp);
+        };
+        assert_eq!((*hop_limit, *managed, *other, *router_lifetime, *reachable_time), (64, true, false, 1800, 30_000));
+        let options: Vec<_> = info.ndp.as_ref().unwrap().options().iter().map(|o| o.to_string()).collect();
+        assert_eq!(
+            options,
+            [
+                "prefix 2001:db8:1::/64 on-link=true autonomous=true valid=86400s preferred=14400s",
+                "mtu 1500",
+                "rdnss 2001:db8::53 lifetime=3600s",
+                "option 200 (8 bytes)",
+            ]
+        );
+
+        // Too short to hold the target address
+        assert!(decode_icmpv6(135, 0, [0; 4], &target.octets()[..10]).ndp.is_none());
+    }
+}
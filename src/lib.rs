@@ -0,0 +1,26 @@
+pub mod arp;
+pub mod bpf;
+pub mod defrag;
+pub mod dhcp;
+pub mod display_filter;
+pub mod dns;
+pub mod encap;
+pub mod endpoints;
+pub mod export;
+pub mod flow;
+pub mod http;
+pub mod icmp;
+pub mod listening;
+pub mod packet;
+pub mod pcapng;
+pub mod quic;
+pub mod reassembly;
+pub mod recovery;
+pub mod report;
+pub mod rotate;
+pub mod sink;
+pub mod source;
+pub mod timeseries;
+pub mod tls;
+pub mod tui;
+pub mod wifi;
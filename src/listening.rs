@@ -1,19 +1,152 @@
-use pcap::{Device, Capture};
-use etherparse::SlicedPacket;
+use crate::arp::{self, ArpInfo};
+use crate::defrag::{DefragStats, Defragmenter};
+use crate::dhcp::{self, DhcpSink, DhcpStats};
+use crate::display_filter::DisplayFilter;
+use crate::dns::{self, DnsSink, DnsStats, DnsStreamConsumer};
+use crate::encap::{self, Inner};
+use crate::endpoints::{EndpointSink, EndpointStats};
+use crate::export::{ExportFormat, ExportSink};
+use crate::flow::{Flow, FlowSink, FlowTotals};
+use crate::http::{self, HttpConsumer, HttpStats};
+use crate::icmp;
+use crate::pcapng::{CommentMode, PcapngSink};
+use crate::quic::{self, QuicSink, QuicStats};
+use crate::reassembly::{ReassemblyConfig, ReassemblySink, ReassemblyStats, StreamConsumer, StreamDumper};
+use crate::recovery::{reconnect, CaptureError, CaptureGap, ReconnectEvent, RecoveryPolicy};
+use crate::rotate::{RotatingSavefileSink, RotationPolicy};
+use crate::packet::{
+    ApplicationLayer, DecodedPacket, FragmentInfo, IpLayer, LinkLayer, MacAddr, NetworkLayer, PayloadRange,
+    TcpFlags, TcpInfo, TransportLayer, UdpInfo, VlanTag,
+};
+use crate::sink::{ConsoleSink, PacketSink, ReconnectStatus, SavefileSink, StatsSink};
+use crate::source::{FileSource, LiveSource, MultiSource, PacketSource, RawPacket, SourceEvent};
+use crate::timeseries::{Interval, IntervalConfig, IntervalSink};
+use crate::tls::{self, TlsConsumer, TlsStats};
+use crate::tui::TuiSink;
+use crate::wifi;
+use etherparse::err::packet::SliceError;
+use etherparse::{EtherType, SlicedPacket};
+use pcap::{Device, Linktype};
 use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub struct CaptureOptions {
-    pub interface: Option<String>,
+    // Devices to capture on; empty means auto-detect, "any" means every usable device
+    pub interfaces: Vec<String>,
     pub filter: Option<String>,
+    // Applied to decoded packets; ones it rejects reach no sink
+    pub display_filter: Option<DisplayFilter>,
     pub promiscuous: bool,
     pub output_file: Option<String>,
     pub packet_limit: Option<u32>,
     pub verbose: bool,
+    pub read_file: Option<String>,
+    pub format: ExportFormat,
+    pub export_file: Option<String>,
+    pub flows: bool,
+    pub flow_idle_timeout: f64,
+    pub flow_active_timeout: f64,
+    // Set from outside (e.g. a Ctrl+C handler) to end the capture cleanly
+    pub stop_flag: Option<Arc<AtomicBool>>,
+    // Stop after this many seconds
+    pub duration: Option<u64>,
+    // Stop after this many captured bytes
+    pub max_bytes: Option<u64>,
+    pub rotation: RotationPolicy,
+    // Write --output as pcapng instead of classic pcap
+    pub pcapng: bool,
+    pub packet_comments: CommentMode,
+    // What to do when the device fails mid-capture
+    pub recovery: RecoveryPolicy,
+    // Told about each reconnect attempt, unless the dashboard is showing them
+    pub on_reconnect: Option<fn(&ReconnectEvent)>,
+    pub reassembly: ReassemblyConfig,
+    // Directory to write each reassembled TCP stream to, tcpflow style
+    pub dump_streams: Option<String>,
+    // Full-screen dashboard instead of console output
+    pub tui: bool,
+    // Per-interval counters, streamed during the capture
+    pub intervals: IntervalConfig,
+    // Collect per-endpoint, port and packet size statistics
+    pub endpoints: bool,
+}
+
+// Limits checked by `run_capture` before every read; the first one hit ends the capture
+#[derive(Default)]
+pub struct StopConditions<'a> {
+    pub packet_limit: Option<u32>,
+    pub duration: Option<Duration>,
+    pub max_bytes: Option<u64>,
+    pub stop_flag: Option<&'a AtomicBool>,
+}
+
+impl CaptureOptions {
+    // Packet records go to stdout in a machine-readable format, so status
+    // messages must stay off stdout to keep it parseable
+    pub fn records_on_stdout(&self) -> bool {
+        self.format != ExportFormat::Text && self.export_file.is_none()
+    }
+
+    fn status(&self, message: &str) {
+        if self.records_on_stdout() {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+}
+
+// A packet as delivered to sinks: the raw bytes plus the decoding result
+pub struct ParsedPacket<'a> {
+    pub number: u32,
+    pub raw: &'a RawPacket,
+    pub decoded: Result<DecodedPacket, SliceError>,
+    // Bytes `decoded` refers to: the captured frame, or the rebuilt datagram
+    // when this fragment completed one
+    pub data: &'a [u8],
 }
 
 pub struct CaptureStats {
     pub packet_count: u32,
     pub protocol_stats: HashMap<String, u32>,
+    // ARP operations, ICMP types and 802.11 subtypes, e.g. "ICMPv6 Neighbor Solicitation"
+    pub message_types: HashMap<String, u32>,
+    // Traffic per VLAN ("100", or "100.200" for QinQ) and per tunnel
+    pub vlans: HashMap<String, TrafficCounters>,
+    pub tunnels: HashMap<String, TrafficCounters>,
+    // Same counters broken down by capture interface
+    pub interfaces: Vec<InterfaceCounters>,
+    // Conversations sorted by total bytes, empty unless flow tracking was enabled
+    pub flows: Vec<Flow>,
+    // Finished flows beyond the ones kept in `flows`
+    pub other_flows: FlowTotals,
+    pub dns: DnsStats,
+    pub dhcp: DhcpStats,
+    pub http: HttpStats,
+    pub tls: TlsStats,
+    pub quic: QuicStats,
+    pub streams: ReassemblyStats,
+    pub fragments: DefragStats,
+    // Periods lost while reconnecting to a failed device
+    pub gaps: Vec<CaptureGap>,
+    // Most recent intervals, oldest first, empty unless intervals were requested
+    pub intervals: Vec<Interval>,
+    // Empty unless endpoint statistics were requested
+    pub endpoints: EndpointStats,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InterfaceCounters {
+    pub name: String,
+    pub packet_count: u32,
+    pub protocol_stats: HashMap<String, u32>,
+    // Packets dropped by the kernel or driver, when the source reports it
+    pub dropped: Option<u64>,
 }
 
 pub fn list_interfaces() -> Result<(), Box<dyn std::error::Error>> {
@@ -52,212 +185,751 @@ pub fn auto_detect_wifi_device() -> Result<Device, Box<dyn std::error::Error>> {
     Ok(wifi_device)
 }
 
-pub fn start_capture(options: CaptureOptions) -> Result<CaptureStats, Box<dyn std::error::Error>> {
-    // Get network interface
-    let device = if let Some(interface_name) = options.interface {
-        Device::list()?
-            .into_iter()
-            .find(|dev| dev.name == interface_name)
-            .ok_or(format!("Interface '{}' not found", interface_name))?
-    } else {
-        // Auto-detect Wi-Fi device
-        auto_detect_wifi_device()?
-    };
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrafficCounters {
+    pub packets: u64,
+    pub bytes: u64,
+}
 
-    println!("Using device: {} ({:?})", device.name, device.desc);
+impl TrafficCounters {
+    pub fn add(&mut self, bytes: u32) {
+        self.packets += 1;
+        self.bytes += bytes as u64;
+    }
+}
 
-    // Configure capture
-    let mut cap_builder = Capture::from_device(device)?;
-    
-    if options.promiscuous {
-        cap_builder = cap_builder.promisc(true);
+pub fn start_capture(options: CaptureOptions) -> Result<CaptureStats, Box<dyn Error>> {
+    if options.tui && options.records_on_stdout() {
+        return Err("The dashboard needs the terminal; write records to a file with --export".into());
     }
-    
-    cap_builder = cap_builder.timeout(1000);
-    
-    let mut cap = cap_builder.open()?;
+
+    let mut source = open_source(&options)?;
 
     // Apply filter if specified
-    if let Some(filter_expr) = options.filter {
-        cap.filter(&filter_expr, true)?;
-        println!("Applied filter: {}", filter_expr);
+    if let Some(filter_expr) = &options.filter {
+        source.set_filter(filter_expr)?;
+        options.status(&format!("Applied filter: {}", filter_expr));
     }
 
-    // Prepare output file if specified
-    let mut savefile = if let Some(output_file) = options.output_file {
-        Some(cap.savefile(&output_file)?)
+    let interfaces = source.interfaces();
+    let mixed_linktypes = interfaces.iter().any(|info| info.linktype != interfaces[0].linktype);
+
+    // Prepare output file if specified, rotating it when asked to
+    let mut savefile: Option<Box<dyn PacketSink>> = match &options.output_file {
+        Some(_) if options.pcapng && options.rotation.is_enabled() => {
+            return Err("File rotation is only supported for classic pcap output".into());
+        }
+        Some(output_file) if options.pcapng => Some(Box::new(PcapngSink::create(
+            output_file,
+            &interfaces,
+            options.packet_comments,
+        )?)),
+        Some(_) if mixed_linktypes => {
+            return Err("Interfaces have different link types; use --pcapng to save them together".into());
+        }
+        Some(output_file) if options.rotation.is_enabled() => Some(Box::new(
+            RotatingSavefileSink::new(output_file, source.datalink(), options.rotation),
+        )),
+        Some(output_file) => Some(Box::new(SavefileSink::create(output_file, source.datalink())?)),
+        None if options.rotation.is_enabled() => {
+            return Err("File rotation requires an --output file".into());
+        }
+        None => None,
+    };
+
+    // Structured per-packet records, unless plain console text was requested
+    let mut export = if options.format != ExportFormat::Text || options.export_file.is_some() {
+        Some(ExportSink::new(options.format, options.export_file.as_deref(), &interfaces)?)
     } else {
         None
     };
 
-    println!("Starting packet capture... Press Ctrl+C to stop.");
+    let mut stats = StatsSink::new(&interfaces);
+    let mut console = ConsoleSink::new(options.verbose, &interfaces);
+    let mut dns = DnsSink::default();
+    let mut dns_streams = DnsStreamConsumer::default();
+    let mut dhcp = DhcpSink::new(&interfaces);
+    let mut http = HttpConsumer::default();
+    let mut tls = TlsConsumer::default();
+    let mut quic = QuicSink::default();
+    let mut dumper = match &options.dump_streams {
+        Some(directory) => Some(StreamDumper::new(directory)?),
+        None => None,
+    };
+    let mut flows = if options.flows {
+        Some(FlowSink::new(options.flow_idle_timeout, options.flow_active_timeout))
+    } else {
+        None
+    };
+
+    let mut endpoints = if options.endpoints {
+        Some(EndpointSink::default())
+    } else {
+        None
+    };
+    let mut intervals = if options.intervals.is_enabled() {
+        // Stream to the console unless the dashboard has it
+        let console: Option<Box<dyn Write>> = if options.tui {
+            None
+        } else if options.records_on_stdout() {
+            Some(Box::new(io::stderr()))
+        } else {
+            Some(Box::new(io::stdout()))
+        };
+        Some(IntervalSink::new(
+            &options.intervals,
+            console,
+            options.flow_idle_timeout,
+            options.flow_active_timeout,
+        )?)
+    } else {
+        None
+    };
+
+    // Quitting the dashboard ends the capture the way Ctrl+C does
+    let stop_flag = options.stop_flag.clone().unwrap_or_default();
+    let mut tui = if options.tui {
+        Some(TuiSink::new(
+            &interfaces,
+            options.flow_idle_timeout,
+            options.flow_active_timeout,
+            stop_flag.clone(),
+        )?)
+    } else {
+        None
+    };
+
+    let mut sinks: Vec<&mut dyn PacketSink> = Vec::new();
+    if let Some(savefile) = &mut savefile {
+        sinks.push(savefile.as_mut());
+    }
+    sinks.push(&mut stats);
+    sinks.push(&mut dns);
+    sinks.push(&mut dhcp);
+    sinks.push(&mut quic);
+
+    // Application parsers that need in-order TCP payload
+    let mut consumers: Vec<&mut dyn StreamConsumer> = vec![&mut dns_streams, &mut http, &mut tls];
+    if let Some(dumper) = &mut dumper {
+        consumers.push(dumper);
+    }
+    let mut reassembly = ReassemblySink::new(options.reassembly, consumers);
+    sinks.push(&mut reassembly);
+    if let Some(flows) = &mut flows {
+        sinks.push(flows);
+    }
+    if let Some(endpoints) = &mut endpoints {
+        sinks.push(endpoints);
+    }
+    if let Some(intervals) = &mut intervals {
+        sinks.push(intervals);
+    }
+    if let Some(export) = &mut export {
+        sinks.push(export);
+    }
+    let mut reconnect_status = options.on_reconnect.map(ReconnectStatus);
+    if let Some(tui) = &mut tui {
+        sinks.push(tui);
+    } else {
+        if !options.records_on_stdout() {
+            sinks.push(&mut console);
+        }
+        if let Some(status) = &mut reconnect_status {
+            sinks.push(status);
+        }
+    }
+
+    let stop = StopConditions {
+        packet_limit: options.packet_limit,
+        duration: options.duration.map(Duration::from_secs),
+        max_bytes: options.max_bytes,
+        stop_flag: Some(&stop_flag),
+    };
+    let mut defrag = Defragmenter::default();
+    run_capture(
+        source.as_mut(),
+        &mut sinks,
+        &mut defrag,
+        options.display_filter.as_ref(),
+        &stop,
+        &options.recovery,
+    )?;
+
+    let mut stats = stats.into_stats();
+    stats.fragments = defrag.stats();
+    stats.streams = reassembly.stats();
+    stats.dns = dns.into_stats();
+    stats.dns.merge(dns_streams.into_stats());
+    stats.dhcp = dhcp.into_stats();
+    stats.http = http.into_stats();
+    stats.tls = tls.into_stats();
+    stats.quic = quic.into_stats();
+    if let Some(flows) = flows {
+        (stats.flows, stats.other_flows) = flows.into_flows();
+    }
+    if let Some(intervals) = intervals {
+        stats.intervals = intervals.into_intervals();
+    }
+    if let Some(endpoints) = endpoints {
+        stats.endpoints = endpoints.into_stats();
+    }
+    Ok(stats)
+}
+
+// Open the packet source described by the options: a savefile or a live device
+pub fn open_source(options: &CaptureOptions) -> Result<Box<dyn PacketSource>, Box<dyn Error>> {
+    // Read from a savefile instead of a live device if requested
+    if let Some(read_file) = &options.read_file {
+        options.status(&format!("Reading packets from file: {}", read_file));
+        return Ok(Box::new(FileSource::open(read_file)?));
+    }
+
+    // Get network interfaces
+    let devices = resolve_devices(&options.interfaces)?;
+
+    let source: Box<dyn PacketSource> = if devices.len() == 1 {
+        let device = devices.into_iter().next().ok_or("No suitable network device found")?;
+        options.status(&format!("Using device: {} ({:?})", device.name, device.desc));
+        Box::new(LiveSource::open(device, options.promiscuous)?)
+    } else {
+        let mut sources: Vec<Box<dyn PacketSource + Send>> = Vec::new();
+        for device in devices {
+            options.status(&format!("Using device: {} ({:?})", device.name, device.desc));
+            sources.push(Box::new(LiveSource::open(device, options.promiscuous)?));
+        }
+        Box::new(MultiSource::new(sources))
+    };
+
+    options.status("Starting packet capture... Press Ctrl+C to stop.");
+
+    Ok(source)
+}
+
+// Map requested interface names to devices. "any" expands to every device
+// that is up and not a loopback, so each gets its own capture.
+fn resolve_devices(names: &[String]) -> Result<Vec<Device>, Box<dyn Error>> {
+    if names.is_empty() {
+        // Auto-detect Wi-Fi device
+        return Ok(vec![auto_detect_wifi_device()?]);
+    }
+
+    let available = Device::list()?;
+    let mut devices: Vec<Device> = Vec::new();
+    for name in names {
+        if name == "any" {
+            let usable = available
+                .iter()
+                .filter(|dev| dev.name != "any" && dev.flags.is_up() && !dev.flags.is_loopback());
+            for dev in usable {
+                if !devices.iter().any(|d| d.name == dev.name) {
+                    devices.push(dev.clone());
+                }
+            }
+        } else {
+            let dev = available
+                .iter()
+                .find(|dev| &dev.name == name)
+                .ok_or(format!("Interface '{}' not found", name))?;
+            if !devices.iter().any(|d| d.name == dev.name) {
+                devices.push(dev.clone());
+            }
+        }
+    }
+
+    if devices.is_empty() {
+        return Err("No suitable network device found".into());
+    }
+    Ok(devices)
+}
 
+// Install a Ctrl+C handler that raises the returned flag. The capture loop
+// notices it within one read timeout; a second Ctrl+C exits immediately.
+pub fn install_interrupt_handler() -> Result<Arc<AtomicBool>, Box<dyn Error>> {
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = stop_flag.clone();
+    ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            eprintln!("\nForced exit.");
+            process::exit(130);
+        }
+        eprintln!("\nStopping capture... (press Ctrl+C again to force exit)");
+    })?;
+    Ok(stop_flag)
+}
+
+// Pull packets from the source and hand each one to every sink.
+// Source failures are handled by the recovery policy; if it gives up, or a sink
+// fails, the capture stops but every sink is still finished before the first
+// error is returned. Packets that fail
+// `display_filter`, or could not be decoded while one is set, are dropped.
+// Returns the number of packets processed.
+pub fn run_capture(
+    source: &mut dyn PacketSource,
+    sinks: &mut [&mut dyn PacketSink],
+    defrag: &mut Defragmenter,
+    display_filter: Option<&DisplayFilter>,
+    stop: &StopConditions,
+    recovery: &RecoveryPolicy,
+) -> Result<u32, Box<dyn Error>> {
     let mut packet_count = 0;
-    let mut protocol_stats: HashMap<String, u32> = HashMap::new();
+    let mut byte_count: u64 = 0;
+    let started = Instant::now();
+    // Capture time of the first packet; offline sources are timed by packet timestamps
+    let mut first_ts: Option<i64> = None;
+    let mut last_ts: i64 = 0;
+    let mut failure: Option<Box<dyn Error>> = None;
+    // Datalink of each interface, to pick the decoder
+    let mut linktypes: Vec<Linktype> = source.interfaces().iter().map(|info| info.linktype).collect();
 
     loop {
+        // Check if we've been interrupted
+        if stop.stop_flag.is_some_and(|flag| flag.load(Ordering::SeqCst)) {
+            break;
+        }
+
         // Check if we've reached the packet limit
-        if let Some(limit) = options.packet_limit {
+        if let Some(limit) = stop.packet_limit {
             if packet_count >= limit {
                 break;
             }
         }
 
-        match cap.next_packet() {
-            Ok(packet) => {
+        if let Some(max_bytes) = stop.max_bytes {
+            if byte_count >= max_bytes {
+                break;
+            }
+        }
+
+        if let Some(duration) = stop.duration {
+            let capture_elapsed = first_ts.map_or(0, |first| last_ts - first);
+            if started.elapsed() >= duration || capture_elapsed >= duration.as_secs() as i64 {
+                break;
+            }
+        }
+
+        match source.next_packet() {
+            Ok(SourceEvent::Packet(raw)) => {
                 packet_count += 1;
-                
-                // Save to file if requested
-                if let Some(savefile) = &mut savefile {
-                    savefile.write(&packet);
-                }
+                byte_count += raw.caplen as u64;
+                first_ts.get_or_insert(raw.ts_sec);
+                last_ts = raw.ts_sec;
 
-                // Parse packet with etherparse
-                match SlicedPacket::from_ethernet(&packet.data) {
-                    Ok(sliced_packet) => {
-                        let (protocol_name, src_addr, dst_addr, src_port, dst_port) = 
-                            parse_packet_with_etherparse(&sliced_packet);
-                        
-                        // Update protocol statistics
-                        *protocol_stats.entry(protocol_name.clone()).or_insert(0) += 1;
-                        
-                        // Display packet information
-                        if options.verbose {
-                            println!("Packet #{}, Length: {} bytes", packet_count, packet.header.len);
-                            println!("  Protocol: {}", protocol_name);
-                            println!("  Source: {}:{}", src_addr, src_port.unwrap_or_default());
-                            println!("  Destination: {}:{}", dst_addr, dst_port.unwrap_or_default());
-                            print_protocol_details(&sliced_packet);
-                            println!("----------------------------------------");
-                        } else if packet_count % 10 == 0 {
-                            // Show brief status update every 10 packets
-                            println!("Captured {} packets...", packet_count);
+                // Parse packet with etherparse; a fragment completing a datagram is
+                // decoded again from the rebuilt frame so the transport is seen
+                let linktype = linktypes.get(raw.interface as usize).copied().unwrap_or(Linktype::ETHERNET);
+                let decoded = parse_packet_with_etherparse(packet_count, &raw, linktype);
+                let rebuilt = decoded.as_ref().ok().and_then(|packet| defrag.process(&raw, packet));
+                let parsed = match &rebuilt {
+                    Some((datagram, reassembled)) => {
+                        let mut decoded = parse_packet_with_etherparse(packet_count, datagram, linktype);
+                        if let Ok(decoded) = &mut decoded {
+                            // Lengths still describe the captured fragment
+                            decoded.caplen = raw.caplen;
+                            decoded.len = raw.len;
+                            decoded.reassembled = Some(*reassembled);
                         }
-                    }
-                    Err(e) => {
-                        if options.verbose {
-                            println!("Packet #{}: Error parsing packet - {}", packet_count, e);
+                        ParsedPacket {
+                            number: packet_count,
+                            raw: &raw,
+                            decoded,
+                            data: &datagram.data,
                         }
                     }
+                    None => ParsedPacket {
+                        number: packet_count,
+                        raw: &raw,
+                        decoded,
+                        data: &raw.data,
+                    },
+                };
+
+                if let Some(filter) = display_filter {
+                    if !parsed.decoded.as_ref().is_ok_and(|packet| filter.matches(packet)) {
+                        continue;
+                    }
+                }
+                if let Err(error) = sinks.iter_mut().try_for_each(|sink| sink.on_packet(&parsed)) {
+                    failure = Some(error);
+                    break;
                 }
             }
-            Err(pcap::Error::TimeoutExpired) => {
+            Ok(SourceEvent::Timeout) => {
                 // Timeout is expected, just continue
                 continue;
             }
-            Err(e) => {
-                eprintln!("Error: {}", e);
+            Ok(SourceEvent::Exhausted) => {
+                // End of savefile reached
                 break;
             }
+            Err(e) => {
+                let name = source.interfaces().first().map(|info| info.name.clone()).unwrap_or_default();
+                let error = CaptureError::from_boxed(&name, e);
+                let mut on_event = |event: &ReconnectEvent| sinks.iter_mut().for_each(|sink| sink.on_reconnect(event));
+                match reconnect(source, error, recovery, stop.stop_flag, &mut on_event) {
+                    Ok(gap) => {
+                        // A re-opened device may come back with a different datalink
+                        linktypes = source.interfaces().iter().map(|info| info.linktype).collect();
+                        for sink in sinks.iter_mut() {
+                            sink.on_gap(&gap);
+                        }
+                    }
+                    Err(error) => {
+                        failure = Some(Box::new(error));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let source_stats = source.stats();
+    if !source_stats.is_empty() {
+        for sink in sinks.iter_mut() {
+            sink.on_source_stats(&source_stats);
+        }
+    }
+
+    // One failing sink must not keep the others from writing their output
+    for sink in sinks.iter_mut() {
+        if let Err(error) = sink.finish() {
+            failure.get_or_insert(error);
+        }
+    }
+
+    match failure {
+        Some(error) => Err(error),
+        None => Ok(packet_count),
+    }
+}
+
+pub fn parse_packet_with_etherparse(
+    number: u32,
+    raw: &RawPacket,
+    linktype: Linktype,
+) -> Result<DecodedPacket, SliceError> {
+    let mut decoded = DecodedPacket {
+        number,
+        interface: raw.interface,
+        ts_sec: raw.ts_sec,
+        ts_usec: raw.ts_usec,
+        caplen: raw.caplen,
+        len: raw.len,
+        link: None,
+        network: None,
+        transport: None,
+        application: None,
+        payload: None,
+        reassembled: None,
+        encapsulation: Vec::new(),
+        wlan: None,
+    };
+
+    let data = &raw.data[..];
+    let sliced_packet = match linktype {
+        Linktype::LINUX_SLL => Some(SlicedPacket::from_linux_sll(data)?),
+        // SLL2: protocol type, then 18 bytes of interface and address details
+        Linktype::LINUX_SLL2 => {
+            let protocol = data.get(..2).map_or(0, |b| u16::from_be_bytes([b[0], b[1]]));
+            Some(SlicedPacket::from_ether_type(EtherType(protocol), data.get(20..).unwrap_or(&[]))?)
+        }
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => Some(SlicedPacket::from_ip(data)?),
+        // BSD loopback: a 4-byte address family, then the IP packet which says its own version
+        Linktype::NULL | Linktype::LOOP => Some(SlicedPacket::from_ip(data.get(4..).unwrap_or(&[]))?),
+        Linktype::IEEE802_11 | Linktype::IEEE802_11_RADIOTAP => {
+            match wifi::decode(data, linktype == Linktype::IEEE802_11_RADIOTAP) {
+                Some((wlan, inner)) => {
+                    decoded.wlan = Some(wlan);
+                    inner.and_then(|(ether_type, payload)| {
+                        SlicedPacket::from_ether_type(EtherType(ether_type), payload).ok()
+                    })
+                }
+                None => None,
+            }
         }
+        _ => Some(SlicedPacket::from_ethernet(data)?),
+    };
+    if let Some(sliced_packet) = &sliced_packet {
+        decode_layers(&mut decoded, &raw.data, sliced_packet);
     }
 
-    Ok(CaptureStats {
-        packet_count,
-        protocol_stats,
-    })
+    // MPLS and PPPoE, which etherparse leaves as Ethernet payload
+    if decoded.network.is_none() && linktype == Linktype::ETHERNET {
+        if let Some((encapsulation, inner)) = encap::link_encapsulation(&raw.data) {
+            decoded.encapsulation.push(encapsulation);
+            if let Some(Ok(sliced)) = inner.map(slice_inner) {
+                decode_layers(&mut decoded, &raw.data, &sliced);
+            }
+        }
+    }
+
+    // GRE, VXLAN and Geneve: the inner packet replaces the outer layers
+    while decoded.encapsulation.len() < encap::MAX_TUNNEL_DEPTH {
+        let Some((encapsulation, inner)) = encap::tunnel(&decoded, &raw.data) else {
+            break;
+        };
+        let Ok(sliced) = slice_inner(inner) else {
+            break;
+        };
+        decoded.encapsulation.push(encapsulation);
+        decoded.network = None;
+        decoded.transport = None;
+        decoded.payload = None;
+        decode_layers(&mut decoded, &raw.data, &sliced);
+    }
+
+    // Application layer, recognised by port
+    decoded.application = dns::decode(&decoded, &raw.data)
+        .map(ApplicationLayer::Dns)
+        .or_else(|| http::decode(&decoded, &raw.data).map(ApplicationLayer::Http))
+        .or_else(|| tls::decode(&decoded, &raw.data).map(|info| ApplicationLayer::Tls(Box::new(info))))
+        .or_else(|| dhcp::decode(&decoded, &raw.data))
+        .or_else(|| quic::decode(&decoded, &raw.data).map(|info| ApplicationLayer::Quic(Box::new(info))));
+
+    Ok(decoded)
 }
 
-fn parse_packet_with_etherparse(sliced_packet: &SlicedPacket) -> (String, String, String, Option<u16>, Option<u16>) {
-    let mut protocol_name = "Unknown".to_string();
-    let mut src_addr = "N/A".to_string();
-    let mut dst_addr = "N/A".to_string();
-    let mut src_port = None;
-    let mut dst_port = None;
+// Fill the link, network and transport layers from one etherparse slicing. `data` is
+// the whole captured buffer, which encapsulated packets are sub-slices of.
+fn decode_layers(decoded: &mut DecodedPacket, data: &[u8], sliced_packet: &SlicedPacket) {
+    // Link layer; an Ethernet frame inside a tunnel keeps the outer link
+    if let (Some(etherparse::LinkSlice::Ethernet2(eth)), None) = (&sliced_packet.link, &decoded.link) {
+        decoded.link = Some(LinkLayer {
+            src_mac: MacAddr(eth.source()),
+            dst_mac: MacAddr(eth.destination()),
+            ether_type: eth.ether_type().0,
+            vlan_tags: vlan_tags(sliced_packet),
+        });
+    }
 
     // Determine network layer protocol
     if let Some(net) = &sliced_packet.net {
         match net {
             etherparse::NetSlice::Ipv4(ipv4) => {
-                src_addr = ipv4.header().source_addr().to_string();
-                dst_addr = ipv4.header().destination_addr().to_string();
-                
-                // Check transport layer protocol
-                if let Some(trans) = &sliced_packet.transport {
-                    match trans {
-                        etherparse::TransportSlice::Tcp(tcp) => {
-                            protocol_name = "TCP".to_string();
-                            src_port = Some(tcp.source_port());
-                            dst_port = Some(tcp.destination_port());
-                        }
-                        etherparse::TransportSlice::Udp(udp) => {
-                            protocol_name = "UDP".to_string();
-                            src_port = Some(udp.source_port());
-                            dst_port = Some(udp.destination_port());
-                        }
-                        etherparse::TransportSlice::Icmpv4(icmp) => {
-                            protocol_name = format!("ICMPv4 ({:?})", icmp.icmp_type());
-                        }
-                        etherparse::TransportSlice::Icmpv6(icmp) => {
-                            protocol_name = format!("ICMPv6 ({:?})", icmp.icmp_type());
-                        }
-                        // Removed the unreachable catch-all pattern
-                    }
+                let header = ipv4.header();
+                let offset = header.fragments_offset().value() * 8;
+                let fragment = if header.more_fragments() || offset > 0 {
+                    Some(FragmentInfo {
+                        id: header.identification() as u32,
+                        offset,
+                        more_fragments: header.more_fragments(),
+                        dont_fragment: header.dont_fragment(),
+                        header_offset: payload_range(data, header.slice()).offset,
+                    })
                 } else {
-                    protocol_name = "IPv4".to_string();
-                }
+                    None
+                };
+
+                decoded.network = Some(NetworkLayer::Ipv4(IpLayer {
+                    src: header.source_addr().into(),
+                    dst: header.destination_addr().into(),
+                    ttl: header.ttl(),
+                    ip_id: Some(header.identification()),
+                    protocol: header.protocol().0,
+                    fragment,
+                }));
+                decoded.payload = Some(payload_range(data, ipv4.payload().payload));
             }
             etherparse::NetSlice::Ipv6(ipv6) => {
-                src_addr = ipv6.header().source_addr().to_string();
-                dst_addr = ipv6.header().destination_addr().to_string();
-                
-                if let Some(trans) = &sliced_packet.transport {
-                    match trans {
-                        etherparse::TransportSlice::Tcp(tcp) => {
-                            protocol_name = "TCP/IPv6".to_string();
-                            src_port = Some(tcp.source_port());
-                            dst_port = Some(tcp.destination_port());
-                        }
-                        etherparse::TransportSlice::Udp(udp) => {
-                            protocol_name = "UDP/IPv6".to_string();
-                            src_port = Some(udp.source_port());
-                            dst_port = Some(udp.destination_port());
-                        }
-                        _ => {
-                            protocol_name = "IPv6 (Other Transport)".to_string();
-                        }
-                    }
-                } else {
-                    protocol_name = "IPv6".to_string();
-                }
+                let header = ipv6.header();
+                let fixed = payload_range(data, header.slice());
+                let payload = payload_range(data, ipv6.payload().payload);
+                let extensions = data.get(fixed.offset + fixed.len..payload.offset).unwrap_or(&[]);
+
+                decoded.network = Some(NetworkLayer::Ipv6(IpLayer {
+                    src: header.source_addr().into(),
+                    dst: header.destination_addr().into(),
+                    ttl: header.hop_limit(),
+                    ip_id: None,
+                    // Protocol after any extension headers
+                    protocol: ipv6.payload().ip_number.0,
+                    fragment: ipv6_fragment(extensions, header.next_header().0, fixed.offset),
+                }));
+                decoded.payload = Some(payload);
             }
-            etherparse::NetSlice::Arp(_arp) => { // Fixed: added underscore to indicate unused variable
-                protocol_name = "ARP".to_string();
+            etherparse::NetSlice::Arp(arp) => {
+                decoded.network = ArpInfo::parse(arp.slice()).map(NetworkLayer::Arp);
             }
         }
-    } else if sliced_packet.link.is_some() {
-        // Link layer protocols
-        protocol_name = match sliced_packet.vlan() {
-            Some(_) => "VLAN".to_string(),
-            None => "Ethernet".to_string(),
-        };
     }
-    
-    (protocol_name, src_addr, dst_addr, src_port, dst_port)
-}
 
-fn print_protocol_details(sliced_packet: &SlicedPacket) {
+    // Check transport layer protocol
     if let Some(trans) = &sliced_packet.transport {
         match trans {
             etherparse::TransportSlice::Tcp(tcp) => {
-                let tcp_header = tcp.to_header();
-                println!("  TCP Flags: FIN={}, SYN={}, RST={}, PSH={}, ACK={}, URG={}, ECE={}, CWR={}",
-                    tcp_header.fin, tcp_header.syn, tcp_header.rst, tcp_header.psh,
-                    tcp_header.ack, tcp_header.urg, tcp_header.ece, tcp_header.cwr);
-                println!("  Sequence Number: {}", tcp.sequence_number());
-                println!("  Acknowledgment Number: {}", tcp.acknowledgment_number());
-                println!("  Window Size: {}", tcp.window_size());
+                decoded.transport = Some(TransportLayer::Tcp(TcpInfo {
+                    src_port: tcp.source_port(),
+                    dst_port: tcp.destination_port(),
+                    flags: TcpFlags {
+                        fin: tcp.fin(),
+                        syn: tcp.syn(),
+                        rst: tcp.rst(),
+                        psh: tcp.psh(),
+                        ack: tcp.ack(),
+                        urg: tcp.urg(),
+                        ece: tcp.ece(),
+                        cwr: tcp.cwr(),
+                    },
+                    seq: tcp.sequence_number(),
+                    ack: tcp.acknowledgment_number(),
+                    window: tcp.window_size(),
+                }));
+                decoded.payload = Some(payload_range(data, tcp.payload()));
             }
             etherparse::TransportSlice::Udp(udp) => {
-                println!("  UDP Length: {}", udp.length());
-                println!("  Checksum: 0x{:04x}", udp.checksum());
+                decoded.transport = Some(TransportLayer::Udp(UdpInfo {
+                    src_port: udp.source_port(),
+                    dst_port: udp.destination_port(),
+                    length: udp.length(),
+                    checksum: udp.checksum(),
+                }));
+                decoded.payload = Some(payload_range(data, udp.payload()));
+            }
+            etherparse::TransportSlice::Icmpv4(slice) => {
+                decoded.transport = Some(TransportLayer::Icmpv4(icmp::decode_icmpv4(
+                    slice.type_u8(),
+                    slice.code_u8(),
+                    slice.bytes5to8(),
+                    slice.payload(),
+                )));
+                decoded.payload = Some(payload_range(data, slice.payload()));
+            }
+            etherparse::TransportSlice::Icmpv6(slice) => {
+                decoded.transport = Some(TransportLayer::Icmpv6(icmp::decode_icmpv6(
+                    slice.type_u8(),
+                    slice.code_u8(),
+                    slice.bytes5to8(),
+                    slice.payload(),
+                )));
+                decoded.payload = Some(payload_range(data, slice.payload()));
+            }
+        }
+    }
+}
+
+fn slice_inner(inner: Inner) -> Result<SlicedPacket, SliceError> {
+    match inner {
+        Inner::Ethernet(data) => SlicedPacket::from_ethernet(data),
+        Inner::Ip(data) => SlicedPacket::from_ip(data),
+    }
+}
+
+fn vlan_tags(sliced_packet: &SlicedPacket) -> Vec<VlanTag> {
+    let tag = |vlan: &etherparse::SingleVlanSlice| VlanTag {
+        id: vlan.vlan_identifier().value(),
+        pcp: vlan.priority_code_point().value(),
+        dei: vlan.drop_eligible_indicator(),
+    };
+
+    match sliced_packet.vlan() {
+        Some(etherparse::VlanSlice::SingleVlan(vlan)) => vec![tag(&vlan)],
+        Some(etherparse::VlanSlice::DoubleVlan(vlan)) => vec![tag(&vlan.outer), tag(&vlan.inner)],
+        None => Vec::new(),
+    }
+}
+
+// Fragment header of an IPv6 packet, found by walking the extension headers between
+// the fixed header and the payload. Atomic fragments (offset 0, no more) count as whole.
+fn ipv6_fragment(extensions: &[u8], mut next_header: u8, header_offset: usize) -> Option<FragmentInfo> {
+    let mut rest = extensions;
+    loop {
+        let len = match next_header {
+            44 => {
+                let header = rest.get(..8)?;
+                let field = u16::from_be_bytes([header[2], header[3]]);
+                let offset = field & 0xfff8;
+                let more_fragments = field & 1 != 0;
+                if offset == 0 && !more_fragments {
+                    return None;
+                }
+                return Some(FragmentInfo {
+                    id: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+                    offset,
+                    more_fragments,
+                    dont_fragment: false,
+                    header_offset,
+                });
             }
-            _ => {}
+            // Hop-by-hop, routing and destination options
+            0 | 43 | 60 => (*rest.get(1)? as usize + 1) * 8,
+            // Authentication header
+            51 => (*rest.get(1)? as usize + 2) * 4,
+            _ => return None,
+        };
+        next_header = *rest.first()?;
+        rest = rest.get(len..)?;
+    }
+}
+
+// Locate a sub-slice produced by etherparse within the original packet buffer
+fn payload_range(data: &[u8], payload: &[u8]) -> PayloadRange {
+    PayloadRange {
+        offset: payload.as_ptr() as usize - data.as_ptr() as usize,
+        len: payload.len(),
+    }
+}
+
+pub fn print_protocol_details(packet: &DecodedPacket) {
+    let _ = write_protocol_details(&mut io::stdout().lock(), packet);
+}
+
+pub fn write_protocol_details(out: &mut dyn Write, packet: &DecodedPacket) -> io::Result<()> {
+    if let Some(reassembled) = &packet.reassembled {
+        writeln!(out, "  Reassembled: {} bytes from {} fragments", reassembled.length, reassembled.fragments)?;
+    }
+    if let Some(trans) = &packet.transport {
+        match trans {
+            TransportLayer::Tcp(tcp) => {
+                let flags = &tcp.flags;
+                writeln!(out, "  TCP Flags: FIN={}, SYN={}, RST={}, PSH={}, ACK={}, URG={}, ECE={}, CWR={}",
+                    flags.fin, flags.syn, flags.rst, flags.psh,
+                    flags.ack, flags.urg, flags.ece, flags.cwr)?;
+                writeln!(out, "  Sequence Number: {}", tcp.seq)?;
+                writeln!(out, "  Acknowledgment Number: {}", tcp.ack)?;
+                writeln!(out, "  Window Size: {}", tcp.window)?;
+            }
+            TransportLayer::Udp(udp) => {
+                writeln!(out, "  UDP Length: {}", udp.length)?;
+                writeln!(out, "  Checksum: 0x{:04x}", udp.checksum)?;
+            }
+            TransportLayer::Icmpv4(info) => icmp::write_icmp_details(out, info, false)?,
+            TransportLayer::Icmpv6(info) => icmp::write_icmp_details(out, info, true)?,
         }
     }
+    if let Some(info) = packet.arp() {
+        arp::write_arp_details(out, info)?;
+    }
+    if let Some(info) = &packet.wlan {
+        wifi::write_wlan_details(out, info)?;
+    }
     
-    // Print VLAN information if present
-    if let Some(_vlan) = sliced_packet.vlan() { // Fixed: added underscore to indicate unused variable
-        println!("  VLAN present");
+    if let Some(dns) = packet.dns() {
+        dns::write_dns_details(out, dns)?;
+    }
+    if let Some(message) = packet.http() {
+        http::write_http_details(out, message)?;
+    }
+    if let Some(info) = packet.tls() {
+        tls::write_tls_details(out, info)?;
+    }
+    if let Some(info) = packet.quic() {
+        quic::write_quic_details(out, info)?;
+    }
+    match &packet.application {
+        Some(ApplicationLayer::Dhcp(message)) => dhcp::write_dhcp_details(out, message)?,
+        Some(ApplicationLayer::Dhcpv6(message)) => dhcp::write_dhcpv6_details(out, message)?,
+        _ => {}
     }
-}
\ No newline at end of file
+
+    // VLAN tags, outermost first; the outer one of a double-tagged frame is the 802.1ad S-tag
+    if let Some(link) = &packet.link {
+        let double_tagged = link.vlan_tags.len() > 1;
+        for (index, tag) in link.vlan_tags.iter().enumerate() {
+            let kind = if double_tagged && index == 0 { "802.1ad" } else { "802.1Q" };
+            writeln!(out, "  VLAN: {} id={} pcp={} dei={}", kind, tag.id, tag.pcp, tag.dei)?;
+        }
+    }
+    for encapsulation in &packet.encapsulation {
+        writeln!(out, "  Encapsulation: {}", encapsulation)?;
+    }
+    Ok(())
+}
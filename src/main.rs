@@ -1,14 +1,31 @@
-mod listening;
-
-use clap::{Arg, Command};
-use listening::{CaptureOptions, list_interfaces}; // Removed unused imports
+use clap::{Arg, ArgAction, Command};
+use std::io::{self, Write};
 use std::process;
+use std::time::Duration;
+use pcap::{Capture, Linktype};
+use testgame::bpf;
+use testgame::dhcp::write_dhcp_summary;
+use testgame::display_filter::DisplayFilter;
+use testgame::dns::write_dns_summary;
+use testgame::endpoints::{self, write_endpoint_summary};
+use testgame::export::ExportFormat;
+use testgame::flow::{write_flow_summary, DEFAULT_ACTIVE_TIMEOUT, DEFAULT_IDLE_TIMEOUT};
+use testgame::http::write_http_summary;
+use testgame::listening::{self, CaptureOptions, list_interfaces};
+use testgame::pcapng::CommentMode;
+use testgame::quic::write_quic_summary;
+use testgame::reassembly::ReassemblyConfig;
+use testgame::recovery::{ReconnectEvent, RecoveryMode, RecoveryPolicy};
+use testgame::report::{largest_first, sorted_counts};
+use testgame::rotate::RotationPolicy;
+use testgame::timeseries::{write_interval_summary, IntervalConfig, IntervalFormat, DEFAULT_HISTORY};
+use testgame::tls::write_tls_summary;
+
+// Entries in each top list of the summary when --top is not given
+const DEFAULT_TOP: usize = 10;
+const DEFAULT_TOP_FLOWS: usize = 20;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Set console window size (Windows only)
-    #[cfg(windows)]
-    set_console_size(150, 30);
-    
     // Parse command line arguments
     let matches = Command::new("PacketCaptureTool")
         .version("1.0")
@@ -28,7 +45,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .short('i')
                 .long("interface")
                 .value_name("INTERFACE")
-                .help("Network interface to use (default: auto-detect)")
+                .action(ArgAction::Append)
+                .help("Network interface to use; repeat for several, 'any' for all (default: auto-detect)")
         )
         .arg(
             Arg::new("filter")
@@ -37,6 +55,40 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("FILTER")
                 .help("BPF filter expression (e.g., 'udp', 'tcp port 80')")
         )
+        .arg(
+            Arg::new("filter-file")
+                .short('F')
+                .long("filter-file")
+                .value_name("FILE")
+                .conflicts_with("filter")
+                .help("Read the BPF filter from FILE; '#' starts a comment")
+        )
+        .arg(
+            Arg::new("check-filter")
+                .long("check-filter")
+                .action(ArgAction::SetTrue)
+                .help("Compile the BPF filter for the target datalink and exit without capturing")
+        )
+        .arg(
+            Arg::new("dump-bpf")
+                .short('d')
+                .long("dump-bpf")
+                .action(ArgAction::SetTrue)
+                .help("Print the compiled BPF program (like tcpdump -d) and exit")
+        )
+        .arg(
+            Arg::new("datalink")
+                .long("datalink")
+                .value_name("DLT")
+                .help("Datalink to compile for with --check-filter, e.g. EN10MB or LINUX_SLL (default: from --read or the interface)")
+        )
+        .arg(
+            Arg::new("display-filter")
+                .short('Y')
+                .long("display-filter")
+                .value_name("EXPR")
+                .help("Only process packets matching a Wireshark-style expression (e.g., 'tcp.port == 443 && ip.dst in 10.0.0.0/8')")
+        )
         .arg(
             Arg::new("promiscuous")
                 .short('p')
@@ -48,7 +100,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .short('o')
                 .long("output")
                 .value_name("FILE")
-                .help("Save packets to file (PCAP format)")
+                .help("Save packets to file (PCAP format, or PCAPNG for *.pcapng)")
+        )
+        .arg(
+            Arg::new("pcapng")
+                .long("pcapng")
+                .action(ArgAction::SetTrue)
+                .help("Write the output file as PCAPNG regardless of its extension")
+        )
+        .arg(
+            Arg::new("packet-comments")
+                .long("packet-comments")
+                .value_name("MODE")
+                .value_parser(["none", "protocol", "anomaly"])
+                .default_value("none")
+                .help("Attach per-packet comments to PCAPNG output")
         )
         .arg(
             Arg::new("count")
@@ -57,12 +123,169 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("NUM")
                 .help("Stop after capturing NUM packets")
         )
+        .arg(
+            Arg::new("duration")
+                .long("duration")
+                .value_name("SECS")
+                .help("Stop after SECS seconds")
+        )
+        .arg(
+            Arg::new("max-bytes")
+                .long("max-bytes")
+                .value_name("BYTES")
+                .help("Stop after capturing BYTES bytes")
+        )
+        .arg(
+            Arg::new("rotate-seconds")
+                .short('G')
+                .long("rotate-seconds")
+                .value_name("SECS")
+                .help("Start a new output file every SECS seconds")
+        )
+        .arg(
+            Arg::new("rotate-size")
+                .short('C')
+                .long("rotate-size")
+                .value_name("MB")
+                .help("Start a new output file once the current one reaches MB megabytes")
+        )
+        .arg(
+            Arg::new("rotate-files")
+                .short('W')
+                .long("rotate-files")
+                .value_name("NUM")
+                .help("Keep at most NUM rotated output files, deleting the oldest")
+        )
+        .arg(
+            Arg::new("on-error")
+                .long("on-error")
+                .value_name("ACTION")
+                .value_parser(["abort", "reconnect"])
+                .default_value("abort")
+                .help("What to do when the device fails mid-capture")
+        )
+        .arg(
+            Arg::new("max-reconnects")
+                .long("max-reconnects")
+                .value_name("NUM")
+                .help("Give up after NUM failed reconnect attempts in a row (default: unlimited)")
+        )
+        .arg(
+            Arg::new("reconnect-backoff")
+                .long("reconnect-backoff")
+                .value_name("SECS")
+                .help("Longest wait between reconnect attempts (default: 60)")
+        )
+        .arg(
+            Arg::new("dump-streams")
+                .long("dump-streams")
+                .value_name("DIR")
+                .help("Write each reassembled TCP stream direction to a file in DIR")
+        )
+        .arg(
+            Arg::new("stream-memory")
+                .long("stream-memory")
+                .value_name("MB")
+                .help("Memory cap for out-of-order TCP data across all streams (default: 64)")
+        )
+        .arg(
+            Arg::new("read")
+                .short('r')
+                .long("read")
+                .value_name("FILE")
+                .help("Read packets from a PCAP/PCAPNG file instead of a live interface")
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(["json", "jsonl", "csv", "text"])
+                .default_value("text")
+                .help("Per-packet output format")
+        )
+        .arg(
+            Arg::new("export")
+                .long("export")
+                .value_name("FILE")
+                .help("Write per-packet records to FILE instead of stdout")
+        )
+        .arg(
+            Arg::new("flows")
+                .long("flows")
+                .action(ArgAction::SetTrue)
+                .help("Track bidirectional flows and list the top conversations at the end")
+        )
+        .arg(
+            Arg::new("flow-idle-timeout")
+                .long("flow-idle-timeout")
+                .value_name("SECS")
+                .help("Expire flows idle for SECS seconds (default: 60)")
+        )
+        .arg(
+            Arg::new("flow-active-timeout")
+                .long("flow-active-timeout")
+                .value_name("SECS")
+                .help("Expire flows active for longer than SECS seconds (default: 1800)")
+        )
         .arg(
             Arg::new("list")
                 .short('l')
                 .long("list")
                 .help("List available interfaces and exit")
         )
+        .arg(
+            Arg::new("endpoints")
+                .long("endpoints")
+                .action(ArgAction::SetTrue)
+                .help("Report top talkers by IP and MAC, destination ports and packet sizes at the end")
+        )
+        .arg(
+            Arg::new("talker-matrix")
+                .long("talker-matrix")
+                .value_name("FILE")
+                .help("Save who-talks-to-whom traffic to FILE, as Graphviz DOT for .dot/.gv and CSV otherwise")
+        )
+        .arg(
+            Arg::new("top")
+                .long("top")
+                .value_name("N")
+                .help("Number of entries in each top list of the summary (default: 10, 20 for flows)")
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .value_name("SECS")
+                .help("Report packets, bytes, protocols and new flows for every SECS seconds of capture time")
+        )
+        .arg(
+            Arg::new("interval-format")
+                .long("interval-format")
+                .value_name("FORMAT")
+                .value_parser(["table", "jsonl"])
+                .default_value("table")
+                .help("Format of interval reports")
+        )
+        .arg(
+            Arg::new("interval-output")
+                .long("interval-output")
+                .value_name("FILE")
+                .requires("interval")
+                .help("Write interval reports to FILE as they complete instead of the console")
+        )
+        .arg(
+            Arg::new("interval-history")
+                .long("interval-history")
+                .value_name("N")
+                .requires("interval")
+                .help("Number of recent intervals kept for the final summary (default: 60)")
+        )
+        .arg(
+            Arg::new("tui")
+                .long("tui")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .help("Show a full-screen live dashboard instead of console output (q quits, space pauses, / filters)")
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
@@ -79,26 +302,248 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         process::exit(0);
     }
 
+    let filter = match matches.get_one::<String>("filter-file") {
+        Some(path) => Some(bpf::read_filter_file(path)?),
+        None => matches.get_one::<String>("filter").cloned(),
+    };
+
+    if matches.get_flag("check-filter") || matches.get_flag("dump-bpf") {
+        let Some(filter) = filter else {
+            eprintln!("No filter to check; give one with --filter or --filter-file");
+            process::exit(2);
+        };
+        let linktype = check_filter_linktype(&matches)?;
+        match bpf::compile(&filter, linktype) {
+            Ok(program) => {
+                if matches.get_flag("dump-bpf") {
+                    for line in bpf::disassemble(&program) {
+                        println!("{}", line);
+                    }
+                } else {
+                    println!(
+                        "Filter OK for {}: {} ({} instructions)",
+                        bpf::linktype_name(linktype),
+                        filter,
+                        program.len()
+                    );
+                }
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Filter does not compile for {}: {}", bpf::linktype_name(linktype), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Compile the display filter before capturing so mistakes are reported up front
+    let display_filter = match matches.get_one::<String>("display-filter") {
+        Some(expression) => match DisplayFilter::parse(expression) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                eprintln!("Invalid display filter at {}", e);
+                eprintln!("{}", e.pointer(expression));
+                process::exit(2);
+            }
+        },
+        None => None,
+    };
+
     // Prepare capture options
     let options = CaptureOptions {
-        interface: matches.get_one::<String>("interface").cloned(),
-        filter: matches.get_one::<String>("filter").cloned(),
+        interfaces: matches.get_many::<String>("interface")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+        filter,
+        display_filter,
         promiscuous: matches.contains_id("promiscuous"),
         output_file: matches.get_one::<String>("output").cloned(),
         packet_limit: matches.get_one::<String>("count")
             .and_then(|s| s.parse::<u32>().ok()),
         verbose: matches.contains_id("verbose"),
+        read_file: matches.get_one::<String>("read").cloned(),
+        format: matches.get_one::<String>("format")
+            .and_then(|s| s.parse::<ExportFormat>().ok())
+            .unwrap_or_default(),
+        export_file: matches.get_one::<String>("export").cloned(),
+        flows: matches.get_flag("flows"),
+        flow_idle_timeout: matches.get_one::<String>("flow-idle-timeout")
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap_or(DEFAULT_IDLE_TIMEOUT),
+        flow_active_timeout: matches.get_one::<String>("flow-active-timeout")
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap_or(DEFAULT_ACTIVE_TIMEOUT),
+        stop_flag: Some(listening::install_interrupt_handler()?),
+        duration: matches.get_one::<String>("duration")
+            .and_then(|s| s.parse::<u64>().ok()),
+        max_bytes: matches.get_one::<String>("max-bytes")
+            .and_then(|s| s.parse::<u64>().ok()),
+        rotation: RotationPolicy {
+            every_seconds: matches.get_one::<String>("rotate-seconds")
+                .and_then(|s| s.parse::<u64>().ok()),
+            every_bytes: matches.get_one::<String>("rotate-size")
+                .and_then(|s| s.parse::<u64>().ok())
+                .map(|mb| mb * 1_000_000),
+            max_files: matches.get_one::<String>("rotate-files")
+                .and_then(|s| s.parse::<usize>().ok()),
+        },
+        pcapng: matches.get_flag("pcapng")
+            || matches.get_one::<String>("output").is_some_and(|f| f.ends_with(".pcapng")),
+        packet_comments: matches.get_one::<String>("packet-comments")
+            .and_then(|s| s.parse::<CommentMode>().ok())
+            .unwrap_or_default(),
+        recovery: RecoveryPolicy {
+            mode: matches.get_one::<String>("on-error")
+                .and_then(|s| s.parse::<RecoveryMode>().ok())
+                .unwrap_or_default(),
+            max_retries: matches.get_one::<String>("max-reconnects")
+                .and_then(|s| s.parse::<u32>().ok()),
+            max_backoff: matches.get_one::<String>("reconnect-backoff")
+                .and_then(|s| s.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(RecoveryPolicy::default().max_backoff),
+            ..RecoveryPolicy::default()
+        },
+        on_reconnect: Some(print_reconnect),
+        reassembly: ReassemblyConfig {
+            max_buffered_total: matches.get_one::<String>("stream-memory")
+                .and_then(|s| s.parse::<usize>().ok())
+                .map(|mb| mb * 1024 * 1024)
+                .unwrap_or(ReassemblyConfig::default().max_buffered_total),
+            ..ReassemblyConfig::default()
+        },
+        dump_streams: matches.get_one::<String>("dump-streams").cloned(),
+        tui: matches.get_flag("tui"),
+        endpoints: matches.get_flag("endpoints") || matches.contains_id("talker-matrix"),
+        intervals: IntervalConfig {
+            seconds: matches.get_one::<String>("interval")
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|secs| *secs > 0.0),
+            history: matches.get_one::<String>("interval-history")
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_HISTORY),
+            format: matches.get_one::<String>("interval-format")
+                .and_then(|s| s.parse::<IntervalFormat>().ok())
+                .unwrap_or_default(),
+            output: matches.get_one::<String>("interval-output").cloned(),
+        },
+    };
+    let interval_format = options.intervals.format;
+    let top = matches.get_one::<String>("top")
+        .and_then(|s| s.parse::<usize>().ok());
+    let (top, top_flows) = (top.unwrap_or(DEFAULT_TOP), top.unwrap_or(DEFAULT_TOP_FLOWS));
+
+    // Keep stdout clean when it carries packet records
+    let mut out: Box<dyn Write> = if options.records_on_stdout() {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
     };
 
     // Start capture
     match listening::start_capture(options) {
         Ok(stats) => {
             // Display final statistics
-            println!("\n=== Capture Summary ===");
-            println!("Total packets captured: {}", stats.packet_count);
-            println!("\n=== Protocol Statistics ===");
-            for (protocol, count) in &stats.protocol_stats {
-                println!("{}: {}", protocol, count);
+            writeln!(out, "\n=== Capture Summary ===")?;
+            writeln!(out, "Total packets captured: {}", stats.packet_count)?;
+            writeln!(out, "\n=== Protocol Statistics ===")?;
+            for (protocol, count) in sorted_counts(&stats.protocol_stats) {
+                writeln!(out, "{}: {}", protocol, count)?;
+            }
+            if !stats.message_types.is_empty() {
+                writeln!(out, "\n=== Message Types ===")?;
+                for (message_type, count) in sorted_counts(&stats.message_types).into_iter().take(top) {
+                    writeln!(out, "{}: {}", message_type, count)?;
+                }
+            }
+            if stats.interfaces.len() > 1 {
+                writeln!(out, "\n=== Per-Interface Statistics ===")?;
+                for interface in &stats.interfaces {
+                    write!(out, "{}: {} packets", interface.name, interface.packet_count)?;
+                    if let Some(dropped) = interface.dropped {
+                        write!(out, ", {} dropped", dropped)?;
+                    }
+                    writeln!(out)?;
+                    for (protocol, count) in sorted_counts(&interface.protocol_stats) {
+                        writeln!(out, "  {}: {}", protocol, count)?;
+                    }
+                }
+            }
+            for (title, counters) in [("Per-VLAN", &stats.vlans), ("Per-Tunnel", &stats.tunnels)] {
+                if counters.is_empty() {
+                    continue;
+                }
+                writeln!(out, "\n=== {} Statistics ===", title)?;
+                for (name, traffic) in largest_first(counters, |traffic| traffic.packets) {
+                    writeln!(out, "{}: {} packets, {} bytes", name, traffic.packets, traffic.bytes)?;
+                }
+            }
+            if !stats.dns.is_empty() {
+                write_dns_summary(&mut out, &stats.dns, top)?;
+            }
+            if !stats.dhcp.is_empty() {
+                write_dhcp_summary(&mut out, &stats.dhcp)?;
+            }
+            if !stats.http.is_empty() {
+                write_http_summary(&mut out, &stats.http, top)?;
+            }
+            if !stats.tls.is_empty() {
+                write_tls_summary(&mut out, &stats.tls, top)?;
+            }
+            if !stats.quic.is_empty() {
+                write_quic_summary(&mut out, &stats.quic, top)?;
+            }
+            if stats.streams.streams > 0 {
+                let streams = &stats.streams;
+                writeln!(out, "\n=== TCP Reassembly ===")?;
+                writeln!(
+                    out,
+                    "{} streams ({} finished, {} reset, {} timed out, {} evicted)",
+                    streams.streams, streams.finished, streams.reset, streams.timed_out, streams.evicted
+                )?;
+                writeln!(out, "{} bytes reassembled, {} bytes missing", streams.bytes, streams.missing_bytes)?;
+            }
+            if stats.fragments.fragments > 0 {
+                let fragments = &stats.fragments;
+                writeln!(out, "\n=== IP Fragments ===")?;
+                writeln!(
+                    out,
+                    "{} fragments, {} datagrams reassembled, {} timed out, {} evicted, {} incomplete",
+                    fragments.fragments, fragments.reassembled, fragments.timed_out, fragments.evicted, fragments.pending
+                )?;
+                if fragments.anomalies() > 0 {
+                    writeln!(
+                        out,
+                        "Anomalies: {} overlapping, {} tiny, {} oversized",
+                        fragments.overlapping, fragments.tiny, fragments.oversized
+                    )?;
+                }
+            }
+            if !stats.gaps.is_empty() {
+                writeln!(out, "\n=== Capture Gaps ===")?;
+                for gap in &stats.gaps {
+                    writeln!(
+                        out,
+                        "{}: {:.3}s after {} reconnect attempt(s) ({})",
+                        gap.device,
+                        gap.seconds(),
+                        gap.attempts,
+                        gap.reason
+                    )?;
+                }
+            }
+            if !stats.flows.is_empty() {
+                write_flow_summary(&mut out, &stats.flows, &stats.other_flows, top_flows)?;
+            }
+            if !stats.endpoints.is_empty() {
+                write_endpoint_summary(&mut out, &stats.endpoints, top)?;
+            }
+            if let Some(path) = matches.get_one::<String>("talker-matrix") {
+                endpoints::export_matrix(path, &stats.endpoints)?;
+                writeln!(out, "\nTalker matrix written to {}", path)?;
+            }
+            if !stats.intervals.is_empty() {
+                write_interval_summary(&mut out, &stats.intervals, interval_format)?;
             }
         }
         Err(e) => {
@@ -110,37 +555,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-// Set console window size (Windows only)
-#[cfg(windows)]
-fn set_console_size(width: u16, height: u16) {
-    use winapi::um::processenv::GetStdHandle;
-    use winapi::um::winbase::STD_OUTPUT_HANDLE;
- // Fixed import
-    use winapi::um::wincon::{SetConsoleWindowInfo, COORD, SMALL_RECT};
-
-    unsafe {
-        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
-        
-        // Set screen buffer size
-        let size = COORD { 
-            X: width as i16, // Fixed: cast u16 to i16
-            Y: 1000 
-        };
-        winapi::um::wincon::SetConsoleScreenBufferSize(handle, size);
-        
-        // Set window size
-        let rect = SMALL_RECT {
-            Left: 0,
-            Top: 0,
-            Right: (width - 1) as i16, // Fixed: cast u16 to i16
-            Bottom: (height - 1) as i16, // Fixed: cast u16 to i16
-        };
-        SetConsoleWindowInfo(handle, 1, &rect);
+// Reconnect progress goes to stderr, which stays clear of packet records on stdout
+fn print_reconnect(event: &ReconnectEvent) {
+    match event {
+        ReconnectEvent::Retrying { .. } => eprintln!("Warning: {}", event),
+        ReconnectEvent::Reconnected { .. } => eprintln!("{}", event),
     }
 }
 
-// For non-Windows platforms, do nothing
-#[cfg(not(windows))]
-fn set_console_size(_width: u16, _height: u16) {
-    // Not implemented for non-Windows platforms
-}
\ No newline at end of file
+// Datalink a filter is checked against: --datalink, else the savefile's, else the
+// first interface's, which is opened but not captured from
+fn check_filter_linktype(matches: &clap::ArgMatches) -> Result<Linktype, Box<dyn std::error::Error>> {
+    if let Some(name) = matches.get_one::<String>("datalink") {
+        return bpf::parse_linktype(name);
+    }
+    if let Some(path) = matches.get_one::<String>("read") {
+        return Ok(Capture::from_file(path)?.get_datalink());
+    }
+    match matches.get_one::<String>("interface") {
+        Some(name) if name != "any" => Ok(Capture::from_device(name.as_str())?.open()?.get_datalink()),
+        _ => Ok(Linktype::ETHERNET),
+    }
+}
@@ -0,0 +1,364 @@
+use crate::arp::{self, ArpInfo};
+use crate::dhcp::{self, DhcpMessage, Dhcpv6Message};
+use crate::dns::DnsMessage;
+use crate::encap::Encapsulation;
+use crate::http::HttpMessage;
+use crate::icmp::{self, EmbeddedPacket, NdpMessage};
+use crate::quic::QuicInfo;
+use crate::tls::TlsInfo;
+use crate::wifi::WlanInfo;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::net::IpAddr;
+
+// Typed view of a captured packet, filled in by `parse_packet_with_etherparse`
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedPacket {
+    pub number: u32,
+    // Index of the capture interface, see `PacketSource::interfaces`
+    pub interface: u32,
+    pub ts_sec: i64,
+    pub ts_usec: i64,
+    pub caplen: u32,
+    pub len: u32,
+    pub link: Option<LinkLayer>,
+    pub network: Option<NetworkLayer>,
+    pub transport: Option<TransportLayer>,
+    // Decoded application protocol carried in the payload, if recognised
+    pub application: Option<ApplicationLayer>,
+    // Byte range of the innermost payload within the captured data
+    pub payload: Option<PayloadRange>,
+    // Set on the fragment that completed an IP datagram; the layers above then
+    // describe the whole datagram, see `ParsedPacket::data`
+    pub reassembled: Option<Reassembled>,
+    // MPLS, PPPoE and tunnel headers around the decoded layers, outermost first
+    pub encapsulation: Vec<Encapsulation>,
+    // 802.11 header and radiotap fields for wireless captures
+    pub wlan: Option<WlanInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", b[0], b[1], b[2], b[3], b[4], b[5])
+    }
+}
+
+// Serialize as the usual colon-separated string rather than a byte array
+impl Serialize for MacAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkLayer {
+    pub src_mac: MacAddr,
+    pub dst_mac: MacAddr,
+    pub ether_type: u16,
+    // Outermost tag first
+    pub vlan_tags: Vec<VlanTag>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct VlanTag {
+    pub id: u16,
+    pub pcp: u8,
+    pub dei: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum NetworkLayer {
+    Ipv4(IpLayer),
+    Ipv6(IpLayer),
+    Arp(ArpInfo),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IpLayer {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    // TTL for IPv4, hop limit for IPv6
+    pub ttl: u8,
+    // Identification field, IPv4 only
+    pub ip_id: Option<u16>,
+    // IP protocol number / IPv6 next header of the payload
+    pub protocol: u8,
+    pub fragment: Option<FragmentInfo>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FragmentInfo {
+    // Identification shared by all fragments of a datagram (32 bits for IPv6)
+    pub id: u32,
+    // Offset in bytes (the header field is in 8-byte units)
+    pub offset: u16,
+    pub more_fragments: bool,
+    pub dont_fragment: bool,
+    // Where the IP header starts in the captured frame
+    #[serde(skip)]
+    pub header_offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Reassembled {
+    pub fragments: u32,
+    // Length of the IP payload after reassembly
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum TransportLayer {
+    Tcp(TcpInfo),
+    Udp(UdpInfo),
+    Icmpv4(IcmpInfo),
+    Icmpv6(IcmpInfo),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TcpInfo {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub flags: TcpFlags,
+    pub seq: u32,
+    pub ack: u32,
+    pub window: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TcpFlags {
+    pub fin: bool,
+    pub syn: bool,
+    pub rst: bool,
+    pub psh: bool,
+    pub ack: bool,
+    pub urg: bool,
+    pub ece: bool,
+    pub cwr: bool,
+}
+
+impl fmt::Display for TcpFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [
+            (self.syn, "SYN"),
+            (self.fin, "FIN"),
+            (self.rst, "RST"),
+            (self.psh, "PSH"),
+            (self.ack, "ACK"),
+            (self.urg, "URG"),
+            (self.ece, "ECE"),
+            (self.cwr, "CWR"),
+        ];
+        let set: Vec<&str> = names.iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+        write!(f, "{}", set.join(","))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UdpInfo {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub length: u16,
+    pub checksum: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IcmpInfo {
+    pub icmp_type: u8,
+    pub code: u8,
+    // Identifier and sequence number of echo requests and replies
+    pub echo: Option<(u16, u16)>,
+    // Next-hop MTU of "fragmentation needed" and "packet too big"
+    pub mtu: Option<u32>,
+    // Header of the packet an error message refers to
+    pub original: Option<EmbeddedPacket>,
+    // Neighbor Discovery, ICMPv6 only
+    pub ndp: Option<NdpMessage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum ApplicationLayer {
+    Dns(DnsMessage),
+    Http(HttpMessage),
+    Tls(Box<TlsInfo>),
+    Dhcp(Box<DhcpMessage>),
+    Dhcpv6(Box<Dhcpv6Message>),
+    Quic(Box<QuicInfo>),
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PayloadRange {
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl DecodedPacket {
+    // Protocol label used for display and for `protocol_stats` buckets
+    pub fn protocol_name(&self) -> String {
+        match &self.application {
+            Some(ApplicationLayer::Dns(_)) => return "DNS".to_string(),
+            Some(ApplicationLayer::Http(_)) => return "HTTP".to_string(),
+            Some(ApplicationLayer::Tls(_)) => return "TLS".to_string(),
+            Some(ApplicationLayer::Dhcp(_)) => return "DHCP".to_string(),
+            Some(ApplicationLayer::Dhcpv6(_)) => return "DHCPv6".to_string(),
+            Some(ApplicationLayer::Quic(_)) => return "QUIC".to_string(),
+            None => {}
+        }
+        let ipv6 = matches!(self.network, Some(NetworkLayer::Ipv6(_)));
+        match (&self.network, &self.transport) {
+            (_, Some(TransportLayer::Tcp(_))) if ipv6 => "TCP/IPv6".to_string(),
+            (_, Some(TransportLayer::Tcp(_))) => "TCP".to_string(),
+            (_, Some(TransportLayer::Udp(_))) if ipv6 => "UDP/IPv6".to_string(),
+            (_, Some(TransportLayer::Udp(_))) => "UDP".to_string(),
+            (_, Some(TransportLayer::Icmpv4(_))) => "ICMPv4".to_string(),
+            (_, Some(TransportLayer::Icmpv6(_))) => "ICMPv6".to_string(),
+            (Some(NetworkLayer::Ipv4(ip)), None) if ip.fragment.is_some() => "IPv4 fragment".to_string(),
+            (Some(NetworkLayer::Ipv6(ip)), None) if ip.fragment.is_some() => "IPv6 fragment".to_string(),
+            (Some(NetworkLayer::Ipv4(_)), None) => "IPv4".to_string(),
+            (Some(NetworkLayer::Ipv6(_)), None) => "IPv6".to_string(),
+            (Some(NetworkLayer::Arp(_)), None) => "ARP".to_string(),
+            (None, None) if self.wlan.is_some() => {
+                format!("802.11 {}", self.wlan.as_ref().map_or("", |wlan| wlan.type_name()))
+            }
+            (None, None) if !self.encapsulation.is_empty() => {
+                self.encapsulation.last().map_or("Unknown", |e| e.kind()).to_string()
+            }
+            (None, None) => match &self.link {
+                Some(link) if !link.vlan_tags.is_empty() => "VLAN".to_string(),
+                Some(_) => "Ethernet".to_string(),
+                None => "Unknown".to_string(),
+            },
+        }
+    }
+
+    // Message type within the protocol, counted apart from `protocol_name` so
+    // each ICMP, DHCP or 802.11 type doesn't get its own protocol bucket
+    pub fn message_type(&self) -> Option<String> {
+        match &self.application {
+            Some(ApplicationLayer::Dhcp(message)) => {
+                let kind = message.message_type.map_or("BOOTP".to_string(), dhcp::message_type_name);
+                return Some(format!("DHCP {}", kind));
+            }
+            Some(ApplicationLayer::Dhcpv6(message)) => {
+                return Some(format!("DHCPv6 {}", dhcp::v6_message_type_name(message.message_type)));
+            }
+            Some(ApplicationLayer::Quic(info)) => return Some(format!("QUIC {}", info.packet_type.name())),
+            _ => {}
+        }
+        match (&self.network, &self.transport) {
+            (Some(NetworkLayer::Arp(arp)), _) => Some(format!("ARP {}", arp::operation_name(arp.operation))),
+            (_, Some(TransportLayer::Icmpv4(icmp))) => Some(format!("ICMPv4 {}", icmp::icmpv4_type_name(icmp.icmp_type))),
+            (_, Some(TransportLayer::Icmpv6(icmp))) => Some(format!("ICMPv6 {}", icmp::icmpv6_type_name(icmp.icmp_type))),
+            _ => self.wlan.as_ref().map(|wlan| format!("802.11 {}", wlan.subtype_name())),
+        }
+    }
+
+    // VLAN the frame was seen on, "outer.inner" when double tagged
+    pub fn vlan_key(&self) -> Option<String> {
+        let tags = &self.link.as_ref()?.vlan_tags;
+        if tags.is_empty() {
+            return None;
+        }
+        let ids: Vec<String> = tags.iter().map(|tag| tag.id.to_string()).collect();
+        Some(ids.join("."))
+    }
+
+    // Capture time in seconds since the epoch
+    pub fn timestamp(&self) -> f64 {
+        self.ts_sec as f64 + self.ts_usec as f64 / 1_000_000.0
+    }
+
+    pub fn ip(&self) -> Option<&IpLayer> {
+        match &self.network {
+            Some(NetworkLayer::Ipv4(ip)) | Some(NetworkLayer::Ipv6(ip)) => Some(ip),
+            _ => None,
+        }
+    }
+
+    pub fn src_addr(&self) -> Option<IpAddr> {
+        self.ip().map(|ip| ip.src)
+    }
+
+    pub fn dst_addr(&self) -> Option<IpAddr> {
+        self.ip().map(|ip| ip.dst)
+    }
+
+    pub fn src_port(&self) -> Option<u16> {
+        match &self.transport {
+            Some(TransportLayer::Tcp(tcp)) => Some(tcp.src_port),
+            Some(TransportLayer::Udp(udp)) => Some(udp.src_port),
+            _ => None,
+        }
+    }
+
+    pub fn dst_port(&self) -> Option<u16> {
+        match &self.transport {
+            Some(TransportLayer::Tcp(tcp)) => Some(tcp.dst_port),
+            Some(TransportLayer::Udp(udp)) => Some(udp.dst_port),
+            _ => None,
+        }
+    }
+
+    pub fn tcp(&self) -> Option<&TcpInfo> {
+        match &self.transport {
+            Some(TransportLayer::Tcp(tcp)) => Some(tcp),
+            _ => None,
+        }
+    }
+
+    pub fn arp(&self) -> Option<&ArpInfo> {
+        match &self.network {
+            Some(NetworkLayer::Arp(arp)) => Some(arp),
+            _ => None,
+        }
+    }
+
+    pub fn dns(&self) -> Option<&DnsMessage> {
+        match &self.application {
+            Some(ApplicationLayer::Dns(dns)) => Some(dns),
+            _ => None,
+        }
+    }
+
+    pub fn http(&self) -> Option<&HttpMessage> {
+        match &self.application {
+            Some(ApplicationLayer::Http(http)) => Some(http),
+            _ => None,
+        }
+    }
+
+    pub fn tls(&self) -> Option<&TlsInfo> {
+        match &self.application {
+            Some(ApplicationLayer::Tls(tls)) => Some(tls.as_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn quic(&self) -> Option<&QuicInfo> {
+        match &self.application {
+            Some(ApplicationLayer::Quic(quic)) => Some(quic.as_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn udp(&self) -> Option<&UdpInfo> {
+        match &self.transport {
+            Some(TransportLayer::Udp(udp)) => Some(udp),
+            _ => None,
+        }
+    }
+
+    // Slice the payload out of the captured bytes this packet was decoded from
+    pub fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        match self.payload {
+            Some(range) => data.get(range.offset..range.offset + range.len).unwrap_or(&[]),
+            None => &[],
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use crate::icmp;
+use crate::listening::ParsedPacket;
+use crate::packet::{ApplicationLayer, TransportLayer};
+use crate::recovery::CaptureGap;
+use crate::sink::PacketSink;
+use crate::source::{InterfaceInfo, SourceStats};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+// Block types (pcapng spec, section 4)
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_INTERFACE_STATISTICS: u32 = 0x0000_0005;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+// Option codes
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const ISB_STARTTIME: u16 = 2;
+const ISB_ENDTIME: u16 = 3;
+const ISB_IFRECV: u16 = 4;
+const ISB_IFDROP: u16 = 5;
+const ISB_USRDELIV: u16 = 8;
+
+// Block body builder that takes care of option padding
+struct BlockBody {
+    bytes: Vec<u8>,
+    has_options: bool,
+}
+
+impl BlockBody {
+    fn new() -> Self {
+        BlockBody { bytes: Vec::new(), has_options: false }
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn padded(&mut self, data: &[u8]) {
+        self.bytes.extend_from_slice(data);
+        self.bytes.resize(self.bytes.len() + pad_len(data.len()), 0);
+    }
+
+    fn option(&mut self, code: u16, value: &[u8]) {
+        self.u16(code);
+        self.u16(value.len() as u16);
+        self.padded(value);
+        self.has_options = true;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.has_options {
+            self.u16(OPT_END);
+            self.u16(0);
+        }
+        self.bytes
+    }
+}
+
+fn pad_len(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+// Timestamps are written with the default microsecond resolution, split in two u32 halves
+fn timestamp_parts(ts_sec: i64, ts_usec: i64) -> (u32, u32) {
+    let micros = (ts_sec as u64).wrapping_mul(1_000_000).wrapping_add(ts_usec as u64);
+    ((micros >> 32) as u32, micros as u32)
+}
+
+fn timestamp_bytes(ts_sec: i64, ts_usec: i64) -> [u8; 8] {
+    let (high, low) = timestamp_parts(ts_sec, ts_usec);
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&high.to_le_bytes());
+    bytes[4..].copy_from_slice(&low.to_le_bytes());
+    bytes
+}
+
+// Low-level pcapng writer: one section, any number of interfaces
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+    interfaces: u32,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    pub fn new(writer: W, application: &str) -> io::Result<Self> {
+        let mut pcapng = PcapngWriter { writer, interfaces: 0 };
+
+        let mut body = BlockBody::new();
+        body.u32(BYTE_ORDER_MAGIC);
+        body.u16(1); // major version
+        body.u16(0); // minor version
+        body.i64(-1); // section length not specified
+        body.option(SHB_USERAPPL, application.as_bytes());
+        pcapng.write_block(BLOCK_SECTION_HEADER, &body.finish())?;
+        Ok(pcapng)
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let total_len = (12 + body.len()) as u32;
+        self.writer.write_all(&block_type.to_le_bytes())?;
+        self.writer.write_all(&total_len.to_le_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(&total_len.to_le_bytes())
+    }
+
+    // Describe an interface; returns the id used by packet and statistics blocks
+    pub fn add_interface(&mut self, linktype: u16, snaplen: u32, info: &InterfaceInfo) -> io::Result<u32> {
+        let mut body = BlockBody::new();
+        body.u16(linktype);
+        body.u16(0); // reserved
+        body.u32(snaplen);
+        body.option(IF_NAME, info.name.as_bytes());
+        if let Some(description) = &info.description {
+            body.option(IF_DESCRIPTION, description.as_bytes());
+        }
+        self.write_block(BLOCK_INTERFACE_DESCRIPTION, &body.finish())?;
+
+        let id = self.interfaces;
+        self.interfaces += 1;
+        Ok(id)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_packet(
+        &mut self,
+        interface_id: u32,
+        ts_sec: i64,
+        ts_usec: i64,
+        caplen: u32,
+        len: u32,
+        data: &[u8],
+        comment: Option<&str>,
+    ) -> io::Result<()> {
+        let (high, low) = timestamp_parts(ts_sec, ts_usec);
+        let mut body = BlockBody::new();
+        body.u32(interface_id);
+        body.u32(high);
+        body.u32(low);
+        body.u32(caplen);
+        body.u32(len);
+        body.padded(data);
+        if let Some(comment) = comment {
+            body.option(OPT_COMMENT, comment.as_bytes());
+        }
+        self.write_block(BLOCK_ENHANCED_PACKET, &body.finish())
+    }
+
+    pub fn write_statistics(&mut self, interface_id: u32, stats: &InterfaceStatistics) -> io::Result<()> {
+        let (high, low) = timestamp_parts(stats.end.0, stats.end.1);
+        let mut body = BlockBody::new();
+        body.u32(interface_id);
+        body.u32(high);
+        body.u32(low);
+        body.option(ISB_STARTTIME, &timestamp_bytes(stats.start.0, stats.start.1));
+        body.option(ISB_ENDTIME, &timestamp_bytes(stats.end.0, stats.end.1));
+        if let Some(received) = stats.received {
+            body.option(ISB_IFRECV, &received.to_le_bytes());
+        }
+        if let Some(dropped) = stats.dropped {
+            body.option(ISB_IFDROP, &dropped.to_le_bytes());
+        }
+        body.option(ISB_USRDELIV, &stats.delivered.to_le_bytes());
+        self.write_block(BLOCK_INTERFACE_STATISTICS, &body.finish())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Counters for an Interface Statistics Block; times are (sec, usec)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterfaceStatistics {
+    pub start: (i64, i64),
+    pub end: (i64, i64),
+    pub received: Option<u64>,
+    pub dropped: Option<u64>,
+    pub delivered: u64,
+}
+
+// What to put in the per-packet comment option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommentMode {
+    #[default]
+    None,
+    // One-line protocol summary on every packet
+    Protocol,
+    // Only packets flagged as anomalous (e.g. undecodable)
+    Anomaly,
+}
+
+impl FromStr for CommentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(CommentMode::None),
+            "protocol" => Ok(CommentMode::Protocol),
+            "anomaly" => Ok(CommentMode::Anomaly),
+            other => Err(format!("Unknown comment mode '{}'", other)),
+        }
+    }
+}
+
+fn packet_comment(packet: &ParsedPacket, mode: CommentMode) -> Option<String> {
+    match (mode, &packet.decoded) {
+        (CommentMode::None, _) => None,
+        (CommentMode::Protocol, Ok(decoded)) => {
+            let mut comment = decoded.protocol_name();
+            if let (Some(src), Some(dst)) = (decoded.src_addr(), decoded.dst_addr()) {
+                comment.push_str(&format!(" {} -> {}", src, dst));
+            }
+            if let Some(arp) = decoded.arp() {
+                comment.push_str(&format!(" {}", arp.summary()));
+            }
+            match &decoded.transport {
+                Some(TransportLayer::Icmpv4(info)) => comment.push_str(&format!(" {}", icmp::icmpv4_type_name(info.icmp_type))),
+                Some(TransportLayer::Icmpv6(info)) => comment.push_str(&format!(" {}", icmp::icmpv6_type_name(info.icmp_type))),
+                _ => {}
+            }
+            if let Some(dns) = decoded.dns() {
+                comment.push_str(&format!(" {}", dns.summary()));
+            }
+            if let Some(http) = decoded.http() {
+                comment.push_str(&format!(" {}", http.summary()));
+            }
+            if let Some(tls) = decoded.tls() {
+                comment.push_str(&format!(" {}", tls.summary()));
+            }
+            if let Some(quic) = decoded.quic() {
+                comment.push_str(&format!(" {}", quic.summary()));
+            }
+            match &decoded.application {
+                Some(ApplicationLayer::Dhcp(message)) => comment.push_str(&format!(" {}", message.summary())),
+                Some(ApplicationLayer::Dhcpv6(message)) => comment.push_str(&format!(" {}", message.summary())),
+                _ => {}
+            }
+            Some(comment)
+        }
+        (CommentMode::Anomaly, Ok(_)) => None,
+        (_, Err(e)) => Some(format!("anomaly: malformed packet ({})", e)),
+    }
+}
+
+// Writes every packet to a pcapng file, one Interface Description Block per
+// source interface so packet blocks keep the interface they came from
+pub struct PcapngSink {
+    writer: PcapngWriter<BufWriter<File>>,
+    comments: CommentMode,
+    statistics: Vec<InterfaceStatistics>,
+    // Gap notes attached to the next packet written, whatever the comment mode
+    pending_gaps: Vec<String>,
+}
+
+impl PcapngSink {
+    pub fn create(path: &str, interfaces: &[InterfaceInfo], comments: CommentMode) -> Result<Self, Box<dyn Error>> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut writer = PcapngWriter::new(file, concat!("PacketCaptureTool ", env!("CARGO_PKG_VERSION")))?;
+        for interface in interfaces {
+            writer.add_interface(interface.linktype.0 as u16, interface.snaplen, interface)?;
+        }
+        Ok(PcapngSink {
+            writer,
+            comments,
+            statistics: vec![InterfaceStatistics::default(); interfaces.len()],
+            pending_gaps: Vec::new(),
+        })
+    }
+}
+
+impl PacketSink for PcapngSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let raw = packet.raw;
+        let mut comment = packet_comment(packet, self.comments);
+        if !self.pending_gaps.is_empty() {
+            let mut notes = std::mem::take(&mut self.pending_gaps);
+            notes.extend(comment);
+            comment = Some(notes.join("; "));
+        }
+        self.writer.write_packet(
+            raw.interface,
+            raw.ts_sec,
+            raw.ts_usec,
+            raw.caplen,
+            raw.len,
+            &raw.data,
+            comment.as_deref(),
+        )?;
+
+        if let Some(statistics) = self.statistics.get_mut(raw.interface as usize) {
+            if statistics.delivered == 0 {
+                statistics.start = (raw.ts_sec, raw.ts_usec);
+            }
+            statistics.end = (raw.ts_sec, raw.ts_usec);
+            statistics.delivered += 1;
+        }
+        Ok(())
+    }
+
+    fn on_source_stats(&mut self, stats: &[SourceStats]) {
+        for stat in stats {
+            if let Some(statistics) = self.statistics.get_mut(stat.interface as usize) {
+                statistics.received = Some(stat.received);
+                statistics.dropped = Some(stat.dropped);
+            }
+        }
+    }
+
+    fn on_gap(&mut self, gap: &CaptureGap) {
+        self.pending_gaps.push(format!(
+            "capture gap: {:.3}s without packets on {} ({})",
+            gap.seconds(),
+            gap.device,
+            gap.reason
+        ));
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        for (interface_id, statistics) in self.statistics.iter().enumerate() {
+            self.writer.write_statistics(interface_id as u32, statistics)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{FileSource, PacketSource, SourceEvent};
+    use pcap::Linktype;
+    use std::fs;
+
+    fn interface(name: &str) -> InterfaceInfo {
+        InterfaceInfo {
+            name: name.to_string(),
+            description: Some(format!("{} test interface", name)),
+            linktype: Linktype::ETHERNET,
+            snaplen: 65_535,
+        }
+    }
+
+    #[test]
+    fn written_files_read_back_through_libpcap() {
+        let path = std::env::temp_dir().join(format!("testgame-pcapng-{}.pcapng", std::process::id()));
+        let first: Vec<u8> = (0..60).collect();
+        let second: Vec<u8> = (0..41).rev().collect();
+        {
+            let mut writer = PcapngWriter::new(BufWriter::new(File::create(&path).unwrap()), "test").unwrap();
+            let eth0 = writer.add_interface(1, 65_535, &interface("eth0")).unwrap();
+            let eth1 = writer.add_interface(1, 65_535, &interface("eth1")).unwrap();
+            assert_eq!((eth0, eth1), (0, 1));
+            writer.write_packet(eth0, 1_700_000_000, 123_456, 60, 60, &first, None).unwrap();
+            // Odd-length data and comment both need padding; the packet was cut short by the snaplen
+            writer
+                .write_packet(eth1, 1_700_000_001, 999_999, 41, 1500, &second, Some("cut short"))
+                .unwrap();
+            let statistics = InterfaceStatistics {
+                start: (1_700_000_001, 999_999),
+                end: (1_700_000_001, 999_999),
+                received: Some(5),
+                dropped: Some(4),
+                delivered: 1,
+            };
+            writer.write_statistics(eth1, &statistics).unwrap();
+            writer.flush().unwrap();
+        }
+        assert!(fs::read(&path).unwrap().windows(9).any(|window| window == b"cut short"));
+
+        let mut source = FileSource::open(path.to_str().unwrap()).unwrap();
+        let info = &source.interfaces()[0];
+        assert_eq!((info.linktype, info.snaplen), (Linktype::ETHERNET, 65_535));
+        let mut packets = Vec::new();
+        while let SourceEvent::Packet(packet) = source.next_packet().unwrap() {
+            packets.push(packet);
+        }
+        fs::remove_file(&path).unwrap();
+
+        let summary: Vec<_> = packets
+            .iter()
+            .map(|p| (p.ts_sec, p.ts_usec, p.caplen, p.len, p.data.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1_700_000_000, 123_456, 60, 60, first.as_slice()),
+                (1_700_000_001, 999_999, 41, 1500, second.as_slice()),
+            ]
+        );
+    }
+}
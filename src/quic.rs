@@ -0,0 +1,762 @@
+use crate::listening::ParsedPacket;
+use crate::packet::{ApplicationLayer, DecodedPacket};
+use crate::report::write_top;
+use crate::sink::PacketSink;
+use crate::tls::{self, ClientHello};
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use hkdf::Hkdf;
+use serde::{Serialize, Serializer};
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+pub const VERSION_1: u32 = 0x0000_0001;
+pub const VERSION_2: u32 = 0x6b33_43cf;
+
+// Initial salts: RFC 9001, RFC 9369, and drafts 29 to 32
+const SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb, 0x7f,
+    0x0a,
+];
+const SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb, 0xf9, 0xbd, 0x2e,
+    0xd9,
+];
+const SALT_DRAFT_29: [u8; 20] = [
+    0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11, 0xe0, 0x43, 0x90, 0xa8,
+    0x99,
+];
+
+// Connection IDs are at most 20 bytes long
+const MAX_CID_LEN: usize = 20;
+// CRYPTO bytes buffered per connection while waiting for the rest of a ClientHello
+const MAX_CRYPTO_LEN: usize = 64 * 1024;
+// Connections with an incomplete ClientHello tracked at once
+const MAX_PENDING: usize = 4096;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ConnectionId(pub Vec<u8>);
+
+impl ConnectionId {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "-");
+        }
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+// Serialize as a hex string rather than a byte array
+impl Serialize for ConnectionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PacketType {
+    Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
+    VersionNegotiation,
+}
+
+impl PacketType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PacketType::Initial => "Initial",
+            PacketType::ZeroRtt => "0-RTT",
+            PacketType::Handshake => "Handshake",
+            PacketType::Retry => "Retry",
+            PacketType::VersionNegotiation => "Version Negotiation",
+        }
+    }
+}
+
+// Part of the TLS handshake carried in a CRYPTO frame
+#[derive(Debug, Clone)]
+pub struct CryptoFragment {
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+// A long-header QUIC packet, plus whatever the Initial keys reveal of a client Initial
+#[derive(Debug, Clone, Serialize)]
+pub struct QuicInfo {
+    pub version: u32,
+    pub packet_type: PacketType,
+    pub dcid: ConnectionId,
+    pub scid: ConnectionId,
+    // Offered by the server in a Version Negotiation packet
+    pub supported_versions: Vec<u32>,
+    // Further long-header packets coalesced into the same datagram
+    pub coalesced: Vec<PacketType>,
+    // Set when the packet decrypted as a client Initial; truncated as sent
+    pub packet_number: Option<u64>,
+    pub frames: Vec<&'static str>,
+    // Kept for reassembling a ClientHello that spans several Initials
+    #[serde(skip)]
+    pub crypto: Vec<CryptoFragment>,
+    pub client_hello: Option<ClientHello>,
+}
+
+impl QuicInfo {
+    pub fn sni(&self) -> Option<&str> {
+        self.client_hello.as_ref()?.sni.as_deref()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{} {} DCID={}", version_name(self.version), self.packet_type.name(), self.dcid);
+        if let Some(hello) = &self.client_hello {
+            summary.push_str(&format!(" ClientHello {}", hello.sni.as_deref().unwrap_or("(no SNI)")));
+        }
+        summary
+    }
+}
+
+pub fn version_name(version: u32) -> String {
+    match version {
+        0 => "QUIC".to_string(),
+        VERSION_1 => "QUICv1".to_string(),
+        VERSION_2 => "QUICv2".to_string(),
+        0xff00_0000..=0xff00_00ff => format!("QUIC draft-{}", version & 0xff),
+        other => format!("QUIC 0x{:08x}", other),
+    }
+}
+
+fn is_known_version(version: u32) -> bool {
+    version == VERSION_1 || version == VERSION_2 || version >> 8 == 0x00ff_0000
+}
+
+fn initial_salt(version: u32) -> Option<&'static [u8]> {
+    match version {
+        VERSION_1 => Some(&SALT_V1),
+        VERSION_2 => Some(&SALT_V2),
+        0xff00_001d..=0xff00_0020 => Some(&SALT_DRAFT_29),
+        _ => None,
+    }
+}
+
+// Labels for the packet protection key, IV and header protection key
+fn key_labels(version: u32) -> [&'static str; 3] {
+    if version == VERSION_2 {
+        ["quicv2 key", "quicv2 iv", "quicv2 hp"]
+    } else {
+        ["quic key", "quic iv", "quic hp"]
+    }
+}
+
+// Version 2 shuffles the long packet type bits
+fn packet_type(version: u32, bits: u8) -> PacketType {
+    match (version == VERSION_2, bits) {
+        (false, 0) | (true, 1) => PacketType::Initial,
+        (false, 1) | (true, 2) => PacketType::ZeroRtt,
+        (false, 2) | (true, 3) => PacketType::Handshake,
+        _ => PacketType::Retry,
+    }
+}
+
+// Variable-length integer: the top two bits of the first byte give its size
+fn varint(data: &[u8], at: &mut usize) -> Option<u64> {
+    let first = *data.get(*at)?;
+    let len = 1 << (first >> 6);
+    let bytes = data.get(*at..*at + len)?;
+    let value = bytes[1..].iter().fold((first & 0x3f) as u64, |value, b| (value << 8) | *b as u64);
+    *at += len;
+    Some(value)
+}
+
+struct LongHeader {
+    version: u32,
+    packet_type: PacketType,
+    dcid: ConnectionId,
+    scid: ConnectionId,
+    supported_versions: Vec<u32>,
+    // Offset of the (protected) packet number and of the end of the packet
+    pn_offset: usize,
+    end: usize,
+}
+
+fn parse_long_header(data: &[u8]) -> Option<LongHeader> {
+    let first = *data.first()?;
+    if first & 0x80 == 0 {
+        return None;
+    }
+    let version = u32::from_be_bytes(data.get(1..5)?.try_into().ok()?);
+    let mut at = 5;
+    let mut cid = || {
+        let len = *data.get(at)? as usize;
+        if len > MAX_CID_LEN {
+            return None;
+        }
+        let id = data.get(at + 1..at + 1 + len)?.to_vec();
+        at += 1 + len;
+        Some(ConnectionId(id))
+    };
+    let dcid = cid()?;
+    let scid = cid()?;
+
+    let mut header = LongHeader {
+        version,
+        packet_type: PacketType::VersionNegotiation,
+        dcid,
+        scid,
+        supported_versions: Vec::new(),
+        pn_offset: at,
+        end: data.len(),
+    };
+    if version == 0 {
+        let list = &data[at..];
+        if list.is_empty() || !list.len().is_multiple_of(4) {
+            return None;
+        }
+        header.supported_versions = list
+            .chunks_exact(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        return Some(header);
+    }
+    // The fixed bit must be set in every version we know
+    if !is_known_version(version) || first & 0x40 == 0 {
+        return None;
+    }
+
+    header.packet_type = packet_type(version, (first >> 4) & 0x3);
+    match header.packet_type {
+        // A Retry runs to the end of the datagram
+        PacketType::Retry => {}
+        packet_type => {
+            if packet_type == PacketType::Initial {
+                let token_len = varint(data, &mut at)? as usize;
+                at = at.checked_add(token_len)?;
+            }
+            let length = varint(data, &mut at)? as usize;
+            header.pn_offset = at;
+            header.end = at.checked_add(length)?;
+            if header.end > data.len() {
+                return None;
+            }
+        }
+    }
+    Some(header)
+}
+
+// HKDF-Expand-Label from TLS 1.3, with an empty context
+fn expand_label(secret: &Hkdf<Sha256>, label: &str, len: usize) -> Option<Vec<u8>> {
+    let label = format!("tls13 {}", label);
+    let mut info = Vec::with_capacity(4 + label.len());
+    info.extend_from_slice(&(len as u16).to_be_bytes());
+    info.push(label.len() as u8);
+    info.extend_from_slice(label.as_bytes());
+    info.push(0);
+    let mut out = vec![0; len];
+    secret.expand(&info, &mut out).ok()?;
+    Some(out)
+}
+
+// Packet protection key, IV and header protection key of a client's Initials
+struct InitialKeys {
+    key: Vec<u8>,
+    iv: Vec<u8>,
+    hp: Vec<u8>,
+}
+
+// Client Initial keys derive from the destination connection ID it chose
+fn client_initial_keys(version: u32, dcid: &ConnectionId) -> Option<InitialKeys> {
+    let salt = initial_salt(version)?;
+    let (_, initial) = Hkdf::<Sha256>::extract(Some(salt), &dcid.0);
+    let client = Hkdf::<Sha256>::from_prk(&expand_label(&initial, "client in", 32)?).ok()?;
+    let [key_label, iv_label, hp_label] = key_labels(version);
+    Some(InitialKeys {
+        key: expand_label(&client, key_label, 16)?,
+        iv: expand_label(&client, iv_label, 12)?,
+        hp: expand_label(&client, hp_label, 16)?,
+    })
+}
+
+// Remove header protection from a client Initial and decrypt it. Its keys derive
+// from its own destination connection ID, so no state is needed. Returns the
+// packet number and the plaintext frames.
+fn decrypt_initial(data: &[u8], header: &LongHeader) -> Option<(u64, Vec<u8>)> {
+    let InitialKeys { key, iv, hp } = client_initial_keys(header.version, &header.dcid)?;
+
+    // The sample starts 4 bytes past the packet number, whatever its real length
+    if header.end < header.pn_offset + 20 {
+        return None;
+    }
+    let mut mask = aes::Block::clone_from_slice(&data[header.pn_offset + 4..header.pn_offset + 20]);
+    Aes128::new_from_slice(&hp).ok()?.encrypt_block(&mut mask);
+
+    let mut packet = data[..header.end].to_vec();
+    packet[0] ^= mask[0] & 0x0f;
+    let pn_len = (packet[0] & 0x03) as usize + 1;
+    let mut packet_number = 0;
+    for i in 0..pn_len {
+        packet[header.pn_offset + i] ^= mask[1 + i];
+        packet_number = (packet_number << 8) | packet[header.pn_offset + i] as u64;
+    }
+
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(&iv);
+    for (n, b) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+        *n ^= b;
+    }
+    let (aad, msg) = packet.split_at(header.pn_offset + pn_len);
+    let plaintext = Aes128Gcm::new_from_slice(&key)
+        .ok()?
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg, aad })
+        .ok()?;
+    Some((packet_number, plaintext))
+}
+
+// Parse one frame at `at`, returning its name. Only frames allowed in Initial
+// packets are understood.
+fn parse_frame(data: &[u8], at: &mut usize, crypto: &mut Vec<CryptoFragment>) -> Option<&'static str> {
+    let frame_type = varint(data, at)?;
+    let name = match frame_type {
+        0x00 => {
+            while data.get(*at) == Some(&0) {
+                *at += 1;
+            }
+            "PADDING"
+        }
+        0x01 => "PING",
+        0x02 | 0x03 => {
+            // Largest acknowledged, delay, range count and first range
+            varint(data, at)?;
+            varint(data, at)?;
+            let ranges = varint(data, at)?;
+            varint(data, at)?;
+            for _ in 0..ranges {
+                varint(data, at)?;
+                varint(data, at)?;
+            }
+            if frame_type == 0x03 {
+                // ECN counts
+                for _ in 0..3 {
+                    varint(data, at)?;
+                }
+            }
+            "ACK"
+        }
+        0x06 => {
+            let offset = varint(data, at)?;
+            let len = varint(data, at)? as usize;
+            let bytes = data.get(*at..at.checked_add(len)?)?;
+            *at += len;
+            crypto.push(CryptoFragment {
+                offset,
+                data: bytes.to_vec(),
+            });
+            "CRYPTO"
+        }
+        0x1c | 0x1d => {
+            // Error code, the offending frame type for transport errors, and a reason
+            varint(data, at)?;
+            if frame_type == 0x1c {
+                varint(data, at)?;
+            }
+            let len = varint(data, at)? as usize;
+            *at = at.checked_add(len)?;
+            "CONNECTION_CLOSE"
+        }
+        _ => return None,
+    };
+    Some(name)
+}
+
+// The contiguous CRYPTO stream from offset 0. Fragments may arrive in any order
+// and overlap, as some clients deliberately scramble them.
+pub fn assemble_crypto(fragments: &[CryptoFragment]) -> Vec<u8> {
+    let mut sorted: Vec<&CryptoFragment> = fragments.iter().collect();
+    sorted.sort_by_key(|fragment| fragment.offset);
+    let mut stream = Vec::new();
+    for fragment in sorted {
+        let start = fragment.offset as usize;
+        if start > stream.len() {
+            break;
+        }
+        if start + fragment.data.len() > stream.len() {
+            stream.extend_from_slice(&fragment.data[stream.len() - start..]);
+        }
+    }
+    stream
+}
+
+// The ClientHello at the start of a CRYPTO stream, once all of it is there
+pub fn client_hello(stream: &[u8]) -> Option<ClientHello> {
+    if *stream.first()? != 1 {
+        return None;
+    }
+    let len = ((*stream.get(1)? as usize) << 16) | ((*stream.get(2)? as usize) << 8) | *stream.get(3)? as usize;
+    let mut hello = tls::parse_client_hello(stream.get(4..4 + len)?)?;
+    hello.ja4 = tls::ja4(&hello, 'q');
+    Some(hello)
+}
+
+// Long-header QUIC packets on any UDP port, recognised by their version
+pub fn decode(packet: &DecodedPacket, data: &[u8]) -> Option<QuicInfo> {
+    packet.udp()?;
+    let payload = packet.payload(data);
+    let header = parse_long_header(payload)?;
+    let mut info = QuicInfo {
+        version: header.version,
+        packet_type: header.packet_type,
+        dcid: header.dcid.clone(),
+        scid: header.scid.clone(),
+        supported_versions: header.supported_versions.clone(),
+        coalesced: Vec::new(),
+        packet_number: None,
+        frames: Vec::new(),
+        crypto: Vec::new(),
+        client_hello: None,
+    };
+
+    // Server Initials are keyed by the client's original DCID, which they do not
+    // carry, so only the client's decrypt here
+    if header.packet_type == PacketType::Initial {
+        if let Some((packet_number, plaintext)) = decrypt_initial(payload, &header) {
+            info.packet_number = Some(packet_number);
+            let mut at = 0;
+            while at < plaintext.len() {
+                let Some(name) = parse_frame(&plaintext, &mut at, &mut info.crypto) else {
+                    break;
+                };
+                if info.frames.last() != Some(&name) {
+                    info.frames.push(name);
+                }
+            }
+            info.client_hello = client_hello(&assemble_crypto(&info.crypto));
+        }
+    }
+
+    let mut rest = &payload[header.end..];
+    while let Some(next) = parse_long_header(rest) {
+        if next.packet_type == PacketType::VersionNegotiation {
+            break;
+        }
+        info.coalesced.push(next.packet_type);
+        rest = &rest[next.end..];
+    }
+    Some(info)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuicStats {
+    pub packets: u64,
+    // Connections whose ClientHello was recovered from their Initials
+    pub connections: u64,
+    pub versions: HashMap<String, u64>,
+    pub sni: HashMap<String, u64>,
+    pub alpn: HashMap<String, u64>,
+    pub ja4: HashMap<String, u64>,
+}
+
+impl QuicStats {
+    pub fn is_empty(&self) -> bool {
+        self.packets == 0
+    }
+
+    fn record(&mut self, hello: &ClientHello) {
+        self.connections += 1;
+        if let Some(sni) = &hello.sni {
+            *self.sni.entry(sni.to_lowercase()).or_insert(0) += 1;
+        }
+        for alpn in &hello.alpn {
+            *self.alpn.entry(alpn.clone()).or_insert(0) += 1;
+        }
+        *self.ja4.entry(hello.ja4.clone()).or_insert(0) += 1;
+    }
+}
+
+// Reassembles ClientHellos split across client Initials, keyed by the original
+// DCID every one of them carries, and accumulates QUIC statistics
+#[derive(Default)]
+pub struct QuicSink {
+    pending: HashMap<ConnectionId, Vec<CryptoFragment>>,
+    // Connections already counted, so retransmitted Initials are not
+    seen: HashSet<ConnectionId>,
+    stats: QuicStats,
+}
+
+impl QuicSink {
+    pub fn into_stats(self) -> QuicStats {
+        self.stats
+    }
+
+    fn on_quic(&mut self, info: &QuicInfo) {
+        self.stats.packets += 1;
+        *self.stats.versions.entry(version_name(info.version)).or_insert(0) += 1;
+        if info.crypto.is_empty() || self.seen.contains(&info.dcid) {
+            return;
+        }
+        let hello = match &info.client_hello {
+            Some(hello) => Some(hello.clone()),
+            None => {
+                if !self.pending.contains_key(&info.dcid) && self.pending.len() >= MAX_PENDING {
+                    return;
+                }
+                let fragments = self.pending.entry(info.dcid.clone()).or_default();
+                fragments.extend(info.crypto.iter().cloned());
+                let buffered: usize = fragments.iter().map(|f| f.data.len()).sum();
+                let hello = client_hello(&assemble_crypto(fragments));
+                if hello.is_none() && buffered > MAX_CRYPTO_LEN {
+                    self.pending.remove(&info.dcid);
+                }
+                hello
+            }
+        };
+        if let Some(hello) = hello {
+            self.pending.remove(&info.dcid);
+            self.seen.insert(info.dcid.clone());
+            self.stats.record(&hello);
+        }
+    }
+}
+
+impl PacketSink for QuicSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        if let Ok(decoded) = &packet.decoded {
+            if let Some(ApplicationLayer::Quic(info)) = &decoded.application {
+                self.on_quic(info);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn write_quic_details(out: &mut dyn Write, info: &QuicInfo) -> io::Result<()> {
+    writeln!(out, "  QUIC: {} {}", version_name(info.version), info.packet_type.name())?;
+    writeln!(out, "    DCID: {}", info.dcid)?;
+    writeln!(out, "    SCID: {}", info.scid)?;
+    if !info.supported_versions.is_empty() {
+        let versions: Vec<String> = info.supported_versions.iter().map(|v| version_name(*v)).collect();
+        writeln!(out, "    Supported versions: {}", versions.join(", "))?;
+    }
+    if let Some(packet_number) = info.packet_number {
+        writeln!(out, "    Packet number: {}", packet_number)?;
+    }
+    if !info.frames.is_empty() {
+        writeln!(out, "    Frames: {}", info.frames.join(", "))?;
+    }
+    if let Some(hello) = &info.client_hello {
+        writeln!(out, "    ClientHello: SNI={}", hello.sni.as_deref().unwrap_or("N/A"))?;
+        if !hello.alpn.is_empty() {
+            writeln!(out, "    ALPN: {}", hello.alpn.join(","))?;
+        }
+        writeln!(out, "    JA4: {}", hello.ja4)?;
+    }
+    if !info.coalesced.is_empty() {
+        let types: Vec<&str> = info.coalesced.iter().map(|t| t.name()).collect();
+        writeln!(out, "    Coalesced: {}", types.join(", "))?;
+    }
+    Ok(())
+}
+
+pub fn write_quic_summary(out: &mut dyn Write, stats: &QuicStats, top: usize) -> io::Result<()> {
+    writeln!(out, "\n=== QUIC Statistics ===")?;
+    writeln!(out, "Long-header packets: {}, Connections with ClientHello: {}", stats.packets, stats.connections)?;
+    write_top(out, "Versions", &stats.versions, top)?;
+    write_top(out, "Top SNI", &stats.sni, top)?;
+    write_top(out, "ALPN offered", &stats.alpn, top)?;
+    write_top(out, "Top JA4", &stats.ja4, top)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{PayloadRange, TransportLayer, UdpInfo};
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // Client Initial of RFC 9001 Appendix A.2 (and RFC 9369 Appendix A.2)
+    const DCID: &str = "8394c8f03e515708";
+    const PACKET_NUMBER: u32 = 2;
+    const CRYPTO_FRAME: &str = concat!(
+        "060040f1010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e868",
+        "04fe3a47f06a2b69484c00000413011302010000c000000010000e00000b6578",
+        "616d706c652e636f6dff01000100000a00080006001d00170018001000070005",
+        "04616c706e000500050100000000003300260024001d00209370b2c9caa47fba",
+        "baf4559fedba753de171fa71f50f1ce15d43e994ec74d748002b000302030400",
+        "0d0010000e0403050306030203080408050806002d00020101001c0002400100",
+        "3900320408ffffffffffffffff05048000ffff07048000ffff08011001048000",
+        "75300901100f088394c8f03e51570806048000ffff",
+    );
+
+    // Apply packet and header protection as a client would, giving a 1200 byte datagram
+    fn protect(version: u32, first_byte: u8, frames: &[u8]) -> Vec<u8> {
+        let dcid = ConnectionId(unhex(DCID));
+        let keys = client_initial_keys(version, &dcid).unwrap();
+        let mut header = vec![first_byte];
+        header.extend_from_slice(&version.to_be_bytes());
+        header.push(dcid.0.len() as u8);
+        header.extend_from_slice(&dcid.0);
+        // No source connection ID or token; 0x449e = 1182 byte length
+        header.extend_from_slice(&[0, 0, 0x44, 0x9e]);
+        let pn_offset = header.len();
+        header.extend_from_slice(&PACKET_NUMBER.to_be_bytes());
+
+        let mut payload = frames.to_vec();
+        payload.resize(1162, 0);
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&keys.iv);
+        nonce[8..].iter_mut().zip(PACKET_NUMBER.to_be_bytes()).for_each(|(n, b)| *n ^= b);
+        let ciphertext = Aes128Gcm::new_from_slice(&keys.key)
+            .unwrap()
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &payload, aad: &header })
+            .unwrap();
+
+        let mut packet = [header, ciphertext].concat();
+        let mut mask = aes::Block::clone_from_slice(&packet[pn_offset + 4..pn_offset + 20]);
+        Aes128::new_from_slice(&keys.hp).unwrap().encrypt_block(&mut mask);
+        packet[0] ^= mask[0] & 0x0f;
+        for i in 0..4 {
+            packet[pn_offset + i] ^= mask[1 + i];
+        }
+        assert_eq!(packet.len(), 1200);
+        packet
+    }
+
+    // A UDP datagram from port 50000 to 443 whose payload is `datagram`
+    fn udp_packet(datagram: &[u8]) -> DecodedPacket {
+        DecodedPacket {
+            number: 1,
+            interface: 0,
+            ts_sec: 0,
+            ts_usec: 0,
+            caplen: datagram.len() as u32,
+            len: datagram.len() as u32,
+            link: None,
+            network: None,
+            transport: Some(TransportLayer::Udp(UdpInfo {
+                src_port: 50000,
+                dst_port: 443,
+                length: (8 + datagram.len()) as u16,
+                checksum: 0,
+            })),
+            application: None,
+            payload: Some(PayloadRange { offset: 0, len: datagram.len() }),
+            reassembled: None,
+            encapsulation: Vec::new(),
+            wlan: None,
+        }
+    }
+
+    fn assert_rfc_client_hello(info: &QuicInfo) {
+        assert_eq!(info.packet_type, PacketType::Initial);
+        assert_eq!(info.dcid.to_string(), DCID);
+        assert_eq!(info.packet_number, Some(2));
+        assert_eq!(info.frames, ["CRYPTO", "PADDING"]);
+        assert_eq!(info.crypto.len(), 1);
+        assert_eq!(info.crypto[0].data.len(), 0xf1);
+        let hello = info.client_hello.as_ref().unwrap();
+        assert_eq!(info.sni(), Some("example.com"));
+        assert_eq!(hello.alpn, ["alpn"]);
+        assert_eq!(hello.cipher_suites, [0x1301, 0x1302]);
+        assert_eq!(hello.ja4.split('_').next(), Some("q13d0211an"));
+    }
+
+    #[test]
+    fn derives_rfc_9001_client_keys() {
+        let keys = client_initial_keys(VERSION_1, &ConnectionId(unhex(DCID))).unwrap();
+        assert_eq!(hex(&keys.key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(hex(&keys.iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(hex(&keys.hp), "9f50449e04a0e810283a1e9933adedd2");
+    }
+
+    #[test]
+    fn derives_rfc_9369_client_keys() {
+        let keys = client_initial_keys(VERSION_2, &ConnectionId(unhex(DCID))).unwrap();
+        assert_eq!(hex(&keys.key), "8b1a0bc121284290a29e0971b5cd045d");
+        assert_eq!(hex(&keys.iv), "91f73e2351d8fa91660e909f");
+        assert_eq!(hex(&keys.hp), "45b95e15235d6f45a6b19cbcb0294ba9");
+    }
+
+    #[test]
+    fn decrypts_the_rfc_9001_client_initial() {
+        let packet = protect(VERSION_1, 0xc3, &unhex(CRYPTO_FRAME));
+        // Protected header and sample as printed in the RFC
+        assert_eq!(
+            hex(&packet[..38]),
+            "c000000001088394c8f03e5157080000449e7b9aec34d1b1c98dd7689fb8ec11d242b123dc9b"
+        );
+        let info = decode(&udp_packet(&packet), &packet).unwrap();
+        assert_eq!(info.version, VERSION_1);
+        assert_rfc_client_hello(&info);
+    }
+
+    #[test]
+    fn decrypts_the_rfc_9369_client_initial() {
+        // Version 2 Initials use long packet type 1
+        let packet = protect(VERSION_2, 0xd3, &unhex(CRYPTO_FRAME));
+        assert_eq!(
+            hex(&packet[..38]),
+            "d76b3343cf088394c8f03e5157080000449ea0c95e82ffe67b6abcdb4298b485dd04de806071"
+        );
+        let info = decode(&udp_packet(&packet), &packet).unwrap();
+        assert_eq!(info.version, VERSION_2);
+        assert_rfc_client_hello(&info);
+    }
+
+    #[test]
+    fn tampered_initial_does_not_decrypt() {
+        let mut packet = protect(VERSION_1, 0xc3, &unhex(CRYPTO_FRAME));
+        packet[600] ^= 1;
+        let info = decode(&udp_packet(&packet), &packet).unwrap();
+        assert_eq!(info.packet_type, PacketType::Initial);
+        assert!(info.packet_number.is_none());
+        assert!(info.client_hello.is_none());
+    }
+
+    #[test]
+    fn reassembles_a_client_hello_split_across_initials() {
+        // The CRYPTO frame data of the RFC packet, cut into overlapping fragments
+        let frame = unhex(CRYPTO_FRAME);
+        let hello = &frame[4..];
+        let fragment = |start: usize, end: usize| CryptoFragment {
+            offset: start as u64,
+            data: hello[start..end].to_vec(),
+        };
+        let fragments = [fragment(150, hello.len()), fragment(0, 100), fragment(60, 160)];
+
+        assert!(client_hello(&assemble_crypto(&fragments[..2])).is_none());
+        assert_eq!(assemble_crypto(&fragments), hello);
+        let parsed = client_hello(&assemble_crypto(&fragments)).unwrap();
+        assert_eq!(parsed.sni.as_deref(), Some("example.com"));
+        assert!(parsed.ja4.starts_with('q'));
+
+        // Each piece in its own packet, sent in reverse order
+        let mut crypto = Vec::new();
+        for piece in fragments.iter().rev() {
+            let mut frames = vec![0x06];
+            frames.extend_from_slice(&[0x40 | (piece.offset >> 8) as u8, piece.offset as u8]);
+            frames.extend_from_slice(&[0x40 | (piece.data.len() >> 8) as u8, piece.data.len() as u8]);
+            frames.extend_from_slice(&piece.data);
+            let packet = protect(VERSION_1, 0xc3, &frames);
+            crypto.extend(decode(&udp_packet(&packet), &packet).unwrap().crypto);
+        }
+        assert_eq!(client_hello(&assemble_crypto(&crypto)).unwrap().sni.as_deref(), Some("example.com"));
+    }
+}
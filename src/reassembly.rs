@@ -0,0 +1,559 @@
+use crate::flow::FlowKey;
+use crate::listening::ParsedPacket;
+use crate::packet::{DecodedPacket, TcpInfo};
+use crate::sink::PacketSink;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+// Signed distance from `b` to `a` in sequence space, correct across wraparound
+fn seq_diff(a: u32, b: u32) -> i32 {
+    a.wrapping_sub(b) as i32
+}
+
+// What one call to `TcpDirection::push` produced, in stream order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivered {
+    Data(Vec<u8>),
+    // Bytes that were never seen and have been skipped over
+    Gap(u64),
+}
+
+// Rebuilds the in-order byte stream of one direction of a TCP connection:
+// retransmitted and overlapping bytes are trimmed, early segments are held
+// until the data before them arrives or the buffer limit forces a skip
+#[derive(Debug, Default)]
+pub struct TcpDirection {
+    next_seq: Option<u32>,
+    // Where `next_seq` falls in sequence space unwrapped to 64 bits, which is
+    // what held segments are keyed by
+    next_pos: i64,
+    pending: BTreeMap<i64, Vec<u8>>,
+    buffered: usize,
+    // Sequence number just past the FIN, once one has been seen
+    fin_seq: Option<u32>,
+    pub delivered_bytes: u64,
+    pub missing_bytes: u64,
+}
+
+impl TcpDirection {
+    // Feed one segment; at most `max_buffered` out-of-order bytes are kept
+    pub fn push(&mut self, tcp: &TcpInfo, data: &[u8], max_buffered: usize) -> Vec<Delivered> {
+        let mut seq = tcp.seq;
+        if tcp.flags.syn {
+            // The SYN takes up one sequence number before the data
+            seq = seq.wrapping_add(1);
+            if self.delivered_bytes == 0 {
+                self.next_pos = self.position(seq);
+                self.next_seq = Some(seq);
+            }
+        }
+        // Joining mid-stream: start from the first segment we see
+        self.next_seq.get_or_insert(seq);
+        if tcp.flags.fin {
+            self.fin_seq = Some(seq.wrapping_add(data.len() as u32));
+        }
+
+        let mut out = Vec::new();
+        if !data.is_empty() {
+            self.hold(seq, data);
+        }
+        self.drain(&mut out);
+        while self.buffered > max_buffered && !self.pending.is_empty() {
+            self.skip_gap(&mut out);
+            self.drain(&mut out);
+        }
+        out
+    }
+
+    // All data up to the FIN has been delivered
+    pub fn is_finished(&self) -> bool {
+        matches!((self.fin_seq, self.next_seq), (Some(fin), Some(next)) if seq_diff(next, fin) >= 0)
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    fn position(&self, seq: u32) -> i64 {
+        match self.next_seq {
+            Some(next) => self.next_pos + seq_diff(seq, next) as i64,
+            None => self.next_pos,
+        }
+    }
+
+    // Queue a segment; of two starting at the same byte the longer is kept
+    fn hold(&mut self, seq: u32, data: &[u8]) {
+        let position = self.position(seq);
+        let held = self.pending.entry(position).or_default();
+        if data.len() > held.len() {
+            self.buffered += data.len() - held.len();
+            *held = data.to_vec();
+        }
+    }
+
+    // Deliver held segments for as long as the next one starts at or before `next_seq`
+    fn drain(&mut self, out: &mut Vec<Delivered>) {
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() > self.next_pos {
+                break;
+            }
+            let (position, data) = entry.remove_entry();
+            self.buffered -= data.len();
+
+            // Trim the part we already delivered
+            let skip = (self.next_pos - position) as usize;
+            if skip >= data.len() {
+                continue;
+            }
+            let fresh = &data[skip..];
+            match out.last_mut() {
+                Some(Delivered::Data(bytes)) => bytes.extend_from_slice(fresh),
+                _ => out.push(Delivered::Data(fresh.to_vec())),
+            }
+            self.delivered_bytes += fresh.len() as u64;
+            self.next_pos += fresh.len() as i64;
+            self.next_seq = self.next_seq.map(|next| next.wrapping_add(fresh.len() as u32));
+        }
+    }
+
+    // Give up on the missing data and continue from the earliest held segment
+    fn skip_gap(&mut self, out: &mut Vec<Delivered>) {
+        let Some(&earliest) = self.pending.keys().next() else {
+            return;
+        };
+        let missing = (earliest - self.next_pos).max(0);
+        self.missing_bytes += missing as u64;
+        out.push(Delivered::Gap(missing as u64));
+        self.next_pos += missing;
+        self.next_seq = self.next_seq.map(|next| next.wrapping_add(missing as u32));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    pub fn index(self) -> usize {
+        match self {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    // Both sides sent FIN and all data was delivered
+    Finished,
+    Reset,
+    IdleTimeout,
+    // Dropped to stay within the memory or flow limits
+    Evicted,
+    EndOfCapture,
+}
+
+// Identity of a reassembled connection, as passed to consumers
+#[derive(Debug, Clone)]
+pub struct StreamInfo {
+    // Unique for the lifetime of the capture, even if the 4-tuple is reused
+    pub id: u64,
+    pub client: (IpAddr, u16),
+    pub server: (IpAddr, u16),
+    pub first_seen: f64,
+}
+
+// Receives the reassembled byte streams; implemented by the application parsers
+pub trait StreamConsumer {
+    fn on_data(&mut self, stream: &StreamInfo, direction: Direction, data: &[u8], ts: f64);
+
+    // Bytes were lost in this direction; stream framing can no longer be trusted
+    fn on_gap(&mut self, _stream: &StreamInfo, _direction: Direction, _missing: u64) {}
+
+    // No more data will arrive for the stream
+    fn on_close(&mut self, _stream: &StreamInfo, _reason: CloseReason) {}
+
+    // End of capture, after every stream has been closed
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReassemblyConfig {
+    // Out-of-order bytes held per direction before skipping the hole
+    pub max_buffered_per_direction: usize,
+    // Out-of-order bytes held across all connections before evicting the oldest
+    pub max_buffered_total: usize,
+    pub max_streams: usize,
+    pub idle_timeout: f64,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        ReassemblyConfig {
+            max_buffered_per_direction: 1024 * 1024,
+            max_buffered_total: 64 * 1024 * 1024,
+            max_streams: 100_000,
+            idle_timeout: 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReassemblyStats {
+    pub streams: u64,
+    pub finished: u64,
+    pub reset: u64,
+    pub timed_out: u64,
+    pub evicted: u64,
+    pub bytes: u64,
+    pub missing_bytes: u64,
+}
+
+impl ReassemblyStats {
+    fn closed(&mut self, reason: CloseReason) {
+        match reason {
+            CloseReason::Finished => self.finished += 1,
+            CloseReason::Reset => self.reset += 1,
+            CloseReason::IdleTimeout => self.timed_out += 1,
+            CloseReason::Evicted => self.evicted += 1,
+            CloseReason::EndOfCapture => {}
+        }
+    }
+}
+
+struct Connection {
+    info: StreamInfo,
+    directions: [TcpDirection; 2],
+    last_seen: f64,
+}
+
+impl Connection {
+    fn buffered(&self) -> usize {
+        self.directions.iter().map(|d| d.buffered()).sum()
+    }
+}
+
+// Tracks TCP connections and feeds their in-order byte streams to consumers
+pub struct ReassemblySink<'a> {
+    config: ReassemblyConfig,
+    connections: HashMap<FlowKey, Connection>,
+    consumers: Vec<&'a mut dyn StreamConsumer>,
+    next_id: u64,
+    buffered_total: usize,
+    last_sweep: f64,
+    stats: ReassemblyStats,
+}
+
+impl<'a> ReassemblySink<'a> {
+    pub fn new(config: ReassemblyConfig, consumers: Vec<&'a mut dyn StreamConsumer>) -> Self {
+        ReassemblySink {
+            config,
+            connections: HashMap::new(),
+            consumers,
+            next_id: 0,
+            buffered_total: 0,
+            last_sweep: 0.0,
+            stats: ReassemblyStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> ReassemblyStats {
+        self.stats
+    }
+
+    fn open(&mut self, key: FlowKey, packet: &DecodedPacket, tcp: &TcpInfo) -> Option<&mut Connection> {
+        let src = (packet.src_addr()?, tcp.src_port);
+        let dst = (packet.dst_addr()?, tcp.dst_port);
+        // The SYN sender is the client; mid-stream, guess the ephemeral (higher) port
+        let src_is_client = if tcp.flags.syn {
+            !tcp.flags.ack
+        } else {
+            tcp.src_port >= tcp.dst_port
+        };
+        let (client, server) = if src_is_client { (src, dst) } else { (dst, src) };
+
+        if self.connections.len() >= self.config.max_streams {
+            self.evict_oldest();
+        }
+        self.next_id += 1;
+        self.stats.streams += 1;
+        let info = StreamInfo { id: self.next_id, client, server, first_seen: packet.timestamp() };
+        Some(self.connections.entry(key).or_insert(Connection {
+            info,
+            directions: [TcpDirection::default(), TcpDirection::default()],
+            last_seen: packet.timestamp(),
+        }))
+    }
+
+    fn close(&mut self, key: &FlowKey, reason: CloseReason) {
+        if let Some(connection) = self.connections.remove(key) {
+            self.buffered_total -= connection.buffered();
+            self.stats.closed(reason);
+            for consumer in self.consumers.iter_mut() {
+                consumer.on_close(&connection.info, reason);
+            }
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .connections
+            .iter()
+            .min_by(|a, b| a.1.last_seen.total_cmp(&b.1.last_seen))
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest {
+            self.close(&key, CloseReason::Evicted);
+        }
+    }
+
+    fn expire(&mut self, now: f64) {
+        let idle: Vec<FlowKey> = self
+            .connections
+            .iter()
+            .filter(|(_, c)| now - c.last_seen > self.config.idle_timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in idle {
+            self.close(&key, CloseReason::IdleTimeout);
+        }
+    }
+}
+
+impl PacketSink for ReassemblySink<'_> {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let Ok(decoded) = &packet.decoded else {
+            return Ok(());
+        };
+        let (Some(tcp), Some(key)) = (decoded.tcp(), FlowKey::from_packet(decoded)) else {
+            return Ok(());
+        };
+        let now = decoded.timestamp();
+        if now - self.last_sweep >= 1.0 {
+            self.expire(now);
+            self.last_sweep = now;
+        }
+
+        let data = decoded.payload(packet.data);
+        if !self.connections.contains_key(&key) {
+            // Nothing to deliver from a stray RST or FIN of a connection we never saw
+            if tcp.flags.rst || (data.is_empty() && !tcp.flags.syn) {
+                return Ok(());
+            }
+            if self.open(key, decoded, tcp).is_none() {
+                return Ok(());
+            }
+        }
+        let max_buffered = self.config.max_buffered_per_direction;
+        let Some(connection) = self.connections.get_mut(&key) else {
+            return Ok(());
+        };
+        connection.last_seen = now;
+
+        let direction = if (decoded.src_addr(), tcp.src_port) == (Some(connection.info.client.0), connection.info.client.1) {
+            Direction::ClientToServer
+        } else {
+            Direction::ServerToClient
+        };
+        let stream = &mut connection.directions[direction.index()];
+        let before = stream.buffered();
+        let delivered = stream.push(tcp, data, max_buffered);
+        let after = stream.buffered();
+        self.buffered_total = self.buffered_total + after - before;
+
+        for chunk in &delivered {
+            match chunk {
+                Delivered::Data(bytes) => {
+                    self.stats.bytes += bytes.len() as u64;
+                    for consumer in self.consumers.iter_mut() {
+                        consumer.on_data(&connection.info, direction, bytes, now);
+                    }
+                }
+                Delivered::Gap(missing) => {
+                    self.stats.missing_bytes += missing;
+                    for consumer in self.consumers.iter_mut() {
+                        consumer.on_gap(&connection.info, direction, *missing);
+                    }
+                }
+            }
+        }
+
+        if tcp.flags.rst {
+            self.close(&key, CloseReason::Reset);
+        } else if connection.directions.iter().all(|d| d.is_finished()) {
+            self.close(&key, CloseReason::Finished);
+        }
+
+        while self.buffered_total > self.config.max_buffered_total && !self.connections.is_empty() {
+            self.evict_oldest();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        let keys: Vec<FlowKey> = self.connections.keys().copied().collect();
+        for key in keys {
+            self.close(&key, CloseReason::EndOfCapture);
+        }
+        for consumer in self.consumers.iter_mut() {
+            consumer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+// tcpflow-style name: zero-padded address and port of the sender, then the receiver
+fn endpoint_name(endpoint: (IpAddr, u16)) -> String {
+    let address = match endpoint.0 {
+        IpAddr::V4(addr) => addr.octets().iter().map(|o| format!("{:03}", o)).collect::<Vec<_>>().join("."),
+        IpAddr::V6(addr) => addr.segments().iter().map(|s| format!("{:04x}", s)).collect::<Vec<_>>().join("."),
+    };
+    format!("{}.{:05}", address, endpoint.1)
+}
+
+// Writes each direction of every stream to its own file, like tcpflow.
+// A reused 4-tuple appends to the existing file.
+pub struct StreamDumper {
+    directory: PathBuf,
+    files: HashMap<(u64, usize), BufWriter<File>>,
+    errors: u64,
+}
+
+impl StreamDumper {
+    pub fn new(directory: &str) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(directory)?;
+        Ok(StreamDumper {
+            directory: PathBuf::from(directory),
+            files: HashMap::new(),
+            errors: 0,
+        })
+    }
+
+    fn file_for(&mut self, stream: &StreamInfo, direction: Direction) -> Option<&mut BufWriter<File>> {
+        let key = (stream.id, direction.index());
+        if !self.files.contains_key(&key) {
+            let (from, to) = match direction {
+                Direction::ClientToServer => (stream.client, stream.server),
+                Direction::ServerToClient => (stream.server, stream.client),
+            };
+            let path = self.directory.join(format!("{}-{}", endpoint_name(from), endpoint_name(to)));
+            match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => {
+                    self.files.insert(key, BufWriter::new(file));
+                }
+                Err(e) => {
+                    if self.errors == 0 {
+                        eprintln!("Warning: could not write stream file {}: {}", path.display(), e);
+                    }
+                    self.errors += 1;
+                    return None;
+                }
+            }
+        }
+        self.files.get_mut(&key)
+    }
+}
+
+impl StreamConsumer for StreamDumper {
+    fn on_data(&mut self, stream: &StreamInfo, direction: Direction, data: &[u8], _ts: f64) {
+        let failed = match self.file_for(stream, direction) {
+            Some(file) => file.write_all(data).is_err(),
+            None => false,
+        };
+        if failed {
+            self.errors += 1;
+        }
+    }
+
+    fn on_close(&mut self, stream: &StreamInfo, _reason: CloseReason) {
+        for index in 0..2 {
+            if let Some(mut file) = self.files.remove(&(stream.id, index)) {
+                if file.flush().is_err() {
+                    self.errors += 1;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        for (_, mut file) in self.files.drain() {
+            file.flush()?;
+        }
+        if self.errors > 0 {
+            eprintln!("Warning: {} stream dump write(s) failed", self.errors);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::TcpFlags;
+
+    fn segment(seq: u32) -> TcpInfo {
+        TcpInfo {
+            src_port: 40000,
+            dst_port: 80,
+            flags: TcpFlags::default(),
+            seq,
+            ack: 0,
+            window: 65535,
+        }
+    }
+
+    fn data(delivered: &[Delivered]) -> Vec<u8> {
+        delivered
+            .iter()
+            .flat_map(|d| match d {
+                Delivered::Data(bytes) => bytes.clone(),
+                Delivered::Gap(_) => Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reorders_and_trims_across_wraparound() {
+        let mut direction = TcpDirection::default();
+        let start = u32::MAX - 4;
+        let mut out = direction.push(&segment(start), b"abc", 1024);
+        // Held until "def" arrives; overlaps it by one byte
+        out.extend(direction.push(&segment(start.wrapping_add(5)), b"fghij", 1024));
+        out.extend(direction.push(&segment(start.wrapping_add(3)), b"de", 1024));
+        // Retransmission of delivered bytes
+        out.extend(direction.push(&segment(start.wrapping_add(1)), b"bcd", 1024));
+        assert_eq!(data(&out), b"abcdefghij");
+        assert_eq!(direction.buffered(), 0);
+        assert_eq!(direction.delivered_bytes, 10);
+    }
+
+    #[test]
+    fn many_held_segments_drain_without_recursion() {
+        let mut direction = TcpDirection::default();
+        direction.push(&segment(0), b"x", usize::MAX);
+        // Every segment but the first missing one, newest first
+        for i in (2..200_000u32).rev() {
+            assert!(direction.push(&segment(i), b"x", usize::MAX).is_empty());
+        }
+        let out = direction.push(&segment(1), b"x", usize::MAX);
+        assert_eq!(data(&out).len(), 199_999);
+        assert_eq!(direction.buffered(), 0);
+    }
+
+    #[test]
+    fn skips_the_hole_when_the_buffer_is_full() {
+        let mut direction = TcpDirection::default();
+        direction.push(&segment(100), b"abc", 4);
+        direction.push(&segment(110), b"kl", 4);
+        let out = direction.push(&segment(106), b"ghij", 4);
+        assert_eq!(out, [Delivered::Gap(3), Delivered::Data(b"ghijkl".to_vec())]);
+        assert_eq!(direction.missing_bytes, 3);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::source::PacketSource;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Why a capture failed, so callers can tell a flapping NIC from a setup mistake
+#[derive(Debug, Clone)]
+pub enum CaptureError {
+    // The interface disappeared or went down
+    DeviceGone { device: String, message: String },
+    // Not allowed to capture; retrying won't help
+    PermissionDenied { device: String, message: String },
+    // The BPF expression did not compile
+    BadFilter { filter: String, message: String },
+    // Anything else reported by libpcap
+    Failed { device: String, message: String },
+}
+
+impl CaptureError {
+    // Classify an error raised while opening or reading `device`
+    pub fn classify(device: &str, error: &pcap::Error) -> Self {
+        let device = device.to_string();
+        let message = error.to_string();
+        if let pcap::Error::IoError(std::io::ErrorKind::PermissionDenied) = error {
+            return CaptureError::PermissionDenied { device, message };
+        }
+
+        let lower = message.to_lowercase();
+        if lower.contains("permission") || lower.contains("not permitted") || lower.contains("access is denied") {
+            CaptureError::PermissionDenied { device, message }
+        } else if lower.contains("no such device")
+            || lower.contains("went down")
+            || lower.contains("network is down")
+            || lower.contains("not up")
+            || lower.contains("device not configured")
+            || lower.contains("packetreceivepacket failed")
+        {
+            CaptureError::DeviceGone { device, message }
+        } else {
+            CaptureError::Failed { device, message }
+        }
+    }
+
+    pub fn bad_filter(filter: &str, error: &pcap::Error) -> Self {
+        CaptureError::BadFilter {
+            filter: filter.to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    // Recover the typed error from a boxed one, wrapping foreign errors as `Failed`
+    pub fn from_boxed(device: &str, error: Box<dyn Error>) -> Self {
+        match error.downcast::<CaptureError>() {
+            Ok(error) => *error,
+            Err(error) => CaptureError::Failed {
+                device: device.to_string(),
+                message: error.to_string(),
+            },
+        }
+    }
+
+    // Worth re-opening the device for; permission and filter errors are not
+    pub fn is_transient(&self) -> bool {
+        matches!(self, CaptureError::DeviceGone { .. } | CaptureError::Failed { .. })
+    }
+
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            CaptureError::DeviceGone { device, .. }
+            | CaptureError::PermissionDenied { device, .. }
+            | CaptureError::Failed { device, .. } => Some(device),
+            CaptureError::BadFilter { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureError::DeviceGone { device, message } => write!(f, "device {} went away: {}", device, message),
+            CaptureError::PermissionDenied { device, message } => {
+                write!(f, "permission denied on {}: {}", device, message)
+            }
+            CaptureError::BadFilter { filter, message } => write!(f, "invalid filter '{}': {}", filter, message),
+            CaptureError::Failed { device, message } => write!(f, "capture on {} failed: {}", device, message),
+        }
+    }
+}
+
+impl Error for CaptureError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecoveryMode {
+    // End the capture with the error (after flushing output)
+    #[default]
+    Abort,
+    // Re-open the device with exponential backoff
+    Reconnect,
+}
+
+impl FromStr for RecoveryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "abort" => Ok(RecoveryMode::Abort),
+            "reconnect" => Ok(RecoveryMode::Reconnect),
+            other => Err(format!("Unknown recovery mode '{}'", other)),
+        }
+    }
+}
+
+// What `run_capture` does when the source fails mid-capture
+#[derive(Debug, Clone, Copy)]
+pub struct RecoveryPolicy {
+    pub mode: RecoveryMode,
+    // Give up after this many failed re-open attempts in a row; None retries forever
+    pub max_retries: Option<u32>,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        RecoveryPolicy {
+            mode: RecoveryMode::Abort,
+            max_retries: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+// Period during which a device was not capturing; times are wall clock (sec, usec)
+#[derive(Debug, Clone)]
+pub struct CaptureGap {
+    pub device: String,
+    pub start: (i64, i64),
+    pub end: (i64, i64),
+    pub reason: String,
+    pub attempts: u32,
+}
+
+impl CaptureGap {
+    pub fn seconds(&self) -> f64 {
+        (self.end.0 - self.start.0) as f64 + (self.end.1 - self.start.1) as f64 / 1_000_000.0
+    }
+}
+
+// Progress of `reconnect`, for the caller to report however suits its output
+#[derive(Debug, Clone)]
+pub enum ReconnectEvent {
+    // About to wait `delay` before re-open attempt number `attempt`
+    Retrying { error: CaptureError, delay: Duration, attempt: u32 },
+    Reconnected { device: String },
+}
+
+impl fmt::Display for ReconnectEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReconnectEvent::Retrying { error, delay, attempt } => {
+                write!(f, "{}; reconnecting in {:.1}s (attempt {})", error, delay.as_secs_f64(), attempt)
+            }
+            ReconnectEvent::Reconnected { device } => write!(f, "Reconnected to {}", device),
+        }
+    }
+}
+
+fn wall_clock() -> (i64, i64) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (now.as_secs() as i64, now.subsec_micros() as i64)
+}
+
+// Sleep for `duration`, waking early if the stop flag is raised. Returns false when stopped.
+fn sleep_unless_stopped(duration: Duration, stop_flag: Option<&AtomicBool>) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if stop_flag.is_some_and(|flag| flag.load(Ordering::SeqCst)) {
+            return false;
+        }
+        thread::sleep((deadline - Instant::now()).min(Duration::from_millis(100)));
+    }
+    true
+}
+
+// Try to bring a failed source back according to the policy, telling `on_event`
+// about each attempt. On success (or when interrupted while waiting) the returned
+// gap covers the time nothing was captured; otherwise the error that made us give
+// up is returned.
+pub fn reconnect(
+    source: &mut dyn PacketSource,
+    error: CaptureError,
+    policy: &RecoveryPolicy,
+    stop_flag: Option<&AtomicBool>,
+    on_event: &mut dyn FnMut(&ReconnectEvent),
+) -> Result<CaptureGap, CaptureError> {
+    if policy.mode != RecoveryMode::Reconnect || !error.is_transient() || !source.can_reopen() {
+        return Err(error);
+    }
+
+    let start = wall_clock();
+    let reason = error.to_string();
+    let device = error.device().unwrap_or("?").to_string();
+    let mut last_error = error;
+    let mut backoff = policy.initial_backoff;
+    let mut attempts = 0;
+
+    loop {
+        if policy.max_retries.is_some_and(|max| attempts >= max) {
+            return Err(last_error);
+        }
+        attempts += 1;
+        on_event(&ReconnectEvent::Retrying {
+            error: last_error.clone(),
+            delay: backoff,
+            attempt: attempts,
+        });
+        if !sleep_unless_stopped(backoff, stop_flag) {
+            break;
+        }
+
+        match source.reopen() {
+            Ok(()) => {
+                on_event(&ReconnectEvent::Reconnected { device: device.clone() });
+                break;
+            }
+            Err(e) => {
+                last_error = CaptureError::from_boxed(&device, e);
+                if !last_error.is_transient() {
+                    return Err(last_error);
+                }
+                backoff = (backoff * 2).min(policy.max_backoff);
+            }
+        }
+    }
+
+    Ok(CaptureGap { device, start, end: wall_clock(), reason, attempts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{InterfaceInfo, SourceEvent};
+
+    // A device that comes back after `failures` more failed re-opens
+    struct Unplugged {
+        failures: u32,
+        reopens: u32,
+    }
+
+    impl PacketSource for Unplugged {
+        fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>> {
+            Ok(SourceEvent::Exhausted)
+        }
+
+        fn interfaces(&self) -> Vec<InterfaceInfo> {
+            Vec::new()
+        }
+
+        fn set_filter(&mut self, _filter: &str) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn can_reopen(&self) -> bool {
+            true
+        }
+
+        fn reopen(&mut self) -> Result<(), Box<dyn Error>> {
+            self.reopens += 1;
+            if self.reopens <= self.failures {
+                return Err(Box::new(gone("still down")));
+            }
+            Ok(())
+        }
+    }
+
+    fn gone(message: &str) -> CaptureError {
+        CaptureError::DeviceGone {
+            device: "eth0".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn policy(max_retries: Option<u32>) -> RecoveryPolicy {
+        RecoveryPolicy {
+            mode: RecoveryMode::Reconnect,
+            max_retries,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn reports_each_attempt_to_the_caller() {
+        let mut source = Unplugged { failures: 1, reopens: 0 };
+        let mut events = Vec::new();
+        let gap = reconnect(&mut source, gone("network is down"), &policy(None), None, &mut |event| {
+            events.push(event.to_string())
+        })
+        .unwrap();
+        assert_eq!(gap.device, "eth0");
+        assert_eq!(gap.attempts, 2);
+        assert_eq!(gap.reason, "device eth0 went away: network is down");
+        assert_eq!(
+            events,
+            [
+                "device eth0 went away: network is down; reconnecting in 0.0s (attempt 1)",
+                "device eth0 went away: still down; reconnecting in 0.0s (attempt 2)",
+                "Reconnected to eth0",
+            ]
+        );
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let mut source = Unplugged { failures: 5, reopens: 0 };
+        let mut attempts = 0;
+        let error = reconnect(&mut source, gone("network is down"), &policy(Some(2)), None, &mut |event| {
+            assert!(matches!(event, ReconnectEvent::Retrying { .. }));
+            attempts += 1;
+        })
+        .unwrap_err();
+        assert_eq!(attempts, 2);
+        assert_eq!(error.to_string(), "device eth0 went away: still down");
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        let mut source = Unplugged { failures: 0, reopens: 0 };
+        let denied = CaptureError::PermissionDenied {
+            device: "eth0".to_string(),
+            message: "operation not permitted".to_string(),
+        };
+        let result = reconnect(&mut source, denied, &policy(None), None, &mut |_| panic!("no attempt expected"));
+        assert!(result.is_err());
+        assert_eq!(source.reopens, 0);
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{self, Write};
+
+// Entries largest first by `weight`, ties broken by key so output is stable between runs
+pub fn largest_first<K: Ord, V, W: Ord>(
+    entries: impl IntoIterator<Item = (K, V)>,
+    weight: impl Fn(&V) -> W,
+) -> Vec<(K, V)> {
+    let mut sorted: Vec<(K, V)> = entries.into_iter().collect();
+    sorted.sort_by(|a, b| weight(&b.1).cmp(&weight(&a.1)).then(a.0.cmp(&b.0)));
+    sorted
+}
+
+// Counts largest first, from a HashMap or BTreeMap
+pub fn sorted_counts<'a, K: Ord, V: Ord>(counts: impl IntoIterator<Item = (&'a K, &'a V)>) -> Vec<(&'a K, &'a V)> {
+    largest_first(counts, |count| *count)
+}
+
+// "Title:" followed by the `top` largest counts, for the protocol summaries
+pub fn write_top<K: Display>(out: &mut dyn Write, title: &str, counts: &HashMap<K, u64>, top: usize) -> io::Result<()> {
+    if counts.is_empty() {
+        return Ok(());
+    }
+    let sorted = largest_first(counts.iter().map(|(k, v)| (k.to_string(), *v)), |count| *count);
+    writeln!(out, "{}:", title)?;
+    for (key, count) in sorted.into_iter().take(top) {
+        writeln!(out, "  {:<50} {}", key, count)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn largest_first_breaks_ties_by_key() {
+        let counts = HashMap::from([("b", 2u64), ("c", 5), ("a", 2)]);
+        assert_eq!(sorted_counts(&counts), [(&"c", &5), (&"a", &2), (&"b", &2)]);
+        let counts = BTreeMap::from([(443u16, 1u32), (80, 1), (53, 3)]);
+        assert_eq!(sorted_counts(&counts), [(&53, &3), (&80, &1), (&443, &1)]);
+        let sizes = [("x", (1, 10)), ("y", (9, 1))];
+        assert_eq!(largest_first(sizes, |(_, bytes)| *bytes), [("x", (1, 10)), ("y", (9, 1))]);
+    }
+
+    #[test]
+    fn write_top_limits_the_list() {
+        let counts = HashMap::from([("one", 1u64), ("three", 3), ("two", 2)]);
+        let mut out = Vec::new();
+        write_top(&mut out, "Top", &counts, 2).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "Top:");
+        assert!(lines[1].starts_with("  three ") && lines[1].ends_with(" 3"));
+        assert!(lines[2].starts_with("  two ") && lines[2].ends_with(" 2"));
+
+        let mut out = Vec::new();
+        write_top(&mut out, "Top", &HashMap::<String, u64>::new(), 2).unwrap();
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+use crate::listening::ParsedPacket;
+use crate::sink::PacketSink;
+use pcap::{Capture, Linktype, Packet, Savefile};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// pcap global header and per-record header sizes, used to track file size
+const PCAP_FILE_HEADER_LEN: u64 = 24;
+const PCAP_RECORD_HEADER_LEN: u64 = 16;
+
+// When to start a new output file and how many to keep (tcpdump -G/-C/-W)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RotationPolicy {
+    pub every_seconds: Option<u64>,
+    pub every_bytes: Option<u64>,
+    pub max_files: Option<usize>,
+}
+
+impl RotationPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.every_seconds.is_some() || self.every_bytes.is_some()
+    }
+}
+
+// Ring of pcap files named `<stem>_<seq>_<UTC time>.<ext>` next to the
+// requested output path. Time-based rotation uses packet timestamps so it
+// behaves the same for live and offline sources.
+pub struct RotatingSavefileSink {
+    base: PathBuf,
+    linktype: Linktype,
+    policy: RotationPolicy,
+    current: Option<Savefile>,
+    current_start: i64,
+    current_bytes: u64,
+    sequence: u32,
+    files: VecDeque<PathBuf>,
+}
+
+impl RotatingSavefileSink {
+    pub fn new(path: &str, linktype: Linktype, policy: RotationPolicy) -> Self {
+        RotatingSavefileSink {
+            base: PathBuf::from(path),
+            linktype,
+            policy,
+            current: None,
+            current_start: 0,
+            current_bytes: 0,
+            sequence: 0,
+            files: VecDeque::new(),
+        }
+    }
+
+    fn file_name(&self, ts_sec: i64) -> PathBuf {
+        let stem = self
+            .base
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "capture".to_string());
+        let ext = self
+            .base
+            .extension()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "pcap".to_string());
+        let name = format!("{}_{:05}_{}.{}", stem, self.sequence, format_utc_compact(ts_sec), ext);
+        match self.base.parent() {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        }
+    }
+
+    fn needs_rotation(&self, ts_sec: i64, next_record: u64) -> bool {
+        if self.current.is_none() {
+            return true;
+        }
+        if let Some(seconds) = self.policy.every_seconds {
+            if ts_sec - self.current_start >= seconds as i64 {
+                return true;
+            }
+        }
+        if let Some(limit) = self.policy.every_bytes {
+            // Never rotate an empty file, even if a single packet exceeds the limit
+            if self.current_bytes > PCAP_FILE_HEADER_LEN && self.current_bytes + next_record > limit {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn rotate(&mut self, ts_sec: i64) -> Result<(), Box<dyn Error>> {
+        if let Some(mut savefile) = self.current.take() {
+            savefile.flush()?;
+        }
+
+        let path = self.file_name(ts_sec);
+        self.sequence += 1;
+        self.current = Some(Capture::dead(self.linktype)?.savefile(&path)?);
+        self.current_start = ts_sec;
+        self.current_bytes = PCAP_FILE_HEADER_LEN;
+        self.files.push_back(path);
+
+        // Drop the oldest files beyond the ring size
+        if let Some(max_files) = self.policy.max_files {
+            while self.files.len() > max_files.max(1) {
+                if let Some(old) = self.files.pop_front() {
+                    remove_quietly(&old);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn remove_quietly(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        eprintln!("Warning: could not remove old capture file {}: {}", path.display(), e);
+    }
+}
+
+impl PacketSink for RotatingSavefileSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let record_len = PCAP_RECORD_HEADER_LEN + packet.raw.caplen as u64;
+        if self.needs_rotation(packet.raw.ts_sec, record_len) {
+            self.rotate(packet.raw.ts_sec)?;
+        }
+
+        if let Some(savefile) = &mut self.current {
+            let header = packet.raw.header();
+            savefile.write(&Packet::new(&header, &packet.raw.data));
+            self.current_bytes += record_len;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(savefile) = &mut self.current {
+            savefile.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// Format seconds since the epoch as YYYYMMDDhhmmss (UTC)
+pub fn format_utc_compact(ts_sec: i64) -> String {
+    let days = ts_sec.div_euclid(86_400);
+    let secs = ts_sec.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+// Days since 1970-01-01 to (year, month, day), proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listening::parse_packet_with_etherparse;
+    use crate::source::RawPacket;
+
+    // Fresh scratch directory per test; each test removes it when it is done
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("testgame-rotate-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // (file name, size in bytes) of everything in `dir`, sorted by name
+    fn files_in(dir: &Path) -> Vec<(String, u64)> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.file_name().to_string_lossy().into_owned(), entry.metadata().unwrap().len())
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn write_packets(sink: &mut RotatingSavefileSink, packets: &[(i64, usize)]) {
+        for (number, &(ts_sec, len)) in packets.iter().enumerate() {
+            let raw = RawPacket::new(ts_sec, 0, vec![0; len]);
+            let parsed = ParsedPacket {
+                number: number as u32 + 1,
+                raw: &raw,
+                decoded: parse_packet_with_etherparse(number as u32 + 1, &raw, Linktype::ETHERNET),
+                data: &raw.data,
+            };
+            sink.on_packet(&parsed).unwrap();
+        }
+        sink.finish().unwrap();
+    }
+
+    fn record(len: u64) -> u64 {
+        PCAP_RECORD_HEADER_LEN + len
+    }
+
+    #[test]
+    fn civil_from_days_handles_the_epoch_and_leap_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        // 1900 and 2100 are not leap years
+        assert_eq!(civil_from_days(-25_508), (1900, 3, 1));
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+        assert_eq!(civil_from_days(47_540), (2100, 2, 28));
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(format_utc_compact(0), "19700101000000");
+        assert_eq!(format_utc_compact(951_782_400), "20000229000000");
+        assert_eq!(format_utc_compact(1_709_251_199), "20240229235959");
+        assert_eq!(format_utc_compact(1_709_251_200), "20240301000000");
+        assert_eq!(format_utc_compact(-1), "19691231235959");
+    }
+
+    #[test]
+    fn rotates_by_size_but_never_leaves_a_file_empty() {
+        let dir = scratch_dir("size");
+        let limit = PCAP_FILE_HEADER_LEN + 2 * record(60);
+        let policy = RotationPolicy { every_bytes: Some(limit), ..Default::default() };
+        let mut sink = RotatingSavefileSink::new(dir.join("out.pcap").to_str().unwrap(), Linktype::ETHERNET, policy);
+        // The 300-byte packet exceeds the limit on its own and gets a file to itself
+        write_packets(&mut sink, &[(0, 60), (0, 60), (0, 60), (0, 300), (0, 60)]);
+
+        let header = PCAP_FILE_HEADER_LEN;
+        assert_eq!(
+            files_in(&dir),
+            vec![
+                ("out_00000_19700101000000.pcap".to_string(), header + 2 * record(60)),
+                ("out_00001_19700101000000.pcap".to_string(), header + record(60)),
+                ("out_00002_19700101000000.pcap".to_string(), header + record(300)),
+                ("out_00003_19700101000000.pcap".to_string(), header + record(60)),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_by_packet_time() {
+        let dir = scratch_dir("time");
+        let policy = RotationPolicy { every_seconds: Some(10), ..Default::default() };
+        let mut sink = RotatingSavefileSink::new(dir.join("trace.pcap").to_str().unwrap(), Linktype::ETHERNET, policy);
+        write_packets(&mut sink, &[(100, 40), (105, 40), (109, 40), (110, 40), (125, 40)]);
+
+        let names: Vec<_> = files_in(&dir).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            [
+                "trace_00000_19700101000140.pcap",
+                "trace_00001_19700101000150.pcap",
+                "trace_00002_19700101000205.pcap",
+            ]
+        );
+        let counts: Vec<_> = names
+            .iter()
+            .map(|name| {
+                let mut capture = Capture::from_file(dir.join(name)).unwrap();
+                std::iter::from_fn(|| capture.next_packet().ok().map(|_| ())).count()
+            })
+            .collect();
+        assert_eq!(counts, [3, 1, 1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn max_files_deletes_the_oldest() {
+        let dir = scratch_dir("ring");
+        let policy = RotationPolicy { every_seconds: Some(1), max_files: Some(2), ..Default::default() };
+        let mut sink = RotatingSavefileSink::new(dir.join("ring.pcap").to_str().unwrap(), Linktype::ETHERNET, policy);
+        write_packets(&mut sink, &[(0, 40), (1, 40), (2, 40), (3, 40)]);
+
+        let names: Vec<_> = files_in(&dir).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["ring_00002_19700101000002.pcap", "ring_00003_19700101000003.pcap"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
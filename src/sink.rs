@@ -0,0 +1,208 @@
+use crate::listening::{print_protocol_details, CaptureStats, InterfaceCounters, ParsedPacket, TrafficCounters};
+use crate::recovery::{CaptureGap, ReconnectEvent};
+use crate::source::{InterfaceInfo, SourceStats};
+use pcap::{Capture, Linktype, Packet, Savefile};
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::IpAddr;
+
+// Observer that receives every packet pulled through the capture loop
+pub trait PacketSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>>;
+
+    // Receive/drop counters from the source, delivered just before `finish`
+    fn on_source_stats(&mut self, _stats: &[SourceStats]) {}
+
+    // A device failed and is being reopened; `on_gap` follows if it comes back
+    fn on_reconnect(&mut self, _event: &ReconnectEvent) {}
+
+    // A device failed and was reopened; nothing was captured during the gap
+    fn on_gap(&mut self, _gap: &CaptureGap) {}
+
+    // Called once after the last packet, e.g. to flush buffered output
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+// Accumulates packet and per-protocol counts, overall and per interface
+pub struct StatsSink {
+    packet_count: u32,
+    protocol_stats: HashMap<String, u32>,
+    message_types: HashMap<String, u32>,
+    vlans: HashMap<String, TrafficCounters>,
+    tunnels: HashMap<String, TrafficCounters>,
+    interfaces: Vec<InterfaceCounters>,
+    gaps: Vec<CaptureGap>,
+}
+
+impl StatsSink {
+    pub fn new(interfaces: &[InterfaceInfo]) -> Self {
+        StatsSink {
+            packet_count: 0,
+            protocol_stats: HashMap::new(),
+            message_types: HashMap::new(),
+            vlans: HashMap::new(),
+            tunnels: HashMap::new(),
+            interfaces: interfaces
+                .iter()
+                .map(|info| InterfaceCounters {
+                    name: info.name.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            gaps: Vec::new(),
+        }
+    }
+
+    pub fn into_stats(self) -> CaptureStats {
+        CaptureStats {
+            packet_count: self.packet_count,
+            protocol_stats: self.protocol_stats,
+            message_types: self.message_types,
+            vlans: self.vlans,
+            tunnels: self.tunnels,
+            interfaces: self.interfaces,
+            flows: Vec::new(),
+            other_flows: Default::default(),
+            dns: Default::default(),
+            dhcp: Default::default(),
+            http: Default::default(),
+            tls: Default::default(),
+            quic: Default::default(),
+            streams: Default::default(),
+            fragments: Default::default(),
+            gaps: self.gaps,
+            intervals: Vec::new(),
+            endpoints: Default::default(),
+        }
+    }
+}
+
+impl PacketSink for StatsSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        self.packet_count += 1;
+        if let Some(interface) = self.interfaces.get_mut(packet.raw.interface as usize) {
+            interface.packet_count += 1;
+            if let Ok(decoded) = &packet.decoded {
+                *interface.protocol_stats.entry(decoded.protocol_name()).or_insert(0) += 1;
+            }
+        }
+
+        if let Ok(decoded) = &packet.decoded {
+            *self.protocol_stats.entry(decoded.protocol_name()).or_insert(0) += 1;
+            if let Some(message_type) = decoded.message_type() {
+                *self.message_types.entry(message_type).or_insert(0) += 1;
+            }
+            if let Some(vlan) = decoded.vlan_key() {
+                self.vlans.entry(vlan).or_default().add(packet.raw.len);
+            }
+            for encapsulation in &decoded.encapsulation {
+                self.tunnels.entry(encapsulation.label()).or_default().add(packet.raw.len);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_source_stats(&mut self, stats: &[SourceStats]) {
+        for stat in stats {
+            if let Some(interface) = self.interfaces.get_mut(stat.interface as usize) {
+                interface.dropped = Some(stat.dropped);
+            }
+        }
+    }
+
+    fn on_gap(&mut self, gap: &CaptureGap) {
+        self.gaps.push(gap.clone());
+    }
+}
+
+// Human-readable output: full details in verbose mode, periodic progress otherwise
+pub struct ConsoleSink {
+    verbose: bool,
+    // Only set when capturing on more than one interface
+    interface_names: Option<Vec<String>>,
+}
+
+impl ConsoleSink {
+    pub fn new(verbose: bool, interfaces: &[InterfaceInfo]) -> Self {
+        let interface_names = if interfaces.len() > 1 {
+            Some(interfaces.iter().map(|info| info.name.clone()).collect())
+        } else {
+            None
+        };
+        ConsoleSink { verbose, interface_names }
+    }
+}
+
+impl PacketSink for ConsoleSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        match &packet.decoded {
+            Ok(decoded) => {
+                if self.verbose {
+                    println!("Packet #{}, Length: {} bytes", packet.number, packet.raw.len);
+                    if let Some(names) = &self.interface_names {
+                        let name = names.get(packet.raw.interface as usize).map_or("?", |n| n.as_str());
+                        println!("  Interface: {}", name);
+                    }
+                    println!("  Protocol: {}", decoded.protocol_name());
+                    println!("  Source: {}:{}", addr_or_na(decoded.src_addr()), decoded.src_port().unwrap_or_default());
+                    println!("  Destination: {}:{}", addr_or_na(decoded.dst_addr()), decoded.dst_port().unwrap_or_default());
+                    print_protocol_details(decoded);
+                    println!("----------------------------------------");
+                } else if packet.number.is_multiple_of(10) {
+                    // Show brief status update every 10 packets
+                    println!("Captured {} packets...", packet.number);
+                }
+            }
+            Err(e) => {
+                if self.verbose {
+                    println!("Packet #{}: Error parsing packet - {}", packet.number, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn addr_or_na(addr: Option<IpAddr>) -> String {
+    addr.map(|a| a.to_string()).unwrap_or_else(|| "N/A".to_string())
+}
+
+// Hands reconnect progress to a function chosen by the caller
+pub struct ReconnectStatus(pub fn(&ReconnectEvent));
+
+impl PacketSink for ReconnectStatus {
+    fn on_packet(&mut self, _packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn on_reconnect(&mut self, event: &ReconnectEvent) {
+        (self.0)(event);
+    }
+}
+
+// Writes raw packets to a classic pcap file
+pub struct SavefileSink {
+    savefile: Savefile,
+}
+
+impl SavefileSink {
+    pub fn create(path: &str, linktype: Linktype) -> Result<Self, Box<dyn Error>> {
+        let savefile = Capture::dead(linktype)?.savefile(path)?;
+        Ok(SavefileSink { savefile })
+    }
+}
+
+impl PacketSink for SavefileSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let header = packet.raw.header();
+        self.savefile.write(&Packet::new(&header, &packet.raw.data));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.savefile.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,704 @@
+use crate::recovery::CaptureError;
+use pcap::{Active, Activated, BpfProgram, Capture, Device, Linktype, Offline, Packet, PacketHeader};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Packet copied out of the pcap buffer so it can outlive the capture handle
+#[derive(Debug, Clone)]
+pub struct RawPacket {
+    // Index into the source's `interfaces()`
+    pub interface: u32,
+    pub ts_sec: i64,
+    pub ts_usec: i64,
+    pub caplen: u32,
+    pub len: u32,
+    pub data: Vec<u8>,
+}
+
+impl RawPacket {
+    pub fn new(ts_sec: i64, ts_usec: i64, data: Vec<u8>) -> Self {
+        let len = data.len() as u32;
+        RawPacket { interface: 0, ts_sec, ts_usec, caplen: len, len, data }
+    }
+
+    // timeval fields are i32 on Windows, hence the conversions
+    #[allow(clippy::useless_conversion)]
+    pub fn from_pcap(packet: &Packet) -> Self {
+        RawPacket {
+            interface: 0,
+            ts_sec: i64::from(packet.header.ts.tv_sec),
+            ts_usec: i64::from(packet.header.ts.tv_usec),
+            caplen: packet.header.caplen,
+            len: packet.header.len,
+            data: packet.data.to_vec(),
+        }
+    }
+
+    // Rebuild the pcap header, e.g. for writing through a Savefile
+    pub fn header(&self) -> PacketHeader {
+        PacketHeader {
+            ts: libc::timeval {
+                tv_sec: self.ts_sec as _,
+                tv_usec: self.ts_usec as _,
+            },
+            caplen: self.caplen,
+            len: self.len,
+        }
+    }
+}
+
+pub enum SourceEvent {
+    Packet(RawPacket),
+    // No packet arrived before the read timeout, try again
+    Timeout,
+    // The source has no more packets (end of file or vector)
+    Exhausted,
+}
+
+// Where packets come from, as recorded in pcapng output
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub linktype: Linktype,
+    // Longest packet the interface captures; 0 means no limit
+    pub snaplen: u32,
+}
+
+// Kernel/driver counters for one interface of a live source
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SourceStats {
+    pub interface: u32,
+    pub received: u64,
+    pub dropped: u64,
+}
+
+pub trait PacketSource {
+    fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>>;
+
+    // Interfaces this source reads from; `RawPacket::interface` indexes this list
+    fn interfaces(&self) -> Vec<InterfaceInfo>;
+
+    // Link-layer type of the first (usually only) interface
+    fn datalink(&self) -> Linktype {
+        self.interfaces()[0].linktype
+    }
+
+    // Install a BPF filter; only matching packets are returned afterwards
+    fn set_filter(&mut self, filter: &str) -> Result<(), Box<dyn Error>>;
+
+    // Receive/drop counters per interface, if the source keeps any
+    fn stats(&mut self) -> Vec<SourceStats> {
+        Vec::new()
+    }
+
+    // Whether `reopen` can bring the source back after a read error
+    fn can_reopen(&self) -> bool {
+        false
+    }
+
+    // Re-open the underlying device after a failure, keeping the filter
+    fn reopen(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("This source cannot be reopened".into())
+    }
+}
+
+fn next_from_capture<T: Activated + ?Sized>(
+    cap: &mut Capture<T>,
+    name: &str,
+) -> Result<SourceEvent, Box<dyn Error>> {
+    match cap.next_packet() {
+        Ok(packet) => Ok(SourceEvent::Packet(RawPacket::from_pcap(&packet))),
+        Err(pcap::Error::TimeoutExpired) => Ok(SourceEvent::Timeout),
+        Err(pcap::Error::NoMorePackets) => Ok(SourceEvent::Exhausted),
+        Err(e) => Err(Box::new(CaptureError::classify(name, &e))),
+    }
+}
+
+// Snapshot length live captures are opened with (libpcap's own default)
+const LIVE_SNAPLEN: u32 = 262_144;
+
+// Live capture on a network device
+pub struct LiveSource {
+    cap: Capture<Active>,
+    device: Device,
+    promiscuous: bool,
+    filter: Option<String>,
+}
+
+impl LiveSource {
+    pub fn open(device: Device, promiscuous: bool) -> Result<Self, Box<dyn Error>> {
+        let cap = open_device(&device, promiscuous)?;
+        Ok(LiveSource { cap, device, promiscuous, filter: None })
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+}
+
+fn open_device(device: &Device, promiscuous: bool) -> Result<Capture<Active>, CaptureError> {
+    let classify = |e: pcap::Error| CaptureError::classify(&device.name, &e);
+    let mut cap_builder = Capture::from_device(device.clone()).map_err(classify)?;
+
+    if promiscuous {
+        cap_builder = cap_builder.promisc(true);
+    }
+
+    cap_builder = cap_builder.timeout(1000).snaplen(LIVE_SNAPLEN as i32);
+
+    cap_builder.open().map_err(classify)
+}
+
+impl PacketSource for LiveSource {
+    fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>> {
+        next_from_capture(&mut self.cap, &self.device.name)
+    }
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        vec![InterfaceInfo {
+            name: self.device.name.clone(),
+            description: self.device.desc.clone(),
+            linktype: self.cap.get_datalink(),
+            snaplen: LIVE_SNAPLEN,
+        }]
+    }
+
+    fn set_filter(&mut self, filter: &str) -> Result<(), Box<dyn Error>> {
+        self.cap
+            .filter(filter, true)
+            .map_err(|e| CaptureError::bad_filter(filter, &e))?;
+        self.filter = Some(filter.to_string());
+        Ok(())
+    }
+
+    fn can_reopen(&self) -> bool {
+        true
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<dyn Error>> {
+        // Look the device up again: after a reset it may come back with new details
+        let device = Device::list()
+            .ok()
+            .and_then(|devices| devices.into_iter().find(|dev| dev.name == self.device.name))
+            .ok_or_else(|| CaptureError::DeviceGone {
+                device: self.device.name.clone(),
+                message: "No such device".to_string(),
+            })?;
+        let mut cap = open_device(&device, self.promiscuous)?;
+        if let Some(filter) = &self.filter {
+            cap.filter(filter, true)
+                .map_err(|e| CaptureError::bad_filter(filter, &e))?;
+        }
+        self.cap = cap;
+        self.device = device;
+        Ok(())
+    }
+
+    fn stats(&mut self) -> Vec<SourceStats> {
+        match self.cap.stats() {
+            Ok(stat) => vec![SourceStats {
+                interface: 0,
+                received: stat.received as u64,
+                dropped: stat.dropped as u64 + stat.if_dropped as u64,
+            }],
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+// Offline capture read from a pcap/pcapng savefile
+pub struct FileSource {
+    cap: Capture<Offline>,
+    path: String,
+    snaplen: u32,
+}
+
+impl FileSource {
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        let cap = Capture::from_file(path)?;
+        let snaplen = savefile_snaplen(path).unwrap_or(0);
+        Ok(FileSource { cap, path: path.to_string(), snaplen })
+    }
+}
+
+// Snapshot length from a savefile's header, or from the first interface of a
+// pcapng file. libpcap doesn't expose it, so the header is read directly.
+fn savefile_snaplen(path: &str) -> Option<u32> {
+    let mut header = Vec::new();
+    File::open(path).ok()?.take(65_536).read_to_end(&mut header).ok()?;
+    let u32_at = |offset: usize, little_endian: bool| -> Option<u32> {
+        let bytes: [u8; 4] = header.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    };
+    match header.get(..4)? {
+        // Classic pcap, microsecond or nanosecond timestamps
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => u32_at(16, true),
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => u32_at(16, false),
+        // pcapng: walk the blocks up to the first Interface Description Block
+        [0x0a, 0x0d, 0x0d, 0x0a] => {
+            let little_endian = header.get(8..12)? == [0x4d, 0x3c, 0x2b, 0x1a];
+            let mut offset = 0;
+            loop {
+                if u32_at(offset, little_endian)? == 1 {
+                    return u32_at(offset + 12, little_endian);
+                }
+                let length = u32_at(offset + 4, little_endian)? as usize;
+                if length < 12 {
+                    return None;
+                }
+                offset += length;
+            }
+        }
+        _ => None,
+    }
+}
+
+impl PacketSource for FileSource {
+    fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>> {
+        next_from_capture(&mut self.cap, &self.path)
+    }
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        vec![InterfaceInfo {
+            name: self.path.clone(),
+            description: Some("Offline savefile".to_string()),
+            linktype: self.cap.get_datalink(),
+            snaplen: self.snaplen,
+        }]
+    }
+
+    fn set_filter(&mut self, filter: &str) -> Result<(), Box<dyn Error>> {
+        self.cap
+            .filter(filter, true)
+            .map_err(|e| CaptureError::bad_filter(filter, &e))?;
+        Ok(())
+    }
+}
+
+// In-memory packets, useful for tests and for replaying already-loaded data
+pub struct VecSource {
+    packets: VecDeque<RawPacket>,
+    linktype: Linktype,
+    filter: Option<BpfProgram>,
+}
+
+impl VecSource {
+    pub fn new(packets: Vec<RawPacket>, linktype: Linktype) -> Self {
+        VecSource {
+            packets: packets.into(),
+            linktype,
+            filter: None,
+        }
+    }
+}
+
+impl PacketSource for VecSource {
+    fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>> {
+        while let Some(packet) = self.packets.pop_front() {
+            let matches = match &self.filter {
+                Some(program) => program.filter(&packet.data),
+                None => true,
+            };
+            if matches {
+                return Ok(SourceEvent::Packet(packet));
+            }
+        }
+        Ok(SourceEvent::Exhausted)
+    }
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        vec![InterfaceInfo {
+            name: "memory".to_string(),
+            description: Some("In-memory packet list".to_string()),
+            linktype: self.linktype,
+            snaplen: 0,
+        }]
+    }
+
+    fn set_filter(&mut self, filter: &str) -> Result<(), Box<dyn Error>> {
+        // Compile against a dead handle since there is no device to attach to
+        let program = Capture::dead(self.linktype)?
+            .compile(filter, true)
+            .map_err(|e| CaptureError::bad_filter(filter, &e))?;
+        self.filter = Some(program);
+        Ok(())
+    }
+}
+
+// How long a packet may wait for slower interfaces before it is released anyway
+const REORDER_WINDOW: Duration = Duration::from_millis(2000);
+
+type WorkerEvent = (usize, Result<SourceEvent, CaptureError>);
+type Worker = JoinHandle<Box<dyn PacketSource + Send>>;
+
+// Several sources read concurrently, one thread each, merged into a single
+// stream ordered by packet timestamp. A packet is released once every other
+// interface has either queued something later or reported since it arrived.
+pub struct MultiSource {
+    // Per interface: the source while it is not owned by a worker thread
+    sources: Vec<Option<Box<dyn PacketSource + Send>>>,
+    interfaces: Vec<InterfaceInfo>,
+    reopenable: bool,
+    sender: Option<Sender<WorkerEvent>>,
+    receiver: Option<Receiver<WorkerEvent>>,
+    workers: Vec<Option<Worker>>,
+    shutdown: Arc<AtomicBool>,
+    pending: Vec<VecDeque<(Instant, RawPacket)>>,
+    last_report: Vec<Instant>,
+    finished: Vec<bool>,
+    // Interfaces whose worker stopped on an error, waiting for `reopen`
+    failed: Vec<bool>,
+}
+
+impl MultiSource {
+    pub fn new(sources: Vec<Box<dyn PacketSource + Send>>) -> Self {
+        let interfaces: Vec<InterfaceInfo> = sources.iter().flat_map(|s| s.interfaces()).collect();
+        let reopenable = sources.iter().all(|s| s.can_reopen());
+        let count = sources.len();
+        MultiSource {
+            sources: sources.into_iter().map(Some).collect(),
+            interfaces,
+            reopenable,
+            sender: None,
+            receiver: None,
+            workers: (0..count).map(|_| None).collect(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            pending: (0..count).map(|_| VecDeque::new()).collect(),
+            last_report: vec![Instant::now(); count],
+            finished: vec![false; count],
+            failed: vec![false; count],
+        }
+    }
+
+    fn start(&mut self) {
+        if self.receiver.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.sender = Some(sender);
+        self.receiver = Some(receiver);
+        for index in 0..self.sources.len() {
+            self.spawn_worker(index);
+        }
+    }
+
+    fn spawn_worker(&mut self, index: usize) {
+        let (Some(sender), Some(mut source)) = (self.sender.clone(), self.sources[index].take()) else {
+            return;
+        };
+        let shutdown = self.shutdown.clone();
+        let name = self.interfaces[index].name.clone();
+        self.workers[index] = Some(thread::spawn(move || {
+            while !shutdown.load(Ordering::SeqCst) {
+                let event = source.next_packet().map_err(|e| CaptureError::from_boxed(&name, e));
+                let done = matches!(event, Ok(SourceEvent::Exhausted) | Err(_));
+                if sender.send((index, event)).is_err() || done {
+                    break;
+                }
+            }
+            source
+        }));
+    }
+
+    // Wait for a worker to exit and take its source back
+    fn join_worker(&mut self, index: usize) {
+        if let Some(worker) = self.workers[index].take() {
+            if let Ok(source) = worker.join() {
+                self.sources[index] = Some(source);
+            }
+        }
+    }
+
+    // Pop the oldest queued packet if no other interface can still produce an earlier one
+    fn pop_ready(&mut self) -> Option<RawPacket> {
+        let (index, arrived) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter_map(|(i, queue)| queue.front().map(|(arrived, p)| (i, *arrived, (p.ts_sec, p.ts_usec))))
+            .min_by_key(|(_, _, ts)| *ts)
+            .map(|(i, arrived, _)| (i, arrived))?;
+
+        let caught_up = (0..self.pending.len()).all(|i| {
+            i == index || self.finished[i] || !self.pending[i].is_empty() || self.last_report[i] >= arrived
+        });
+        if caught_up || arrived.elapsed() >= REORDER_WINDOW {
+            self.pending[index].pop_front().map(|(_, packet)| packet)
+        } else {
+            None
+        }
+    }
+
+    fn stop_workers(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Drop the channel so workers blocked on send notice and exit
+        self.sender = None;
+        self.receiver = None;
+        for index in 0..self.workers.len() {
+            self.join_worker(index);
+        }
+    }
+}
+
+impl PacketSource for MultiSource {
+    fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>> {
+        self.start();
+        let deadline = Instant::now() + Duration::from_millis(1000);
+
+        loop {
+            if let Some(packet) = self.pop_ready() {
+                return Ok(SourceEvent::Packet(packet));
+            }
+            if self.finished.iter().all(|f| *f) && self.pending.iter().all(|q| q.is_empty()) {
+                return Ok(SourceEvent::Exhausted);
+            }
+
+            let receiver = match &self.receiver {
+                Some(receiver) => receiver,
+                None => return Ok(SourceEvent::Exhausted),
+            };
+            match receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok((index, event)) => {
+                    self.last_report[index] = Instant::now();
+                    match event {
+                        Ok(SourceEvent::Packet(mut packet)) => {
+                            packet.interface = index as u32;
+                            self.pending[index].push_back((Instant::now(), packet));
+                        }
+                        Ok(SourceEvent::Timeout) => {}
+                        Ok(SourceEvent::Exhausted) => self.finished[index] = true,
+                        Err(e) => {
+                            // The other interfaces keep capturing while this one is reopened
+                            self.finished[index] = true;
+                            self.failed[index] = true;
+                            return Err(Box::new(e));
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if Instant::now() >= deadline {
+                        return Ok(SourceEvent::Timeout);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.finished.iter_mut().for_each(|f| *f = true);
+                }
+            }
+        }
+    }
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        self.interfaces.clone()
+    }
+
+    fn set_filter(&mut self, filter: &str) -> Result<(), Box<dyn Error>> {
+        if self.receiver.is_some() {
+            return Err("Filter must be set before the capture starts".into());
+        }
+        for source in self.sources.iter_mut().flatten() {
+            source.set_filter(filter)?;
+        }
+        Ok(())
+    }
+
+    // Stops the worker threads; only meaningful once the capture is over
+    fn stats(&mut self) -> Vec<SourceStats> {
+        self.stop_workers();
+        self.sources
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, source)| source.as_mut().map(|source| (index, source)))
+            .flat_map(|(index, source)| {
+                source.stats().into_iter().map(move |mut stats| {
+                    stats.interface = index as u32;
+                    stats
+                })
+            })
+            .collect()
+    }
+
+    fn can_reopen(&self) -> bool {
+        self.reopenable
+    }
+
+    // Reopen only the interfaces that failed and restart their workers
+    fn reopen(&mut self) -> Result<(), Box<dyn Error>> {
+        for index in 0..self.failed.len() {
+            if !self.failed[index] {
+                continue;
+            }
+            self.join_worker(index);
+            if let Some(source) = self.sources[index].as_mut() {
+                source.reopen()?;
+            }
+            self.failed[index] = false;
+            self.finished[index] = false;
+            self.spawn_worker(index);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MultiSource {
+    fn drop(&mut self) {
+        self.stop_workers();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Condvar, Mutex};
+
+    // Shared capture clock: turn N may only be read once turns 0..N have been
+    type Clock = Arc<(Mutex<u64>, Condvar)>;
+
+    // A VecSource that hands out its packets on given clock turns, so several of
+    // them interleave the way live interfaces do instead of replaying in bursts
+    struct Paced {
+        name: &'static str,
+        packets: VecSource,
+        turns: VecDeque<u64>,
+        // Turn on which the read fails instead of returning a packet
+        fail_on: Option<u64>,
+        clock: Clock,
+        received: u64,
+        dropped: u64,
+    }
+
+    impl Paced {
+        fn new(name: &'static str, clock: &Clock, script: &[(u64, f64)]) -> Self {
+            let packets = script
+                .iter()
+                .map(|&(turn, ts)| {
+                    let usec = (ts.fract() * 1_000_000.0).round() as i64;
+                    RawPacket::new(ts.trunc() as i64, usec, vec![turn as u8])
+                })
+                .collect();
+            Paced {
+                name,
+                packets: VecSource::new(packets, Linktype::ETHERNET),
+                turns: script.iter().map(|&(turn, _)| turn).collect(),
+                fail_on: None,
+                clock: clock.clone(),
+                received: 0,
+                dropped: 0,
+            }
+        }
+
+        fn failing_on(mut self, turn: u64) -> Self {
+            let position = self.turns.iter().position(|&t| t > turn).unwrap_or(self.turns.len());
+            self.turns.insert(position, turn);
+            self.fail_on = Some(turn);
+            self
+        }
+    }
+
+    impl PacketSource for Paced {
+        fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>> {
+            let Some(turn) = self.turns.pop_front() else {
+                return self.packets.next_packet();
+            };
+            let (now, ticked) = &*self.clock;
+            let mut now = ticked.wait_while(now.lock().unwrap(), |now| *now != turn).unwrap();
+            let event = if self.fail_on == Some(turn) {
+                Err("read error".into())
+            } else {
+                self.packets.next_packet()
+            };
+            if let Ok(SourceEvent::Packet(_)) = event {
+                self.received += 1;
+            }
+            *now += 1;
+            ticked.notify_all();
+            event
+        }
+
+        fn interfaces(&self) -> Vec<InterfaceInfo> {
+            let mut interfaces = self.packets.interfaces();
+            interfaces[0].name = self.name.to_string();
+            interfaces
+        }
+
+        fn set_filter(&mut self, filter: &str) -> Result<(), Box<dyn Error>> {
+            self.packets.set_filter(filter)
+        }
+
+        fn stats(&mut self) -> Vec<SourceStats> {
+            vec![SourceStats { interface: 0, received: self.received + self.dropped, dropped: self.dropped }]
+        }
+
+        fn can_reopen(&self) -> bool {
+            true
+        }
+
+        fn reopen(&mut self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+    }
+
+    fn clock() -> Clock {
+        Arc::new((Mutex::new(0), Condvar::new()))
+    }
+
+    // Reads `source` to the end, reopening after errors like the capture loop does.
+    // Returns (ts, interface) per packet and the devices that failed.
+    fn drain(source: &mut MultiSource) -> (Vec<(f64, u32)>, Vec<String>) {
+        let (mut packets, mut failures) = (Vec::new(), Vec::new());
+        loop {
+            match source.next_packet() {
+                Ok(SourceEvent::Packet(packet)) => {
+                    let ts = packet.ts_sec as f64 + packet.ts_usec as f64 / 1_000_000.0;
+                    packets.push((ts, packet.interface));
+                }
+                Ok(SourceEvent::Timeout) => {}
+                Ok(SourceEvent::Exhausted) => return (packets, failures),
+                Err(error) => {
+                    let error = error.downcast::<CaptureError>().expect("worker errors are classified");
+                    failures.push(error.device().unwrap_or_default().to_string());
+                    source.reopen().unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn merges_interfaces_in_timestamp_order() {
+        let clock = clock();
+        let eth0 = Paced::new("eth0", &clock, &[(0, 1.0), (3, 3.0), (4, 3.5)]);
+        let mut eth1 = Paced::new("eth1", &clock, &[(1, 2.0), (2, 2.5), (5, 4.0)]);
+        eth1.dropped = 2;
+        let mut source = MultiSource::new(vec![Box::new(eth0), Box::new(eth1)]);
+
+        let names: Vec<_> = source.interfaces().into_iter().map(|info| info.name).collect();
+        assert_eq!(names, ["eth0", "eth1"]);
+
+        let (packets, failures) = drain(&mut source);
+        assert_eq!(packets, [(1.0, 0), (2.0, 1), (2.5, 1), (3.0, 0), (3.5, 0), (4.0, 1)]);
+        assert!(failures.is_empty());
+
+        // Each source's counters come back under its own interface index
+        let stats: Vec<_> = source.stats().iter().map(|s| (s.interface, s.received, s.dropped)).collect();
+        assert_eq!(stats, [(0, 3, 0), (1, 5, 2)]);
+    }
+
+    #[test]
+    fn a_failed_interface_is_reported_and_reopened_on_its_own() {
+        let clock = clock();
+        let eth0 = Paced::new("eth0", &clock, &[(0, 1.0), (2, 2.0), (4, 3.0)]);
+        let eth1 = Paced::new("eth1", &clock, &[(1, 1.5), (5, 3.5)]).failing_on(3);
+        let mut source = MultiSource::new(vec![Box::new(eth0), Box::new(eth1)]);
+
+        let (packets, failures) = drain(&mut source);
+        assert_eq!(failures, ["eth1"]);
+        assert_eq!(packets, [(1.0, 0), (1.5, 1), (2.0, 0), (3.0, 0), (3.5, 1)]);
+    }
+}
@@ -0,0 +1,415 @@
+use crate::flow::FlowTable;
+use crate::listening::ParsedPacket;
+use crate::report::sorted_counts;
+use crate::sink::PacketSink;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+// Completed intervals kept for the final summary
+pub const DEFAULT_HISTORY: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntervalFormat {
+    #[default]
+    Table,
+    // One JSON object per interval
+    Jsonl,
+}
+
+impl FromStr for IntervalFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(IntervalFormat::Table),
+            "jsonl" => Ok(IntervalFormat::Jsonl),
+            other => Err(format!("Unknown interval format '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntervalConfig {
+    // Bucket width; None disables interval statistics
+    pub seconds: Option<f64>,
+    pub history: usize,
+    pub format: IntervalFormat,
+    // File to stream intervals to as they complete, instead of the console
+    pub output: Option<String>,
+}
+
+impl Default for IntervalConfig {
+    fn default() -> Self {
+        IntervalConfig {
+            seconds: None,
+            history: DEFAULT_HISTORY,
+            format: IntervalFormat::default(),
+            output: None,
+        }
+    }
+}
+
+impl IntervalConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.seconds.is_some()
+    }
+}
+
+// Traffic seen in one bucket of capture time
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Interval {
+    pub start: f64,
+    pub end: f64,
+    pub packets: u64,
+    pub bytes: u64,
+    // Flows whose first packet fell in this interval
+    pub new_flows: u64,
+    pub protocols: BTreeMap<String, u64>,
+}
+
+// Splits the capture into fixed intervals (tshark -z io,stat style). Buckets are
+// aligned to multiples of the width in packet time, so live and offline runs of
+// the same traffic produce the same intervals. Finished ones are streamed out and
+// the last `history` are kept for the summary.
+pub struct IntervalSink {
+    seconds: f64,
+    history: usize,
+    format: IntervalFormat,
+    writer: Option<Box<dyn Write>>,
+    header_written: bool,
+    // Bucket number (start / seconds) of `current`
+    index: i64,
+    current: Option<Interval>,
+    completed: VecDeque<Interval>,
+    flows: FlowTable,
+}
+
+impl IntervalSink {
+    // Intervals stream to `config.output` if set, otherwise to `console` if given
+    pub fn new(
+        config: &IntervalConfig,
+        console: Option<Box<dyn Write>>,
+        flow_idle_timeout: f64,
+        flow_active_timeout: f64,
+    ) -> Result<Self, Box<dyn Error>> {
+        let seconds = config.seconds.filter(|s| *s > 0.0).ok_or("Interval must be a positive number of seconds")?;
+        let writer: Option<Box<dyn Write>> = match &config.output {
+            Some(path) => Some(Box::new(BufWriter::new(File::create(path)?))),
+            None => console,
+        };
+        Ok(IntervalSink {
+            seconds,
+            history: config.history.max(1),
+            format: config.format,
+            writer,
+            header_written: false,
+            index: 0,
+            current: None,
+            completed: VecDeque::new(),
+            flows: FlowTable::new(flow_idle_timeout, flow_active_timeout),
+        })
+    }
+
+    pub fn into_intervals(self) -> Vec<Interval> {
+        self.completed.into()
+    }
+
+    fn open(&mut self, index: i64) {
+        self.index = index;
+        self.current = Some(Interval {
+            start: index as f64 * self.seconds,
+            end: (index + 1) as f64 * self.seconds,
+            ..Default::default()
+        });
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        let Some(interval) = self.current.take() else {
+            return Ok(());
+        };
+        if let Some(writer) = &mut self.writer {
+            if self.format == IntervalFormat::Table && !self.header_written {
+                write_table_header(writer.as_mut())?;
+                self.header_written = true;
+            }
+            write_interval(writer.as_mut(), &interval, self.format)?;
+            writer.flush()?;
+        }
+        if self.completed.len() == self.history {
+            self.completed.pop_front();
+        }
+        self.completed.push_back(interval);
+        // Only new flows are counted; expired ones need not be kept
+        self.flows.take_finished();
+        Ok(())
+    }
+}
+
+impl PacketSink for IntervalSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        let timestamp = packet.raw.ts_sec as f64 + packet.raw.ts_usec as f64 / 1_000_000.0;
+        let index = (timestamp / self.seconds).floor() as i64;
+        match self.current {
+            None => self.open(index),
+            Some(_) if index > self.index => {
+                let last = self.index;
+                self.close()?;
+                // Quiet intervals are reported as empty, but no more of them
+                // than the summary keeps
+                let first_empty = (last + 1).max(index - self.history as i64);
+                for empty in first_empty..index {
+                    self.open(empty);
+                    self.close()?;
+                }
+                self.open(index);
+            }
+            // Packets slightly out of order across interfaces stay in the current interval
+            Some(_) => {}
+        }
+
+        let new_flow = match &packet.decoded {
+            Ok(decoded) => self
+                .flows
+                .update(decoded, packet.data)
+                .and_then(|key| self.flows.get(&key))
+                .is_some_and(|flow| flow.packets() == 1),
+            Err(_) => false,
+        };
+        if let Some(interval) = &mut self.current {
+            interval.packets += 1;
+            interval.bytes += packet.raw.len as u64;
+            if new_flow {
+                interval.new_flows += 1;
+            }
+            let protocol = match &packet.decoded {
+                Ok(decoded) => decoded.protocol_name(),
+                Err(_) => "Malformed".to_string(),
+            };
+            *interval.protocols.entry(protocol).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    // The interval in progress is reported as it stands
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.close()?;
+        Ok(())
+    }
+}
+
+fn write_table_header(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<12} {:<12} {:>10} {:>12} {:>9} Protocols",
+        "Start (UTC)", "End", "Packets", "Bytes", "New flows"
+    )
+}
+
+fn write_interval(out: &mut dyn Write, interval: &Interval, format: IntervalFormat) -> io::Result<()> {
+    match format {
+        IntervalFormat::Jsonl => writeln!(out, "{}", serde_json::to_string(interval)?),
+        IntervalFormat::Table => {
+            let protocols: Vec<String> = sorted_counts(&interval.protocols)
+                .into_iter()
+                .map(|(name, count)| format!("{}={}", name, count))
+                .collect();
+            let line = format!(
+                "{:<12} {:<12} {:>10} {:>12} {:>9} {}",
+                time_of_day(interval.start),
+                time_of_day(interval.end),
+                interval.packets,
+                interval.bytes,
+                interval.new_flows,
+                protocols.join(" ")
+            );
+            writeln!(out, "{}", line.trim_end())
+        }
+    }
+}
+
+// hh:mm:ss.mmm (UTC) of a timestamp
+fn time_of_day(timestamp: f64) -> String {
+    let millis = (timestamp * 1000.0).round() as i64;
+    let secs = millis.div_euclid(1000).rem_euclid(86_400);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60,
+        millis.rem_euclid(1000)
+    )
+}
+
+pub fn write_interval_summary(
+    out: &mut dyn Write,
+    intervals: &[Interval],
+    format: IntervalFormat,
+) -> io::Result<()> {
+    let seconds = intervals.first().map_or(0.0, |interval| interval.end - interval.start);
+    writeln!(out, "\n=== Intervals (every {}s, last {}) ===", seconds, intervals.len())?;
+    if format == IntervalFormat::Table {
+        write_table_header(out)?;
+    }
+    for interval in intervals {
+        write_interval(out, interval, format)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listening::parse_packet_with_etherparse;
+    use crate::source::RawPacket;
+    use etherparse::PacketBuilder;
+    use pcap::Linktype;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Writer whose bytes stay readable after the sink has taken it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    // UDP datagram from 10.0.0.1:<src_port> to 10.0.0.2:9999 with `payload` bytes
+    fn udp(ts: f64, src_port: u16, payload: usize) -> RawPacket {
+        let builder = PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4([10, 0, 0, 1], [10, 0, 0, 2], 64)
+            .udp(src_port, 9999);
+        let mut frame = Vec::new();
+        builder.write(&mut frame, &vec![0; payload]).unwrap();
+        RawPacket::new(ts.trunc() as i64, (ts.fract() * 1_000_000.0).round() as i64, frame)
+    }
+
+    fn sink(seconds: f64, history: usize, format: IntervalFormat, console: &SharedBuffer) -> IntervalSink {
+        let config = IntervalConfig { seconds: Some(seconds), history, format, output: None };
+        IntervalSink::new(&config, Some(Box::new(console.clone())), 60.0, 3600.0).unwrap()
+    }
+
+    fn feed(sink: &mut IntervalSink, packets: &[RawPacket]) {
+        for (index, raw) in packets.iter().enumerate() {
+            let number = index as u32 + 1;
+            let parsed = ParsedPacket {
+                number,
+                raw,
+                decoded: parse_packet_with_etherparse(number, raw, Linktype::ETHERNET),
+                data: &raw.data,
+            };
+            sink.on_packet(&parsed).unwrap();
+        }
+        sink.finish().unwrap();
+    }
+
+    // (start, packets, new flows) of each interval
+    fn buckets(intervals: &[Interval]) -> Vec<(f64, u64, u64)> {
+        intervals.iter().map(|i| (i.start, i.packets, i.new_flows)).collect()
+    }
+
+    #[test]
+    fn packets_fall_into_aligned_buckets() {
+        let console = SharedBuffer::default();
+        let mut intervals = sink(0.5, 10, IntervalFormat::Jsonl, &console);
+        // 10.0 and 10.499999 share a bucket; 10.5 starts the next one
+        feed(&mut intervals, &[udp(10.0, 1000, 10), udp(10.499_999, 1000, 10), udp(10.5, 1001, 10)]);
+
+        let completed = intervals.into_intervals();
+        assert_eq!(buckets(&completed), [(10.0, 2, 1), (10.5, 1, 1)]);
+        assert_eq!(completed[0].end, 10.5);
+        // 14 Ethernet + 20 IPv4 + 8 UDP + 10 payload
+        assert_eq!(completed[0].bytes, 2 * 52);
+        assert_eq!(completed[0].protocols.get("UDP"), Some(&2));
+
+        let lines: Vec<serde_json::Value> = console.text().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!((&lines[1]["start"], &lines[1]["packets"]), (&serde_json::json!(10.5), &serde_json::json!(1)));
+    }
+
+    #[test]
+    fn quiet_periods_are_reported_as_empty_intervals() {
+        let console = SharedBuffer::default();
+        let mut intervals = sink(1.0, 10, IntervalFormat::Table, &console);
+        // The late packet from another interface stays in the interval in progress
+        feed(&mut intervals, &[udp(3600.2, 1000, 0), udp(3603.5, 1000, 0), udp(3603.9, 1001, 0), udp(3602.9, 1002, 0)]);
+
+        assert_eq!(
+            buckets(&intervals.into_intervals()),
+            [(3600.0, 1, 1), (3601.0, 0, 0), (3602.0, 0, 0), (3603.0, 3, 2)]
+        );
+        assert_eq!(
+            console.text(),
+            "Start (UTC)  End             Packets        Bytes New flows Protocols\n\
+             01:00:00.000 01:00:01.000          1           42         1 UDP=1\n\
+             01:00:01.000 01:00:02.000          0            0         0\n\
+             01:00:02.000 01:00:03.000          0            0         0\n\
+             01:00:03.000 01:00:04.000          3          126         2 UDP=3\n"
+        );
+    }
+
+    #[test]
+    fn long_gaps_report_no_more_empty_intervals_than_are_kept() {
+        let console = SharedBuffer::default();
+        let mut intervals = sink(1.0, 3, IntervalFormat::Jsonl, &console);
+        feed(&mut intervals, &[udp(0.5, 1000, 0), udp(100.5, 1000, 0)]);
+
+        // The flow sat idle past its 60s timeout, so it is new again
+        assert_eq!(buckets(&intervals.into_intervals()), [(98.0, 0, 0), (99.0, 0, 0), (100.0, 1, 1)]);
+        let starts: Vec<f64> = console
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["start"].as_f64().unwrap())
+            .collect();
+        assert_eq!(starts, [0.0, 97.0, 98.0, 99.0, 100.0]);
+    }
+
+    #[test]
+    fn a_capture_without_packets_has_no_intervals() {
+        let console = SharedBuffer::default();
+        let mut intervals = sink(1.0, 10, IntervalFormat::Table, &console);
+        feed(&mut intervals, &[]);
+        assert!(intervals.into_intervals().is_empty());
+        assert_eq!(console.text(), "");
+
+        let config = IntervalConfig { seconds: Some(0.0), ..Default::default() };
+        assert!(IntervalSink::new(&config, None, 60.0, 3600.0).is_err());
+    }
+
+    #[test]
+    fn summary_lists_the_kept_intervals() {
+        let interval = Interval {
+            start: 86_399.5,
+            end: 86_400.0,
+            packets: 2,
+            bytes: 120,
+            new_flows: 1,
+            protocols: [("TCP".to_string(), 1), ("UDP".to_string(), 1)].into_iter().collect(),
+        };
+        let mut out = Vec::new();
+        write_interval_summary(&mut out, &[interval], IntervalFormat::Table).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\n=== Intervals (every 0.5s, last 1) ===\n\
+             Start (UTC)  End             Packets        Bytes New flows Protocols\n\
+             23:59:59.500 00:00:00.000          2          120         1 TCP=1 UDP=1\n"
+        );
+    }
+}
@@ -0,0 +1,837 @@
+use crate::packet::{DecodedPacket, TransportLayer};
+use crate::reassembly::{CloseReason, Direction, StreamConsumer, StreamInfo};
+use crate::report::write_top;
+use md5::{Digest, Md5};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+// Record content types
+const CHANGE_CIPHER_SPEC: u8 = 20;
+const ALERT: u8 = 21;
+const HANDSHAKE: u8 = 22;
+const APPLICATION_DATA: u8 = 23;
+
+// Handshake message types
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const CERTIFICATE: u8 = 11;
+
+// Extension types
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+const EXT_EC_POINT_FORMATS: u16 = 11;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+const EXT_ALPN: u16 = 16;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+
+// Handshake bytes buffered per direction before giving up on a connection
+const MAX_HANDSHAKE_LEN: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TlsRecord {
+    pub content_type: u8,
+    pub version: u16,
+    pub length: u16,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientHello {
+    pub legacy_version: u16,
+    // From the supported_versions extension (TLS 1.3 capable clients)
+    pub supported_versions: Vec<u16>,
+    pub cipher_suites: Vec<u16>,
+    // Extension types in the order sent
+    pub extensions: Vec<u16>,
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+    pub supported_groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub signature_algorithms: Vec<u16>,
+    pub ja3: String,
+    pub ja3_hash: String,
+    pub ja4: String,
+}
+
+impl ClientHello {
+    // Highest version offered, ignoring GREASE
+    pub fn max_version(&self) -> u16 {
+        self.supported_versions
+            .iter()
+            .copied()
+            .filter(|v| !is_grease(*v))
+            .max()
+            .unwrap_or(self.legacy_version)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerHello {
+    pub legacy_version: u16,
+    // Negotiated version: supported_versions if present, else the legacy field
+    pub version: u16,
+    pub cipher_suite: u16,
+    pub extensions: Vec<u16>,
+    pub alpn: Option<String>,
+    pub ja3s: String,
+    pub ja3s_hash: String,
+}
+
+// Certificates are only visible before TLS 1.3, which encrypts them
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+}
+
+// TLS content of one segment (or of a reassembled handshake)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TlsInfo {
+    pub records: Vec<TlsRecord>,
+    pub client_hello: Option<ClientHello>,
+    pub server_hello: Option<ServerHello>,
+    pub certificates: Vec<CertificateInfo>,
+}
+
+impl TlsInfo {
+    pub fn summary(&self) -> String {
+        if let Some(hello) = &self.client_hello {
+            format!("ClientHello {} {}", hello.sni.as_deref().unwrap_or("(no SNI)"), hello.ja4)
+        } else if let Some(hello) = &self.server_hello {
+            format!("ServerHello {} 0x{:04x}", version_name(hello.version), hello.cipher_suite)
+        } else {
+            let types: Vec<String> = self.records.iter().map(|r| content_type_name(r.content_type)).collect();
+            types.join(",")
+        }
+    }
+}
+
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0300 => "SSL 3.0".to_string(),
+        0x0301 => "TLS 1.0".to_string(),
+        0x0302 => "TLS 1.1".to_string(),
+        0x0303 => "TLS 1.2".to_string(),
+        0x0304 => "TLS 1.3".to_string(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+fn content_type_name(content_type: u8) -> String {
+    match content_type {
+        CHANGE_CIPHER_SPEC => "ChangeCipherSpec".to_string(),
+        ALERT => "Alert".to_string(),
+        HANDSHAKE => "Handshake".to_string(),
+        APPLICATION_DATA => "ApplicationData".to_string(),
+        other => format!("Type{}", other),
+    }
+}
+
+// GREASE values (RFC 8701) look like 0x?a?a and are skipped in fingerprints
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && (value >> 8) == (value & 0xff)
+}
+
+fn is_record_header(data: &[u8]) -> bool {
+    data.len() >= 5 && (CHANGE_CIPHER_SPEC..=APPLICATION_DATA).contains(&data[0]) && data[1] == 3 && data[2] <= 4
+}
+
+// Big-endian cursor over a handshake body
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let b = self.bytes(2)?;
+        Some(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        let b = self.bytes(3)?;
+        Some(((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.bytes(len)
+    }
+
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect()
+}
+
+fn alpn_list(data: &[u8]) -> Vec<String> {
+    let mut reader = Reader { data };
+    let mut protocols = Vec::new();
+    if let Some(list) = reader.vec16() {
+        let mut list = Reader { data: list };
+        while let Some(protocol) = list.vec8() {
+            protocols.push(String::from_utf8_lossy(protocol).into_owned());
+        }
+    }
+    protocols
+}
+
+fn server_name(data: &[u8]) -> Option<String> {
+    let mut reader = Reader { data };
+    let mut list = Reader { data: reader.vec16()? };
+    while !list.is_empty() {
+        let name_type = list.u8()?;
+        let name = list.vec16()?;
+        if name_type == 0 {
+            return Some(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    None
+}
+
+// Iterate (type, data) pairs of an extensions block
+fn extensions(data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut reader = Reader { data };
+    let mut result = Vec::new();
+    while !reader.is_empty() {
+        let (Some(ext_type), Some(ext_data)) = (reader.u16(), reader.vec16()) else {
+            break;
+        };
+        result.push((ext_type, ext_data));
+    }
+    result
+}
+
+pub fn parse_client_hello(body: &[u8]) -> Option<ClientHello> {
+    let mut reader = Reader { data: body };
+    let mut hello = ClientHello {
+        legacy_version: reader.u16()?,
+        ..Default::default()
+    };
+    reader.bytes(32)?; // random
+    reader.vec8()?; // session id
+    hello.cipher_suites = u16_list(reader.vec16()?);
+    reader.vec8()?; // compression methods
+
+    let ext_block = if reader.is_empty() { &[][..] } else { reader.vec16()? };
+    for (ext_type, data) in extensions(ext_block) {
+        hello.extensions.push(ext_type);
+        match ext_type {
+            EXT_SERVER_NAME => hello.sni = server_name(data),
+            EXT_SUPPORTED_GROUPS => hello.supported_groups = u16_list(Reader { data }.vec16().unwrap_or(&[])),
+            EXT_EC_POINT_FORMATS => hello.ec_point_formats = Reader { data }.vec8().unwrap_or(&[]).to_vec(),
+            EXT_SIGNATURE_ALGORITHMS => {
+                hello.signature_algorithms = u16_list(Reader { data }.vec16().unwrap_or(&[]))
+            }
+            EXT_ALPN => hello.alpn = alpn_list(data),
+            EXT_SUPPORTED_VERSIONS => hello.supported_versions = u16_list(Reader { data }.vec8().unwrap_or(&[])),
+            _ => {}
+        }
+    }
+
+    hello.ja3 = ja3_string(&hello);
+    hello.ja3_hash = hex(&Md5::digest(hello.ja3.as_bytes()));
+    hello.ja4 = ja4(&hello, 't');
+    Some(hello)
+}
+
+pub fn parse_server_hello(body: &[u8]) -> Option<ServerHello> {
+    let mut reader = Reader { data: body };
+    let legacy_version = reader.u16()?;
+    reader.bytes(32)?; // random
+    reader.vec8()?; // session id
+    let mut hello = ServerHello {
+        legacy_version,
+        version: legacy_version,
+        cipher_suite: reader.u16()?,
+        ..Default::default()
+    };
+    reader.u8()?; // compression method
+
+    let ext_block = if reader.is_empty() { &[][..] } else { reader.vec16()? };
+    for (ext_type, data) in extensions(ext_block) {
+        hello.extensions.push(ext_type);
+        match ext_type {
+            EXT_SUPPORTED_VERSIONS if data.len() == 2 => hello.version = u16::from_be_bytes([data[0], data[1]]),
+            EXT_ALPN => hello.alpn = alpn_list(data).into_iter().next(),
+            _ => {}
+        }
+    }
+
+    hello.ja3s = format!(
+        "{},{},{}",
+        hello.legacy_version,
+        hello.cipher_suite,
+        join_decimal(hello.extensions.iter().copied().filter(|e| !is_grease(*e)))
+    );
+    hello.ja3s_hash = hex(&Md5::digest(hello.ja3s.as_bytes()));
+    Some(hello)
+}
+
+fn parse_certificates(body: &[u8]) -> Vec<CertificateInfo> {
+    let mut reader = Reader { data: body };
+    let Some(list_len) = reader.u24() else {
+        return Vec::new();
+    };
+    let mut list = Reader { data: reader.bytes(list_len).unwrap_or(reader.data) };
+    let mut certificates = Vec::new();
+    while let Some(len) = list.u24() {
+        let Some(der) = list.bytes(len) else {
+            break;
+        };
+        if let Some(info) = parse_x509_names(der) {
+            certificates.push(info);
+        }
+    }
+    certificates
+}
+
+// Minimal DER walk down to the issuer and subject of an X.509 certificate
+fn parse_x509_names(der: &[u8]) -> Option<CertificateInfo> {
+    let (_, certificate, _) = der_element(der)?;
+    let (_, tbs, _) = der_element(certificate)?;
+    let mut fields = tbs;
+    let mut next = || -> Option<(u8, &[u8])> {
+        let (tag, content, rest) = der_element(fields)?;
+        fields = rest;
+        Some((tag, content))
+    };
+
+    let (mut tag, _) = next()?;
+    if tag == 0xa0 {
+        // Explicit version, followed by the serial number
+        tag = next()?.0;
+    }
+    if tag != 0x02 {
+        return None;
+    }
+    next()?; // signature algorithm
+    let (_, issuer) = next()?;
+    next()?; // validity
+    let (_, subject) = next()?;
+    Some(CertificateInfo {
+        subject: format_name(subject),
+        issuer: format_name(issuer),
+    })
+}
+
+// Split off one TLV element: (tag, content, remaining bytes)
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let count = first & 0x7f;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let bytes = data.get(2..2 + count)?;
+        (bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize), 2 + count)
+    };
+    let content = data.get(header..header + len)?;
+    Some((tag, content, &data[header + len..]))
+}
+
+// Render an X.509 Name as "CN=example.com, O=Example"
+fn format_name(mut name: &[u8]) -> String {
+    let mut parts = Vec::new();
+    while let Some((_, set, rest)) = der_element(name) {
+        name = rest;
+        let Some((_, attribute, _)) = der_element(set) else {
+            continue;
+        };
+        let Some((_, oid, value)) = der_element(attribute) else {
+            continue;
+        };
+        let Some((value_tag, value, _)) = der_element(value) else {
+            continue;
+        };
+        let label = match oid {
+            [0x55, 0x04, 0x03] => "CN",
+            [0x55, 0x04, 0x06] => "C",
+            [0x55, 0x04, 0x07] => "L",
+            [0x55, 0x04, 0x08] => "ST",
+            [0x55, 0x04, 0x0a] => "O",
+            [0x55, 0x04, 0x0b] => "OU",
+            _ => continue,
+        };
+        let text = if value_tag == 0x1e {
+            // BMPString is UTF-16BE
+            String::from_utf16_lossy(&u16_list(value))
+        } else {
+            String::from_utf8_lossy(value).into_owned()
+        };
+        parts.push(format!("{}={}", label, text));
+    }
+    parts.join(", ")
+}
+
+fn join_decimal<T: ToString>(values: impl Iterator<Item = T>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join("-")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// SSLVersion,Ciphers,Extensions,EllipticCurves,EllipticCurvePointFormats
+fn ja3_string(hello: &ClientHello) -> String {
+    let no_grease = |values: &[u16]| join_decimal(values.iter().copied().filter(|v| !is_grease(*v)));
+    format!(
+        "{},{},{},{},{}",
+        hello.legacy_version,
+        no_grease(&hello.cipher_suites),
+        no_grease(&hello.extensions),
+        no_grease(&hello.supported_groups),
+        join_decimal(hello.ec_point_formats.iter())
+    )
+}
+
+// First 12 hex digits of the SHA-256 of a comma-separated list, zeros if empty
+fn ja4_hash(items: &[String]) -> String {
+    if items.is_empty() {
+        return "000000000000".to_string();
+    }
+    hex(&Sha256::digest(items.join(",").as_bytes()))[..12].to_string()
+}
+
+// JA4 client fingerprint; `transport` is 't' for TCP or 'q' for QUIC
+pub fn ja4(hello: &ClientHello, transport: char) -> String {
+    let version = match hello.max_version() {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    };
+    let sni = if hello.sni.is_some() { 'd' } else { 'i' };
+    let ciphers: Vec<u16> = hello.cipher_suites.iter().copied().filter(|c| !is_grease(*c)).collect();
+    let exts: Vec<u16> = hello.extensions.iter().copied().filter(|e| !is_grease(*e)).collect();
+    let alpn = match hello.alpn.first().map(|a| a.as_bytes()) {
+        Some([first, .., last]) if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() => {
+            format!("{}{}", *first as char, *last as char)
+        }
+        Some([only]) if only.is_ascii_alphanumeric() => format!("{}{}", *only as char, *only as char),
+        Some(bytes @ [_, ..]) => {
+            let digits = hex(bytes);
+            format!("{}{}", &digits[..1], &digits[digits.len() - 1..])
+        }
+        _ => "00".to_string(),
+    };
+    let a = format!(
+        "{}{}{}{:02}{:02}{}",
+        transport,
+        version,
+        sni,
+        ciphers.len().min(99),
+        exts.len().min(99),
+        alpn
+    );
+
+    let mut sorted_ciphers: Vec<String> = ciphers.iter().map(|c| format!("{:04x}", c)).collect();
+    sorted_ciphers.sort();
+    let mut sorted_exts: Vec<String> = exts
+        .iter()
+        .filter(|e| **e != EXT_SERVER_NAME && **e != EXT_ALPN)
+        .map(|e| format!("{:04x}", e))
+        .collect();
+    sorted_exts.sort();
+
+    let c = if sorted_exts.is_empty() {
+        "000000000000".to_string()
+    } else {
+        let mut input = sorted_exts.join(",");
+        let sigs: Vec<String> = hello.signature_algorithms.iter().map(|s| format!("{:04x}", s)).collect();
+        if !sigs.is_empty() {
+            input.push('_');
+            input.push_str(&sigs.join(","));
+        }
+        hex(&Sha256::digest(input.as_bytes()))[..12].to_string()
+    };
+
+    format!("{}_{}_{}", a, ja4_hash(&sorted_ciphers), c)
+}
+
+// Parse whole handshake messages from `data`, returning how many bytes were used
+fn parse_handshakes(data: &[u8], info: &mut TlsInfo) -> usize {
+    let mut used = 0;
+    while data.len() - used >= 4 {
+        let msg_type = data[used];
+        let len = ((data[used + 1] as usize) << 16) | ((data[used + 2] as usize) << 8) | data[used + 3] as usize;
+        let Some(body) = data.get(used + 4..used + 4 + len) else {
+            break;
+        };
+        match msg_type {
+            CLIENT_HELLO => info.client_hello = parse_client_hello(body),
+            SERVER_HELLO => info.server_hello = parse_server_hello(body),
+            CERTIFICATE => info.certificates = parse_certificates(body),
+            _ => {}
+        }
+        used += 4 + len;
+    }
+    used
+}
+
+// Records and any complete handshake messages within a single segment
+pub fn decode(packet: &DecodedPacket, data: &[u8]) -> Option<TlsInfo> {
+    if !matches!(packet.transport, Some(TransportLayer::Tcp(_))) {
+        return None;
+    }
+    let mut payload = packet.payload(data);
+    if !is_record_header(payload) {
+        return None;
+    }
+
+    let mut info = TlsInfo::default();
+    let mut handshake = Vec::new();
+    while is_record_header(payload) {
+        let record = TlsRecord {
+            content_type: payload[0],
+            version: u16::from_be_bytes([payload[1], payload[2]]),
+            length: u16::from_be_bytes([payload[3], payload[4]]),
+        };
+        let end = (5 + record.length as usize).min(payload.len());
+        if record.content_type == HANDSHAKE {
+            handshake.extend_from_slice(&payload[5..end]);
+        }
+        info.records.push(record);
+        payload = &payload[end..];
+    }
+    parse_handshakes(&handshake, &mut info);
+    Some(info)
+}
+
+// Handshake reassembly for one direction; stops once traffic is encrypted
+struct TlsSide {
+    records: Vec<u8>,
+    handshake: Vec<u8>,
+    done: bool,
+}
+
+impl TlsSide {
+    fn new() -> Self {
+        TlsSide {
+            records: Vec::new(),
+            handshake: Vec::new(),
+            done: false,
+        }
+    }
+
+    // Add stream bytes and return whatever handshake messages completed
+    fn feed(&mut self, data: &[u8]) -> TlsInfo {
+        let mut info = TlsInfo::default();
+        if self.done {
+            return info;
+        }
+        self.records.extend_from_slice(data);
+
+        let mut used = 0;
+        while self.records.len() - used >= 5 {
+            let header = &self.records[used..];
+            if !is_record_header(header) {
+                self.done = true;
+                break;
+            }
+            let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+            if header.len() < 5 + len {
+                break;
+            }
+            match header[0] {
+                HANDSHAKE => self.handshake.extend_from_slice(&header[5..5 + len]),
+                // Everything after these is encrypted
+                CHANGE_CIPHER_SPEC | APPLICATION_DATA => self.done = true,
+                _ => {}
+            }
+            used += 5 + len;
+            if self.done {
+                break;
+            }
+        }
+        self.records.drain(..used);
+
+        let consumed = parse_handshakes(&self.handshake, &mut info);
+        self.handshake.drain(..consumed);
+        if self.records.len() + self.handshake.len() > MAX_HANDSHAKE_LEN {
+            self.done = true;
+        }
+        if self.done {
+            self.records = Vec::new();
+            self.handshake = Vec::new();
+        }
+        info
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TlsStats {
+    pub client_hellos: u64,
+    pub server_hellos: u64,
+    pub sni: HashMap<String, u64>,
+    pub alpn: HashMap<String, u64>,
+    // Negotiated versions and cipher suites, from ServerHello
+    pub versions: HashMap<String, u64>,
+    pub cipher_suites: HashMap<u16, u64>,
+    pub ja3: HashMap<String, u64>,
+    pub ja4: HashMap<String, u64>,
+    pub issuers: HashMap<String, u64>,
+}
+
+impl TlsStats {
+    pub fn is_empty(&self) -> bool {
+        self.client_hellos == 0 && self.server_hellos == 0
+    }
+
+    fn record(&mut self, info: &TlsInfo) {
+        if let Some(hello) = &info.client_hello {
+            self.client_hellos += 1;
+            if let Some(sni) = &hello.sni {
+                *self.sni.entry(sni.to_lowercase()).or_insert(0) += 1;
+            }
+            *self.ja3.entry(hello.ja3_hash.clone()).or_insert(0) += 1;
+            *self.ja4.entry(hello.ja4.clone()).or_insert(0) += 1;
+        }
+        if let Some(hello) = &info.server_hello {
+            self.server_hellos += 1;
+            *self.versions.entry(version_name(hello.version)).or_insert(0) += 1;
+            *self.cipher_suites.entry(hello.cipher_suite).or_insert(0) += 1;
+            if let Some(alpn) = &hello.alpn {
+                *self.alpn.entry(alpn.clone()).or_insert(0) += 1;
+            }
+        }
+        // The first certificate is the server's own; its issuer is the CA that signed it
+        if let Some(certificate) = info.certificates.first() {
+            *self.issuers.entry(certificate.issuer.clone()).or_insert(0) += 1;
+        }
+    }
+}
+
+// Follows TLS handshakes on reassembled TCP streams and accumulates TLS statistics
+#[derive(Default)]
+pub struct TlsConsumer {
+    // Per stream, indexed by `Direction::index`
+    streams: HashMap<u64, [TlsSide; 2]>,
+    stats: TlsStats,
+}
+
+impl TlsConsumer {
+    pub fn into_stats(self) -> TlsStats {
+        self.stats
+    }
+}
+
+impl StreamConsumer for TlsConsumer {
+    fn on_data(&mut self, stream: &StreamInfo, direction: Direction, data: &[u8], _ts: f64) {
+        let sides = self.streams.entry(stream.id).or_insert_with(|| [TlsSide::new(), TlsSide::new()]);
+        // A stream that does not open with a TLS record is marked done on its first bytes
+        let info = sides[direction.index()].feed(data);
+        self.stats.record(&info);
+    }
+
+    fn on_gap(&mut self, stream: &StreamInfo, direction: Direction, _missing: u64) {
+        if let Some(sides) = self.streams.get_mut(&stream.id) {
+            sides[direction.index()].done = true;
+        }
+    }
+
+    fn on_close(&mut self, stream: &StreamInfo, _reason: CloseReason) {
+        self.streams.remove(&stream.id);
+    }
+}
+
+pub fn write_tls_details(out: &mut dyn Write, info: &TlsInfo) -> io::Result<()> {
+    for record in &info.records {
+        writeln!(
+            out,
+            "  TLS Record: {} {} length={}",
+            content_type_name(record.content_type),
+            version_name(record.version),
+            record.length
+        )?;
+    }
+    if let Some(hello) = &info.client_hello {
+        writeln!(out, "    ClientHello: SNI={}", hello.sni.as_deref().unwrap_or("N/A"))?;
+        writeln!(out, "    Max version: {}", version_name(hello.max_version()))?;
+        if !hello.alpn.is_empty() {
+            writeln!(out, "    ALPN: {}", hello.alpn.join(","))?;
+        }
+        writeln!(out, "    Cipher suites: {}", hello.cipher_suites.len())?;
+        writeln!(out, "    JA3: {}", hello.ja3_hash)?;
+        writeln!(out, "    JA4: {}", hello.ja4)?;
+    }
+    if let Some(hello) = &info.server_hello {
+        writeln!(
+            out,
+            "    ServerHello: {} cipher=0x{:04x}",
+            version_name(hello.version),
+            hello.cipher_suite
+        )?;
+        if let Some(alpn) = &hello.alpn {
+            writeln!(out, "    ALPN: {}", alpn)?;
+        }
+        writeln!(out, "    JA3S: {}", hello.ja3s_hash)?;
+    }
+    for certificate in &info.certificates {
+        writeln!(out, "    Certificate: {} (issuer: {})", certificate.subject, certificate.issuer)?;
+    }
+    Ok(())
+}
+
+pub fn write_tls_summary(out: &mut dyn Write, stats: &TlsStats, top: usize) -> io::Result<()> {
+    writeln!(out, "\n=== TLS Statistics ===")?;
+    writeln!(out, "ClientHello: {}, ServerHello: {}", stats.client_hellos, stats.server_hellos)?;
+    write_top(out, "Top SNI", &stats.sni, top)?;
+    write_top(out, "Negotiated versions", &stats.versions, top)?;
+    let ciphers: HashMap<String, u64> = stats
+        .cipher_suites
+        .iter()
+        .map(|(suite, count)| (format!("0x{:04x}", suite), *count))
+        .collect();
+    write_top(out, "Negotiated cipher suites", &ciphers, top)?;
+    write_top(out, "ALPN", &stats.alpn, top)?;
+    write_top(out, "Top JA4", &stats.ja4, top)?;
+    write_top(out, "Top JA3", &stats.ja3, top)?;
+    write_top(out, "Certificate issuers", &stats.issuers, top)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extension(body: &mut Vec<u8>, ext_type: u16, data: &[u8]) {
+        body.extend_from_slice(&ext_type.to_be_bytes());
+        body.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        body.extend_from_slice(data);
+    }
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn vec16(data: &[u8]) -> Vec<u8> {
+        [&(data.len() as u16).to_be_bytes()[..], data].concat()
+    }
+
+    // ClientHello body laid out like Chrome's, optionally with its GREASE values
+    fn chrome_hello(grease: bool) -> Vec<u8> {
+        let grease_value = |value: u16| if grease { vec![value] } else { Vec::new() };
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x42; 32]); // random
+        body.push(32);
+        body.extend_from_slice(&[0x17; 32]); // session id
+
+        let ciphers = [
+            0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc013, 0xc014, 0x009c,
+            0x009d, 0x002f, 0x0035,
+        ];
+        body.extend(vec16(&u16s(&[grease_value(0x0a0a), ciphers.to_vec()].concat())));
+        body.extend_from_slice(&[1, 0]); // null compression
+
+        let mut exts = Vec::new();
+        if grease {
+            extension(&mut exts, 0x1a1a, &[]);
+        }
+        let host = b"www.example.com";
+        let sni = [&[0][..], &vec16(host)].concat();
+        extension(&mut exts, EXT_SERVER_NAME, &vec16(&sni));
+        extension(&mut exts, 0x0017, &[]);
+        extension(&mut exts, 0xff01, &[0]);
+        let groups = [grease_value(0x2a2a), vec![0x001d, 0x0017, 0x0018]].concat();
+        extension(&mut exts, EXT_SUPPORTED_GROUPS, &vec16(&u16s(&groups)));
+        extension(&mut exts, EXT_EC_POINT_FORMATS, &[1, 0]);
+        extension(&mut exts, 0x0023, &[]);
+        extension(&mut exts, EXT_ALPN, &vec16(b"\x02h2\x08http/1.1"));
+        extension(&mut exts, 0x0005, &[1, 0, 0, 0, 0]);
+        let signatures = [0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601];
+        extension(&mut exts, EXT_SIGNATURE_ALGORITHMS, &vec16(&u16s(&signatures)));
+        extension(&mut exts, 0x0012, &[]);
+        extension(&mut exts, 0x0033, &vec16(&[0x00, 0x1d, 0x00, 0x01, 0x00]));
+        extension(&mut exts, 0x002d, &[1, 1]);
+        let versions = u16s(&[grease_value(0x3a3a), vec![0x0304, 0x0303]].concat());
+        extension(&mut exts, EXT_SUPPORTED_VERSIONS, &[&[versions.len() as u8][..], &versions].concat());
+        extension(&mut exts, 0x001b, &[2, 0, 2]);
+        extension(&mut exts, 0x4469, &vec16(b"\x02h2"));
+        if grease {
+            extension(&mut exts, 0x4a4a, &[0]);
+        }
+        extension(&mut exts, 0x0015, &[0; 16]);
+        body.extend(vec16(&exts));
+        body
+    }
+
+    #[test]
+    fn fingerprints_a_client_hello() {
+        let hello = parse_client_hello(&chrome_hello(true)).unwrap();
+        assert_eq!(hello.sni.as_deref(), Some("www.example.com"));
+        assert_eq!(hello.alpn, ["h2", "http/1.1"]);
+        assert_eq!(hello.max_version(), 0x0304);
+        assert_eq!(
+            hello.ja3,
+            "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-49171-49172-156-157-47-53,\
+             0-23-65281-10-11-35-16-5-13-18-51-45-43-27-17513-21,29-23-24,0"
+        );
+        assert_eq!(hello.ja3_hash, "cd08e31494f9531f560d64c695473da9");
+        // Chrome example from the JA4 specification
+        assert_eq!(hello.ja4, "t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert_eq!(ja4(&hello, 'q'), "q13d1516h2_8daaf6152771_e5627efa2ab1");
+    }
+
+    #[test]
+    fn grease_values_are_left_out_of_fingerprints() {
+        let with_grease = parse_client_hello(&chrome_hello(true)).unwrap();
+        let without = parse_client_hello(&chrome_hello(false)).unwrap();
+        // GREASE still shows in the raw lists, but not in the fingerprints
+        assert_eq!(with_grease.cipher_suites.len(), without.cipher_suites.len() + 1);
+        assert_eq!(with_grease.extensions.len(), without.extensions.len() + 2);
+        assert_eq!(with_grease.ja3, without.ja3);
+        assert_eq!(with_grease.ja3_hash, without.ja3_hash);
+        assert_eq!(with_grease.ja4, without.ja4);
+
+        for high in 0..16u16 {
+            let value = (high << 12) | 0x0a00 | (high << 4) | 0x0a;
+            assert!(is_grease(value), "0x{:04x}", value);
+        }
+        for value in [0x0a1a, 0x1a0a, 0x0303, 0xaaab, 0x0000] {
+            assert!(!is_grease(value), "0x{:04x}", value);
+        }
+    }
+
+    #[test]
+    fn client_hello_without_extensions() {
+        let mut body = vec![0x03, 0x01];
+        body.extend_from_slice(&[0; 32]);
+        body.push(0);
+        body.extend(vec16(&u16s(&[0x002f, 0x0035])));
+        body.extend_from_slice(&[1, 0]);
+        let hello = parse_client_hello(&body).unwrap();
+        assert_eq!(hello.ja3, "769,47-53,,,");
+        assert!(hello.ja4.starts_with("t10i020000_"));
+        assert!(hello.ja4.ends_with("_000000000000"));
+
+        // Cut inside the cipher suites
+        assert!(parse_client_hello(&body[..36]).is_none());
+    }
+}
@@ -0,0 +1,676 @@
+use crate::display_filter::DisplayFilter;
+use crate::flow::{self, Flow, FlowTable};
+use crate::listening::{write_protocol_details, ParsedPacket, TrafficCounters};
+use crate::packet::DecodedPacket;
+use crate::recovery::{CaptureGap, ReconnectEvent};
+use crate::report::largest_first;
+use crate::sink::PacketSink;
+use crate::source::{InterfaceInfo, SourceStats};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Sparkline, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Packets kept for the scrolling list; older ones fall off the top
+const MAX_LISTED: usize = 10_000;
+// Seconds of packet rate kept for the sparkline
+const RATE_HISTORY: usize = 300;
+// How often the screen is redrawn and the keyboard polled
+const TICK: Duration = Duration::from_millis(250);
+
+// One row of the packet list
+#[derive(Clone)]
+struct Listed {
+    number: u32,
+    len: u32,
+    interface: u32,
+    decoded: Result<DecodedPacket, String>,
+}
+
+// What the capture thread has seen so far, shared with the drawing thread
+struct Dashboard {
+    interfaces: Vec<String>,
+    packets: u64,
+    bytes: u64,
+    dropped: u64,
+    protocols: HashMap<String, u64>,
+    talkers: HashMap<IpAddr, TrafficCounters>,
+    flows: FlowTable,
+    listed: VecDeque<Listed>,
+    first_timestamp: Option<f64>,
+    paused: bool,
+    // Packets captured while paused, which the list leaves out
+    skipped: u64,
+    notice: Option<String>,
+    finished: bool,
+    abort: bool,
+}
+
+impl Dashboard {
+    fn add(&mut self, packet: &ParsedPacket) {
+        self.packets += 1;
+        self.bytes += packet.raw.len as u64;
+        if let Ok(decoded) = &packet.decoded {
+            *self.protocols.entry(decoded.protocol_name()).or_insert(0) += 1;
+            for addr in [decoded.src_addr(), decoded.dst_addr()].into_iter().flatten() {
+                self.talkers.entry(addr).or_default().add(packet.raw.len);
+            }
+            self.flows.update(decoded, packet.data);
+            // Only active flows are shown; drop the ones the last sweep expired
+            self.flows.take_finished();
+            self.first_timestamp.get_or_insert(decoded.timestamp());
+        }
+
+        if self.paused {
+            self.skipped += 1;
+            return;
+        }
+        if self.listed.len() == MAX_LISTED {
+            self.listed.pop_front();
+        }
+        self.listed.push_back(Listed {
+            number: packet.number,
+            len: packet.raw.len,
+            interface: packet.raw.interface,
+            decoded: match &packet.decoded {
+                Ok(decoded) => Ok(decoded.clone()),
+                Err(e) => Err(e.to_string()),
+            },
+        });
+    }
+
+    // Indexes into `listed` of the packets the view filter lets through
+    fn visible(&self, filter: Option<&DisplayFilter>) -> Vec<usize> {
+        self.listed
+            .iter()
+            .enumerate()
+            .filter(|(_, listed)| match (filter, &listed.decoded) {
+                (None, _) => true,
+                (Some(filter), Ok(decoded)) => filter.matches(decoded),
+                (Some(_), Err(_)) => false,
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // Copy out what one frame shows. No pane is taller than `rows`, so the
+    // talkers, flows and packet list are cut to that.
+    fn snapshot(&self, view: &View, rows: usize) -> Snapshot {
+        let protocols = largest_first(self.protocols.iter().map(|(name, n)| (name.clone(), *n)), |count| *count);
+        let mut talkers = largest_first(self.talkers.iter().map(|(addr, t)| (*addr, *t)), |traffic| traffic.bytes);
+        talkers.truncate(rows);
+        let mut flows: Vec<&Flow> = self.flows.active_flows().collect();
+        flows.sort_by(|a, b| b.cmp_size(a));
+
+        // The table scrolls just far enough to show the cursor, so the rows
+        // above it are all that can be on screen
+        let visible = self.visible(view.filter.as_ref());
+        let position = (!visible.is_empty()).then(|| cursor(self, &visible, view.selected));
+        let start = position.map_or(0, |position| (position + 1).saturating_sub(rows));
+        let window = visible.iter().skip(start).take(rows).map(|&index| self.listed[index].clone()).collect();
+
+        Snapshot {
+            interfaces: self.interfaces.clone(),
+            packets: self.packets,
+            bytes: self.bytes,
+            dropped: self.dropped,
+            protocols,
+            talkers,
+            flows: flows.into_iter().take(rows).cloned().collect(),
+            active_flows: self.flows.active_count(),
+            first_timestamp: self.first_timestamp.unwrap_or_default(),
+            window,
+            cursor: position.map(|position| position - start),
+            visible: visible.len(),
+            listed: self.listed.len(),
+            paused: self.paused,
+            skipped: self.skipped,
+            notice: self.notice.clone(),
+            finished: self.finished,
+        }
+    }
+}
+
+// One frame's worth of the dashboard, drawn after the lock is released so the
+// capture thread is not held up by the terminal
+struct Snapshot {
+    interfaces: Vec<String>,
+    packets: u64,
+    bytes: u64,
+    dropped: u64,
+    // Sorted largest first
+    protocols: Vec<(String, u64)>,
+    talkers: Vec<(IpAddr, TrafficCounters)>,
+    flows: Vec<Flow>,
+    active_flows: usize,
+    first_timestamp: f64,
+    // A screen's worth of visible packets, scrolled to the cursor, and the cursor's row among them
+    window: Vec<Listed>,
+    cursor: Option<usize>,
+    visible: usize,
+    listed: usize,
+    paused: bool,
+    skipped: u64,
+    notice: Option<String>,
+    finished: bool,
+}
+
+// Packet and bit rates, sampled about once a second
+struct Rate {
+    last: Instant,
+    packets: u64,
+    bytes: u64,
+    pps: f64,
+    bps: f64,
+    history: VecDeque<u64>,
+}
+
+impl Rate {
+    fn new() -> Self {
+        Rate {
+            last: Instant::now(),
+            packets: 0,
+            bytes: 0,
+            pps: 0.0,
+            bps: 0.0,
+            history: VecDeque::new(),
+        }
+    }
+
+    fn sample(&mut self, packets: u64, bytes: u64) {
+        let elapsed = self.last.elapsed().as_secs_f64();
+        if elapsed < 1.0 {
+            return;
+        }
+        self.pps = (packets - self.packets) as f64 / elapsed;
+        self.bps = (bytes - self.bytes) as f64 * 8.0 / elapsed;
+        (self.last, self.packets, self.bytes) = (Instant::now(), packets, bytes);
+        if self.history.len() == RATE_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.pps.round() as u64);
+    }
+}
+
+// State of the screen itself, owned by the drawing thread
+struct View {
+    filter: Option<DisplayFilter>,
+    // Filter expression being typed after '/'
+    editing: Option<String>,
+    error: Option<String>,
+    // Packet number under the cursor; None follows the newest packet
+    selected: Option<u32>,
+    // Rows the packet list showed on the last draw, for paging
+    page: usize,
+    rate: Rate,
+}
+
+// Full-screen live dashboard. Packets are accounted here on the capture thread;
+// a second thread redraws the screen and handles the keyboard.
+pub struct TuiSink {
+    dashboard: Arc<Mutex<Dashboard>>,
+    ui: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl TuiSink {
+    // Take over the terminal. Quitting raises `stop_flag` to end the capture.
+    pub fn new(
+        interfaces: &[InterfaceInfo],
+        flow_idle_timeout: f64,
+        flow_active_timeout: f64,
+        stop_flag: Arc<AtomicBool>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let dashboard = Arc::new(Mutex::new(Dashboard {
+            interfaces: interfaces.iter().map(|info| info.name.clone()).collect(),
+            packets: 0,
+            bytes: 0,
+            dropped: 0,
+            protocols: HashMap::new(),
+            talkers: HashMap::new(),
+            flows: FlowTable::new(flow_idle_timeout, flow_active_timeout),
+            listed: VecDeque::new(),
+            first_timestamp: None,
+            paused: false,
+            skipped: 0,
+            notice: None,
+            finished: false,
+            abort: false,
+        }));
+        let terminal = ratatui::try_init()?;
+        let shared = dashboard.clone();
+        let ui = thread::spawn(move || {
+            let result = run_ui(terminal, &shared, &stop_flag).map_err(|e| e.to_string());
+            ratatui::restore();
+            result
+        });
+        Ok(TuiSink { dashboard, ui: Some(ui) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Dashboard> {
+        self.dashboard.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn join(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.ui.take().map(JoinHandle::join) {
+            Some(Ok(Err(e))) => Err(format!("Terminal error: {}", e).into()),
+            Some(Err(_)) => Err("Dashboard thread panicked".into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl PacketSink for TuiSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        self.lock().add(packet);
+        Ok(())
+    }
+
+    fn on_source_stats(&mut self, stats: &[SourceStats]) {
+        self.lock().dropped = stats.iter().map(|stat| stat.dropped).sum();
+    }
+
+    // Shown in the footer while the capture thread waits to reconnect
+    fn on_reconnect(&mut self, event: &ReconnectEvent) {
+        self.lock().notice = Some(event.to_string());
+    }
+
+    fn on_gap(&mut self, gap: &CaptureGap) {
+        self.lock().notice = Some(format!(
+            "{} was down for {:.1}s ({})",
+            gap.device,
+            gap.seconds(),
+            gap.reason
+        ));
+    }
+
+    // Keep the final figures on screen until the user quits
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.lock().finished = true;
+        self.join()
+    }
+}
+
+impl Drop for TuiSink {
+    // The capture failed before `finish`; give the terminal back right away
+    fn drop(&mut self) {
+        if self.ui.is_some() {
+            self.lock().abort = true;
+            let _ = self.join();
+        }
+    }
+}
+
+fn run_ui(mut terminal: DefaultTerminal, dashboard: &Mutex<Dashboard>, stop_flag: &AtomicBool) -> std::io::Result<()> {
+    let mut view = View {
+        filter: None,
+        editing: None,
+        error: None,
+        selected: None,
+        page: 0,
+        rate: Rate::new(),
+    };
+    loop {
+        let rows = terminal.size()?.height as usize;
+        let snapshot = {
+            let dashboard = dashboard.lock().unwrap_or_else(|e| e.into_inner());
+            if dashboard.abort {
+                return Ok(());
+            }
+            dashboard.snapshot(&view, rows)
+        };
+        view.rate.sample(snapshot.packets, snapshot.bytes);
+        terminal.draw(|frame| draw(frame, &snapshot, &mut view))?;
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let mut dashboard = dashboard.lock().unwrap_or_else(|e| e.into_inner());
+        if handle_key(key, &mut dashboard, &mut view) {
+            stop_flag.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
+    }
+}
+
+// Apply one key press; returns true when the user asked to quit
+fn handle_key(key: KeyEvent, dashboard: &mut Dashboard, view: &mut View) -> bool {
+    // Raw mode swallows SIGINT, so Ctrl+C arrives as a key
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+        return true;
+    }
+
+    if let Some(input) = &mut view.editing {
+        match key.code {
+            KeyCode::Enter => {
+                let expression = input.trim().to_string();
+                view.editing = None;
+                if expression.is_empty() {
+                    view.filter = None;
+                    view.error = None;
+                } else {
+                    match DisplayFilter::parse(&expression) {
+                        Ok(filter) => {
+                            view.filter = Some(filter);
+                            view.error = None;
+                        }
+                        Err(e) => view.error = Some(format!("Invalid display filter at {}", e)),
+                    }
+                }
+                view.selected = None;
+            }
+            KeyCode::Esc => view.editing = None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+        return false;
+    }
+
+    let page = view.page.max(1) as isize;
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return true,
+        KeyCode::Char(' ') | KeyCode::Char('p') => {
+            dashboard.paused = !dashboard.paused;
+        }
+        KeyCode::Char('/') => {
+            let current = view.filter.as_ref().map(|f| f.expression().to_string());
+            view.editing = Some(current.unwrap_or_default());
+        }
+        KeyCode::Up | KeyCode::Char('k') => scroll(dashboard, view, -1),
+        KeyCode::Down | KeyCode::Char('j') => scroll(dashboard, view, 1),
+        KeyCode::PageUp => scroll(dashboard, view, -page),
+        KeyCode::PageDown => scroll(dashboard, view, page),
+        KeyCode::Home | KeyCode::Char('g') => scroll(dashboard, view, isize::MIN / 2),
+        KeyCode::End | KeyCode::Char('G') => view.selected = None,
+        _ => {}
+    }
+    false
+}
+
+// Move the cursor by `delta` rows. Reaching the bottom follows new packets again.
+fn scroll(dashboard: &Dashboard, view: &mut View, delta: isize) {
+    let visible = dashboard.visible(view.filter.as_ref());
+    let Some(last) = visible.len().checked_sub(1) else {
+        return;
+    };
+    let current = cursor(dashboard, &visible, view.selected);
+    let target = (current as isize).saturating_add(delta).clamp(0, last as isize) as usize;
+    view.selected = if target == last && delta > 0 {
+        None
+    } else {
+        Some(dashboard.listed[visible[target]].number)
+    };
+}
+
+// Position of the selected packet among the visible ones; a packet that has
+// scrolled out of the list puts the cursor at the top
+fn cursor(dashboard: &Dashboard, visible: &[usize], selected: Option<u32>) -> usize {
+    match selected {
+        Some(number) => visible
+            .iter()
+            .position(|&index| dashboard.listed[index].number == number)
+            .unwrap_or(0),
+        None => visible.len().saturating_sub(1),
+    }
+}
+
+fn draw(frame: &mut Frame, snapshot: &Snapshot, view: &mut View) {
+    let [header, rate, overview, packets, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(4),
+        Constraint::Length(10),
+        Constraint::Min(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [protocols, talkers, flows] = Layout::horizontal([
+        Constraint::Percentage(25),
+        Constraint::Percentage(30),
+        Constraint::Percentage(45),
+    ])
+    .areas(overview);
+    let [list, details] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(packets);
+
+    draw_header(frame, header, snapshot, view);
+    let history: Vec<u64> = view.rate.history.iter().copied().collect();
+    let skip = history.len().saturating_sub(rate.width.saturating_sub(2) as usize);
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title("Packets/s"))
+            .data(&history[skip..])
+            .style(Style::default().fg(Color::Green)),
+        rate,
+    );
+    draw_protocols(frame, protocols, snapshot);
+    draw_talkers(frame, talkers, snapshot);
+    draw_flows(frame, flows, snapshot);
+    draw_packets(frame, list, details, snapshot, view);
+    draw_footer(frame, footer, snapshot, view);
+}
+
+fn draw_header(frame: &mut Frame, area: Rect, snapshot: &Snapshot, view: &View) {
+    let state = if snapshot.finished {
+        "FINISHED".to_string()
+    } else if snapshot.paused {
+        format!("PAUSED ({} not listed)", snapshot.skipped)
+    } else {
+        "LIVE".to_string()
+    };
+    let mut text = format!(
+        " {} | {} | {} packets, {} | {:.0} pkt/s, {}",
+        snapshot.interfaces.join(","),
+        state,
+        snapshot.packets,
+        format_bytes(snapshot.bytes),
+        view.rate.pps,
+        format_bits(view.rate.bps)
+    );
+    if snapshot.dropped > 0 {
+        text.push_str(&format!(" | {} dropped", snapshot.dropped));
+    }
+    if let Some(filter) = &view.filter {
+        text.push_str(&format!(" | filter: {}", filter.expression()));
+    }
+    frame.render_widget(
+        Paragraph::new(text).style(Style::default().add_modifier(Modifier::REVERSED)),
+        area,
+    );
+}
+
+fn draw_protocols(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let total = snapshot.packets.max(1) as f64;
+    let rows = snapshot.protocols.iter().map(|(name, count)| {
+        Row::new(vec![
+            name.clone(),
+            count.to_string(),
+            format!("{:.1}%", *count as f64 * 100.0 / total),
+        ])
+    });
+    let table = Table::new(rows, [Constraint::Fill(1), Constraint::Length(9), Constraint::Length(6)])
+        .header(heading(["Protocol", "Packets", "%"]))
+        .block(Block::bordered().title("Protocols"));
+    frame.render_widget(table, area);
+}
+
+fn draw_talkers(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let rows = snapshot.talkers.iter().take(area.height as usize).map(|(addr, traffic)| {
+        Row::new(vec![addr.to_string(), traffic.packets.to_string(), format_bytes(traffic.bytes)])
+    });
+    let table = Table::new(rows, [Constraint::Fill(1), Constraint::Length(9), Constraint::Length(10)])
+        .header(heading(["Address", "Packets", "Bytes"]))
+        .block(Block::bordered().title("Top talkers"));
+    frame.render_widget(table, area);
+}
+
+fn draw_flows(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let rows = snapshot.flows.iter().take(area.height as usize).map(|flow| {
+        Row::new(vec![
+            flow::ip_protocol_name(flow.protocol),
+            format!(
+                "{} -> {}",
+                flow::endpoint(flow.src, flow.src_port),
+                flow::endpoint(flow.dst, flow.dst_port)
+            ),
+            format_bytes(flow.bytes()),
+            flow.state(),
+        ])
+    });
+    let widths = [
+        Constraint::Length(6),
+        Constraint::Fill(1),
+        Constraint::Length(10),
+        Constraint::Length(12),
+    ];
+    let table = Table::new(rows, widths)
+        .header(heading(["Proto", "Conversation", "Bytes", "State"]))
+        .block(Block::bordered().title(format!("Top flows ({} active)", snapshot.active_flows)));
+    frame.render_widget(table, area);
+}
+
+fn draw_packets(frame: &mut Frame, list: Rect, details: Rect, snapshot: &Snapshot, view: &mut View) {
+    let first = snapshot.first_timestamp;
+    let rows = snapshot.window.iter().map(|listed| {
+        match &listed.decoded {
+            Ok(decoded) => Row::new(vec![
+                listed.number.to_string(),
+                format!("{:.3}", decoded.timestamp() - first),
+                addr_or_dash(decoded.src_addr()),
+                addr_or_dash(decoded.dst_addr()),
+                decoded.protocol_name(),
+                listed.len.to_string(),
+                decoded.message_type().unwrap_or_default(),
+            ]),
+            Err(e) => Row::new(vec![
+                listed.number.to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                "?".to_string(),
+                listed.len.to_string(),
+                e.clone(),
+            ]),
+        }
+    });
+    let widths = [
+        Constraint::Length(7),
+        Constraint::Length(10),
+        Constraint::Length(20),
+        Constraint::Length(20),
+        Constraint::Length(8),
+        Constraint::Length(6),
+        Constraint::Fill(1),
+    ];
+    let title = match &view.filter {
+        Some(_) => format!("Packets ({} of {} listed)", snapshot.visible, snapshot.listed),
+        None => format!("Packets ({} listed)", snapshot.listed),
+    };
+    let table = Table::new(rows, widths)
+        .header(heading(["No.", "Time", "Source", "Destination", "Proto", "Length", "Info"]))
+        .block(Block::bordered().title(title))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = TableState::default().with_selected(snapshot.cursor);
+    // Borders and the heading row take three lines
+    view.page = list.height.saturating_sub(3) as usize;
+    frame.render_stateful_widget(table, list, &mut state);
+
+    let text = match snapshot.cursor.map(|cursor| &snapshot.window[cursor]) {
+        Some(listed) => packet_details(listed, &snapshot.interfaces),
+        None => String::new(),
+    };
+    let paragraph = Paragraph::new(text)
+        .block(Block::bordered().title("Details"))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, details);
+}
+
+fn draw_footer(frame: &mut Frame, area: Rect, snapshot: &Snapshot, view: &View) {
+    let line = if let Some(input) = &view.editing {
+        Line::from(format!("Display filter: {}_", input))
+    } else if let Some(error) = &view.error {
+        Line::styled(error.clone(), Style::default().fg(Color::Red))
+    } else if let Some(notice) = &snapshot.notice {
+        Line::styled(notice.clone(), Style::default().fg(Color::Yellow))
+    } else if snapshot.finished {
+        Line::from("Capture finished. q quit  / filter  arrows/PgUp/PgDn/Home/End scroll")
+    } else {
+        Line::from("q quit  space pause  / filter  arrows/PgUp/PgDn/Home/End scroll")
+    };
+    frame.render_widget(Paragraph::new(line), area);
+}
+
+// The same lines verbose console output prints for a packet
+fn packet_details(listed: &Listed, interfaces: &[String]) -> String {
+    let decoded = match &listed.decoded {
+        Ok(decoded) => decoded,
+        Err(e) => return format!("Packet #{}: Error parsing packet - {}", listed.number, e),
+    };
+    let mut text = format!("Packet #{}, Length: {} bytes\n", listed.number, listed.len);
+    if interfaces.len() > 1 {
+        let name = interfaces.get(listed.interface as usize).map_or("?", |n| n.as_str());
+        text.push_str(&format!("  Interface: {}\n", name));
+    }
+    text.push_str(&format!("  Protocol: {}\n", decoded.protocol_name()));
+    text.push_str(&format!(
+        "  Source: {}:{}\n",
+        addr_or_dash(decoded.src_addr()),
+        decoded.src_port().unwrap_or_default()
+    ));
+    text.push_str(&format!(
+        "  Destination: {}:{}\n",
+        addr_or_dash(decoded.dst_addr()),
+        decoded.dst_port().unwrap_or_default()
+    ));
+    // Writing to a Vec cannot fail
+    let mut out = Vec::new();
+    let _ = write_protocol_details(&mut out, decoded);
+    text.push_str(&String::from_utf8_lossy(&out));
+    text
+}
+
+fn heading<const N: usize>(titles: [&'static str; N]) -> Row<'static> {
+    Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn addr_or_dash(addr: Option<IpAddr>) -> String {
+    addr.map(|a| a.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    scaled(bytes as f64, "B")
+}
+
+fn format_bits(bits: f64) -> String {
+    scaled(bits, "bit/s")
+}
+
+fn scaled(mut value: f64, unit: &str) -> String {
+    let prefixes = ["", "k", "M", "G", "T"];
+    let mut prefix = 0;
+    while value >= 1000.0 && prefix < prefixes.len() - 1 {
+        value /= 1000.0;
+        prefix += 1;
+    }
+    if prefix == 0 {
+        format!("{:.0} {}", value, unit)
+    } else {
+        format!("{:.1} {}{}", value, prefixes[prefix], unit)
+    }
+}
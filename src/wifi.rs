@@ -0,0 +1,409 @@
+use crate::packet::MacAddr;
+use serde::Serialize;
+use std::io::{self, Write};
+
+// What the radiotap header and the 802.11 MAC header tell us about a frame
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WlanInfo {
+    // 0 management, 1 control, 2 data
+    pub frame_type: u8,
+    pub subtype: u8,
+    pub to_ds: bool,
+    pub from_ds: bool,
+    pub retry: bool,
+    pub protected: bool,
+    pub receiver: Option<MacAddr>,
+    pub transmitter: Option<MacAddr>,
+    pub bssid: Option<MacAddr>,
+    // From beacons, probes and association requests; empty for hidden networks
+    pub ssid: Option<String>,
+    pub channel: Option<u16>,
+    // Radiotap fields
+    pub frequency: Option<u16>,
+    pub rssi: Option<i8>,
+    pub rate_mbps: Option<f32>,
+}
+
+impl WlanInfo {
+    pub fn type_name(&self) -> &'static str {
+        match self.frame_type {
+            0 => "Management",
+            1 => "Control",
+            2 => "Data",
+            _ => "Extension",
+        }
+    }
+
+    pub fn subtype_name(&self) -> String {
+        let name = match (self.frame_type, self.subtype) {
+            (0, 0) => "Association Request",
+            (0, 1) => "Association Response",
+            (0, 2) => "Reassociation Request",
+            (0, 3) => "Reassociation Response",
+            (0, 4) => "Probe Request",
+            (0, 5) => "Probe Response",
+            (0, 8) => "Beacon",
+            (0, 9) => "ATIM",
+            (0, 10) => "Disassociation",
+            (0, 11) => "Authentication",
+            (0, 12) => "Deauthentication",
+            (0, 13) => "Action",
+            (1, 8) => "Block Ack Request",
+            (1, 9) => "Block Ack",
+            (1, 10) => "PS-Poll",
+            (1, 11) => "RTS",
+            (1, 12) => "CTS",
+            (1, 13) => "ACK",
+            (1, 14) => "CF-End",
+            (2, 0) => "Data",
+            (2, 4) => "Null",
+            (2, 8) => "QoS Data",
+            (2, 12) => "QoS Null",
+            (frame_type, subtype) => return format!("type {} subtype {}", frame_type, subtype),
+        };
+        name.to_string()
+    }
+}
+
+// Channel number for a centre frequency in MHz (2.4, 5 and 6 GHz bands)
+pub fn channel_for_frequency(frequency: u16) -> Option<u16> {
+    match frequency {
+        2484 => Some(14),
+        2412..=2472 => Some((frequency - 2407) / 5),
+        5955..=7115 => Some((frequency - 5950) / 5),
+        5000..=5925 => Some((frequency - 5000) / 5),
+        _ => None,
+    }
+}
+
+fn u16_le(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_le(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn mac_at(data: &[u8], at: usize) -> Option<MacAddr> {
+    data.get(at..at + 6).and_then(|b| b.try_into().ok()).map(MacAddr)
+}
+
+// Parse a radiotap header into `info`. Returns the 802.11 frame after it, minus
+// the FCS when radiotap says one is present.
+fn parse_radiotap<'a>(data: &'a [u8], info: &mut WlanInfo) -> Option<&'a [u8]> {
+    if *data.first()? != 0 {
+        return None;
+    }
+    let header_len = u16_le(data, 2)? as usize;
+    let header = data.get(..header_len)?;
+
+    // The present bitmap may be extended by further words while bit 31 is set
+    let present = u32_le(header, 4)?;
+    let mut offset = 8;
+    let mut word = present;
+    while word & 0x8000_0000 != 0 {
+        word = u32_le(header, offset)?;
+        offset += 4;
+    }
+
+    // Fields in bit order, each aligned to its natural size; only the first six
+    // are needed so parsing stops there
+    let mut has_fcs = false;
+    let align = |offset: usize, to: usize| offset.div_ceil(to) * to;
+    if present & 0x01 != 0 {
+        // TSFT
+        offset = align(offset, 8) + 8;
+    }
+    if present & 0x02 != 0 {
+        has_fcs = header.get(offset)? & 0x10 != 0;
+        offset += 1;
+    }
+    if present & 0x04 != 0 {
+        // Rate in 500 kb/s units
+        info.rate_mbps = Some(*header.get(offset)? as f32 / 2.0);
+        offset += 1;
+    }
+    if present & 0x08 != 0 {
+        offset = align(offset, 2);
+        let frequency = u16_le(header, offset)?;
+        info.frequency = Some(frequency);
+        info.channel = channel_for_frequency(frequency);
+        offset += 4;
+    }
+    if present & 0x10 != 0 {
+        // FHSS hop set and pattern
+        offset += 2;
+    }
+    if present & 0x20 != 0 {
+        info.rssi = Some(*header.get(offset)? as i8);
+    }
+
+    let frame = data.get(header_len..)?;
+    if has_fcs {
+        frame.get(..frame.len().checked_sub(4)?)
+    } else {
+        Some(frame)
+    }
+}
+
+// Walk the information elements of a management frame body
+fn parse_elements(mut data: &[u8], info: &mut WlanInfo) {
+    while data.len() >= 2 {
+        let (id, len) = (data[0], data[1] as usize);
+        let Some(value) = data.get(2..2 + len) else {
+            break;
+        };
+        match id {
+            0 if info.ssid.is_none() => {
+                info.ssid = Some(String::from_utf8_lossy(value).trim_end_matches('\0').to_string());
+            }
+            // DS Parameter Set: the channel the AP is on
+            3 if len >= 1 && info.channel.is_none() => info.channel = Some(value[0] as u16),
+            _ => {}
+        }
+        data = &data[2 + len..];
+    }
+}
+
+// Ether type and payload following an LLC/SNAP header
+pub type SnapPayload<'a> = (u16, &'a [u8]);
+
+// Decode an 802.11 frame, with a radiotap header in front when `radiotap` is set.
+// For unencrypted data frames, returns the LLC/SNAP ether type and payload too.
+pub fn decode(data: &[u8], radiotap: bool) -> Option<(WlanInfo, Option<SnapPayload<'_>>)> {
+    let mut info = WlanInfo::default();
+    let frame = if radiotap { parse_radiotap(data, &mut info)? } else { data };
+
+    let fc0 = *frame.first()?;
+    let fc1 = *frame.get(1)?;
+    info.frame_type = (fc0 >> 2) & 0x3;
+    info.subtype = fc0 >> 4;
+    info.to_ds = fc1 & 0x01 != 0;
+    info.from_ds = fc1 & 0x02 != 0;
+    info.retry = fc1 & 0x08 != 0;
+    info.protected = fc1 & 0x40 != 0;
+    info.receiver = mac_at(frame, 4);
+
+    let mut inner = None;
+    match info.frame_type {
+        0 => {
+            info.transmitter = mac_at(frame, 10);
+            info.bssid = mac_at(frame, 16);
+            // Fixed fields ahead of the information elements
+            let fixed = match info.subtype {
+                0 => 4,
+                2 => 10,
+                4 => 0,
+                5 | 8 => 12,
+                _ => return Some((info, None)),
+            };
+            if !info.protected {
+                if let Some(body) = frame.get(24 + fixed..) {
+                    parse_elements(body, &mut info);
+                }
+            }
+        }
+        // ACK and CTS carry only the receiver address
+        1 if !matches!(info.subtype, 12 | 13) => {
+            info.transmitter = mac_at(frame, 10);
+        }
+        2 => {
+            info.transmitter = mac_at(frame, 10);
+            info.bssid = match (info.to_ds, info.from_ds) {
+                (false, false) => mac_at(frame, 16),
+                (true, false) => info.receiver,
+                (false, true) => info.transmitter,
+                (true, true) => None,
+            };
+            let mut header_len = 24;
+            if info.to_ds && info.from_ds {
+                header_len += 6;
+            }
+            let qos = info.subtype & 0x08 != 0;
+            if qos {
+                header_len += 2;
+                // HT control field
+                if fc1 & 0x80 != 0 {
+                    header_len += 4;
+                }
+            }
+            // Null-function subtypes have no body
+            if !info.protected && info.subtype & 0x04 == 0 {
+                let body = frame.get(header_len..).unwrap_or(&[]);
+                if body.len() > 8 && body[..3] == [0xaa, 0xaa, 0x03] {
+                    let ether_type = u16::from_be_bytes([body[6], body[7]]);
+                    inner = Some((ether_type, &body[8..]));
+                }
+            }
+        }
+        _ => {}
+    }
+    Some((info, inner))
+}
+
+pub fn write_wlan_details(out: &mut dyn Write, info: &WlanInfo) -> io::Result<()> {
+    writeln!(out, "  802.11: {} {}", info.type_name(), info.subtype_name())?;
+    let addresses = [("Receiver", info.receiver), ("Transmitter", info.transmitter), ("BSSID", info.bssid)];
+    for (label, mac) in addresses {
+        if let Some(mac) = mac {
+            writeln!(out, "    {}: {}", label, mac)?;
+        }
+    }
+    if let Some(ssid) = &info.ssid {
+        writeln!(out, "    SSID: {}", if ssid.is_empty() { "<hidden>" } else { ssid })?;
+    }
+    match (info.channel, info.frequency) {
+        (Some(channel), Some(frequency)) => writeln!(out, "    Channel: {} ({} MHz)", channel, frequency)?,
+        (Some(channel), None) => writeln!(out, "    Channel: {}", channel)?,
+        (None, Some(frequency)) => writeln!(out, "    Frequency: {} MHz", frequency)?,
+        (None, None) => {}
+    }
+    if let Some(rssi) = info.rssi {
+        writeln!(out, "    Signal: {} dBm", rssi)?;
+    }
+    if let Some(rate) = info.rate_mbps {
+        writeln!(out, "    Rate: {} Mb/s", rate)?;
+    }
+    if info.protected {
+        writeln!(out, "    Protected")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listening::parse_packet_with_etherparse;
+    use crate::packet::DecodedPacket;
+    use crate::source::RawPacket;
+    use pcap::Linktype;
+
+    const AP: [u8; 6] = [0x02, 0xaa, 0xbb, 0xcc, 0xdd, 0xee];
+    const STATION: [u8; 6] = [0x02, 0x11, 0x22, 0x33, 0x44, 0x55];
+
+    // Radiotap with TSFT, flags (FCS at end), rate 6 Mb/s, channel 2437 MHz and -42 dBm
+    fn radiotap() -> Vec<u8> {
+        let mut header = vec![0x00, 0x00, 23, 0x00, 0x2f, 0x00, 0x00, 0x00];
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&[0x10, 0x0c, 0x85, 0x09, 0xa0, 0x00, 0xd6]);
+        header
+    }
+
+    fn beacon(elements: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x80, 0x00, 0x00, 0x00];
+        frame.extend_from_slice(&[0xff; 6]);
+        frame.extend_from_slice(&AP);
+        frame.extend_from_slice(&AP);
+        frame.extend_from_slice(&[0x10, 0x00]);
+        // Timestamp, beacon interval, capabilities
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&[0x64, 0x00, 0x11, 0x04]);
+        frame.extend_from_slice(elements);
+        frame
+    }
+
+    fn decode_frame(frame: Vec<u8>, linktype: Linktype) -> DecodedPacket {
+        parse_packet_with_etherparse(1, &RawPacket::new(0, 0, frame), linktype).unwrap()
+    }
+
+    #[test]
+    fn radiotap_beacon_with_ssid_and_channel() {
+        let mut frame = radiotap();
+        // SSID, supported rates, then a DS Parameter Set that radiotap's channel wins over
+        frame.extend(beacon(&[0, 6, b'H', b'o', b'm', b'e', b'A', b'P', 1, 1, 0x82, 3, 1, 11]));
+        frame.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let packet = decode_frame(frame, Linktype::IEEE802_11_RADIOTAP);
+
+        assert_eq!(packet.protocol_name(), "802.11 Management");
+        assert_eq!(packet.message_type().as_deref(), Some("802.11 Beacon"));
+        let wlan = packet.wlan.as_ref().unwrap();
+        assert_eq!(wlan.ssid.as_deref(), Some("HomeAP"));
+        assert_eq!((wlan.frequency, wlan.channel), (Some(2437), Some(6)));
+        assert_eq!((wlan.rssi, wlan.rate_mbps), (Some(-42), Some(6.0)));
+        assert_eq!(wlan.bssid, Some(MacAddr(AP)));
+
+        let mut details = Vec::new();
+        write_wlan_details(&mut details, wlan).unwrap();
+        assert_eq!(
+            String::from_utf8(details).unwrap(),
+            "  802.11: Management Beacon\n    \
+             Receiver: ff:ff:ff:ff:ff:ff\n    \
+             Transmitter: 02:aa:bb:cc:dd:ee\n    \
+             BSSID: 02:aa:bb:cc:dd:ee\n    \
+             SSID: HomeAP\n    \
+             Channel: 6 (2437 MHz)\n    \
+             Signal: -42 dBm\n    \
+             Rate: 6 Mb/s\n"
+        );
+    }
+
+    #[test]
+    fn the_fcs_is_not_read_as_an_element() {
+        // Radiotap with only the flags field, FCS present, and no channel
+        let mut frame = vec![0x00, 0x00, 9, 0x00, 0x02, 0x00, 0x00, 0x00, 0x10];
+        frame.extend(beacon(&[0, 0]));
+        // An FCS that happens to look like a DS Parameter Set for channel 11
+        frame.extend_from_slice(&[3, 1, 11, 0]);
+        let (wlan, _) = decode(&frame, true).unwrap();
+        assert_eq!(wlan.channel, None);
+        // Hidden network
+        assert_eq!(wlan.ssid.as_deref(), Some(""));
+
+        // Without radiotap the channel comes from the DS Parameter Set
+        let (wlan, _) = decode(&beacon(&[0, 3, 0, 0, 0, 3, 1, 11]), false).unwrap();
+        assert_eq!((wlan.ssid.as_deref(), wlan.channel), (Some(""), Some(11)));
+    }
+
+    #[test]
+    fn data_frames_carry_ip_through_llc_snap() {
+        // QoS data from a station to the AP (To DS)
+        let mut frame = vec![0x88, 0x01, 0x00, 0x00];
+        frame.extend_from_slice(&AP);
+        frame.extend_from_slice(&STATION);
+        frame.extend_from_slice(&[0x02, 0x99, 0x99, 0x99, 0x99, 0x99]);
+        frame.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        frame.extend_from_slice(&[0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00]);
+        // IPv4 + UDP 10.0.0.5:5000 -> 10.0.0.1:53 with a 4-byte payload
+        frame.extend_from_slice(&[0x45, 0x00, 0x00, 0x20, 0, 1, 0, 0, 64, 17, 0, 0, 10, 0, 0, 5, 10, 0, 0, 1]);
+        frame.extend_from_slice(&[0x13, 0x88, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00, 1, 2, 3, 4]);
+        let packet = decode_frame(frame.clone(), Linktype::IEEE802_11);
+
+        assert_eq!(packet.protocol_name(), "UDP");
+        assert_eq!(packet.src_addr().unwrap().to_string(), "10.0.0.5");
+        assert_eq!(packet.dst_port(), Some(53));
+        let wlan = packet.wlan.as_ref().unwrap();
+        assert!(wlan.to_ds && !wlan.from_ds);
+        assert_eq!(wlan.bssid, Some(MacAddr(AP)));
+        assert_eq!(wlan.subtype_name(), "QoS Data");
+
+        // The same frame encrypted: addresses only
+        frame[1] |= 0x40;
+        let (wlan, inner) = decode(&frame, false).unwrap();
+        assert!(wlan.protected && inner.is_none());
+    }
+
+    #[test]
+    fn control_frames_and_truncation() {
+        // ACK: receiver only
+        let mut ack = vec![0xd4, 0x00, 0x00, 0x00];
+        ack.extend_from_slice(&STATION);
+        let (wlan, inner) = decode(&ack, false).unwrap();
+        assert_eq!(wlan.subtype_name(), "ACK");
+        assert_eq!((wlan.receiver, wlan.transmitter), (Some(MacAddr(STATION)), None));
+        assert!(inner.is_none());
+
+        // Radiotap claiming more bytes than were captured
+        let header = radiotap();
+        assert!(decode(&header[..20], true).is_none());
+        assert!(decode(&[0x01, 0x00, 0x08, 0x00], true).is_none());
+    }
+
+    #[test]
+    fn channels_for_each_band() {
+        assert_eq!(channel_for_frequency(2412), Some(1));
+        assert_eq!(channel_for_frequency(2484), Some(14));
+        assert_eq!(channel_for_frequency(5180), Some(36));
+        assert_eq!(channel_for_frequency(5955), Some(1));
+        assert_eq!(channel_for_frequency(915), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use testgame::defrag::Defragmenter;
+use testgame::listening::{run_capture, CaptureStats, StopConditions};
+use testgame::recovery::RecoveryPolicy;
+use testgame::sink::{PacketSink, StatsSink};
+use testgame::source::{FileSource, PacketSource};
+
+// Ten Ethernet frames, one second apart:
+//   1     ARP request for 10.0.0.1
+//   2-3   DNS query and response for example.com
+//   4-6   TCP handshake from 10.0.0.2:40000 to 93.184.216.34:80
+//   7-8   ICMP echo request and reply
+//   9     UDP datagram to 10.0.0.3:6000
+//   10    TCP SYN over IPv6 to port 22
+const SAMPLE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/sample.pcap");
+
+fn capture(filter: Option<&str>, stop: &StopConditions) -> (u32, CaptureStats) {
+    let mut source = FileSource::open(SAMPLE).expect("sample capture should open");
+    if let Some(filter) = filter {
+        source.set_filter(filter).expect("filter should compile");
+    }
+    let mut stats = StatsSink::new(&source.interfaces());
+    let mut sinks: Vec<&mut dyn PacketSink> = vec![&mut stats];
+    let count = run_capture(
+        &mut source,
+        &mut sinks,
+        &mut Defragmenter::default(),
+        None,
+        stop,
+        &RecoveryPolicy::default(),
+    )
+    .expect("capture should succeed");
+    (count, stats.into_stats())
+}
+
+fn counts(entries: &[(&str, u32)]) -> HashMap<String, u32> {
+    entries.iter().map(|(name, count)| (name.to_string(), *count)).collect()
+}
+
+#[test]
+fn reads_every_packet_of_a_savefile() {
+    let (count, stats) = capture(None, &StopConditions::default());
+    assert_eq!(count, 10);
+    assert_eq!(stats.packet_count, 10);
+    assert_eq!(
+        stats.protocol_stats,
+        counts(&[("ARP", 1), ("DNS", 2), ("TCP", 3), ("ICMPv4", 2), ("UDP", 1), ("TCP/IPv6", 1)])
+    );
+    assert_eq!(
+        stats.message_types,
+        counts(&[("ARP Request", 1), ("ICMPv4 Echo Request", 1), ("ICMPv4 Echo Reply", 1)])
+    );
+}
+
+#[test]
+fn packet_limit_stops_after_count_packets() {
+    let stop = StopConditions {
+        packet_limit: Some(4),
+        ..Default::default()
+    };
+    let (count, stats) = capture(None, &stop);
+    assert_eq!(count, 4);
+    assert_eq!(stats.protocol_stats, counts(&[("ARP", 1), ("DNS", 2), ("TCP", 1)]));
+}
+
+#[test]
+fn capture_filter_selects_matching_packets() {
+    let (count, stats) = capture(Some("tcp"), &StopConditions::default());
+    assert_eq!(count, 4);
+    assert_eq!(stats.protocol_stats, counts(&[("TCP", 3), ("TCP/IPv6", 1)]));
+
+    let (count, stats) = capture(Some("udp port 53"), &StopConditions::default());
+    assert_eq!(count, 2);
+    assert_eq!(stats.protocol_stats, counts(&[("DNS", 2)]));
+}
+
+#[test]
+fn filter_and_count_combine() {
+    let stop = StopConditions {
+        packet_limit: Some(1),
+        ..Default::default()
+    };
+    let (count, stats) = capture(Some("icmp"), &stop);
+    assert_eq!(count, 1);
+    assert_eq!(stats.message_types, counts(&[("ICMPv4 Echo Request", 1)]));
+}
+
+#[test]
+fn bad_filter_is_rejected() {
+    let mut source = FileSource::open(SAMPLE).expect("sample capture should open");
+    assert!(source.set_filter("tcp port").is_err());
+}
+
+#[test]
+fn savefile_snaplen_is_reported() {
+    let source = FileSource::open(SAMPLE).expect("sample capture should open");
+    let interfaces = source.interfaces();
+    assert_eq!(interfaces.len(), 1);
+    assert_eq!(interfaces[0].snaplen, 65535);
+}
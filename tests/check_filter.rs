@@ -0,0 +1,34 @@
+use std::process::{Command, Output};
+
+// Run the command-line tool; --check-filter and --dump-bpf exit before opening a device
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_testgame")).args(args).output().expect("binary should run")
+}
+
+#[test]
+fn check_filter_reports_a_valid_filter() {
+    let output = run(&["--check-filter", "--filter", "tcp", "--datalink", "EN10MB"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Filter OK for EN10MB: tcp ("), "{}", stdout);
+    assert!(stdout.ends_with(" instructions)\n"), "{}", stdout);
+}
+
+#[test]
+fn dump_bpf_prints_the_program() {
+    let output = run(&["--dump-bpf", "--filter", "tcp", "--datalink", "EN10MB"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines[0], "(000) ldh      [12]");
+    assert!(lines.last().unwrap().ends_with("ret      #0"), "{}", stdout);
+}
+
+#[test]
+fn check_filter_rejects_a_bad_filter() {
+    let output = run(&["--check-filter", "--filter", "tcp port", "--datalink", "EN10MB"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Filter does not compile for EN10MB: "), "{}", stderr);
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,322 @@
+use pcap::Linktype;
+use std::error::Error;
+use std::time::Duration;
+use testgame::defrag::Defragmenter;
+use testgame::display_filter::DisplayFilter;
+use testgame::listening::{parse_packet_with_etherparse, run_capture, ParsedPacket, StopConditions};
+use testgame::recovery::{CaptureError, CaptureGap, ReconnectEvent, RecoveryMode, RecoveryPolicy};
+use testgame::sink::PacketSink;
+use testgame::source::{FileSource, InterfaceInfo, PacketSource, RawPacket, SourceEvent, VecSource};
+
+// The frames of tests/data/sample.pcap (see tests/capture.rs), loaded into memory
+fn sample_packets() -> Vec<RawPacket> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/sample.pcap");
+    let mut source = FileSource::open(path).expect("sample capture should open");
+    let mut packets = Vec::new();
+    while let SourceEvent::Packet(packet) = source.next_packet().expect("sample capture should read") {
+        packets.push(packet);
+    }
+    packets
+}
+
+// Remembers what the capture loop handed it
+#[derive(Default)]
+struct Recorder {
+    numbers: Vec<u32>,
+    undecoded: u32,
+    reconnects: Vec<String>,
+    gaps: Vec<CaptureGap>,
+    finished: u32,
+}
+
+impl PacketSink for Recorder {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        assert_eq!(self.finished, 0, "packet delivered after finish");
+        self.numbers.push(packet.number);
+        if packet.decoded.is_err() {
+            self.undecoded += 1;
+        }
+        Ok(())
+    }
+
+    fn on_reconnect(&mut self, event: &ReconnectEvent) {
+        self.reconnects.push(event.to_string());
+    }
+
+    fn on_gap(&mut self, gap: &CaptureGap) {
+        self.gaps.push(gap.clone());
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.finished += 1;
+        Ok(())
+    }
+}
+
+fn run(
+    source: &mut dyn PacketSource,
+    display_filter: Option<&DisplayFilter>,
+    stop: &StopConditions,
+    recovery: &RecoveryPolicy,
+) -> (Result<u32, Box<dyn Error>>, Recorder) {
+    let mut recorder = Recorder::default();
+    let mut sinks: Vec<&mut dyn PacketSink> = vec![&mut recorder];
+    let result = run_capture(
+        source,
+        &mut sinks,
+        &mut Defragmenter::default(),
+        display_filter,
+        stop,
+        recovery,
+    );
+    (result, recorder)
+}
+
+#[test]
+fn every_packet_reaches_the_sinks_in_order() {
+    let mut source = VecSource::new(sample_packets(), Linktype::ETHERNET);
+    let (result, recorder) = run(&mut source, None, &StopConditions::default(), &RecoveryPolicy::default());
+    assert_eq!(result.unwrap(), 10);
+    assert_eq!(recorder.numbers, (1..=10).collect::<Vec<_>>());
+    assert_eq!(recorder.finished, 1);
+}
+
+#[test]
+fn display_filter_drops_packets_before_the_sinks() {
+    let filter = DisplayFilter::parse("dns").unwrap();
+    let mut source = VecSource::new(sample_packets(), Linktype::ETHERNET);
+    let (result, recorder) = run(&mut source, Some(&filter), &StopConditions::default(), &RecoveryPolicy::default());
+    // Filtered packets still count, and keep their capture numbers
+    assert_eq!(result.unwrap(), 10);
+    assert_eq!(recorder.numbers, vec![2, 3]);
+
+    let filter = DisplayFilter::parse("udp && !dns").unwrap();
+    let mut source = VecSource::new(sample_packets(), Linktype::ETHERNET);
+    let (_, recorder) = run(&mut source, Some(&filter), &StopConditions::default(), &RecoveryPolicy::default());
+    assert_eq!(recorder.numbers, vec![9]);
+}
+
+#[test]
+fn display_filter_drops_undecodable_packets() {
+    let mut packets = sample_packets();
+    packets.push(RawPacket::new(1_700_000_010, 0, vec![0xff; 3]));
+
+    let mut source = VecSource::new(packets.clone(), Linktype::ETHERNET);
+    let (_, recorder) = run(&mut source, None, &StopConditions::default(), &RecoveryPolicy::default());
+    assert_eq!(recorder.undecoded, 1);
+
+    let filter = DisplayFilter::parse("frame").unwrap();
+    let mut source = VecSource::new(packets, Linktype::ETHERNET);
+    let (_, recorder) = run(&mut source, Some(&filter), &StopConditions::default(), &RecoveryPolicy::default());
+    assert_eq!(recorder.numbers.len(), 10);
+    assert_eq!(recorder.undecoded, 0);
+}
+
+#[test]
+fn finish_is_called_when_a_limit_ends_the_capture() {
+    let stop = StopConditions {
+        packet_limit: Some(3),
+        ..Default::default()
+    };
+    let mut source = VecSource::new(sample_packets(), Linktype::ETHERNET);
+    let (result, recorder) = run(&mut source, None, &stop, &RecoveryPolicy::default());
+    assert_eq!(result.unwrap(), 3);
+    assert_eq!(recorder.numbers, vec![1, 2, 3]);
+    assert_eq!(recorder.finished, 1);
+}
+
+#[test]
+fn every_datalink_reaches_the_same_ip_decoding() {
+    // IPv4 + UDP 10.0.0.5:5000 -> 10.0.0.1:53 with a 4-byte payload
+    let mut ip = vec![0x45, 0x00, 0x00, 0x20, 0, 1, 0, 0, 64, 17, 0, 0, 10, 0, 0, 5, 10, 0, 0, 1];
+    ip.extend_from_slice(&[0x13, 0x88, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00, 1, 2, 3, 4]);
+    let framed = |header: &[u8]| [header, ip.as_slice()].concat();
+
+    let sll = [0, 0, 0, 1, 0, 6, 2, 0x11, 0x22, 0x33, 0x44, 0x55, 0, 0, 0x08, 0x00];
+    let sll2 = [0x08, 0x00, 0, 0, 0, 0, 0, 2, 0, 1, 0, 6, 2, 0x11, 0x22, 0x33, 0x44, 0x55, 0, 0];
+    let cases = [
+        (Linktype::LINUX_SLL, framed(&sll)),
+        (Linktype::LINUX_SLL2, framed(&sll2)),
+        (Linktype::RAW, ip.clone()),
+        // BSD loopback: AF_INET in host byte order
+        (Linktype::NULL, framed(&[2, 0, 0, 0])),
+    ];
+    for (linktype, frame) in cases {
+        let packet = parse_packet_with_etherparse(1, &RawPacket::new(0, 0, frame), linktype).unwrap();
+        let summary = (packet.protocol_name(), packet.src_addr().map(|a| a.to_string()), packet.dst_port());
+        assert_eq!(summary, ("UDP".to_string(), Some("10.0.0.5".to_string()), Some(53)), "{:?}", linktype);
+    }
+}
+
+#[test]
+fn vec_source_applies_capture_filters() {
+    let mut source = VecSource::new(sample_packets(), Linktype::ETHERNET);
+    source.set_filter("icmp").unwrap();
+    let (result, recorder) = run(&mut source, None, &StopConditions::default(), &RecoveryPolicy::default());
+    assert_eq!(result.unwrap(), 2);
+    assert_eq!(recorder.numbers, vec![1, 2]);
+}
+
+// In-memory source whose "device" fails once after `fail_after` packets
+struct FlakySource {
+    inner: VecSource,
+    fail_after: usize,
+    delivered: usize,
+    failed: bool,
+    reopens: u32,
+}
+
+impl FlakySource {
+    fn new(fail_after: usize) -> Self {
+        FlakySource {
+            inner: VecSource::new(sample_packets(), Linktype::ETHERNET),
+            fail_after,
+            delivered: 0,
+            failed: false,
+            reopens: 0,
+        }
+    }
+}
+
+impl PacketSource for FlakySource {
+    fn next_packet(&mut self) -> Result<SourceEvent, Box<dyn Error>> {
+        if self.delivered == self.fail_after && !self.failed {
+            self.failed = true;
+            return Err(Box::new(CaptureError::DeviceGone {
+                device: "flaky0".to_string(),
+                message: "network is down".to_string(),
+            }));
+        }
+        self.delivered += 1;
+        self.inner.next_packet()
+    }
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        self.inner.interfaces()
+    }
+
+    fn set_filter(&mut self, filter: &str) -> Result<(), Box<dyn Error>> {
+        self.inner.set_filter(filter)
+    }
+
+    fn can_reopen(&self) -> bool {
+        true
+    }
+
+    fn reopen(&mut self) -> Result<(), Box<dyn Error>> {
+        self.reopens += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn gaps_are_delivered_and_the_capture_continues() {
+    let recovery = RecoveryPolicy {
+        mode: RecoveryMode::Reconnect,
+        initial_backoff: Duration::ZERO,
+        ..RecoveryPolicy::default()
+    };
+    let mut source = FlakySource::new(4);
+    let (result, recorder) = run(&mut source, None, &StopConditions::default(), &recovery);
+    assert_eq!(result.unwrap(), 10);
+    assert_eq!(source.reopens, 1);
+    assert_eq!(recorder.numbers, (1..=10).collect::<Vec<_>>());
+    assert_eq!(
+        recorder.reconnects,
+        [
+            "device flaky0 went away: network is down; reconnecting in 0.0s (attempt 1)",
+            "Reconnected to flaky0",
+        ]
+    );
+    assert_eq!(recorder.gaps.len(), 1);
+    assert_eq!(recorder.gaps[0].device, "flaky0");
+    assert_eq!(recorder.gaps[0].attempts, 1);
+    assert_eq!(recorder.finished, 1);
+}
+
+#[test]
+fn sinks_are_finished_when_the_source_fails() {
+    let mut source = FlakySource::new(4);
+    let (result, recorder) = run(&mut source, None, &StopConditions::default(), &RecoveryPolicy::default());
+    assert!(result.is_err());
+    assert_eq!(source.reopens, 0);
+    assert!(recorder.reconnects.is_empty());
+    assert_eq!(recorder.numbers, vec![1, 2, 3, 4]);
+    assert!(recorder.gaps.is_empty());
+    assert_eq!(recorder.finished, 1);
+}
+
+// Fails on the given packet, or when finished
+#[derive(Default)]
+struct FailingSink {
+    fail_on: Option<u32>,
+    fail_finish: bool,
+    finished: u32,
+}
+
+impl PacketSink for FailingSink {
+    fn on_packet(&mut self, packet: &ParsedPacket) -> Result<(), Box<dyn Error>> {
+        if self.fail_on == Some(packet.number) {
+            return Err("sink failed".into());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.finished += 1;
+        if self.fail_finish {
+            return Err("finish failed".into());
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn a_failing_sink_stops_the_capture_but_every_sink_is_finished() {
+    let mut failing = FailingSink {
+        fail_on: Some(3),
+        ..Default::default()
+    };
+    let mut recorder = Recorder::default();
+    let mut sinks: Vec<&mut dyn PacketSink> = vec![&mut failing, &mut recorder];
+    let mut source = VecSource::new(sample_packets(), Linktype::ETHERNET);
+    let result = run_capture(
+        &mut source,
+        &mut sinks,
+        &mut Defragmenter::default(),
+        None,
+        &StopConditions::default(),
+        &RecoveryPolicy::default(),
+    );
+    assert_eq!(result.unwrap_err().to_string(), "sink failed");
+    // The sink after the failing one never saw packet 3
+    assert_eq!(recorder.numbers, vec![1, 2]);
+    assert_eq!(failing.finished, 1);
+    assert_eq!(recorder.finished, 1);
+}
+
+#[test]
+fn sinks_after_a_failed_finish_are_still_finished() {
+    let mut first = FailingSink {
+        fail_finish: true,
+        ..Default::default()
+    };
+    let mut second = FailingSink {
+        fail_finish: true,
+        ..Default::default()
+    };
+    let mut recorder = Recorder::default();
+    let mut sinks: Vec<&mut dyn PacketSink> = vec![&mut first, &mut second, &mut recorder];
+    let mut source = VecSource::new(sample_packets(), Linktype::ETHERNET);
+    let result = run_capture(
+        &mut source,
+        &mut sinks,
+        &mut Defragmenter::default(),
+        None,
+        &StopConditions::default(),
+        &RecoveryPolicy::default(),
+    );
+    assert_eq!(result.unwrap_err().to_string(), "finish failed");
+    assert_eq!(recorder.numbers.len(), 10);
+    assert_eq!((first.finished, second.finished, recorder.finished), (1, 1, 1));
+}